@@ -1,20 +1,14 @@
 use clap::{App, Arg};
-use ratman::Router;
-use ratman_configure::config::{Endpoint, Network, Params};
-use std::collections::BTreeMap;
-use std::{env, fs::File, io::Read, path::PathBuf, sync::Arc};
+use ratman_configure::config::{Network, Params};
+use std::{env, fs, path::PathBuf};
 
 /// The hub configuration
 #[derive(Debug, Clone)]
 pub(crate) struct Config {
-    /// Path to initial peer set
-    pub(crate) peers: PathBuf,
-    /// Runtime mode (in netmod-tcp)
-    pub(crate) mode: String,
-    /// What interface to bind on
-    pub(crate) addr: String,
-    /// What port to bind on
-    pub(crate) port: u16,
+    /// Path to the network configuration
+    pub(crate) network: PathBuf,
+    /// The secret that the data directory is encrypted with
+    pub(crate) secret: String,
     /// Disable upnp port forwarding
     pub(crate) no_upnp: bool,
     /// Disable multicast local discovery
@@ -22,81 +16,40 @@ pub(crate) struct Config {
 }
 
 impl Config {
-    /// Consume the application config into a fully initialised router
-    pub(crate) fn into_router(self) -> Arc<Router> {
-        let mut buf = String::new();
-        let mut f = File::open(self.peers)
-            .unwrap_or_else(|_| crate::elog("Peers configuration not found!", 128));
-        f.read_to_string(&mut buf).unwrap();
-
-        let ep = Endpoint {
-            id: 0,
-            params: Params::Tcp {
-                addr: self.addr,
-                port: self.port,
-                peers: buf
-                    .split("\n")
-                    .map(|s| {
-                        s.parse()
-                            .unwrap_or_else(|_| crate::elog("Invalid peer port-address format!", 2))
-                    })
-                    .collect(),
-                dynamic: false,
-            },
-        };
-
-        Network {
-            endpoints: {
-                let mut map = BTreeMap::new();
-                map.insert(0, ep);
-                map
-            },
-            patches: Default::default(),
-        }
-        .into_router()
+    /// Load the network configuration file
+    pub(crate) fn network(&self) -> Network {
+        ratman_configure::load(&self.network)
+            .unwrap_or_else(|e| crate::elog(format!("Invalid network configuration: {}", e), 2))
     }
 }
 
+/// Find the port to forward via upnp, if the network has a tcp endpoint
+pub(crate) fn tcp_port(net: &Network) -> Option<u16> {
+    net.values().find_map(|ep| match ep.params {
+        Params::Tcp { port, .. } => Some(port),
+        _ => None,
+    })
+}
+
 pub(crate) fn cli<'a>() -> App<'a, 'a> {
     App::new("qaul-hubd")
         .about(env!("CARGO_PKG_DESCRIPTION"))
         .version(env!("CARGO_PKG_VERSION"))
         .arg(
-            Arg::with_name("PEERS_PATH")
-                .short("P")
-                .long("peers")
+            Arg::with_name("CONFIG_PATH")
+                .short("c")
+                .long("config")
                 .takes_value(true)
-                .required(true)
                 .value_name("PATH")
-                .help("The path to a file, containing a list of newline separated initial peers"),
+                .help("The path to a network configuration file (json or toml)"),
         )
         .arg(
-            Arg::with_name("RUN_MODE")
-                .short("m")
-                .long("mode")
+            Arg::with_name("SECRET_PATH")
+                .short("s")
+                .long("secret-file")
                 .takes_value(true)
-                .value_name("MODE")
-                .default_value("static")
-                .possible_values(&["static", "dynamic"])
-                .help("The hub's run mode"),
-        )
-        .arg(
-            Arg::with_name("SOCKET_ADDR")
-                .short("a")
-                .long("addr")
-                .takes_value(true)
-                .value_name("ADDR")
-                .default_value("0.0.0.0")
-                .help("The hub's bound socket address"),
-        )
-        .arg(
-            Arg::with_name("SOCKET_PORT")
-                .short("p")
-                .long("port")
-                .takes_value(true)
-                .required(true)
-                .value_name("PORT")
-                .help("The hub's bound socket port"),
+                .value_name("PATH")
+                .help("The path to a file with the secret that encrypts the data directory"),
         )
         .arg(
             Arg::with_name("NO_UPNP")
@@ -119,29 +72,24 @@ pub(crate) fn match_fold<'a>(app: App<'a, 'a>) -> Config {
     let m = app.get_matches();
 
     Config {
-        peers: {
-            let p = m.value_of("PEERS_PATH").map(|s| s.to_owned()).unwrap();
-            let mut buf = PathBuf::new();
-            buf.push(p);
-            buf
-        },
-        mode: m
-            .value_of("RUN_MODE")
+        network: m
+            .value_of("CONFIG_PATH")
             .map(|s| s.to_owned())
-            .or(env::var("QAUL_HUBD_MODE").ok())
-            .unwrap_or("static".into()),
-        addr: m
-            .value_of("SOCKET_ADDR")
+            .or(env::var("QAUL_HUBD_CONFIG").ok())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| crate::elog("No network configuration provided!", 128)),
+        secret: m
+            .value_of("SECRET_PATH")
             .map(|s| s.to_owned())
-            .or(env::var("QAUL_HUBD_ADDR").ok())
-            .unwrap_or("0.0.0.0".into()),
-        port: m
-            .value_of("SOCKET_PORT")
-            .map(|s| str::parse(s).unwrap())
-            .or(env::var("QAUL_HUBD_PORT")
-                .ok()
-                .map(|s| str::parse(&s).unwrap()))
-            .unwrap_or(9001),
+            .or(env::var("QAUL_HUBD_SECRET_FILE").ok())
+            .map(|path| {
+                fs::read_to_string(&path).unwrap_or_else(|e| {
+                    crate::elog(format!("Failed to read secret file `{}`: {}", path, e), 2)
+                })
+            })
+            .map(|s| s.trim_end_matches(&['\r', '\n'][..]).to_owned())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| crate::elog("No secret provided!", 128)),
         no_upnp: m.is_present("NO_UPNP"),
         no_multicast: m.is_present("NO_UDP_DISCOVER"),
     }
@@ -13,7 +13,11 @@ pub(crate) fn parse_log_level() {
         .add_directive("async_std=error".parse().unwrap())
         .add_directive("mio=error".parse().unwrap());
 
-    // Initialise the logger
-    fmt().with_env_filter(filter).init();
+    // Initialise the logger on stderr, because a unix endpoint might
+    // be using stdout to send frames
+    fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
     info!("Initialised logger: welcome to qaul-hubd!");
 }
@@ -24,14 +24,15 @@ async fn main() {
 
     let app = cfg::cli();
     let cfg = cfg::match_fold(app);
-    let _state = State::new(&cfg).await;
+    let network = cfg.network();
+    let port = cfg::tcp_port(&network);
+    let _state = State::new(&cfg, network).await;
 
     // !no_upnp means upnp has _not_ been disabled
-    if !cfg.no_upnp {
-        let cfg = cfg.clone();
+    if let (false, Some(port)) = (cfg.no_upnp, port) {
         // the upnp crate does synchronous i/o and we don't want to block this async worker
         std::thread::spawn(move || {
-            if upnp::open_port(cfg.port).is_none() {
+            if upnp::open_port(port).is_none() {
                 error!("Failed to open UPNP port; your router probably doesn't support it...");
             }
         });
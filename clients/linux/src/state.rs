@@ -2,10 +2,10 @@
 
 use crate::cfg::Config;
 use directories::ProjectDirs;
-use netmod_tcp::{Endpoint, Mode};
 use ratman::Router;
+use ratman_configure::config::Network;
 use std::collections::HashSet;
-use std::{fs::File, io::Read, net::SocketAddr, str::FromStr, sync::Arc};
+use std::{net::SocketAddr, str::FromStr, sync::Arc};
 use async_std::{task, task::spawn};
 use {
     libqaul::{users::UserUpdate, Qaul},
@@ -23,31 +23,24 @@ pub(crate) struct State {
 
 impl State {
     /// Create a new run state
-    pub(crate) async fn new(cfg: &Config) -> State {
-        let ep = Endpoint::new(
-            &cfg.addr,
-            cfg.port,
-            "qaul-linux",
-            match cfg.mode.as_str() {
-                "dynamic" => Mode::Dynamic,
-                _ => Mode::Static,
-            },
-        )
-        .await
-        .unwrap();
-
-        let mut buf = String::new();
-        let mut peersfd = File::open(&cfg.peers).unwrap();
-        peersfd.read_to_string(&mut buf).unwrap();
-
-        let peers = buf.split("\n").map(|s| s.to_string()).collect();
-        ep.add_peers(peers).await.unwrap();
-
-        let router = Router::new();
-        router.add_endpoint(ep).await;
+    pub(crate) async fn new(cfg: &Config, network: Network) -> State {
+        let router = network
+            .into_router()
+            .await
+            .unwrap_or_else(|e| crate::elog(format!("Failed to initialise router: {}", e), 2));
 
         let dirs = ProjectDirs::from("net", "qaul", "hubd").unwrap();
-        let qaul = Qaul::new(Arc::clone(&router));
+        let qaul =
+            Qaul::open(Arc::clone(&router), dirs.data_dir(), &cfg.secret).unwrap_or_else(|e| {
+                crate::elog(
+                    format!(
+                        "Failed to open data directory `{}`: {}",
+                        dirs.data_dir().display(),
+                        e
+                    ),
+                    2,
+                )
+            });
 
         // services
         let chat = Chat::new(Arc::clone(&qaul)).await.unwrap();
@@ -3,10 +3,22 @@
 //! Adds REST Routes for WebGUI
 
 use async_std::sync::Arc;
-use tide::Server;
+use tide::{Request, Response, Server};
 
 use crate::{http::http2rpc, Responder};
 
+/// List all local users, or search the user directory with `?q=`
+async fn user_list(req: Request<Arc<Responder>>) -> Response {
+    let search = req.uri().query().map_or(false, |query| {
+        query.split('&').any(|param| param.starts_with("q="))
+    });
+
+    match search {
+        true => http2rpc::http2rpc_query(req, "user", "search").await,
+        false => http2rpc::http2rpc(req, "user", "list").await,
+    }
+}
+
 /// Creates the Tide server and routes for the REST endpoint
 pub fn http_routes(http_state: Arc<Responder>) -> Server<Arc<Responder>> {
     let mut app_http = tide::with_state(http_state);
@@ -21,7 +33,7 @@ pub fn http_routes(http_state: Arc<Responder>) -> Server<Arc<Responder>> {
     // user management
     app_http
         .at("/user")
-        .get(|req| async move { http2rpc::http2rpc(req, "user", "list").await })
+        .get(user_list)
         .post(|req| async move { http2rpc::http2rpc(req, "user", "create").await });
     app_http
         .at("/user/:id")
@@ -3,7 +3,7 @@
 use crate::QaulRpc;
 use async_trait::async_trait;
 use libqaul::{
-    contacts::{ContactEntry, ContactQuery},
+    contacts::{ContactEntry, ContactQuery, Proof, SafetyNumber as Number},
     error::Result,
     helpers::{ItemDiff, ItemDiffExt},
     users::UserAuth,
@@ -14,18 +14,18 @@ use serde::{Deserialize, Serialize};
 /// Apply a modification to a contact entry
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub struct Modify {
-    auth: UserAuth,
-    contact: Identity,
+    pub auth: UserAuth,
+    pub contact: Identity,
     #[serde(default)]
-    nick: ItemDiff<String>,
+    pub nick: ItemDiff<String>,
     #[serde(default)]
-    trust: Option<i8>,
+    pub trust: Option<i8>,
     #[serde(default)]
-    met: Option<bool>,
+    pub met: Option<bool>,
     #[serde(default)]
-    location: ItemDiff<String>,
+    pub location: ItemDiff<String>,
     #[serde(default)]
-    notes: ItemDiff<String>,
+    pub notes: ItemDiff<String>,
 }
 
 #[async_trait]
@@ -41,58 +41,97 @@ impl QaulRpc for Modify {
             location,
             notes,
         } = self;
-        qaul.contacts().modify(auth, &contact, move |contact| {
-            nick.apply(&mut contact.nick);
-            if let Some(trust) = trust {
-                contact.trust = trust;
-            }
-            if let Some(met) = met {
-                contact.met = met;
-            }
-            location.apply(&mut contact.location);
-            notes.apply(&mut contact.notes);
-        })
+        qaul.contacts()
+            .modify(auth, &contact, move |contact| {
+                nick.apply(&mut contact.nick);
+                if let Some(trust) = trust {
+                    contact.trust = trust;
+                }
+                if let Some(met) = met {
+                    contact.met = met;
+                }
+                location.apply(&mut contact.location);
+                notes.apply(&mut contact.notes);
+            })
+            .await
     }
 }
 
 /// Get the contact entry for an identity
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub struct Get {
-    auth: UserAuth,
-    contact: Identity,
+    pub auth: UserAuth,
+    pub contact: Identity,
 }
 
 #[async_trait]
 impl QaulRpc for Get {
     type Response = Result<ContactEntry>;
     async fn apply(self, qaul: &Qaul) -> Self::Response {
-        qaul.contacts().get(self.auth, &self.contact)
+        qaul.contacts().get(self.auth, &self.contact).await
     }
 }
 
+/// Find contacts by their contact entry
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Query {
-    auth: UserAuth,
-    query: ContactQuery,
+    pub auth: UserAuth,
+    pub query: ContactQuery,
 }
 
 #[async_trait]
 impl QaulRpc for Query {
     type Response = Result<Vec<Identity>>;
     async fn apply(self, qaul: &Qaul) -> Self::Response {
-        qaul.contacts().query(self.auth, self.query)
+        qaul.contacts().query(self.auth, self.query).await
     }
 }
 
+/// List all contacts of a user
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub struct All {
-    auth: UserAuth,
+    pub auth: UserAuth,
 }
 
 #[async_trait]
 impl QaulRpc for All {
     type Response = Result<Vec<Identity>>;
     async fn apply(self, qaul: &Qaul) -> Self::Response {
-        qaul.contacts().all(self.auth)
+        qaul.contacts().all(self.auth).await
+    }
+}
+
+/// Get the safety number for a user and one of their contacts
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct SafetyNumber {
+    pub auth: UserAuth,
+    pub contact: Identity,
+}
+
+#[async_trait]
+impl QaulRpc for SafetyNumber {
+    type Response = Result<Number>;
+    async fn apply(self, qaul: &Qaul) -> Self::Response {
+        qaul.contacts()
+            .safety_number(self.auth, &self.contact)
+            .await
+    }
+}
+
+/// Mark a contact as verified, with proof of their safety number
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct Verify {
+    pub auth: UserAuth,
+    pub contact: Identity,
+    pub proof: Proof,
+}
+
+#[async_trait]
+impl QaulRpc for Verify {
+    type Response = Result<()>;
+    async fn apply(self, qaul: &Qaul) -> Self::Response {
+        qaul.contacts()
+            .verify(self.auth, &self.contact, self.proof)
+            .await
     }
 }
@@ -1,6 +1,6 @@
 use super::*;
 use libqaul::{
-    contacts::ContactEntry,
+    contacts::{ContactEntry, SafetyNumber},
     messages::{Message, MsgId, MsgRef},
     users::{UserAuth, UserProfile},
     Identity,
@@ -54,6 +54,8 @@ pub enum Request {
     ContactGet(contacts::Get),
     ContactQuery(contacts::Query),
     ContactAll(contacts::All),
+    ContactSafetyNumber(contacts::SafetyNumber),
+    ContactVerify(contacts::Verify),
 
     // =^-^= Generic/ low level commands =^-^=
     MsgSend(messages::Send),
@@ -62,6 +64,7 @@ pub enum Request {
     // =^-^= Users =^-^=
     UserList(users::List),
     UserListRemote(users::ListRemote),
+    UserSearch(users::Search),
     UserIsAuthenticated(users::IsAuthenticated),
     UserCreate(users::Create),
     UserDelete(users::Delete),
@@ -70,6 +73,8 @@ pub enum Request {
     UserLogout(users::Logout),
     UserGet(users::Get),
     UserUpdate(users::Update),
+    UserExport(users::Export),
+    UserImport(users::Import),
 
     // =^-^= Voice calls =^-^=
     #[cfg(feature = "voice")]
@@ -101,10 +106,16 @@ pub enum Response {
     // =^-^= authentication data =^-^=
     Auth(UserAuth),
 
+    // =^-^= an encrypted user backup =^-^=
+    Backup(Vec<u8>),
+
     // =^-^= additional user contact data =^-^=
     Contact(ContactEntry),
     Contacts(Vec<ContactEntry>),
 
+    // =^-^= a safety number to verify a contact =^-^=
+    SafetyNumber(SafetyNumber),
+
     // =^-^= binary payload messages =^-^=
     Message(Message),
     Messages(Vec<Message>),
@@ -210,6 +221,12 @@ impl From<Vec<ContactEntry>> for Response {
     }
 }
 
+impl From<SafetyNumber> for Response {
+    fn from(number: SafetyNumber) -> Self {
+        Response::SafetyNumber(number)
+    }
+}
+
 ////
 //// =^-^= Binary message responses
 ////
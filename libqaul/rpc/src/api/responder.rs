@@ -103,7 +103,10 @@ impl<K: StreamResponder + Send + Sync + 'static> Responder<K> {
             // =^-^= Contacts =^-^=
             // FIXME: this should be a contacts type!
             Request::UserListRemote(r) => self.respond_qaul(r).await.into(),
+            Request::ContactModify(r) => self.respond_qaul(r).await.into(),
             Request::ContactGet(r) => self.respond_qaul(r).await.into(),
+            Request::ContactSafetyNumber(r) => self.respond_qaul(r).await.into(),
+            Request::ContactVerify(r) => self.respond_qaul(r).await.into(),
 
             // TODO: Currently the "query" functions don't return
             // actual data, but just the IDs.  Maybe we should change
@@ -133,6 +136,7 @@ impl<K: StreamResponder + Send + Sync + 'static> Responder<K> {
 
             // =^-^= Users =^-^=
             Request::UserList(r) => self.respond_qaul(r).await.into(),
+            Request::UserSearch(r) => self.respond_qaul(r).await.into(),
             Request::UserIsAuthenticated(r) => match self.respond_qaul(r).await {
                 Ok(()) => Response::Success,
                 Err(_) => Response::Error("Not authorised".into()),
@@ -144,6 +148,11 @@ impl<K: StreamResponder + Send + Sync + 'static> Responder<K> {
             Request::UserLogout(r) => self.respond_qaul(r).await.into(),
             Request::UserGet(r) => self.respond_qaul(r).await.into(),
             Request::UserUpdate(r) => self.respond_qaul(r).await.into(),
+            Request::UserExport(r) => match self.respond_qaul(r).await {
+                Ok(backup) => Response::Backup(backup),
+                Err(e) => Response::Error(e.to_string()),
+            },
+            Request::UserImport(r) => self.respond_qaul(r).await.into(),
 
             // =^-^= Voices =^-^=
             #[cfg(feature = "voice")]
@@ -2,11 +2,10 @@
 
 use crate::QaulRpc;
 use async_trait::async_trait;
-use futures::future;
 use libqaul::{
     error::Result,
     helpers::{ItemDiff, ItemDiffExt, MapDiff, MapDiffExt, SetDiff, SetDiffExt},
-    users::{UserAuth, UserProfile, UserUpdate},
+    users::{UserAuth, UserProfile, UserScope, UserUpdate},
     Identity, Qaul,
 };
 use serde::{Deserialize, Serialize};
@@ -36,6 +35,29 @@ impl QaulRpc for ListRemote {
     }
 }
 
+/// Search the user directory
+///
+/// The query is passed as `q`, so that it can be given as a query
+/// parameter via HTTP (`/user?q=...`).
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Search {
+    pub auth: UserAuth,
+    #[serde(rename = "q")]
+    pub query: String,
+    #[serde(default)]
+    pub scope: UserScope,
+}
+
+#[async_trait]
+impl QaulRpc for Search {
+    type Response = Result<Vec<UserProfile>>;
+    async fn apply(self, qaul: &Qaul) -> Self::Response {
+        qaul.users()
+            .search(self.auth, &self.query, self.scope)
+            .await
+    }
+}
+
 /// Check if a user's token is still valid
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct IsAuthenticated {
@@ -85,14 +107,17 @@ impl QaulRpc for Delete {
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct ChangePw {
     auth: UserAuth,
+    old: String,
     new: String,
 }
 
 #[async_trait]
 impl QaulRpc for ChangePw {
-    type Response = Result<()>;
+    type Response = Result<UserAuth>;
     async fn apply(self, qaul: &Qaul) -> Self::Response {
-        qaul.users().change_pw(self.auth, &self.new)
+        qaul.users()
+            .change_pw(self.auth, &self.old, &self.new)
+            .await
     }
 }
 
@@ -125,6 +150,41 @@ impl QaulRpc for Logout {
     }
 }
 
+/// Export a user into an encrypted backup
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Export {
+    auth: UserAuth,
+    passphrase: String,
+    /// Indicate whether messages should be included as well
+    #[serde(default)]
+    messages: bool,
+}
+
+#[async_trait]
+impl QaulRpc for Export {
+    type Response = Result<Vec<u8>>;
+    async fn apply(self, qaul: &Qaul) -> Self::Response {
+        qaul.users()
+            .export(self.auth, &self.passphrase, self.messages)
+            .await
+    }
+}
+
+/// Create a local user from an encrypted backup
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Import {
+    backup: Vec<u8>,
+    passphrase: String,
+}
+
+#[async_trait]
+impl QaulRpc for Import {
+    type Response = Result<UserAuth>;
+    async fn apply(self, qaul: &Qaul) -> Self::Response {
+        qaul.users().import(&self.backup, &self.passphrase).await
+    }
+}
+
 /// Get the user profile for any remote or local user
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct Get {
@@ -193,13 +253,18 @@ impl QaulRpc for Update {
             SetDiff::Remove(val) => changes.push(UserUpdate::RemoveService(val)),
         });
 
+        match avatar {
+            ItemDiff::Ignore => {}
+            ItemDiff::Set(data) => changes.push(UserUpdate::AvatarData(Some(data))),
+            ItemDiff::Unset => changes.push(UserUpdate::AvatarData(None)),
+        }
+
+        // Every update bumps the profile version, so they can't be
+        // applied concurrently
         let users = qaul.users();
-        future::join_all(changes.into_iter().fold(vec![], |mut vec, u| {
-            vec.push(users.update(auth.clone(), u));
-            vec
-        }))
-        .await
-        .into_iter()
-        .fold(Ok(()), |prev, res| prev.and_then(|_| res))
+        for update in changes {
+            users.update(auth.clone(), update).await?;
+        }
+        Ok(())
     }
 }
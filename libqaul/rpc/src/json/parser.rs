@@ -28,6 +28,7 @@ fn de_json<'env, T: DeserializeOwned>(
         ("user", "list")
         | ("user", "login")
         | ("user", "create")
+        | ("user", "import")
         | ("user", "get")
         | ("file", "list") => {}
         (_, _) => {
@@ -85,12 +86,16 @@ impl RequestEnv {
 
                 // libqaul contact functions
                 ("contact", "list") => Request::UserListRemote(de_json(data, auth)?),
-                // ("contact", "get") => Request::ContactGet(de_json(data, auth)?),
-                // ("contact", "query") => Request::ContactQuery(de_json(data, auth)?),
-                // ("contact", "modify") => Request::ContactQuery(de_json(data, auth)?),
+                ("contact", "all") => Request::ContactAll(de_json(data, auth)?),
+                ("contact", "get") => Request::ContactGet(de_json(data, auth)?),
+                ("contact", "query") => Request::ContactQuery(de_json(data, auth)?),
+                ("contact", "modify") => Request::ContactModify(de_json(data, auth)?),
+                ("contact", "safety_number") => Request::ContactSafetyNumber(de_json(data, auth)?),
+                ("contact", "verify") => Request::ContactVerify(de_json(data, auth)?),
 
                 // libqaul user functions
                 ("user", "list") => Request::UserList(de_json(data, auth)?),
+                ("user", "search") => Request::UserSearch(de_json(data, auth)?),
                 ("user", "create") => Request::UserCreate(de_json(data, auth)?),
                 ("user", "delete") => Request::UserDelete(de_json(data, auth)?),
                 ("user", "repass") => Request::UserChangePw(de_json(data, auth)?),
@@ -99,6 +104,8 @@ impl RequestEnv {
                 ("user", "validate") => Request::UserIsAuthenticated(de_json(data, auth)?),
                 ("user", "get") => Request::UserGet(de_json(data, auth)?),
                 ("user", "modify") => Request::UserUpdate(de_json(data, auth)?),
+                ("user", "export") => Request::UserExport(de_json(data, auth)?),
+                ("user", "import") => Request::UserImport(de_json(data, auth)?),
                 (kind, method) => {
                     return Err(format!("Unknown parse tuple: ({}, {})", kind, method));
                 }
@@ -205,6 +212,52 @@ fn envelope_chat_user_list() {
     assert_eq!(env.data, Request::UserList(crate::api::users::List {}));
 }
 
+#[test]
+fn envelope_user_search() {
+    use libqaul::users::UserScope;
+
+    // Query parameters from the HTTP API are all strings
+    let auth = UserAuth::test();
+    let json = json_builder(
+        "user",
+        "search",
+        Some(auth.clone()),
+        Some(vec![
+            ("q", Value::String("alice".into())),
+            ("scope", Value::String("Contacts".into())),
+        ]),
+    );
+
+    let je: RequestEnv = serde_json::from_str(&json).expect("JsonEnvelope failed");
+    let env = je.generate_envelope().unwrap();
+    assert_eq!(
+        env.data,
+        Request::UserSearch(crate::api::users::Search {
+            auth: auth.clone(),
+            query: "alice".into(),
+            scope: UserScope::Contacts,
+        })
+    );
+
+    // Without a scope all users are searched
+    let json = json_builder(
+        "user",
+        "search",
+        Some(auth.clone()),
+        Some(vec![("q", Value::String("alice".into()))]),
+    );
+    let je: RequestEnv = serde_json::from_str(&json).expect("JsonEnvelope failed");
+    let env = je.generate_envelope().unwrap();
+    assert_eq!(
+        env.data,
+        Request::UserSearch(crate::api::users::Search {
+            auth,
+            query: "alice".into(),
+            scope: UserScope::All,
+        })
+    );
+}
+
 #[test]
 fn envelope_chat_room_create() {
     use libqaul::Identity;
@@ -304,3 +357,116 @@ fn envelope_chat_room_modify() {
         })
     );
 }
+
+#[test]
+fn envelope_contact_modify() {
+    use libqaul::Identity;
+
+    let contact = Identity::random();
+    let auth = UserAuth::test();
+    let json = json_builder(
+        "contact",
+        "modify",
+        Some(auth.clone()),
+        Some(vec![
+            ("contact", Value::String(contact.to_string())),
+            ("nick", json!({ "set": "friend" })),
+            ("met", Value::Bool(true)),
+        ]),
+    );
+
+    let je: RequestEnv = serde_json::from_str(&json).expect("JsonEnvelope failed");
+    let env = je.generate_envelope().unwrap();
+
+    assert_eq!(
+        env.data,
+        Request::ContactModify(crate::api::contacts::Modify {
+            auth,
+            contact,
+            nick: ItemDiff::Set("friend".into()),
+            trust: None,
+            met: Some(true),
+            location: ItemDiff::Ignore,
+            notes: ItemDiff::Ignore,
+        })
+    );
+}
+
+#[test]
+fn envelope_contact_query() {
+    use libqaul::contacts::ContactQuery;
+
+    let auth = UserAuth::test();
+    let json = json_builder(
+        "contact",
+        "query",
+        Some(auth.clone()),
+        Some(vec![(
+            "query",
+            json!({ "And": [
+                { "Trust": { "val": 100, "fuz": 20 } },
+                { "Or": [{ "Met": true }, { "Location": "Berlin" }] }
+            ]}),
+        )]),
+    );
+
+    let je: RequestEnv = serde_json::from_str(&json).expect("JsonEnvelope failed");
+    let env = je.generate_envelope().unwrap();
+
+    assert_eq!(
+        env.data,
+        Request::ContactQuery(crate::api::contacts::Query {
+            auth,
+            query: ContactQuery::And(vec![
+                ContactQuery::Trust { val: 100, fuz: 20 },
+                ContactQuery::Or(vec![
+                    ContactQuery::Met(true),
+                    ContactQuery::Location("Berlin".into()),
+                ]),
+            ]),
+        })
+    );
+}
+
+#[test]
+fn envelope_contact_all() {
+    let auth = UserAuth::test();
+    let json = json_builder("contact", "all", Some(auth.clone()), None);
+
+    let je: RequestEnv = serde_json::from_str(&json).expect("JsonEnvelope failed");
+    let env = je.generate_envelope().unwrap();
+
+    assert_eq!(
+        env.data,
+        Request::ContactAll(crate::api::contacts::All { auth })
+    );
+}
+
+#[test]
+fn envelope_contact_verify() {
+    use libqaul::{contacts::Proof, Identity};
+
+    let contact = Identity::random();
+    let auth = UserAuth::test();
+    let json = json_builder(
+        "contact",
+        "verify",
+        Some(auth.clone()),
+        Some(vec![
+            ("contact", Value::String(contact.to_string())),
+            ("proof", json!({ "Compared": "12345 67890" })),
+        ]),
+    );
+
+    let je: RequestEnv = serde_json::from_str(&json).expect("JsonEnvelope failed");
+    let env = je.generate_envelope().unwrap();
+
+    assert_eq!(
+        env.data,
+        Request::ContactVerify(crate::api::contacts::Verify {
+            auth,
+            contact,
+            proof: Proof::Compared("12345 67890".into()),
+        })
+    );
+}
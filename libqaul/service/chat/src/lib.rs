@@ -57,6 +57,7 @@ impl Chat {
                     match cmd {
                         ServiceEvent::Open(auth) => sender.send(worker::Command::Start(auth)).await,
                         ServiceEvent::Close(auth) => sender.send(worker::Command::Stop(auth)).await,
+                        ServiceEvent::KeyChanged { .. } => {}
                     }
                 })
             })
@@ -58,6 +58,7 @@ impl Fileshare {
                 match cmd {
                     ServiceEvent::Open(auth) => sender.send(worker::Command::Start(auth)).await,
                     ServiceEvent::Close(auth) => sender.send(worker::Command::Stop(auth)).await,
+                    ServiceEvent::KeyChanged { .. } => {}
                 }
             });
         });
@@ -105,6 +105,7 @@ impl Voice {
                         }
                     }
                 }
+                ServiceEvent::KeyChanged { .. } => {}
             })
             .await?;
         Ok(this)
@@ -40,7 +40,7 @@ pub(crate) async fn client_message_worker(user: Identity, voice: Arc<Voice>) {
             .tags
             .iter()
             .filter(|tag| tag.key == "call-id")
-            .filter(|tag| tag.val.len() != ID_LEN)
+            .filter(|tag| tag.val.len() == ID_LEN)
             .map(|tag| Identity::from_bytes(&tag.val))
             .next();
         let id: CallId = match id {
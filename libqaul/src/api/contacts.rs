@@ -1,5 +1,11 @@
-use crate::{error::Result, users::UserAuth, Identity, Qaul};
+use crate::{
+    contacts::verify,
+    error::{Error, Result},
+    users::UserAuth,
+    Identity, Qaul,
+};
 use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
 
 /// Per-user local contact access on the network
 ///
@@ -8,7 +14,7 @@ use serde::{Deserialize, Serialize};
 /// specify about another user, that are not available or shared with
 /// the network. This is meant to allow users to curate a list of
 /// trusted contacts, or build friend circles.
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
 pub struct ContactEntry {
     /// The name by which the associated contact is known by the owning user.
     pub nick: Option<String>,
@@ -20,18 +26,36 @@ pub struct ContactEntry {
     pub location: Option<String>,
     /// A general plain text notes section
     pub notes: Option<String>,
+    /// The contact's safety number was verified
+    ///
+    /// This can only be set via `Contacts::verify`.  Setting it to
+    /// `false` removes the verification again.
+    pub verified: bool,
 }
 
 /// Query structure to find contacts by
 ///
 /// A query is always applied to a field that is present in
 /// `ContactEntry`, and will filter contacts by what set of
-/// prerequisites they fulfill.
+/// prerequisites they fulfill.  Queries can be combined with `And`
+/// and `Or`, which can be nested as well.
+///
+/// ```
+/// # use libqaul::contacts::ContactQuery;
+/// // Trusted contacts that were either met, or are from Berlin
+/// ContactQuery::And(vec![
+///     ContactQuery::Trust { val: 100, fuz: 27 },
+///     ContactQuery::Or(vec![
+///         ContactQuery::Met(true),
+///         ContactQuery::Location("Berlin".into()),
+///     ]),
+/// ]);
+/// ```
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum ContactQuery {
     /// A fuzzy nickname search
     Nick(String),
-    /// A fuzzy trust level search
+    /// Trust levels at most `fuz` away from `val`
     Trust { val: i8, fuz: i8 },
     /// Filter by physical meeting
     Met(bool),
@@ -39,6 +63,49 @@ pub enum ContactQuery {
     Location(String),
     /// A fuzzy notes string search
     Notes(String),
+    /// Filter by safety number verification
+    Verified(bool),
+    /// Match contacts that match all of these queries
+    And(Vec<ContactQuery>),
+    /// Match contacts that match any of these queries
+    Or(Vec<ContactQuery>),
+}
+
+/// A number to verify the keys of two users with each other
+///
+/// A safety number is made up from the fingerprints of both users'
+/// identity keys, and is the same on both sides.  Two users can
+/// either read their numbers out to each other, or one of them scans
+/// the `qr` payload shown by the other, and pass the result on to
+/// `Contacts::verify`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SafetyNumber {
+    /// 60 digits, to compare by eye
+    pub digits: String,
+    /// A payload to encode as a QR code, for the other user to scan
+    pub qr: Vec<u8>,
+}
+
+impl Display for SafetyNumber {
+    /// Print the digits in groups of five
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let groups: Vec<_> = self
+            .digits
+            .as_bytes()
+            .chunks(5)
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect();
+        write!(f, "{}", groups.join(" "))
+    }
+}
+
+/// Proof that two users have the same safety number
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Proof {
+    /// The `SafetyNumber::qr` payload, scanned from the contact's device
+    Scanned(Vec<u8>),
+    /// The digits of the contact's safety number, spaces are ignored
+    Compared(String),
 }
 
 /// API scope type to access contact book functions
@@ -80,13 +147,12 @@ impl<'qaul> Contacts<'qaul> {
     ///
     /// If no contact entry existed before, a fresh one will be
     /// created before calling the passed-in lambda.
-    pub fn modify<F>(&self, user: UserAuth, contact: &Identity, modify: F) -> Result<()>
+    pub async fn modify<F>(&self, user: UserAuth, contact: &Identity, modify: F) -> Result<()>
     where
         F: FnOnce(&mut ContactEntry),
     {
-        let (ref id, _) = self.q.auth.trusted(user)?;
-        self.q.contacts.modify(id, contact, modify);
-        Ok(())
+        let (id, _) = self.q.auth.trusted(user)?;
+        self.q.contacts.modify(id, *contact, modify).await
     }
 
     /// Get a single `ContactEntry` from a user's contact book
@@ -95,14 +161,13 @@ impl<'qaul> Contacts<'qaul> {
     /// of data, this is the only way to return a reference to the
     /// full object. When trying to query all data from all contact
     /// entries, it's advised to get a list of Identities via
-    /// `Contacts::get_all` first, and then map this collection over
+    /// `Contacts::all` first, and then map this collection over
     /// `Contacts::get` afterwards.
-    pub fn get(&self, user: UserAuth, contact: &Identity) -> Result<ContactEntry> {
-        let (ref id, _) = self.q.auth.trusted(user)?;
-        self.q.contacts.get(id, contact)
+    pub async fn get(&self, user: UserAuth, contact: &Identity) -> Result<ContactEntry> {
+        let (id, _) = self.q.auth.trusted(user)?;
+        self.q.contacts.get(id, *contact).await
     }
 
-    // TODO: This test needs to be in an executor cause create is async now
     /// Query for a subset of users that have a `ContactEntry`
     ///
     /// To get a list of all `ContactEntry` objects, map the result of
@@ -113,52 +178,41 @@ impl<'qaul> Contacts<'qaul> {
     /// # let qaul = Qaul::dummy();
     /// # let user = qaul.users().create("abc").await.unwrap();
     /// let contacts = qaul.contacts();
-    /// # (|| -> Result<()> {
-    /// contacts
-    ///     .query(user.clone(), ContactQuery::Nick("buddy".to_string()))?
-    ///     .into_iter()
-    ///     .map(|i| contacts.get(user.clone(), &i));
-    /// # Ok(())
-    /// # })().unwrap();
+    /// for id in contacts
+    ///     .query(user.clone(), ContactQuery::Nick("buddy".to_string()))
+    ///     .await?
+    /// {
+    ///     let entry = contacts.get(user.clone(), &id).await?;
+    /// }
     /// ````
-    pub fn query(&self, user: UserAuth, query: ContactQuery) -> Result<Vec<Identity>> {
-        let (ref id, _) = self.q.auth.trusted(user)?;
-        self.q.contacts.query(id, query)
+    pub async fn query(&self, user: UserAuth, query: ContactQuery) -> Result<Vec<Identity>> {
+        let (id, _) = self.q.auth.trusted(user)?;
+        Ok(self.q.contacts.query(id, &query).await)
     }
 
-    /// Get all users that have a `ContactEntry` for this user
-    pub fn all(&self, user: UserAuth) -> Result<Vec<Identity>> {
-        let (ref _id, _) = self.q.auth.trusted(user)?;
-        Ok(vec![])
+    /// Get the safety number for a user and one of their contacts
+    pub async fn safety_number(&self, user: UserAuth, contact: &Identity) -> Result<SafetyNumber> {
+        let (id, _) = self.q.auth.trusted(user)?;
+        Ok(verify::safety_number(id, *contact))
     }
-}
-
-// impl Qaul {
 
-//     /// Find a subset of contacts with some query
-//     pub fn contacts_query(
-//         &self,
-//         user: UserAuth,
-//         query: ContactQuery,
-//     ) -> QaulResult<Vec<UserProfile>> {
-//         let (ref my_id, ref token) = user.trusted()?;
-//         self.auth.verify_token(my_id, token)?;
-//         self.contacts
-//             .query(my_id, query)?
-//             .into_iter()
-//             .map(|ref id| self.users.get(id))
-//             .collect()
-//     }
-
-//     /// Enumerate all contacts known by a user
-//     pub fn contacts_get_all(&self, user: UserAuth) -> QaulResult<Vec<UserProfile>> {
-//         let (ref my_id, ref token) = user.trusted()?;
-//         self.auth.verify_token(my_id, token)?;
+    /// Mark a contact as verified, with proof of their safety number
+    ///
+    /// Fails with `Error::BadProof` if the proof doesn't match the
+    /// safety number of the two users, which means that one of them
+    /// doesn't have the right key for the other.  Like `modify`, this
+    /// creates a contact entry if none existed.
+    pub async fn verify(&self, user: UserAuth, contact: &Identity, proof: Proof) -> Result<()> {
+        let (id, _) = self.q.auth.trusted(user)?;
+        if !verify::check(id, *contact, &proof) {
+            return Err(Error::BadProof);
+        }
+        self.q.contacts.verify(id, *contact).await
+    }
 
-//         self.contacts
-//             .get_all(my_id)?
-//             .into_iter()
-//             .map(|ref id| self.users.get(id))
-//             .collect()
-//     }
-// }
+    /// Get all users that have a `ContactEntry` for this user
+    pub async fn all(&self, user: UserAuth) -> Result<Vec<Identity>> {
+        let (id, _) = self.q.auth.trusted(user)?;
+        Ok(self.q.contacts.all(id).await)
+    }
+}
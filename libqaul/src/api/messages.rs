@@ -1,7 +1,7 @@
 use crate::{
     error::{Error, Result},
     helpers::{QueryResult, Subscription, Tag, TagSet},
-    messages::{Envelope, MsgUtils, RatMessageProto, TAG_UNREAD},
+    messages::{Envelope, MsgUtils, Onion, RatMessageProto, TAG_UNREAD},
     qaul::{Identity, Qaul},
     services::Service,
     users::UserAuth,
@@ -15,7 +15,10 @@ use std::sync::Arc;
 pub type MsgRef = Arc<Message>;
 
 /// Length of an `MsgId`, for converting to and from arrays
-pub const ID_LEN: usize = 16;
+///
+/// Message IDs are regular identities, so this is always the same as
+/// `ratman::ID_LEN`.
+pub const ID_LEN: usize = ratman::ID_LEN;
 
 /// A unique, randomly generated message ID
 pub type MsgId = Identity;
@@ -23,18 +26,21 @@ pub type MsgId = Identity;
 /// Signature trust level of an incoming `Message`
 ///
 /// The three variants encode `trusted`, `unverified` and `invalid`,
-/// according to signature verification of the internal keystore.
+/// according to the signature the sender attached to the message.
+/// Direct messages sent by users that were created before libqaul
+/// signed messages are `unverified`.  Flooded messages can't be
+/// attributed to their sender without a signature, so unsigned ones
+/// are `invalid`.
 ///
 /// The `SigTrust::ok` convenience function can be used to reject
 /// non-verifiable (unknown or bad) `Message` signatures.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SigTrust {
-    /// A verified signature by a known contact
+    /// A valid signature by the sender
     Trusted,
-    /// An unverified signature by a known contact
-    /// (pubkey not available!)
+    /// The direct message wasn't signed
     Unverified,
-    /// A fraudulent signature
+    /// A fraudulent signature, or an unsigned flooded message
     Invalid,
 }
 
@@ -63,12 +69,24 @@ pub enum Mode {
     Flood,
     /// Address only a single identity
     Std(Identity),
+    /// Address a single identity, without revealing the sender
+    ///
+    /// The message is sent through a random path of relays (other
+    /// users this node knows about), with one layer of encryption for
+    /// each of them.  A relay only learns where to forward the message
+    /// to, which means that nodes in the network can't tell who sent
+    /// a message to whom.  The recipient still learns the sender.
+    ///
+    /// Sending fails with `Error::NoRoute` if no relays are known.
+    /// Anonymous messages take longer to arrive, and are lost when
+    /// any relay on the path goes offline.
+    Anonymous(Identity),
 }
 
 impl Mode {
     pub fn id(&self) -> Option<Identity> {
         match self {
-            Self::Std(id) => Some(*id),
+            Self::Std(id) | Self::Anonymous(id) => Some(*id),
             Self::Flood => None,
         }
     }
@@ -84,7 +102,7 @@ impl From<Mode> for Recipient {
     fn from(sm: Mode) -> Self {
         match sm {
             Mode::Flood => Self::Flood,
-            Mode::Std(id) => Self::User(id),
+            Mode::Std(id) | Mode::Anonymous(id) => Self::User(id),
         }
     }
 }
@@ -212,6 +230,14 @@ pub struct Message {
     pub tags: TagSet,
     /// A raw byte `Message` payload
     pub payload: Vec<u8>,
+    /// Whether the message was signed by its sender
+    pub sign: SigTrust,
+    /// Whether the sender was a verified contact of the recipient
+    ///
+    /// This is decided when the message arrives.  Flooded messages
+    /// are shared by all users on a device, and are never marked as
+    /// verified; use `Messages::verified` for those.
+    pub verified: bool,
 }
 
 /// Interface to access messages from the network
@@ -265,6 +291,10 @@ impl<'qaul> Messages<'qaul> {
     {
         let (sender, _) = self.q.auth.trusted(user)?;
         let recipient = mode.into();
+        let relays = match mode {
+            Mode::Anonymous(id) => Onion::path(self.q.users.known_remote().await, sender, id)?,
+            _ => vec![],
+        };
         let associator = service.into();
         let id = id_type.consume();
         let tags: TagSet = tags.into();
@@ -281,6 +311,13 @@ impl<'qaul> Messages<'qaul> {
         println!("Sending message with ID `{:?}`", id);
         println!("Sending message to {:?}", recipient);
 
+        // Our own messages are as trustworthy as the key we sign with
+        let sign = if self.q.users.get_key(sender).await.can_sign() {
+            SigTrust::Trusted
+        } else {
+            SigTrust::Unverified
+        };
+
         // Only insert the message into the store if the Id is unique!
         if !self.q.messages.probe_id(sender, id).await {
             self.q
@@ -293,6 +330,8 @@ impl<'qaul> Messages<'qaul> {
                         associator,
                         tags,
                         payload,
+                        sign,
+                        verified: false,
                     }),
                     mode,
                 )
@@ -304,12 +343,36 @@ impl<'qaul> Messages<'qaul> {
         MsgUtils::send(
             &self.q.users,
             &self.q.router,
-            RatMessageProto { env, recipient },
+            RatMessageProto {
+                env,
+                recipient,
+                relays,
+            },
         )
         .await
         .map(|_| id)
     }
 
+    /// Set whether messages with invalid signatures are dropped
+    ///
+    /// By default they are stored, and marked as `SigTrust::Invalid`,
+    /// leaving it to services to decide what to do with them.
+    /// Unsigned messages are always kept.
+    pub fn set_drop_invalid(&self, drop: bool) {
+        self.q.messages.set_drop_invalid(drop)
+    }
+
+    /// Check if a message was signed by a verified contact
+    ///
+    /// A message is verified if it has a valid signature (see
+    /// `SigTrust`), and the user has verified the sender's safety
+    /// number via `Contacts::verify`.  Unlike `Message::verified`
+    /// this checks the user's contact book as it is now.
+    pub async fn verified(&self, user: UserAuth, msg: &Message) -> Result<bool> {
+        let (id, _) = self.q.auth.trusted(user)?;
+        Ok(self.q.contacts.verified(id, msg).await)
+    }
+
     /// Subscribe to a stream of future message updates
     pub async fn subscribe<S, T>(
         &self,
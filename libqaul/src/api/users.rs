@@ -1,18 +1,30 @@
 use crate::{
-    error::Result,
-    users::{UserProfile, UserUpdate},
+    error::{Error, Result},
+    security::KeyId,
+    services::MetadataMap,
+    users::{Announcer, Backup, UserProfile, UserUpdate},
     Identity, Qaul,
 };
 use serde::{de::Deserializer, ser::Serializer, Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    time::{Duration, SystemTime},
+};
 
 /// A random authentication token
 pub type Token = String;
 
+/// A public identifier for a login session
+///
+/// Unlike the session `Token` it can't be used to authenticate, and
+/// is safe to show to other sessions of the same user.
+pub type SessionId = String;
+
 /// Wrapper to encode `User` authentication state
 ///
 /// This structure can be aquired by challenging an authentication
-/// endpoint, such as `User::login` to yield a token. If a session for
-/// this `Identity` already exists, it will be re-used.
+/// endpoint, such as `User::login` to yield a token. Every login
+/// creates a new session, with its own token.
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct UserAuth(pub Identity, pub Token);
 
@@ -22,6 +34,44 @@ impl UserAuth {
     }
 }
 
+/// Information about an active login session of a user
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Identifier used to revoke this session
+    pub id: SessionId,
+    /// The client label that was given on login
+    pub client: Option<String>,
+    /// When the user logged in
+    pub created: SystemTime,
+    /// The last time the session token was used
+    pub last_used: SystemTime,
+    /// When the token expires, unless it is refreshed
+    pub expires: Option<SystemTime>,
+    /// Whether this is the session that asked for the listing
+    pub current: bool,
+}
+
+/// Which users a `Users::search` looks at
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UserScope {
+    /// Every user this device knows about
+    All,
+    /// Only users in the contact book of the searching user
+    Contacts,
+    /// Only users that were seen on the network in the last ten
+    /// minutes, and are likely still reachable
+    Recent,
+}
+
+impl Default for UserScope {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+/// How long ago a user can have been seen to be `UserScope::Recent`
+const RECENT: Duration = Duration::from_secs(10 * 60);
+
 /// Local user data and session management
 ///
 /// Used entirely to namespace API endpoints on `Qaul` instance,
@@ -49,6 +99,40 @@ impl<'qaul> Users<'qaul> {
         self.q.users.all_remote().await
     }
 
+    /// Search the user directory
+    ///
+    /// The query is matched against names, bio values and the start
+    /// of user IDs, ignoring case, and doesn't need to be spelled
+    /// out completely (`"alc"` finds "Alice").  Results are ranked
+    /// from best to worst match.  An empty query lists all users in
+    /// the scope.
+    pub async fn search(
+        &self,
+        user: UserAuth,
+        query: &str,
+        scope: UserScope,
+    ) -> Result<Vec<UserProfile>> {
+        let (id, _) = self.q.auth.trusted(user)?;
+        let only: Option<BTreeSet<Identity>> = match scope {
+            UserScope::All => None,
+            UserScope::Contacts => Some(self.q.contacts.all(id).await.into_iter().collect()),
+            UserScope::Recent => Some(self.q.users.seen_within(RECENT).await),
+        };
+
+        let mut found: Vec<_> = self
+            .q
+            .users
+            .all_remote()
+            .await
+            .into_iter()
+            .filter(|p| only.as_ref().map_or(true, |only| only.contains(&p.id)))
+            .filter_map(|p| p.search_score(query).map(|score| (score, p)))
+            .collect();
+
+        found.sort_by(|(a, pa), (b, pb)| b.cmp(a).then_with(|| pa.id.cmp(&pb.id)));
+        Ok(found.into_iter().map(|(_, p)| p).collect())
+    }
+
     /// Check if a user ID and token combination is valid
     pub async fn is_authenticated(&self, user: UserAuth) -> Result<()> {
         self.q.auth.trusted(user).map(|_| ())
@@ -75,8 +159,17 @@ impl<'qaul> Users<'qaul> {
 
         // Create user login
         self.q.users.create_local(keyd, pw).await;
-        self.q.auth.set_pw(id, pw);
-        let auth = self.q.auth.new_login(id, pw).map(|t| UserAuth(id, t))?;
+        self.start_local(id, pw).await
+    }
+
+    /// Set the password for a new local user, and log them in
+    async fn start_local(&self, id: Identity, pw: &str) -> Result<UserAuth> {
+        self.q.auth.set_pw(id, pw)?;
+        let auth = self
+            .q
+            .auth
+            .new_login(id, pw, None)
+            .map(|t| UserAuth(id, t))?;
         self.q.services.open_user(&auth).await;
 
         // Start announcing user profile changes
@@ -87,33 +180,188 @@ impl<'qaul> Users<'qaul> {
         Ok(auth)
     }
 
+    /// Export a local user into an encrypted backup
+    ///
+    /// The backup contains the user's keypair, encryption sessions,
+    /// profile, contact book and service metadata, as well as their
+    /// messages if `messages` is set.  It is encrypted with
+    /// `passphrase`, which doesn't need to be the user's password,
+    /// and can be loaded on another device (or the same one, after a
+    /// re-install) via `import`.  The sessions are copied as they are
+    /// at the time of the export, so the user shouldn't keep sending
+    /// messages from the old device afterwards.
+    pub async fn export(
+        &self,
+        user: UserAuth,
+        passphrase: &str,
+        messages: bool,
+    ) -> Result<Vec<u8>> {
+        let (id, _) = self.q.auth.trusted(user)?;
+
+        Backup {
+            keypair: self.q.users.get_key(id).await,
+            crypto: self.q.users.crypto().export(id).await?,
+            profile: self.q.users.get(id).await?,
+            contacts: self.q.contacts.list(id).await,
+            services: self.q.services.store().all(id).await,
+            messages: match messages {
+                true => self.q.messages.all_local(id).await,
+                false => vec![],
+            },
+        }
+        .seal(passphrase)
+    }
+
+    /// Create a local user from a backup made with `export`
+    ///
+    /// The `passphrase` the backup was encrypted with becomes the
+    /// user's password, which can be changed via `change_pw`
+    /// afterwards.  Fails with `Error::UserExists` if the user is
+    /// already a local user on this device.  A user that was only
+    /// known as a remote user so far is moved to this device.
+    pub async fn import(&self, backup: &[u8], passphrase: &str) -> Result<UserAuth> {
+        let Backup {
+            keypair,
+            crypto,
+            profile,
+            contacts,
+            services,
+            messages,
+        } = Backup::open(backup, passphrase)?;
+
+        let id = keypair.id();
+        if profile.id != id {
+            return Err(Error::InvalidPayload);
+        }
+        if self.q.users.is_local(id).await {
+            return Err(Error::UserExists);
+        }
+
+        // Nothing was written yet, but every step from here on needs
+        // to be undone if a later one fails
+        let prev = self.q.users.get(id).await.ok();
+        self.q
+            .users
+            .restore_local(KeyId { id, keypair }, passphrase, profile)
+            .await?;
+
+        let res = async {
+            self.q.users.crypto().restore(id, crypto).await?;
+            self.q.contacts.restore(id, contacts).await?;
+            for data in services {
+                let map = MetadataMap::from(data.name, data.map);
+                self.q
+                    .services
+                    .store()
+                    .save(id, data.service, map, data.tags)
+                    .await?;
+            }
+            self.q.messages.restore(id, messages).await;
+
+            // Inform Router about new local user, which replaces the
+            // route to it if it was known as a remote user
+            if self.q.router.local(id).await.is_err() {
+                self.q.router.add_user(id).await?;
+            }
+            self.q.router.online(id).await?;
+            self.start_local(id, passphrase).await
+        }
+        .await;
+
+        if res.is_err() {
+            let _ = self.q.router.offline(id).await;
+            let _ = self.q.router.del_user(id, false).await;
+            self.q.users.remove_local(id, prev).await;
+        }
+        res
+    }
+
     /// Delete a local user from the auth store
     ///
     /// This function requires a valid login for the user that's being
-    /// deleted.  This does not delete any data associated with this
-    /// user, or messages from the node (or other device nodes).
+    /// deleted.  All sessions of the user are logged out.  This does
+    /// not delete any data associated with this user, or messages
+    /// from the node (or other device nodes).
     pub async fn delete(&self, user: UserAuth) -> Result<()> {
         let id = user.0;
 
         // If logout succeeds, we can delete the user
         self.q.announcer.offline(id).await;
-        self.logout(user).await?;
+        self.logout_all(user).await?;
         self.q.router.del_user(id, true).await?;
         self.q.users.delete_local(id).await;
         Ok(())
     }
 
     /// Change the passphrase for an authenticated user
-    pub fn change_pw(&self, user: UserAuth, newpw: &str) -> Result<()> {
-        let (id, _) = self.q.auth.trusted(user)?;
-        self.q.auth.set_pw(id, newpw);
-        Ok(())
+    ///
+    /// The `old` passphrase needs to be provided again, and all of the
+    /// user's encrypted data is re-encrypted, so that `old` can't be
+    /// used to read it anymore.  All other sessions of the user are
+    /// logged out, and the calling session gets a new token, which is
+    /// returned.
+    pub async fn change_pw(&self, user: UserAuth, old: &str, new: &str) -> Result<UserAuth> {
+        let (id, token) = self.q.auth.trusted(user)?;
+        self.q.auth.check_pw(id, old)?;
+        self.q.users.change_pw(id, old, new).await?;
+
+        let (token, revoked) = match self.q.auth.change_pw(id, &token, new) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                // Keep the storage password in line with the pw hash
+                self.q.users.change_pw(id, new, old).await?;
+                return Err(e);
+            }
+        };
+
+        // Services hold on to the tokens they were started with
+        for token in revoked {
+            self.q.services.close_user(&UserAuth(id, token)).await;
+        }
+        let auth = UserAuth(id, token);
+        self.q.services.open_user(&auth).await;
+        Ok(auth)
     }
 
     /// Create a new session login for a local User
     pub async fn login(&self, user: Identity, pw: &str) -> Result<UserAuth> {
-        let token = self.q.auth.new_login(user, pw)?;
-        self.q.router.online(user).await?;
+        self.start_session(user, pw, None).await
+    }
+
+    /// Create a new session login, labelled with a client name
+    ///
+    /// The label is shown in the `sessions` listing, to make it
+    /// easier for users to tell their sessions apart.
+    pub async fn login_client(&self, user: Identity, pw: &str, client: &str) -> Result<UserAuth> {
+        self.start_session(user, pw, Some(client.into())).await
+    }
+
+    async fn start_session(
+        &self,
+        user: Identity,
+        pw: &str,
+        client: Option<String>,
+    ) -> Result<UserAuth> {
+        let token = self.q.auth.new_login(user, pw, client)?;
+        let res: Result<()> = async {
+            self.q.users.open_local(user, pw).await?;
+            self.q.contacts.migrate(user).await?;
+
+            // Users that were loaded from disk aren't known to the
+            // router until they log in for the first time
+            if self.q.router.local(user).await.is_err() {
+                self.q.router.add_user(user).await?;
+            }
+            self.q.router.online(user).await?;
+            Ok(())
+        }
+        .await;
+
+        // Don't hand out a token for a login that failed
+        if let Err(e) = res {
+            let _ = self.q.auth.logout(&user, &token);
+            return Err(e);
+        }
         let auth = UserAuth(user, token);
         self.q.services.open_user(&auth).await;
 
@@ -125,6 +373,38 @@ impl<'qaul> Users<'qaul> {
         Ok(auth)
     }
 
+    /// Set how long session tokens stay valid
+    ///
+    /// Tokens expire once they are older than `ttl`, and need to be
+    /// replaced via `refresh` before that.  `None` (the default)
+    /// means that tokens never expire.
+    pub fn set_session_ttl(&self, ttl: Option<Duration>) {
+        self.q.auth.set_ttl(ttl)
+    }
+
+    /// Replace a session token with a new one, resetting its expiry
+    ///
+    /// The old token becomes invalid.
+    pub fn refresh(&self, user: UserAuth) -> Result<UserAuth> {
+        let UserAuth(id, token) = user;
+        self.q.auth.refresh(&id, &token).map(|t| UserAuth(id, t))
+    }
+
+    /// List all active sessions for the authenticated user
+    pub fn sessions(&self, user: UserAuth) -> Result<Vec<Session>> {
+        let UserAuth(ref id, ref token) = user;
+        self.q.auth.sessions(id, token)
+    }
+
+    /// Revoke one of the sessions of the authenticated user
+    ///
+    /// The session token stops working, but this does not take the
+    /// user offline, even if it was the last session.
+    pub fn revoke_session(&self, user: UserAuth, session: SessionId) -> Result<()> {
+        let (ref id, _) = self.q.auth.trusted(user)?;
+        self.q.auth.revoke(id, &session)
+    }
+
     /// Drop the current session Token, invalidating it
     pub async fn logout(&self, user: UserAuth) -> Result<()> {
         let (ref id, ref token) = self.q.auth.trusted(user.clone())?;
@@ -135,6 +415,16 @@ impl<'qaul> Users<'qaul> {
         Ok(())
     }
 
+    /// Drop all session tokens of a user, logging out everywhere
+    pub async fn logout_all(&self, user: UserAuth) -> Result<()> {
+        let (ref id, _) = self.q.auth.trusted(user.clone())?;
+        self.q.services.close_user(&user).await;
+        self.q.announcer.offline(*id).await;
+        self.q.router.offline(*id).await?;
+        self.q.auth.logout_all(id);
+        Ok(())
+    }
+
     /// Fetch the `UserProfile` for a known identity, remote or local
     ///
     /// No athentication is required for this endpoint, seeing as only
@@ -145,9 +435,14 @@ impl<'qaul> Users<'qaul> {
     }
 
     /// Update a `UserProfile` with a lambda, if authentication passes
+    ///
+    /// The new version of the profile is announced to the network
+    /// right away, and peers fetch the changes from there.
     pub async fn update(&self, user: UserAuth, update: UserUpdate) -> Result<()> {
         let (id, _) = self.q.auth.trusted(user)?;
-        self.q.users.modify(id, update).await
+        self.q.users.modify(id, update).await?;
+        Announcer::announce(&self.q.users, &self.q.router, id).await;
+        Ok(())
     }
 
     /// Validate that a `UserAuth` represents a currently logged in user
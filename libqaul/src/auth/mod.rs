@@ -8,25 +8,72 @@ pub(crate) use pwhash::PwHash;
 
 use crate::{
     error::{Error, Result},
-    users::{Token, UserAuth},
+    users::{Session, SessionId, Token, UserAuth},
     utils, Identity,
 };
 
 use base64::{encode_config, URL_SAFE};
 use std::{
     collections::BTreeMap,
+    mem,
+    path::PathBuf,
     sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
 
+/// Book-keeping for a single login token
+#[derive(Clone)]
+struct SessionData {
+    user: Identity,
+    id: SessionId,
+    client: Option<String>,
+    created: SystemTime,
+    /// When the current token was handed out
+    issued: SystemTime,
+    last_used: SystemTime,
+}
+
+impl SessionData {
+    fn new(user: Identity, client: Option<String>) -> Self {
+        let now = SystemTime::now();
+        Self {
+            user,
+            // A single random u64, hex encoded
+            id: hex::encode(utils::random(1)),
+            client,
+            created: now,
+            issued: now,
+            last_used: now,
+        }
+    }
+
+    fn expires(&self, ttl: Option<Duration>) -> Option<SystemTime> {
+        ttl.map(|ttl| self.issued + ttl)
+    }
+
+    fn expired(&self, ttl: Option<Duration>) -> bool {
+        self.expires(ttl)
+            .map(|exp| exp <= SystemTime::now())
+            .unwrap_or(false)
+    }
+}
+
 /// Internal storage component that tracks user auth state
 ///
 /// Fundamentally it has two functions: hand out authentication
 /// tokens, and compare password hashes with their recordings to make
 /// sure that users are valid.
+///
+/// Password hashes can be kept in a file, but tokens only ever live
+/// in memory, which means that all users are logged out on restart.
+/// If a session TTL is set, tokens also expire once they are older
+/// than it, unless they are refreshed before that.
 #[derive(Clone)]
 pub(crate) struct AuthStore {
-    tokens: Arc<Mutex<BTreeMap<Token, Identity>>>,
+    tokens: Arc<Mutex<BTreeMap<Token, SessionData>>>,
     hashes: Arc<Mutex<BTreeMap<Identity, PwHash>>>,
+    ttl: Arc<Mutex<Option<Duration>>>,
+    path: Option<PathBuf>,
 }
 
 impl AuthStore {
@@ -34,15 +81,109 @@ impl AuthStore {
         Self {
             tokens: Arc::new(Mutex::new(BTreeMap::new())),
             hashes: Arc::new(Mutex::new(BTreeMap::new())),
+            ttl: Arc::new(Mutex::new(None)),
+            path: None,
         }
     }
 
+    /// Load password hashes from a file, and keep it up to date
+    pub(crate) fn open(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            tokens: Arc::new(Mutex::new(BTreeMap::new())),
+            hashes: Arc::new(Mutex::new(utils::load_file(&path)?)),
+            ttl: Arc::new(Mutex::new(None)),
+            path: Some(path),
+        })
+    }
+
+    /// Set how long tokens stay valid after being handed out
+    ///
+    /// `None` means that tokens never expire.  The new TTL also
+    /// applies to already existing sessions.
+    pub(crate) fn set_ttl(&self, ttl: Option<Duration>) {
+        *self.ttl.lock().expect("Failed to lock ttl") = ttl;
+    }
+
+    fn ttl(&self) -> Option<Duration> {
+        *self.ttl.lock().expect("Failed to lock ttl")
+    }
+
     /// Set a user's password hash
-    pub(crate) fn set_pw(&self, user: Identity, pw: &str) {
+    pub(crate) fn set_pw(&self, user: Identity, pw: &str) -> Result<()> {
+        let mut hashes = self.hashes.lock().expect("Failed to unlock hash store");
+        let prev = hashes.insert(user, PwHash::new(pw));
+        self.sync(&mut hashes, user, prev)
+    }
+
+    /// Write the password hashes to disk, or undo a change to them
+    ///
+    /// `prev` is the previous hash of `user`, which is put back if
+    /// the hashes can't be written.
+    fn sync(
+        &self,
+        hashes: &mut BTreeMap<Identity, PwHash>,
+        user: Identity,
+        prev: Option<PwHash>,
+    ) -> Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+
+        let res = utils::sync_file(path, &*hashes);
+        if res.is_err() {
+            match prev {
+                Some(hash) => hashes.insert(user, hash),
+                None => hashes.remove(&user),
+            };
+        }
+        res
+    }
+
+    /// Check that a password matches the one a user has set
+    pub(crate) fn check_pw(&self, user: Identity, pw: &str) -> Result<()> {
         self.hashes
             .lock()
             .expect("Failed to unlock hash store")
-            .insert(user, PwHash::new(pw));
+            .get(&user)
+            .filter(|hash| hash.matches_with(pw))
+            .map_or(Err(Error::NotAuthorised), |_| Ok(()))
+    }
+
+    /// Change a user's password hash, ending all but one session
+    ///
+    /// The session for `token` is kept, but gets a new token.  Returns
+    /// the new token, and all tokens that stopped being valid.
+    pub(crate) fn change_pw(
+        &self,
+        user: Identity,
+        token: &Token,
+        pw: &str,
+    ) -> Result<(Token, Vec<Token>)> {
+        let mut tokens = self.tokens.lock().expect("Failed to lock token store");
+        match tokens.get(token) {
+            Some(data) if data.user == user => {}
+            Some(_) | None => return Err(Error::NotAuthorised),
+        }
+
+        let mut hashes = self.hashes.lock().expect("Failed to unlock hash store");
+        let prev = hashes.insert(user, PwHash::new(pw));
+        self.sync(&mut hashes, user, prev)?;
+
+        let old: Vec<_> = tokens
+            .iter()
+            .filter(|(_, data)| data.user == user)
+            .map(|(t, _)| t.clone())
+            .collect();
+        let mut data = tokens.remove(token).unwrap();
+        for t in &old {
+            tokens.remove(t);
+        }
+
+        data.issued = SystemTime::now();
+        let new = Self::generate();
+        tokens.insert(new.clone(), data);
+        Ok((new, old))
     }
 
     /// `UserAuth` convenience wrapper for `AuthStore::verify_token`
@@ -54,9 +195,14 @@ impl AuthStore {
 
     /// Generate a new login token, if password is valid
     ///
-    /// If a token already exists, and the password is valid, it will
-    /// be returned instead of generating a new one.
-    pub(crate) fn new_login(&self, user: Identity, pw: &str) -> Result<Token> {
+    /// Every login creates a new session, optionally labelled with
+    /// the name of the client that requested it.
+    pub(crate) fn new_login(
+        &self,
+        user: Identity,
+        pw: &str,
+        client: Option<String>,
+    ) -> Result<Token> {
         self.hashes
             .lock()
             .expect("Failed to unlock hash store")
@@ -66,16 +212,31 @@ impl AuthStore {
 
         let mut tokens = self.tokens.lock().expect("Failed to lock token store!");
         let token = Self::generate();
-        tokens.insert(token.clone(), user);
+        tokens.insert(token.clone(), SessionData::new(user, client));
         Ok(token)
     }
 
+    /// Replace a valid token with a new one, resetting its expiry
+    ///
+    /// The session keeps its identifier and client label, but the
+    /// old token can no longer be used.
+    pub(crate) fn refresh(&self, user: &Identity, token: &Token) -> Result<Token> {
+        self.verify_token(user, token)?;
+        let mut tokens = self.tokens.lock().expect("Failed to lock token store");
+
+        let mut data = tokens.remove(token).ok_or(Error::NotAuthorised)?;
+        data.issued = SystemTime::now();
+        let new = Self::generate();
+        tokens.insert(new.clone(), data);
+        Ok(new)
+    }
+
     /// Yield a token for a session, logging out a user
     pub(crate) fn logout(&self, user: &Identity, token: &Token) -> Result<()> {
         let mut tokens = self.tokens.lock().expect("Failed to lock token store");
 
         match tokens.get(token) {
-            Some(u) if u == user => {
+            Some(data) if data.user == *user => {
                 tokens.remove(token);
                 Ok(())
             }
@@ -83,15 +244,73 @@ impl AuthStore {
         }
     }
 
+    /// Drop all tokens for a user, logging out every session
+    pub(crate) fn logout_all(&self, user: &Identity) {
+        let mut tokens = self.tokens.lock().expect("Failed to lock token store");
+        *tokens = mem::take(&mut *tokens)
+            .into_iter()
+            .filter(|(_, data)| data.user != *user)
+            .collect();
+    }
+
+    /// List all active sessions for a user
+    ///
+    /// The session that `token` belongs to is marked as `current`.
+    pub(crate) fn sessions(&self, user: &Identity, token: &Token) -> Result<Vec<Session>> {
+        self.verify_token(user, token)?;
+        let ttl = self.ttl();
+        let mut tokens = self.tokens.lock().expect("Failed to lock token store");
+        *tokens = mem::take(&mut *tokens)
+            .into_iter()
+            .filter(|(_, data)| !data.expired(ttl))
+            .collect();
+
+        Ok(tokens
+            .iter()
+            .filter(|(_, data)| data.user == *user)
+            .map(|(t, data)| Session {
+                id: data.id.clone(),
+                client: data.client.clone(),
+                created: data.created,
+                last_used: data.last_used,
+                expires: data.expires(ttl),
+                current: t == token,
+            })
+            .collect())
+    }
+
+    /// Remove a session of a user via its identifier
+    pub(crate) fn revoke(&self, user: &Identity, session: &SessionId) -> Result<()> {
+        let mut tokens = self.tokens.lock().expect("Failed to lock token store");
+        let token = tokens
+            .iter()
+            .find(|(_, data)| data.user == *user && data.id == *session)
+            .map(|(t, _)| t.clone())
+            .ok_or(Error::NoSession)?;
+
+        tokens.remove(&token);
+        Ok(())
+    }
+
     /// Verify that a user's token is valid
+    ///
+    /// Expired tokens are removed, and the last-use time of valid
+    /// ones is updated.
     pub(crate) fn verify_token(&self, user: &Identity, token: &Token) -> Result<()> {
-        self.tokens
-            .lock()
-            .expect("Failed to lock token store")
-            .get(token)
-            .map(|u| u == user)
-            .map_or(Err(Error::NotAuthorised), |_| Ok(()))?;
-        Ok(())
+        let ttl = self.ttl();
+        let mut tokens = self.tokens.lock().expect("Failed to lock token store");
+
+        match tokens.get_mut(token) {
+            Some(data) if data.expired(ttl) => {
+                tokens.remove(token);
+                Err(Error::NotAuthorised)
+            }
+            Some(data) if data.user == *user => {
+                data.last_used = SystemTime::now();
+                Ok(())
+            }
+            Some(_) | None => Err(Error::NotAuthorised),
+        }
     }
 
     /// Generate a new base64 encoded token
@@ -101,64 +320,126 @@ impl AuthStore {
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     use rand::{rngs::OsRng, Rng};
-
-//     struct Context {
-//         id1: Identity,
-//         id1pw: String,
-
-//         id2: Identity,
-//         id2pw: String,
-
-//         auth: AuthStore,
-//     }
-
-//     /// A small function that will seed an AuthStore for test purposes
-//     fn setup() -> Context {
-//         let id1 = Identity::truncate(&AuthStore::random(12));
-//         let id1pw = "sunflowers".into();
-
-//         let id2 = Identity::truncate(&AuthStore::random(12));
-//         let id2pw = "mushrooms".into();
-
-//         let mut auth = AuthStore::new();
-//         {
-//             let mut hashes = auth.hashes.lock().unwrap();
-//             hashes.insert(id1.clone(), PwHash::new(&id1pw));
-//             hashes.insert(id2.clone(), PwHash::new(&id2pw));
-//         }
-
-//         Context {
-//             id1,
-//             id1pw,
-//             id2,
-//             id2pw,
-//             auth,
-//         }
-//     }
-
-//     #[test]
-//     fn collection() {
-//         let Context {
-//             id1,
-//             id1pw,
-//             id2,
-//             id2pw,
-//             mut auth,
-//         } = setup();
-
-//         // Test that correct user gets accepted, wrong gets rejected
-//         let t1 = auth.new_login(id1.clone(), &id1pw).unwrap();
-//         assert!(auth.new_login(id2.clone(), &id1pw).is_err());
-
-//         // Logging-in again results in the same token
-//         let t1_2 = auth.new_login(id1.clone(), &id1pw).unwrap();
-//         assert_eq!(t1, t1_2);
-
-//         // Verify "verify_token" endpoint
-//         assert!(auth.verify_token(&id1, &t1_2).is_ok())
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Context {
+        id1: Identity,
+        id1pw: String,
+
+        id2: Identity,
+        id2pw: String,
+
+        auth: AuthStore,
+    }
+
+    /// A small function that will seed an AuthStore for test purposes
+    fn setup() -> Context {
+        let id1 = Identity::random();
+        let id1pw: String = "sunflowers".into();
+
+        let id2 = Identity::random();
+        let id2pw: String = "mushrooms".into();
+
+        let auth = AuthStore::new();
+        auth.set_pw(id1, &id1pw).unwrap();
+        auth.set_pw(id2, &id2pw).unwrap();
+
+        Context {
+            id1,
+            id1pw,
+            id2,
+            id2pw,
+            auth,
+        }
+    }
+
+    #[test]
+    fn collection() {
+        let Context {
+            id1,
+            id1pw,
+            id2,
+            id2pw,
+            auth,
+        } = setup();
+
+        // Test that correct user gets accepted, wrong gets rejected
+        let t1 = auth.new_login(id1, &id1pw, None).unwrap();
+        assert!(auth.new_login(id2, &id1pw, None).is_err());
+
+        // Logging-in again results in a second session
+        let t1_2 = auth.new_login(id1, &id1pw, None).unwrap();
+        assert_ne!(t1, t1_2);
+
+        // Verify "verify_token" endpoint
+        assert!(auth.verify_token(&id1, &t1_2).is_ok());
+        assert!(auth.verify_token(&id2, &t1_2).is_err());
+
+        let t2 = auth.new_login(id2, &id2pw, None).unwrap();
+        assert!(auth.verify_token(&id2, &t2).is_ok());
+    }
+
+    #[test]
+    fn expiry() {
+        let Context {
+            id1, id1pw, auth, ..
+        } = setup();
+        let t1 = auth.new_login(id1, &id1pw, None).unwrap();
+
+        auth.set_ttl(Some(Duration::from_secs(60)));
+        let t1 = auth.refresh(&id1, &t1).unwrap();
+        assert!(auth.verify_token(&id1, &t1).is_ok());
+
+        auth.set_ttl(Some(Duration::from_secs(0)));
+        assert_eq!(auth.verify_token(&id1, &t1), Err(Error::NotAuthorised));
+
+        // Expired tokens are gone, even with a longer TTL
+        auth.set_ttl(None);
+        assert_eq!(auth.verify_token(&id1, &t1), Err(Error::NotAuthorised));
+    }
+
+    #[test]
+    fn list_and_revoke() {
+        let Context {
+            id1,
+            id1pw,
+            id2,
+            id2pw,
+            auth,
+        } = setup();
+
+        let t1 = auth.new_login(id1, &id1pw, Some("web".into())).unwrap();
+        let t1_2 = auth.new_login(id1, &id1pw, Some("cli".into())).unwrap();
+        let t2 = auth.new_login(id2, &id2pw, None).unwrap();
+
+        let sessions = auth.sessions(&id1, &t1).unwrap();
+        assert_eq!(sessions.len(), 2);
+        let other = sessions.iter().find(|s| !s.current).unwrap();
+        assert_eq!(other.client, Some("cli".into()));
+
+        // Sessions of other users can't be revoked
+        assert_eq!(auth.revoke(&id2, &other.id), Err(Error::NoSession));
+        auth.revoke(&id1, &other.id).unwrap();
+        assert!(auth.verify_token(&id1, &t1_2).is_err());
+        assert!(auth.verify_token(&id1, &t1).is_ok());
+
+        auth.logout_all(&id1);
+        assert!(auth.verify_token(&id1, &t1).is_err());
+        assert!(auth.verify_token(&id2, &t2).is_ok());
+    }
+
+    #[test]
+    fn failed_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let auth = AuthStore::open(dir.path().join("auth")).unwrap();
+        let id = Identity::random();
+        auth.set_pw(id, "sunflowers").unwrap();
+
+        // Hashes that can't be written aren't kept in memory either
+        let auth = AuthStore::open(dir.path().join("missing").join("auth")).unwrap();
+        assert!(auth.set_pw(id, "sunflowers").is_err());
+        assert_eq!(auth.check_pw(id, "sunflowers"), Err(Error::NotAuthorised));
+    }
+}
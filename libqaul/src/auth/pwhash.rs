@@ -5,9 +5,11 @@
 
 use blake2::{Blake2b, Digest as _};
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::fmt::{self, Debug, Formatter};
 
 /// A wrapper around a salted password hash
+#[derive(Serialize, Deserialize)]
 pub(crate) struct PwHash {
     hash: Vec<u8>,
     salt: Vec<u8>,
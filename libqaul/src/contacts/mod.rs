@@ -0,0 +1,384 @@
+//! Network user contact metadata and utilities
+//!
+//! Users' contact books exist as:
+//!
+//! - lists of users they know about, by identity, plus
+//! - local-only information about those users, like personal nicknames
+
+pub(crate) mod verify;
+
+// Public exports
+pub use crate::api::contacts::{ContactEntry, ContactQuery, Proof, SafetyNumber};
+
+use crate::{
+    error::{Error, Result},
+    messages::{Message, SigTrust},
+    store::ContactWrap,
+    users::{UserProfile, UserStore},
+    utils, Identity,
+};
+use alexandria::{
+    query::{Query, QueryResult},
+    utils::{Diff, Path, Tag},
+    Library, Session,
+};
+use async_std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+use tracing::info;
+
+/// A collection of contacts associated with their local-only data.
+pub(crate) type ContactList = BTreeMap<Identity, ContactEntry>;
+
+/// A contact entry from before contacts could be verified
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct LegacyEntry {
+    nick: Option<String>,
+    trust: i8,
+    met: bool,
+    location: Option<String>,
+    notes: Option<String>,
+}
+
+impl From<LegacyEntry> for ContactEntry {
+    fn from(e: LegacyEntry) -> Self {
+        Self {
+            nick: e.nick,
+            trust: e.trust,
+            met: e.met,
+            location: e.location,
+            notes: e.notes,
+            verified: false,
+        }
+    }
+}
+
+/// A contact list with legacy entries
+pub(crate) type LegacyList = BTreeMap<Identity, LegacyEntry>;
+
+const TAG_CONTACT: &str = "libqaul._int.contact";
+
+fn contact_path(contact: Identity) -> Path {
+    Path::from(format!("/contacts:{}", contact))
+}
+
+impl ContactQuery {
+    /// Check if a contact entry matches this query
+    pub(crate) fn matches(&self, con: &ContactEntry) -> bool {
+        fn contains(field: &Option<String>, s: &str) -> bool {
+            field.as_ref().map_or(false, |f| f.contains(s))
+        }
+
+        match self {
+            Self::Nick(nick) => contains(&con.nick, nick),
+            Self::Trust { val, fuz } => (*val as i16 - con.trust as i16).abs() <= *fuz as i16,
+            Self::Met(met) => con.met == *met,
+            Self::Location(loc) => contains(&con.location, loc),
+            Self::Notes(notes) => contains(&con.notes, notes),
+            Self::Verified(verified) => con.verified == *verified,
+            Self::And(queries) => queries.iter().all(|q| q.matches(con)),
+            Self::Or(queries) => queries.iter().any(|q| q.matches(con)),
+        }
+    }
+}
+
+/// Contact books that were kept in a separate file
+///
+/// Contacts used to be stored outside of the library, unencrypted.
+/// They are moved into a user's session the next time they log in.
+struct Legacy {
+    path: PathBuf,
+    books: BTreeMap<Identity, LegacyList>,
+}
+
+/// Wraps around user-local contact books
+///
+/// Every contact entry is a record in the owning user's encrypted
+/// alexandria session, so a user's contact book can only be
+/// accessed while they are logged in.
+#[derive(Clone)]
+pub(crate) struct ContactStore {
+    inner: Arc<Library>,
+    legacy: Arc<Mutex<Option<Legacy>>>,
+}
+
+impl ContactStore {
+    pub(crate) fn new(inner: Arc<Library>) -> Self {
+        Self {
+            inner,
+            legacy: Default::default(),
+        }
+    }
+
+    /// Create a store that picks up contact books from a legacy file
+    pub(crate) fn open(inner: Arc<Library>, path: PathBuf) -> Result<Self> {
+        let legacy = match path.exists() {
+            true => Some(Legacy {
+                books: utils::load_file(&path)?,
+                path,
+            }),
+            false => None,
+        };
+
+        Ok(Self {
+            inner,
+            legacy: Arc::new(Mutex::new(legacy)),
+        })
+    }
+
+    /// Move a user's contact book out of the legacy file
+    ///
+    /// This needs to be called after the user's session was opened.
+    /// The file is removed once the last contact book was moved.  A
+    /// contact book is only dropped from the file once all of its
+    /// entries were saved in the library.
+    pub(crate) async fn migrate(&self, id: Identity) -> Result<()> {
+        let mut legacy = self.legacy.lock().await;
+        let Legacy { path, books } = match legacy.as_mut() {
+            Some(l) if l.books.contains_key(&id) => l,
+            _ => return Ok(()),
+        };
+
+        info!("Moving contact book of `{}` into the library", id);
+        for (contact, entry) in books[&id].clone() {
+            self.save(id, contact, entry.into()).await?;
+        }
+
+        let list = books.remove(&id).unwrap();
+        let empty = books.is_empty();
+        let res = match empty {
+            true => fs::remove_file(path).map_err(|_| Error::StorageFault),
+            false => utils::sync_file(path, books),
+        };
+
+        match res {
+            Ok(()) if empty => {
+                *legacy = None;
+                Ok(())
+            }
+            Ok(()) => Ok(()),
+            Err(e) => {
+                books.insert(id, list);
+                Err(e)
+            }
+        }
+    }
+
+    async fn load(&self, id: Identity, contact: Identity) -> Option<ContactEntry> {
+        match self
+            .inner
+            .query(Session::Id(id), Query::Path(contact_path(contact)))
+            .await
+        {
+            Ok(QueryResult::Single(rec)) => Some(ContactWrap::from(&*rec).1),
+            _ => None,
+        }
+    }
+
+    /// Store a contact entry, replacing the previous one
+    async fn save(&self, id: Identity, contact: Identity, entry: ContactEntry) -> Result<()> {
+        let sess = Session::Id(id);
+        let path = contact_path(contact);
+        let wrap = ContactWrap(contact, entry);
+
+        match self.load(id, contact).await {
+            // All changes are applied as one diff, so that an entry is
+            // never only changed half-way
+            Some(prev) => {
+                let diffs = wrap.gen_diffset(&prev).into_iter();
+                let diff = diffs.fold(BTreeMap::new(), |mut map, diff| {
+                    if let Diff::Map(m) = diff {
+                        map.extend(m);
+                    }
+                    map
+                });
+                if !diff.is_empty() {
+                    self.inner
+                        .update(sess, path, Diff::from(diff))
+                        .await
+                        .map_err(|_| Error::StorageFault)?;
+                }
+            }
+            None => {
+                self.inner
+                    .batch(sess, path, Tag::empty(TAG_CONTACT), wrap.init_diff())
+                    .await
+                    .map_err(|_| Error::StorageFault)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Modify a users personal contact entry via a callback
+    ///
+    /// `id` in this case is the current session user, `contact` is
+    /// the contact entry they want to modify. **If none previously
+    /// existed, a fresh one will be created.**
+    ///
+    /// The callback can remove a verification, but not add one.
+    pub(crate) async fn modify<F>(&self, id: Identity, contact: Identity, modify: F) -> Result<()>
+    where
+        F: FnOnce(&mut ContactEntry),
+    {
+        let mut entry = self.load(id, contact).await.unwrap_or_default();
+        let verified = entry.verified;
+        modify(&mut entry);
+        entry.verified &= verified;
+        self.save(id, contact, entry).await
+    }
+
+    /// Mark a contact as verified, after checking the proof
+    pub(crate) async fn verify(&self, id: Identity, contact: Identity) -> Result<()> {
+        let mut entry = self.load(id, contact).await.unwrap_or_default();
+        entry.verified = true;
+        self.save(id, contact, entry).await
+    }
+
+    pub(crate) async fn get(&self, id: Identity, contact: Identity) -> Result<ContactEntry> {
+        self.load(id, contact).await.ok_or(Error::NoContact)
+    }
+
+    /// Check if a message was signed by a verified contact of a user
+    pub(crate) async fn verified(&self, id: Identity, msg: &Message) -> bool {
+        msg.sign == SigTrust::Trusted
+            && self.get(id, msg.sender).await.map_or(false, |c| c.verified)
+    }
+
+    /// Find the verified contacts that `profile` might replace
+    ///
+    /// A user that re-creates their account gets a new identity, so
+    /// there is no way to link it to the old one.  Instead, a new
+    /// identity with the same display name as a verified contact is
+    /// reported, so that the user can check its safety number.
+    pub(crate) async fn replaced(
+        &self,
+        id: Identity,
+        profile: &UserProfile,
+        users: &UserStore,
+    ) -> Vec<Identity> {
+        let name = match profile.display_name {
+            Some(ref name) => name,
+            None => return vec![],
+        };
+        let contacts = self.list(id).await;
+        if contacts.get(&profile.id).map_or(false, |c| c.verified) {
+            return vec![];
+        }
+
+        let mut replaced = vec![];
+        for (contact, entry) in contacts {
+            if !entry.verified || contact == profile.id || contact == id {
+                continue;
+            }
+            let same = users
+                .get(contact)
+                .await
+                .map_or(false, |p| p.display_name.as_ref() == Some(name));
+            if same {
+                replaced.push(contact);
+            }
+        }
+        replaced
+    }
+
+    /// Get a copy of a user's whole contact book
+    ///
+    /// If this user hasn't yet specified any contact entries, then
+    /// just return an empty list instead.
+    pub(crate) async fn list(&self, id: Identity) -> ContactList {
+        let query = Query::tags().subset(Tag::empty(TAG_CONTACT));
+        let recs = match self.inner.query(Session::Id(id), query).await {
+            Ok(QueryResult::Single(rec)) => vec![rec],
+            Ok(QueryResult::Many(recs)) => recs,
+            Err(_) => vec![],
+        };
+
+        recs.iter()
+            .map(|rec| {
+                let ContactWrap(contact, entry) = ContactWrap::from(&**rec);
+                (contact, entry)
+            })
+            .collect()
+    }
+
+    /// Query a user's contact book data
+    pub(crate) async fn query(&self, id: Identity, query: &ContactQuery) -> Vec<Identity> {
+        self.list(id)
+            .await
+            .into_iter()
+            .filter(|(_, con)| query.matches(con))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Get the identities of all of a user's contacts
+    pub(crate) async fn all(&self, id: Identity) -> Vec<Identity> {
+        self.list(id).await.into_iter().map(|(k, _)| k).collect()
+    }
+
+    /// Replace a user's contact book
+    pub(crate) async fn restore(&self, id: Identity, list: ContactList) -> Result<()> {
+        for contact in self.all(id).await {
+            if !list.contains_key(&contact) {
+                self.inner
+                    .delete(Session::Id(id), contact_path(contact))
+                    .await
+                    .map_err(|_| Error::StorageFault)?;
+            }
+        }
+
+        for (contact, entry) in list {
+            self.save(id, contact, entry).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(trust: i8, met: bool, location: Option<&str>) -> ContactEntry {
+        ContactEntry {
+            trust,
+            met,
+            location: location.map(Into::into),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn trust_range() {
+        let query = ContactQuery::Trust { val: 10, fuz: 5 };
+        assert!(query.matches(&entry(10, false, None)));
+        assert!(query.matches(&entry(5, false, None)));
+        assert!(query.matches(&entry(15, false, None)));
+        assert!(!query.matches(&entry(16, false, None)));
+        assert!(!query.matches(&entry(-10, false, None)));
+
+        // No overflows at the edges
+        let query = ContactQuery::Trust { val: 127, fuz: 127 };
+        assert!(query.matches(&entry(0, false, None)));
+        assert!(!query.matches(&entry(-128, false, None)));
+    }
+
+    #[test]
+    fn combined() {
+        let query = ContactQuery::And(vec![
+            ContactQuery::Trust { val: 100, fuz: 27 },
+            ContactQuery::Or(vec![
+                ContactQuery::Met(true),
+                ContactQuery::Location("Berlin".into()),
+            ]),
+        ]);
+
+        assert!(query.matches(&entry(100, true, None)));
+        assert!(query.matches(&entry(100, false, Some("Berlin, Germany"))));
+        assert!(!query.matches(&entry(100, false, Some("Paris"))));
+        assert!(!query.matches(&entry(0, true, Some("Berlin"))));
+
+        // Empty combinations behave like `all` and `any`
+        assert!(ContactQuery::And(vec![]).matches(&entry(0, false, None)));
+        assert!(!ContactQuery::Or(vec![]).matches(&entry(0, false, None)));
+    }
+}
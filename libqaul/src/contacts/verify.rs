@@ -0,0 +1,115 @@
+//! Safety numbers, to verify the identity keys of contacts
+//!
+//! This works much like the safety numbers in Signal: every identity
+//! has a fingerprint, which is an iterated hash of its public key.
+//! Both fingerprints are turned into 30 digits each, which are
+//! ordered by identity so that both users see the same number.
+//!
+//! Because an identity is a public key, an account that is
+//! re-created with a new key also has a new identity, which starts
+//! out unverified.
+
+use crate::{
+    api::contacts::{Proof, SafetyNumber},
+    Identity,
+};
+use sodiumoxide::crypto::generichash;
+
+/// The version of the fingerprint and QR payload format
+const VERSION: u8 = 0;
+
+/// Hash iterations, to make it expensive to find a key with a
+/// similar fingerprint
+const ITERATIONS: usize = 5200;
+
+const INFO: &[u8] = b"libqaul.safety";
+
+fn hash(data: &[&[u8]]) -> Vec<u8> {
+    let mut state = generichash::State::new(32, None).unwrap();
+    data.iter().for_each(|d| state.update(d).unwrap());
+    state.finalize().unwrap().as_ref().to_vec()
+}
+
+fn fingerprint(id: Identity) -> Vec<u8> {
+    let mut fp = hash(&[INFO, &[VERSION], id.as_ref()]);
+    for _ in 0..ITERATIONS {
+        fp = hash(&[&fp, id.as_ref()]);
+    }
+    fp
+}
+
+/// Encode a fingerprint as 30 digits, from 6 chunks of 5 bytes
+fn digits(fp: &[u8]) -> String {
+    fp[..30]
+        .chunks(5)
+        .map(|chunk| {
+            let num = chunk.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+            format!("{:05}", num % 100_000)
+        })
+        .collect()
+}
+
+/// Compute the safety number between two users
+///
+/// The QR payload is made from the point of view of `local`, and
+/// needs to be scanned by `remote`.
+pub(crate) fn safety_number(local: Identity, remote: Identity) -> SafetyNumber {
+    let (l, r) = (fingerprint(local), fingerprint(remote));
+    let digits = match local < remote {
+        true => digits(&l) + &digits(&r),
+        false => digits(&r) + &digits(&l),
+    };
+
+    SafetyNumber {
+        digits,
+        qr: [&[VERSION], l.as_slice(), r.as_slice()].concat(),
+    }
+}
+
+/// Check a proof that `local` has the right key for `remote`
+pub(crate) fn check(local: Identity, remote: Identity, proof: &Proof) -> bool {
+    match proof {
+        // The payload was made on the other side, so it starts with
+        // the remote fingerprint
+        Proof::Scanned(data) => safety_number(remote, local).qr == *data,
+        Proof::Compared(digits) => {
+            let digits: String = digits.chars().filter(|c| !c.is_whitespace()).collect();
+            safety_number(local, remote).digits == digits
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_on_both_sides() {
+        let (alice, bob) = (Identity::random(), Identity::random());
+        let a = safety_number(alice, bob);
+        let b = safety_number(bob, alice);
+
+        assert_eq!(a.digits, b.digits);
+        assert_eq!(a.digits.len(), 60);
+        assert!(a.digits.chars().all(|c| c.is_ascii_digit()));
+        assert_ne!(a.qr, b.qr);
+        assert_eq!(a.to_string().split(' ').count(), 12);
+    }
+
+    #[test]
+    fn proofs() {
+        let (alice, bob, eve) = (Identity::random(), Identity::random(), Identity::random());
+
+        // Bob scans Alice's screen
+        let qr = safety_number(alice, bob).qr;
+        assert!(check(bob, alice, &Proof::Scanned(qr.clone())));
+        assert!(!check(alice, bob, &Proof::Scanned(qr.clone())));
+        assert!(!check(bob, eve, &Proof::Scanned(qr)));
+
+        // Or they read out the numbers to each other
+        let number = safety_number(alice, bob).to_string();
+        assert!(check(bob, alice, &Proof::Compared(number.clone())));
+        assert!(check(alice, bob, &Proof::Compared(number.clone())));
+        assert!(!check(alice, eve, &Proof::Compared(number)));
+    }
+}
@@ -0,0 +1,81 @@
+//! Forward-secret end-to-end encryption
+//!
+//! Unicast payloads are encrypted with a double ratchet per pair of
+//! users, which is bootstrapped with an X3DH-style key agreement.
+//! Every local user has a medium-term prekey that is published along
+//! with their profile by the `Announcer`.  The first message to a
+//! peer derives a shared secret from both identity keys, the peer's
+//! prekey and a fresh ephemeral key, and every message after that
+//! uses a new key.  Compromising a user's identity key later on
+//! doesn't reveal past messages.
+//!
+//! As long as no prekey is known for a recipient, messages fall back
+//! to being sealed with the static identity keys (see `Sec`).  Once
+//! a ratchet is established with a peer, such messages are refused.
+//!
+//! Prekeys are signed with the identity key of their owner, and only
+//! used if the signature checks out.  Users without a signing key
+//! (see `Keypair`) can't publish a prekey.
+//!
+//! The sessions and prekeys of a user are part of their backup, so
+//! that peers can keep using their sessions after the user moved to
+//! another device.
+
+mod ratchet;
+mod store;
+mod x3dh;
+
+pub(crate) use self::{
+    store::{CryptoState, CryptoStore},
+    x3dh::SignedPrekey,
+};
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::{
+    box_::{self, PublicKey, SecretKey},
+    generichash::State,
+    scalarmult::curve25519::{scalarmult, GroupElement, Scalar},
+};
+
+/// A 32 byte symmetric key
+pub(self) type Key = [u8; 32];
+
+/// A curve25519 keypair used for key agreement
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(self) struct DhPair {
+    pub(self) public: PublicKey,
+    pub(self) secret: SecretKey,
+}
+
+impl DhPair {
+    pub(self) fn generate() -> Self {
+        let (public, secret) = box_::gen_keypair();
+        Self { public, secret }
+    }
+}
+
+/// Compute a shared secret between a secret and a public key
+pub(self) fn dh(secret: &SecretKey, public: &PublicKey) -> Result<Key> {
+    scalarmult(&Scalar(secret.0), &GroupElement(public.0))
+        .map(|ge| ge.0)
+        // Only fails for low-order points, which a peer has no
+        // business sending us
+        .map_err(|_| Error::InvalidPayload)
+}
+
+/// Keyed blake2b over a set of inputs, with `len` bytes of output
+pub(self) fn hash(key: Option<&[u8]>, len: usize, data: &[&[u8]]) -> Vec<u8> {
+    let mut state = State::new(len, key).unwrap();
+    data.iter().for_each(|d| state.update(d).unwrap());
+    state.finalize().unwrap().as_ref().to_vec()
+}
+
+/// Split a buffer of 64 bytes into two keys
+pub(self) fn split(buf: &[u8]) -> (Key, Key) {
+    let mut a = [0; 32];
+    let mut b = [0; 32];
+    a.copy_from_slice(&buf[..32]);
+    b.copy_from_slice(&buf[32..64]);
+    (a, b)
+}
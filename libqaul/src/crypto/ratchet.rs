@@ -0,0 +1,352 @@
+//! The double ratchet
+//!
+//! See the [Signal specification] for how this works.  Keys are
+//! derived with keyed blake2b, and messages are encrypted with
+//! XChaCha20-Poly1305, so nonces can be picked at random.
+//!
+//! Messages can arrive out of order, or not at all.  The keys for
+//! messages that were skipped over are kept around, so that they can
+//! still be decrypted when they show up later on.
+//!
+//! [Signal specification]: https://signal.org/docs/specifications/doubleratchet/
+
+use super::{dh, hash, split, DhPair, Key};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::{
+    aead::xchacha20poly1305_ietf::{self as aead, Nonce},
+    box_::PublicKey,
+};
+use std::collections::VecDeque;
+
+/// The most message keys that can be skipped in a single chain
+const MAX_SKIP: u32 = 1000;
+
+/// The most skipped message keys to keep around in total
+const MAX_STORED: usize = 2000;
+
+/// The header that is sent along with every message
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct Header {
+    /// The sender's current ratchet key
+    pub(super) dh: PublicKey,
+    /// Length of the sender's previous sending chain
+    pub(super) pn: u32,
+    /// Message number in the current sending chain
+    pub(super) n: u32,
+}
+
+/// A message encrypted by a `Ratchet`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(super) struct Encrypted {
+    pub(super) header: Header,
+    pub(super) nonce: Vec<u8>,
+    pub(super) data: Vec<u8>,
+}
+
+/// A key for a message that hasn't been received yet
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Skipped {
+    dh: PublicKey,
+    n: u32,
+    key: Key,
+}
+
+/// Ratchet state for one side of a conversation
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(super) struct Ratchet {
+    dh_self: DhPair,
+    dh_remote: Option<PublicKey>,
+    root: Key,
+    send: Option<Key>,
+    recv: Option<Key>,
+    ns: u32,
+    nr: u32,
+    pn: u32,
+    skipped: VecDeque<Skipped>,
+}
+
+/// Advance the root chain with a new DH output
+fn kdf_rk(root: &Key, dh: &Key) -> (Key, Key) {
+    split(&hash(Some(root), 64, &[dh]))
+}
+
+/// Advance a symmetric chain, returning the next chain and message keys
+fn kdf_ck(chain: &Key) -> (Key, Key) {
+    split(&hash(Some(chain), 64, &[&[0x01]]))
+}
+
+/// Prefix the associated data with the encoded header
+fn with_header(ad: &[u8], header: &Header) -> Vec<u8> {
+    let mut buf = ad.to_vec();
+    buf.append(&mut bincode::serialize(header).unwrap());
+    buf
+}
+
+fn aead_key(key: &Key) -> aead::Key {
+    aead::Key(*key)
+}
+
+impl Ratchet {
+    /// Create the ratchet for the side that sends the first message
+    pub(super) fn initiator(sk: Key, remote: PublicKey) -> Result<Self> {
+        let dh_self = DhPair::generate();
+        let (root, send) = kdf_rk(&sk, &dh(&dh_self.secret, &remote)?);
+
+        Ok(Self {
+            dh_self,
+            dh_remote: Some(remote),
+            root,
+            send: Some(send),
+            recv: None,
+            ns: 0,
+            nr: 0,
+            pn: 0,
+            skipped: VecDeque::new(),
+        })
+    }
+
+    /// Create the ratchet for the side that receives the first message
+    ///
+    /// `dh_self` is the prekey that the initiator used.
+    pub(super) fn responder(sk: Key, dh_self: DhPair) -> Self {
+        Self {
+            dh_self,
+            dh_remote: None,
+            root: sk,
+            send: None,
+            recv: None,
+            ns: 0,
+            nr: 0,
+            pn: 0,
+            skipped: VecDeque::new(),
+        }
+    }
+
+    /// Check if this side is able to send
+    ///
+    /// A responder can only send after receiving the first message.
+    pub(super) fn can_send(&self) -> bool {
+        self.send.is_some()
+    }
+
+    /// Encrypt a message, binding it to some associated data
+    pub(super) fn encrypt(&mut self, data: &[u8], ad: &[u8]) -> Result<Encrypted> {
+        let (chain, key) = kdf_ck(self.send.as_ref().ok_or(Error::NoSession)?);
+        let header = Header {
+            dh: self.dh_self.public,
+            pn: self.pn,
+            n: self.ns,
+        };
+        self.send = Some(chain);
+        self.ns += 1;
+
+        let nonce = aead::gen_nonce();
+        let ad = with_header(ad, &header);
+        Ok(Encrypted {
+            data: aead::seal(data, Some(&ad), &nonce, &aead_key(&key)),
+            nonce: nonce.0.to_vec(),
+            header,
+        })
+    }
+
+    /// Decrypt a message
+    ///
+    /// The state is only changed if decryption succeeds, so a forged
+    /// or replayed message can't break a session.
+    pub(super) fn decrypt(&mut self, msg: &Encrypted, ad: &[u8]) -> Result<Vec<u8>> {
+        let Encrypted {
+            header,
+            nonce,
+            data,
+        } = msg;
+        let nonce = Nonce::from_slice(nonce).ok_or(Error::InvalidPayload)?;
+        let ad = with_header(ad, header);
+        let mut next = self.clone();
+
+        let key = match next.take_skipped(header) {
+            Some(key) => key,
+            None => {
+                if Some(header.dh) != next.dh_remote {
+                    next.skip(header.pn)?;
+                    next.step(header.dh)?;
+                }
+                next.skip(header.n)?;
+
+                let (chain, key) = kdf_ck(next.recv.as_ref().ok_or(Error::InvalidPayload)?);
+                next.recv = Some(chain);
+                next.nr += 1;
+                key
+            }
+        };
+
+        let clear = aead::open(data, Some(&ad), &nonce, &aead_key(&key))
+            .map_err(|_| Error::InvalidPayload)?;
+        *self = next;
+        Ok(clear)
+    }
+
+    fn take_skipped(&mut self, header: &Header) -> Option<Key> {
+        let idx = self
+            .skipped
+            .iter()
+            .position(|s| s.dh == header.dh && s.n == header.n)?;
+        self.skipped.remove(idx).map(|s| s.key)
+    }
+
+    /// Store the keys of the current receiving chain up to `until`
+    fn skip(&mut self, until: u32) -> Result<()> {
+        let (chain, dh) = match (self.recv.as_mut(), self.dh_remote) {
+            (Some(chain), Some(dh)) => (chain, dh),
+            _ => return Ok(()),
+        };
+
+        if until > self.nr + MAX_SKIP {
+            return Err(Error::InvalidPayload);
+        }
+
+        while self.nr < until {
+            let (next, key) = kdf_ck(chain);
+            *chain = next;
+            self.skipped.push_back(Skipped {
+                dh,
+                n: self.nr,
+                key,
+            });
+            self.nr += 1;
+        }
+
+        // Messages that still haven't shown up are considered lost
+        while self.skipped.len() > MAX_STORED {
+            self.skipped.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Perform a DH ratchet step with the peer's new ratchet key
+    fn step(&mut self, remote: PublicKey) -> Result<()> {
+        self.pn = self.ns;
+        self.ns = 0;
+        self.nr = 0;
+        self.dh_remote = Some(remote);
+
+        let (root, recv) = kdf_rk(&self.root, &dh(&self.dh_self.secret, &remote)?);
+        self.dh_self = DhPair::generate();
+        let (root, send) = kdf_rk(&root, &dh(&self.dh_self.secret, &remote)?);
+
+        self.root = root;
+        self.recv = Some(recv);
+        self.send = Some(send);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AD: &[u8] = b"alice+bob";
+
+    fn pair() -> (Ratchet, Ratchet) {
+        let sk = [7; 32];
+        let prekey = DhPair::generate();
+        let alice = Ratchet::initiator(sk, prekey.public).unwrap();
+        let bob = Ratchet::responder(sk, prekey);
+        (alice, bob)
+    }
+
+    fn send(r: &mut Ratchet, text: &str) -> Encrypted {
+        r.encrypt(text.as_bytes(), AD).unwrap()
+    }
+
+    fn recv(r: &mut Ratchet, msg: &Encrypted) -> Result<String> {
+        r.decrypt(msg, AD)
+            .map(|clear| String::from_utf8(clear).unwrap())
+    }
+
+    #[test]
+    fn conversation() {
+        let (mut alice, mut bob) = pair();
+        assert!(!bob.can_send());
+
+        let m = send(&mut alice, "hi bob");
+        assert_eq!(recv(&mut bob, &m).unwrap(), "hi bob");
+
+        for i in 0..3 {
+            let m = send(&mut bob, &format!("hey {}", i));
+            assert_eq!(recv(&mut alice, &m).unwrap(), format!("hey {}", i));
+            let m = send(&mut alice, "ok");
+            assert_eq!(recv(&mut bob, &m).unwrap(), "ok");
+        }
+
+        // Every message uses a different ratchet key after a reply
+        let a = send(&mut alice, "a");
+        let b = send(&mut bob, "b");
+        assert_ne!(a.header.dh, b.header.dh);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let (mut alice, mut bob) = pair();
+        let msgs: Vec<_> = (0..5).map(|i| send(&mut alice, &i.to_string())).collect();
+
+        for i in &[3, 0, 4, 2, 1] {
+            assert_eq!(recv(&mut bob, &msgs[*i]).unwrap(), i.to_string());
+        }
+
+        // Messages from an old chain arrive after a ratchet step
+        let m = send(&mut bob, "reply");
+        recv(&mut alice, &m).unwrap();
+        let late = send(&mut alice, "late");
+        let m = send(&mut bob, "again");
+        recv(&mut alice, &m).unwrap();
+        let new = send(&mut alice, "new");
+
+        assert_eq!(recv(&mut bob, &new).unwrap(), "new");
+        assert_eq!(recv(&mut bob, &late).unwrap(), "late");
+    }
+
+    #[test]
+    fn lost_messages() {
+        let (mut alice, mut bob) = pair();
+        let _lost = send(&mut alice, "lost");
+        let m = send(&mut alice, "found");
+        assert_eq!(recv(&mut bob, &m).unwrap(), "found");
+
+        // A whole chain can go missing
+        let m = send(&mut bob, "one");
+        recv(&mut alice, &m).unwrap();
+        let _lost = send(&mut alice, "lost");
+        let m = send(&mut bob, "two");
+        recv(&mut alice, &m).unwrap();
+        let m = send(&mut alice, "three");
+        assert_eq!(recv(&mut bob, &m).unwrap(), "three");
+    }
+
+    #[test]
+    fn replay_and_tamper() {
+        let (mut alice, mut bob) = pair();
+        let m = send(&mut alice, "once");
+        recv(&mut bob, &m).unwrap();
+        assert!(recv(&mut bob, &m).is_err());
+
+        let mut m = send(&mut alice, "twice");
+        m.data[0] ^= 1;
+        assert!(recv(&mut bob, &m).is_err());
+        m.data[0] ^= 1;
+        assert!(bob.decrypt(&m, b"someone else").is_err());
+
+        // Failed attempts don't change the state
+        assert_eq!(recv(&mut bob, &m).unwrap(), "twice");
+    }
+
+    #[test]
+    fn skip_limit() {
+        let (mut alice, mut bob) = pair();
+        for _ in 0..=MAX_SKIP {
+            send(&mut alice, "lost");
+        }
+        let m = send(&mut alice, "too far");
+        assert_eq!(recv(&mut bob, &m), Err(Error::InvalidPayload));
+    }
+}
@@ -0,0 +1,603 @@
+//! Persistent ratchet sessions and prekeys
+
+use super::{
+    ratchet::{Encrypted, Ratchet},
+    x3dh::{self, Prekeys, SignedPrekey},
+};
+use crate::{
+    error::{Error, Result},
+    security::{Keypair, Sec},
+    Identity,
+};
+use alexandria::{
+    query::{Query, QueryResult},
+    record::kv::Value,
+    utils::{Diff, Path, Tag, TagSet},
+    Library, Session, GLOBAL,
+};
+use async_std::sync::{Arc, Mutex, RwLock};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sodiumoxide::crypto::box_::PublicKey;
+use std::collections::BTreeMap;
+
+/// The most sessions to keep per peer
+///
+/// Both sides can start a session at the same time, or a peer might
+/// have lost their state, so there can be more than one.  The most
+/// recently used one is always at the front.
+const MAX_SESSIONS: usize = 4;
+
+const DATA: &str = "data";
+
+const TAG_SESSION: &str = "libqaul._int.crypto.session";
+const TAG_PEER: &str = "libqaul._int.crypto.peer";
+
+fn prekey_path() -> Path {
+    Path::from("/crypto:prekeys")
+}
+
+fn remote_prekey_path(peer: Identity) -> Path {
+    Path::from(format!("/crypto/prekeys:{}", peer))
+}
+
+fn session_path(peer: Identity) -> Path {
+    Path::from(format!("/crypto/sessions:{}", peer))
+}
+
+fn session_tags(peer: Identity) -> TagSet {
+    let mut tags = TagSet::empty();
+    tags.insert(Tag::empty(TAG_SESSION));
+    tags.insert(Tag::new(TAG_PEER, peer.as_bytes().to_vec()));
+    tags
+}
+
+/// Information a peer needs to set up a session from a first message
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Init {
+    /// The initiator's ephemeral key
+    ek: PublicKey,
+    /// The prekey of the recipient that was used
+    spk: PublicKey,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RatchetSession {
+    ratchet: Ratchet,
+    /// Sent along until the peer replies, which means they have the
+    /// session too
+    init: Option<Init>,
+    /// The ephemeral key the session was created with
+    ek: PublicKey,
+}
+
+/// The sessions and prekeys of a local user, as they are backed up
+///
+/// Peers keep using their sessions with a user after the user moved
+/// to another device, so these need to move along.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct CryptoState {
+    prekeys: Option<Prekeys>,
+    sessions: Vec<(Identity, Vec<RatchetSession>)>,
+}
+
+/// An encrypted unicast payload
+#[derive(Debug, Serialize, Deserialize)]
+enum Sealed {
+    /// Sealed with the static identity keys
+    Static(Vec<u8>),
+    /// Sealed with a double ratchet
+    Ratchet { init: Option<Init>, msg: Encrypted },
+}
+
+/// Associated data for a message, which binds it to both identities
+fn assoc(sender: Identity, recipient: Identity) -> Vec<u8> {
+    [sender.as_ref(), recipient.as_ref()].concat()
+}
+
+/// Session state for the end-to-end encryption of unicast messages
+///
+/// Ratchet sessions and prekeys are secret, so they are stored in
+/// the user's encrypted alexandria session, which means that a user
+/// needs to be logged in to send or receive messages.  Prekeys of
+/// remote users are stored in the global session, so that sessions
+/// can be started right after a restart.
+#[derive(Clone)]
+pub(crate) struct CryptoStore {
+    inner: Arc<Library>,
+    /// Serialises read-modify-write cycles on the stored state
+    lock: Arc<Mutex<()>>,
+    /// Remote prekeys that were loaded or stored since the start
+    remote: Arc<RwLock<BTreeMap<Identity, SignedPrekey>>>,
+}
+
+impl CryptoStore {
+    pub(crate) fn new(inner: Arc<Library>) -> Self {
+        Self {
+            inner,
+            lock: Default::default(),
+            remote: Default::default(),
+        }
+    }
+
+    async fn load<T: DeserializeOwned>(&self, id: Session, path: Path) -> Result<Option<T>> {
+        match self.inner.query(id, Query::Path(path)).await {
+            Ok(QueryResult::Single(rec)) => match rec.kv().get(DATA) {
+                Some(Value::Vec(bytes)) => Ok(Some(bincode::deserialize(bytes)?)),
+                _ => Err(Error::StorageFault),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    async fn save<T: Serialize>(
+        &self,
+        id: Session,
+        path: Path,
+        tags: TagSet,
+        data: &T,
+    ) -> Result<()> {
+        let bytes = bincode::serialize(data)?;
+
+        // Try to insert, otherwise update
+        if self
+            .inner
+            .insert(
+                id,
+                path.clone(),
+                tags,
+                Diff::map().insert(DATA, bytes.clone()),
+            )
+            .await
+            .is_err()
+        {
+            self.inner
+                .update(id, path, Diff::map().update(DATA, bytes))
+                .await
+                .map_err(|_| Error::StorageFault)?;
+        }
+        Ok(())
+    }
+
+    /// Get the signed prekey a local user should publish
+    ///
+    /// The prekey is created on first use, and rotated when it's due.
+    /// Returns `None` for users that can't sign a prekey.
+    pub(crate) async fn prekey(&self, keypair: &Keypair) -> Result<Option<SignedPrekey>> {
+        let user = Session::Id(keypair.id());
+        let _lock = self.lock.lock().await;
+        let mut prekeys = match self.load(user, prekey_path()).await? {
+            Some(prekeys) => prekeys,
+            None => {
+                let prekeys = Prekeys::new();
+                self.save(user, prekey_path(), TagSet::empty(), &prekeys)
+                    .await?;
+                prekeys
+            }
+        };
+
+        if prekeys.rotate() {
+            self.save(user, prekey_path(), TagSet::empty(), &prekeys)
+                .await?;
+        }
+        Ok(SignedPrekey::new(keypair, prekeys.public()))
+    }
+
+    /// Remember the announced prekey of a peer
+    ///
+    /// Fails with `Error::BadSign` if the prekey wasn't signed by the
+    /// peer.
+    pub(crate) async fn add_prekey(&self, peer: Identity, prekey: SignedPrekey) -> Result<()> {
+        if !prekey.verify(peer) {
+            return Err(Error::BadSign);
+        }
+
+        let mut remote = self.remote.write().await;
+        if remote.get(&peer) == Some(&prekey) {
+            return Ok(());
+        }
+        self.save(GLOBAL, remote_prekey_path(peer), TagSet::empty(), &prekey)
+            .await?;
+        remote.insert(peer, prekey);
+        Ok(())
+    }
+
+    /// Get the prekey of a peer, if it's known and correctly signed
+    async fn remote_prekey(&self, peer: Identity) -> Result<Option<PublicKey>> {
+        let cached = self.remote.read().await.get(&peer).cloned();
+        let prekey = match cached {
+            Some(prekey) => Some(prekey),
+            None => self.load(GLOBAL, remote_prekey_path(peer)).await?,
+        };
+
+        Ok(prekey
+            .filter(|prekey| prekey.verify(peer))
+            .map(|prekey| prekey.key))
+    }
+
+    /// Encrypt a payload for a single recipient
+    pub(crate) async fn encrypt(
+        &self,
+        keypair: &Keypair,
+        recipient: Identity,
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let sender = keypair.id();
+        let _lock = self.lock.lock().await;
+        let mut sessions: Vec<RatchetSession> = self
+            .load(Session::Id(sender), session_path(recipient))
+            .await?
+            .unwrap_or_default();
+
+        if !sessions.first().map_or(false, |s| s.ratchet.can_send()) {
+            let spk = match self.remote_prekey(recipient).await? {
+                Some(spk) => spk,
+                None => {
+                    let sealed = Sec::encrypt(keypair.clone(), recipient, &data.to_vec());
+                    return Ok(bincode::serialize(&Sealed::Static(sealed))?);
+                }
+            };
+
+            let (sk, ek) = x3dh::initiate(keypair, recipient, &spk)?;
+            sessions.insert(
+                0,
+                RatchetSession {
+                    ratchet: Ratchet::initiator(sk, spk)?,
+                    init: Some(Init { ek, spk }),
+                    ek,
+                },
+            );
+            sessions.truncate(MAX_SESSIONS);
+        }
+
+        let session = &mut sessions[0];
+        let sealed = Sealed::Ratchet {
+            msg: session.ratchet.encrypt(data, &assoc(sender, recipient))?,
+            init: session.init.clone(),
+        };
+
+        self.save(
+            Session::Id(sender),
+            session_path(recipient),
+            session_tags(recipient),
+            &sessions,
+        )
+        .await?;
+        Ok(bincode::serialize(&sealed)?)
+    }
+
+    /// Decrypt a payload sent to a local user
+    pub(crate) async fn decrypt(
+        &self,
+        keypair: &Keypair,
+        sender: Identity,
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let sealed = bincode::deserialize(data).map_err(|_| Error::InvalidPayload)?;
+
+        let recipient = keypair.id();
+        let _lock = self.lock.lock().await;
+        let mut sessions: Vec<RatchetSession> = self
+            .load(Session::Id(recipient), session_path(sender))
+            .await?
+            .unwrap_or_default();
+
+        let (init, msg) = match sealed {
+            // A peer that has a ratchet with us never needs to fall
+            // back to the static keys, so this would be a downgrade
+            Sealed::Static(_) if sessions.iter().any(|s| s.init.is_none()) => {
+                return Err(Error::InvalidPayload)
+            }
+            Sealed::Static(data) => return Sec::decrypt(keypair.clone(), sender, &data),
+            Sealed::Ratchet { init, msg } => (init, msg),
+        };
+
+        // A first message from a session we don't know yet
+        if let Some(Init { ek, spk }) = init {
+            if !sessions.iter().any(|s| s.ek == ek) {
+                let prekeys: Prekeys = self
+                    .load(Session::Id(recipient), prekey_path())
+                    .await?
+                    .ok_or(Error::InvalidPayload)?;
+                let spk = prekeys.find(&spk).ok_or(Error::InvalidPayload)?;
+                let sk = x3dh::respond(keypair, sender, spk, &ek)?;
+
+                sessions.push(RatchetSession {
+                    ratchet: Ratchet::responder(sk, spk.clone()),
+                    init: None,
+                    ek,
+                });
+            }
+        }
+
+        let ad = assoc(sender, recipient);
+        let (idx, clear) = sessions
+            .iter_mut()
+            .enumerate()
+            .find_map(|(idx, s)| s.ratchet.decrypt(&msg, &ad).ok().map(|c| (idx, c)))
+            .ok_or(Error::InvalidPayload)?;
+
+        // The peer is using this session, so there's no need to keep
+        // sending the init, and it's the one we should reply on
+        let mut session = sessions.remove(idx);
+        session.init = None;
+        sessions.insert(0, session);
+        sessions.truncate(MAX_SESSIONS);
+
+        self.save(
+            Session::Id(recipient),
+            session_path(sender),
+            session_tags(sender),
+            &sessions,
+        )
+        .await?;
+        Ok(clear)
+    }
+
+    /// Get the prekeys and all sessions of a local user
+    pub(crate) async fn export(&self, user: Identity) -> Result<CryptoState> {
+        let id = Session::Id(user);
+        let _lock = self.lock.lock().await;
+        let recs = match self
+            .inner
+            .query(id, Query::tags().subset(Tag::empty(TAG_SESSION)))
+            .await
+        {
+            Ok(QueryResult::Single(rec)) => vec![rec],
+            Ok(QueryResult::Many(recs)) => recs,
+            Err(_) => vec![],
+        };
+
+        let mut sessions = vec![];
+        for rec in recs {
+            let peer = rec
+                .header
+                .tags
+                .iter()
+                .find(|t| t.key == TAG_PEER)
+                .map(|t| Identity::from_bytes(&t.val))
+                .ok_or(Error::StorageFault)?;
+            match rec.kv().get(DATA) {
+                Some(Value::Vec(bytes)) => sessions.push((peer, bincode::deserialize(bytes)?)),
+                _ => return Err(Error::StorageFault),
+            }
+        }
+
+        Ok(CryptoState {
+            prekeys: self.load(id, prekey_path()).await?,
+            sessions,
+        })
+    }
+
+    /// Store the prekeys and sessions of a user from a backup
+    pub(crate) async fn restore(&self, user: Identity, state: CryptoState) -> Result<()> {
+        let id = Session::Id(user);
+        let _lock = self.lock.lock().await;
+        if let Some(prekeys) = state.prekeys {
+            self.save(id, prekey_path(), TagSet::empty(), &prekeys)
+                .await?;
+        }
+        for (peer, sessions) in state.sessions {
+            self.save(id, session_path(peer), session_tags(peer), &sessions)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::KeyId;
+    use alexandria::Builder;
+
+    struct Env {
+        store: CryptoStore,
+        alice: KeyId,
+        bob: KeyId,
+    }
+
+    async fn env() -> Env {
+        let lib = Builder::new().build().unwrap();
+
+        let sec = Sec::new();
+        let alice = sec.generate().await;
+        let bob = sec.generate().await;
+        lib.sessions().create(alice.id, "alice").await.unwrap();
+        lib.sessions().create(bob.id, "bob").await.unwrap();
+
+        Env {
+            store: CryptoStore::new(lib),
+            alice,
+            bob,
+        }
+    }
+
+    fn is_static(data: &[u8]) -> bool {
+        match bincode::deserialize(data).unwrap() {
+            Sealed::Static(_) => true,
+            Sealed::Ratchet { .. } => false,
+        }
+    }
+
+    #[async_std::test]
+    async fn static_fallback() {
+        let Env { store, alice, bob } = env().await;
+
+        let sealed = store.encrypt(&alice.keypair, bob.id, b"hi").await.unwrap();
+        assert!(is_static(&sealed));
+        assert_eq!(
+            store
+                .decrypt(&bob.keypair, alice.id, &sealed)
+                .await
+                .unwrap(),
+            b"hi"
+        );
+    }
+
+    #[async_std::test]
+    async fn ratchet_session() {
+        let Env { store, alice, bob } = env().await;
+
+        let spk = store.prekey(&bob.keypair).await.unwrap().unwrap();
+        assert_eq!(store.prekey(&bob.keypair).await.unwrap(), Some(spk.clone()));
+        store.add_prekey(bob.id, spk).await.unwrap();
+
+        // Messages before the first reply carry the init
+        let first = store.encrypt(&alice.keypair, bob.id, b"1").await.unwrap();
+        let second = store.encrypt(&alice.keypair, bob.id, b"2").await.unwrap();
+        assert!(!is_static(&first));
+
+        assert_eq!(
+            store
+                .decrypt(&bob.keypair, alice.id, &second)
+                .await
+                .unwrap(),
+            b"2"
+        );
+        assert_eq!(
+            store.decrypt(&bob.keypair, alice.id, &first).await.unwrap(),
+            b"1"
+        );
+        assert!(store.decrypt(&bob.keypair, alice.id, &first).await.is_err());
+
+        // Bob doesn't need to know Alice's prekey to reply
+        let reply = store.encrypt(&bob.keypair, alice.id, b"3").await.unwrap();
+        assert!(!is_static(&reply));
+        assert_eq!(
+            store.decrypt(&alice.keypair, bob.id, &reply).await.unwrap(),
+            b"3"
+        );
+
+        let next = store.encrypt(&alice.keypair, bob.id, b"4").await.unwrap();
+        match bincode::deserialize(&next).unwrap() {
+            Sealed::Ratchet { init, .. } => assert_eq!(init, None),
+            Sealed::Static(_) => unreachable!(),
+        }
+        assert_eq!(
+            store.decrypt(&bob.keypair, alice.id, &next).await.unwrap(),
+            b"4"
+        );
+    }
+
+    #[async_std::test]
+    async fn wrong_sender() {
+        let Env { store, alice, bob } = env().await;
+        let mallory = Sec::new().generate().await;
+
+        let spk = store.prekey(&bob.keypair).await.unwrap().unwrap();
+        store.add_prekey(bob.id, spk).await.unwrap();
+
+        let sealed = store.encrypt(&alice.keypair, bob.id, b"hi").await.unwrap();
+        assert!(store
+            .decrypt(&bob.keypair, mallory.id, &sealed)
+            .await
+            .is_err());
+    }
+
+    #[async_std::test]
+    async fn forged_prekey() {
+        let Env { store, alice, bob } = env().await;
+
+        // Alice's prekey, announced in the name of Bob
+        let spk = store.prekey(&alice.keypair).await.unwrap().unwrap();
+        assert_eq!(store.add_prekey(bob.id, spk).await, Err(Error::BadSign));
+
+        let sealed = store.encrypt(&alice.keypair, bob.id, b"hi").await.unwrap();
+        assert!(is_static(&sealed));
+    }
+
+    #[async_std::test]
+    async fn stored_prekeys() {
+        let Env { store, alice, bob } = env().await;
+
+        let spk = store.prekey(&bob.keypair).await.unwrap().unwrap();
+        store.add_prekey(bob.id, spk).await.unwrap();
+
+        // The prekey is still known after a restart
+        let store = CryptoStore::new(Arc::clone(&store.inner));
+        let sealed = store.encrypt(&alice.keypair, bob.id, b"hi").await.unwrap();
+        assert!(!is_static(&sealed));
+        assert_eq!(
+            store
+                .decrypt(&bob.keypair, alice.id, &sealed)
+                .await
+                .unwrap(),
+            b"hi"
+        );
+    }
+
+    #[async_std::test]
+    async fn static_after_ratchet() {
+        let Env { store, alice, bob } = env().await;
+
+        // Sent before Alice knew Bob's prekey
+        let old = store.encrypt(&alice.keypair, bob.id, b"1").await.unwrap();
+        assert!(is_static(&old));
+
+        let spk = store.prekey(&bob.keypair).await.unwrap().unwrap();
+        store.add_prekey(bob.id, spk).await.unwrap();
+        let sealed = store.encrypt(&alice.keypair, bob.id, b"2").await.unwrap();
+
+        // Until Bob got a ratchet message, static ones are fine
+        assert_eq!(
+            store.decrypt(&bob.keypair, alice.id, &old).await.unwrap(),
+            b"1"
+        );
+        store
+            .decrypt(&bob.keypair, alice.id, &sealed)
+            .await
+            .unwrap();
+        assert_eq!(
+            store.decrypt(&bob.keypair, alice.id, &old).await,
+            Err(Error::InvalidPayload)
+        );
+
+        // Alice refuses them too, once Bob replied
+        let forged = bincode::serialize(&Sealed::Static(Sec::encrypt(
+            bob.keypair.clone(),
+            alice.id,
+            &b"3".to_vec(),
+        )))
+        .unwrap();
+        let reply = store.encrypt(&bob.keypair, alice.id, b"4").await.unwrap();
+        store.decrypt(&alice.keypair, bob.id, &reply).await.unwrap();
+        assert_eq!(
+            store.decrypt(&alice.keypair, bob.id, &forged).await,
+            Err(Error::InvalidPayload)
+        );
+    }
+
+    #[async_std::test]
+    async fn export_and_restore() {
+        let Env { store, alice, bob } = env().await;
+
+        let spk = store.prekey(&bob.keypair).await.unwrap().unwrap();
+        store.add_prekey(bob.id, spk).await.unwrap();
+        let first = store.encrypt(&alice.keypair, bob.id, b"1").await.unwrap();
+        store.decrypt(&bob.keypair, alice.id, &first).await.unwrap();
+        let reply = store.encrypt(&bob.keypair, alice.id, b"2").await.unwrap();
+        store.decrypt(&alice.keypair, bob.id, &reply).await.unwrap();
+
+        // Bob moves to another device
+        let state = store.export(bob.id).await.unwrap();
+        let lib = Builder::new().build().unwrap();
+        lib.sessions().create(bob.id, "bob").await.unwrap();
+        let moved = CryptoStore::new(lib);
+        moved.restore(bob.id, state).await.unwrap();
+
+        // Alice keeps using her session
+        let sealed = store.encrypt(&alice.keypair, bob.id, b"3").await.unwrap();
+        assert_eq!(
+            moved
+                .decrypt(&bob.keypair, alice.id, &sealed)
+                .await
+                .unwrap(),
+            b"3"
+        );
+        let reply = moved.encrypt(&bob.keypair, alice.id, b"4").await.unwrap();
+        assert_eq!(
+            store.decrypt(&alice.keypair, bob.id, &reply).await.unwrap(),
+            b"4"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! X3DH-style initial key agreement
+//!
+//! This follows the Signal X3DH scheme without one-time prekeys:
+//! one-time keys would need to be fetched from a server, which a
+//! delay tolerant mesh doesn't have.  Instead every user has a single
+//! prekey at a time, which is rotated every `PREKEY_LIFETIME`.  A
+//! few retired prekeys are kept around so that first messages that
+//! were sent before a rotation can still be read.
+
+use super::{dh, hash, DhPair, Key};
+use crate::{
+    error::Result,
+    security::{Keypair, Sec, Signature},
+    Identity,
+};
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::box_::PublicKey;
+use std::time::{Duration, SystemTime};
+
+/// How long a prekey is handed out before it's replaced
+const PREKEY_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The number of retired prekeys to keep
+const PREKEY_RETIRED: usize = 3;
+
+/// Domain separation for the derived secret
+const INFO: &[u8] = b"libqaul.x3dh";
+
+/// Domain separation for prekey signatures
+const PREKEY_SIGN: &[u8] = b"libqaul.x3dh.prekey";
+
+/// A published prekey, signed by the identity key of its owner
+///
+/// Without the signature anybody could hand out a prekey in the name
+/// of a user, and read the messages that are sent to it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SignedPrekey {
+    pub(crate) key: PublicKey,
+    sig: Signature,
+}
+
+impl SignedPrekey {
+    /// Sign a prekey, which fails for users without a signing key
+    pub(super) fn new(ik: &Keypair, key: PublicKey) -> Option<Self> {
+        ik.sign(&[PREKEY_SIGN, key.as_ref()].concat())
+            .map(|sig| Self { key, sig })
+    }
+
+    /// Check that the prekey was signed by `owner`
+    pub(crate) fn verify(&self, owner: Identity) -> bool {
+        Sec::verify(owner, &[PREKEY_SIGN, self.key.as_ref()].concat(), &self.sig)
+    }
+}
+
+/// The prekeys of a local user
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(super) struct Prekeys {
+    current: DhPair,
+    created: SystemTime,
+    retired: Vec<DhPair>,
+}
+
+impl Prekeys {
+    pub(super) fn new() -> Self {
+        Self {
+            current: DhPair::generate(),
+            created: SystemTime::now(),
+            retired: vec![],
+        }
+    }
+
+    /// The prekey to publish
+    pub(super) fn public(&self) -> PublicKey {
+        self.current.public
+    }
+
+    /// Replace the current prekey if it's past its lifetime
+    ///
+    /// Returns `true` if a new prekey was generated.
+    pub(super) fn rotate(&mut self) -> bool {
+        let due = self
+            .created
+            .elapsed()
+            .map(|age| age >= PREKEY_LIFETIME)
+            .unwrap_or(false);
+
+        if due {
+            let old = std::mem::replace(&mut self.current, DhPair::generate());
+            self.created = SystemTime::now();
+            self.retired.insert(0, old);
+            self.retired.truncate(PREKEY_RETIRED);
+        }
+        due
+    }
+
+    /// Find the keypair for a public prekey, current or retired
+    pub(super) fn find(&self, public: &PublicKey) -> Option<&DhPair> {
+        Some(&self.current)
+            .into_iter()
+            .chain(self.retired.iter())
+            .find(|pair| &pair.public == public)
+    }
+}
+
+fn id_key(id: Identity) -> PublicKey {
+    // Identities are always valid public keys (see `security`)
+    PublicKey::from_slice(id.as_ref()).unwrap()
+}
+
+fn derive(dh1: Key, dh2: Key, dh3: Key) -> Key {
+    let mut sk = [0; 32];
+    sk.copy_from_slice(&hash(None, 32, &[INFO, &dh1, &dh2, &dh3]));
+    sk
+}
+
+/// Start a session with a peer, from their published prekey
+///
+/// Returns the shared secret, and the ephemeral key that the peer
+/// needs to compute it.
+pub(super) fn initiate(ik: &Keypair, peer: Identity, spk: &PublicKey) -> Result<(Key, PublicKey)> {
+    let ek = DhPair::generate();
+    let sk = derive(
+        dh(ik.secret(), spk)?,
+        dh(&ek.secret, &id_key(peer))?,
+        dh(&ek.secret, spk)?,
+    );
+    Ok((sk, ek.public))
+}
+
+/// Accept a session started by a peer with one of our prekeys
+pub(super) fn respond(ik: &Keypair, peer: Identity, spk: &DhPair, ek: &PublicKey) -> Result<Key> {
+    Ok(derive(
+        dh(&spk.secret, &id_key(peer))?,
+        dh(ik.secret(), ek)?,
+        dh(&spk.secret, ek)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::Sec;
+
+    #[async_std::test]
+    async fn agreement() {
+        let sec = Sec::new();
+        let a = sec.generate().await;
+        let b = sec.generate().await;
+        let prekeys = Prekeys::new();
+
+        let (sk, ek) = initiate(&a.keypair, b.id, &prekeys.public()).unwrap();
+        let spk = prekeys.find(&prekeys.public()).unwrap();
+        assert_eq!(respond(&b.keypair, a.id, spk, &ek).unwrap(), sk);
+
+        // Somebody else can't take over the session
+        let c = sec.generate().await;
+        assert_ne!(respond(&b.keypair, c.id, spk, &ek).unwrap(), sk);
+    }
+
+    #[test]
+    fn rotation() {
+        let mut prekeys = Prekeys::new();
+        let first = prekeys.public();
+        assert!(!prekeys.rotate());
+
+        for _ in 0..=PREKEY_RETIRED {
+            prekeys.created -= PREKEY_LIFETIME;
+            assert!(prekeys.rotate());
+        }
+
+        assert_ne!(prekeys.public(), first);
+        assert_eq!(prekeys.retired.len(), PREKEY_RETIRED);
+        assert!(prekeys.find(&first).is_none());
+    }
+
+    #[async_std::test]
+    async fn signed_prekey() {
+        let sec = Sec::new();
+        let a = sec.generate().await;
+        let b = sec.generate().await;
+        let prekeys = Prekeys::new();
+
+        let signed = SignedPrekey::new(&a.keypair, prekeys.public()).unwrap();
+        assert!(signed.verify(a.id));
+        assert!(!signed.verify(b.id));
+
+        // The signature only covers the key it was made for
+        let swapped = SignedPrekey {
+            key: Prekeys::new().public(),
+            ..signed
+        };
+        assert!(!swapped.verify(a.id));
+    }
+}
@@ -1,12 +1,12 @@
 use crate::{
-    messages::MsgUtils,
-    users::{Announcer, TAG_PROFILE},
-    Qaul,
+    messages::{MsgUtils, Onion, Peeled, SigTrust},
+    users::{Announcer, UserProfile, TAG_PROFILE},
+    Identity, Qaul,
 };
 use alexandria::utils::Tag;
 use async_std::task;
 use ratman::{netmod::Recipient, Router};
-use std::sync::Arc;
+use std::{collections::BTreeSet, sync::Arc};
 use tracing::{debug, info, warn};
 
 /// A thread-detached discovery service running inside libqaul
@@ -39,6 +39,7 @@ impl Discovery {
             loop {
                 let id = router.discover().await;
                 debug!(id = id.to_string().as_str(), "Received announcement!");
+                qaul.users.mark_seen(id).await;
 
                 if !qaul.users.known_remote().await.contains(&id) {
                     info!(id = id.to_string().as_str(), "Discovered new user!");
@@ -54,6 +55,7 @@ impl Discovery {
     #[tracing::instrument(skip(qaul, router), level = "info")]
     fn inc_handler(qaul: Arc<Qaul>, router: Arc<Router>) {
         task::spawn(async move {
+            let mut changed = BTreeSet::new();
             loop {
                 let msg = router.next().await;
                 let sender = msg.sender;
@@ -64,32 +66,72 @@ impl Discovery {
                     Recipient::Flood => None,
                 };
 
-                // Filter internal status messages
-                // panic!("Getting an announcer message!!!!");
-                if let Some(profile) = Announcer::check_message(&msg) {
-                    // If we had a previous version, generate diffs for update
-                    if let Some(old) = qaul.users.get(msg.sender).await.ok() {
-                        let diffs = old.generate_updates(profile);
-
-                        for update in diffs.into_iter() {
-                            qaul.users.modify(msg.sender, update).await.unwrap();
+                // Anonymous messages are passed on until the last layer
+                // is removed
+                let msg = match Onion::peel(msg, &qaul.users).await {
+                    Peeled::Relay(next) => {
+                        debug!("Relaying anonymous message to {:?}", next.recipient);
+                        if let Err(e) = router.send(next).await {
+                            warn!("Failed to relay anonymous message: {:?}", e);
                         }
+                        continue;
                     }
+                    Peeled::Message(msg) => msg,
+                };
 
-                    continue;
-                }
-
-                let msg = match MsgUtils::process(msg, &qaul.users).await {
-                    Ok(msg) => Arc::new(msg),
+                let mut msg = match MsgUtils::process(msg, &qaul.users).await {
+                    Ok(msg) => msg,
                     Err(_) => {
                         warn!("Skipping malformed message by `{}`", sender);
                         continue;
                     }
                 };
+                if let Some(recp) = recp {
+                    msg.verified = qaul.contacts.verified(recp, &msg).await;
+                }
+                let msg = Arc::new(msg);
+
+                // Anyone can put a name on a message, only a signed
+                // one shows that the sender is around
+                if msg.sign == SigTrust::Trusted {
+                    qaul.users.mark_seen(msg.sender).await;
+                }
+
+                // Filter internal profile announcements
+                if let Some(ann) = Announcer::check_message(&msg) {
+                    if let Some(profile) = qaul
+                        .announcer
+                        .handle(&router, &qaul.users, &msg, recp, ann)
+                        .await
+                    {
+                        Self::key_changes(&qaul, &profile, &mut changed).await;
+                    }
+                    continue;
+                }
 
                 qaul.messages.insert_remote(recp, Arc::clone(&msg)).await;
                 info!("Finished processing incoming message!");
             }
         });
     }
+    /// Tell services about verified contacts that a new profile
+    /// might replace
+    ///
+    /// `changed` holds the changes that were already reported, so
+    /// that profile updates don't raise the same event again.
+    async fn key_changes(
+        qaul: &Qaul,
+        profile: &UserProfile,
+        changed: &mut BTreeSet<(Identity, Identity, Identity)>,
+    ) {
+        for local in qaul.users.all_local().await {
+            let user = local.id;
+            for old in qaul.contacts.replaced(user, profile, &qaul.users).await {
+                if changed.insert((user, old, profile.id)) {
+                    warn!("Contact `{}` of `{}` may have a new key", old, user);
+                    qaul.services.key_changed(user, old, profile.id).await;
+                }
+            }
+        }
+    }
 }
@@ -54,10 +54,14 @@ pub enum Error {
     NotAuthorised,
     /// The desired user was not known
     NoUser,
+    /// The provided user already exists
+    UserExists,
     /// The provided contact already exists
     ContactExists,
     /// The desired contact does not exist
     NoContact,
+    /// The desired session does not exist
+    NoSession,
     /// Invalid search query
     InvalidQuery,
     /// No data was returned for the provided query
@@ -70,6 +74,8 @@ pub enum Error {
     NoSign,
     /// Fraudulent signature for a known public key
     BadSign,
+    /// A key verification proof didn't match
+    BadProof,
     /// A generic networking error occured
     NetworkFault,
     /// Failed to find a route to this user
@@ -82,6 +88,8 @@ pub enum Error {
     ServiceExists,
     /// Some internal components failed to communicate
     CommFault,
+    /// Failed to read or write persistent storage
+    StorageFault,
 }
 
 impl Error {
@@ -100,20 +108,24 @@ impl Display for Error {
         let msg = match self {
             Self::NotAuthorised => "Not authorised to perform this action",
             Self::NoUser => "The desired user was not known",
+            Self::UserExists => "The provided user already exists",
             Self::ContactExists => "The provided contact already exists",
             Self::NoContact => "The desired contact does not exist",
+            Self::NoSession => "The desired session does not exist",
             Self::InvalidQuery => "Invalid search query",
             Self::NoData => "No data was returned for the provided query",
             Self::InvalidPayload => "Invalid payload (probably too big)",
             Self::CallbackTimeout => "A function callback timed out",
             Self::NoSign => "Signature with an unknown public key",
             Self::BadSign => "Fraudulent signature for a known public key",
+            Self::BadProof => "A key verification proof didn't match",
             Self::NetworkFault => "A generic networking error occured",
             Self::NoRoute => "Failed to find a route to this user",
             Self::BadSerialise => "Some serialisation action failed",
             Self::NoService => "No such service was found",
             Self::ServiceExists => "A sevice with this name already exists",
             Self::CommFault => "Some internal components failed to communicate",
+            Self::StorageFault => "Failed to read or write persistent storage",
         };
         write!(f, "{}", msg)
     }
@@ -97,6 +97,8 @@ impl JavaId {
     }
 
     pub(crate) fn into_identity(self) -> Identity {
-        Identity::from_string(&self.0)
+        self.0
+            .parse()
+            .expect("Received an invalid identity from the Java side")
     }
 }
@@ -26,6 +26,8 @@ pub struct MsgBuilder {
     tags: Option<TagSet>,
     /// A raw byte `Message` payload
     payload: Option<Vec<u8>>,
+    /// The signature trust level
+    sign: Option<SigTrust>,
 }
 
 impl MsgBuilder {
@@ -58,6 +60,12 @@ impl MsgBuilder {
         self
     }
 
+    /// Set the signature trust level of the resulting message.
+    pub fn with_sign(mut self, sign: SigTrust) -> Self {
+        self.sign = Some(sign);
+        self
+    }
+
     pub fn with_tags(mut self, tags: impl Into<TagSet>) -> Self {
         self.tags = Some(tags.into());
         self
@@ -81,12 +89,15 @@ impl MsgBuilder {
             .clone()
             .unwrap_or_else(|| Standard.sample_iter(rng).take(1024).collect());
         let tags = self.tags.clone().unwrap_or_default();
+        let sign = self.sign.clone().unwrap_or(SigTrust::Trusted);
         Message {
             id,
             associator,
             sender,
             tags,
             payload,
+            sign,
+            verified: false,
         }
     }
 }
@@ -6,10 +6,18 @@ pub use crate::api::messages::{IdType, Message, Mode, MsgId, MsgQuery, MsgRef, S
 mod store;
 pub(crate) use self::store::{MsgStore, TAG_UNREAD};
 
+mod onion;
+pub(crate) use self::onion::{Onion, Peeled};
+
 #[cfg(feature = "generate-message")]
 pub(crate) mod generator;
 
-use crate::{error::Result, helpers::Tag, security::Sec, users::UserStore};
+use crate::{
+    error::{Error, Result},
+    helpers::Tag,
+    security::{Sec, Signature},
+    users::UserStore,
+};
 use ratman::{
     netmod::Recipient as RatRecipient, Identity, Message as RatMessage, Router, TimePair,
 };
@@ -32,16 +40,29 @@ pub(crate) struct Envelope {
     pub(crate) tags: Vec<Tag>,
 }
 
+/// An encoded `Envelope`, and the sender's signature over it
+///
+/// The signature is part of the payload, and not the `ratman`
+/// message, so that it's encrypted along with the envelope for
+/// messages to a single user.
+#[derive(Debug, Serialize, Deserialize)]
+struct Signed {
+    env: Vec<u8>,
+    sig: Option<Signature>,
+}
+
 /// A `ratman::Message` set prototype structure
 pub(crate) struct RatMessageProto {
     /// The high level `Message` to send and validate for
     pub(crate) env: Envelope,
     /// Readdressed `Recipient` information
     pub(crate) recipient: RatRecipient,
+    /// Relays to send an anonymous message through (see `Onion`)
+    pub(crate) relays: Vec<Identity>,
 }
 
 impl RatMessageProto {
-    pub(crate) async fn build(&self, store: &UserStore) -> RatMessage {
+    pub(crate) async fn build(&self, store: &UserStore) -> Result<RatMessage> {
         let sender = self.env.sender;
         let keypair = store.get_key(sender).await;
 
@@ -49,18 +70,29 @@ impl RatMessageProto {
         // envelope contains all data that is libqaul specific and
         // can't (or shouldn't) be taken from the ratman message
         // headers.
-        let raw_payload = bincode::serialize(&self.env).unwrap();
+        let env = bincode::serialize(&self.env).unwrap();
+        let raw_payload = bincode::serialize(&Signed {
+            sig: keypair.sign(&env),
+            env,
+        })
+        .unwrap();
 
         // Encrypt the payload only if the recipient is a single user
         let payload = match self.recipient {
-            RatRecipient::User(id) => Sec::encrypt(keypair.clone(), id, &raw_payload),
+            RatRecipient::User(id) => store.crypto().encrypt(&keypair, id, &raw_payload).await?,
             RatRecipient::Flood => raw_payload,
         };
 
-        let sender = self.env.sender;
-        let recipient = self.recipient;
+        // Anonymous messages are wrapped for, and sent to the relays
+        let (recipient, payload) = match self.recipient {
+            RatRecipient::User(id) if !self.relays.is_empty() => {
+                let (first, payload) = Onion::wrap(sender, id, payload, &self.relays);
+                (RatRecipient::User(first), payload)
+            }
+            recipient => (recipient, payload),
+        };
 
-        RatMessage {
+        Ok(RatMessage {
             // Ratman generates a new message ID here to keep the real
             // message ID a secret and prevents header inspection to
             // figure out who is talking to whom.
@@ -70,13 +102,12 @@ impl RatMessageProto {
             payload,
             timesig: TimePair::sending(),
 
-            // Payloads are encrypted and signed via libnacl, which
-            // means that no payload can be forged. Ratman still takes
-            // a signature argument at the moment for other
-            // applications, and to keep the possibility to add
-            // further verifications down the line.
+            // Envelopes are signed as part of the payload (see
+            // `Signed`), so that the signature of a message to a
+            // single user is encrypted too.  Ratman still takes a
+            // signature argument for other applications.
             sign: vec![],
-        }
+        })
     }
 }
 
@@ -89,13 +120,33 @@ impl MsgUtils {
         router: &Router,
         msg: RatMessageProto,
     ) -> Result<()> {
-        Ok(router.send(msg.build(store).await).await?)
+        Ok(router.send(msg.build(store).await?).await?)
     }
 
-    pub(crate) fn extract_simple_payload(msg: &RatMessage) -> Option<Vec<u8>> {
-        let RatMessage { payload, .. } = msg;
-        let Envelope { payload, .. } = bincode::deserialize(&payload).ok()?;
-        Some(payload)
+    /// Decode a signed envelope, and check the signature against its sender
+    ///
+    /// `sender` is the sender of the `ratman` message, which the
+    /// envelope has to agree with.  Nothing but the signature ties a
+    /// `flood` message to its sender, so unsigned ones are invalid.
+    /// Direct messages were already authenticated by decrypting them.
+    pub(crate) fn verify(
+        sender: Identity,
+        payload: &[u8],
+        flood: bool,
+    ) -> Result<(Envelope, SigTrust)> {
+        let Signed { env, sig } =
+            bincode::deserialize(payload).map_err(|_| Error::InvalidPayload)?;
+        let envelope: Envelope = bincode::deserialize(&env).map_err(|_| Error::InvalidPayload)?;
+
+        let trust = match sig {
+            _ if envelope.sender != sender => SigTrust::Invalid,
+            Some(ref sig) if Sec::verify(sender, &env, sig) => SigTrust::Trusted,
+            Some(_) => SigTrust::Invalid,
+            None if flood => SigTrust::Invalid,
+            None => SigTrust::Unverified,
+        };
+
+        Ok((envelope, trust))
     }
 
     /// Process incoming RATMAN message, verifying it's signature and payload
@@ -110,26 +161,30 @@ impl MsgUtils {
         } = msg;
 
         // Decrypt only if the message was directly addressed
+        let flood = recipient == RatRecipient::Flood;
         let payload = match recipient {
             RatRecipient::User(recp) => {
                 let keypair = store.get_key(recp).await;
 
                 // Decrypting the message makes sure the inner payload
-                // structure was intact, as well as making sure the
-                // signature is unharmed.  We want to drop a message
-                // when the signature verification fails.
-                Sec::decrypt(keypair, sender, &payload)?
+                // structure was intact.  We want to drop a message
+                // when decryption fails.  The sender's signature is
+                // checked afterwards, like for flooded messages.
+                store.crypto().decrypt(&keypair, sender, &payload).await?
             }
             RatRecipient::Flood => payload,
         };
 
-        let Envelope {
-            id,
-            sender: _,
-            associator,
-            payload,
-            tags,
-        } = bincode::deserialize(&payload).unwrap();
+        let (
+            Envelope {
+                id,
+                sender: _,
+                associator,
+                payload,
+                tags,
+            },
+            sign,
+        ) = Self::verify(sender, &payload, flood)?;
 
         Ok(Message {
             id: id.into(),
@@ -137,6 +192,89 @@ impl MsgUtils {
             associator,
             tags: tags.into(),
             payload,
+            sign,
+            // Set by the caller, which knows the recipient's contacts
+            verified: false,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::Keypair;
+
+    /// Encode an envelope claiming to be from `sender`, optionally
+    /// signed with `key`
+    fn payload(sender: Identity, key: Option<&Keypair>) -> Vec<u8> {
+        let env = bincode::serialize(&Envelope {
+            id: MsgId::random(),
+            sender,
+            associator: "net.qaul.testing".into(),
+            payload: vec![1, 3, 1, 2],
+            tags: vec![],
+        })
+        .unwrap();
+
+        let sig = key.and_then(|key| key.sign(&env));
+        bincode::serialize(&Signed { env, sig }).unwrap()
+    }
+
+    #[async_std::test]
+    async fn verify_signature() {
+        let sec = Sec::new();
+        let alice = sec.generate().await;
+        let mallory = sec.generate().await;
+
+        let signed = payload(alice.id, Some(&alice.keypair));
+        let (env, trust) = MsgUtils::verify(alice.id, &signed, true).unwrap();
+        assert_eq!(env.sender, alice.id);
+        assert_eq!(trust, SigTrust::Trusted);
+
+        // Only direct messages can be unsigned
+        let unsigned = payload(alice.id, None);
+        let (_, trust) = MsgUtils::verify(alice.id, &unsigned, false).unwrap();
+        assert_eq!(trust, SigTrust::Unverified);
+        let (_, trust) = MsgUtils::verify(alice.id, &unsigned, true).unwrap();
+        assert_eq!(trust, SigTrust::Invalid);
+
+        // Signed by somebody other than the claimed sender
+        let forged = payload(alice.id, Some(&mallory.keypair));
+        let (_, trust) = MsgUtils::verify(alice.id, &forged, true).unwrap();
+        assert_eq!(trust, SigTrust::Invalid);
+
+        // Envelope and frame don't agree on the sender
+        let (_, trust) = MsgUtils::verify(mallory.id, &signed, true).unwrap();
+        assert_eq!(trust, SigTrust::Invalid);
+    }
+
+    /// Anybody can put any sender on a flooded frame, so an unsigned
+    /// envelope doesn't say anything about who sent it
+    #[async_std::test]
+    async fn unsigned_flood() {
+        use alexandria::Builder;
+
+        let store = UserStore::new(Builder::new().build().unwrap());
+        let sec = Sec::new();
+        let alice = sec.generate().await;
+        let mallory = sec.generate().await;
+
+        let flood = |sender| RatMessage {
+            id: MsgId::random(),
+            sender,
+            recipient: RatRecipient::Flood,
+            payload: payload(alice.id, None),
+            timesig: TimePair::sending(),
+            sign: vec![],
+        };
+
+        // Mallory claims to be Alice, on the frame and in the envelope
+        let msg = MsgUtils::process(flood(alice.id), &store).await.unwrap();
+        assert_eq!(msg.sender, alice.id);
+        assert_eq!(msg.sign, SigTrust::Invalid);
+
+        // Or only in the envelope
+        let msg = MsgUtils::process(flood(mallory.id), &store).await.unwrap();
+        assert_eq!(msg.sign, SigTrust::Invalid);
+    }
+}
@@ -0,0 +1,182 @@
+//! Onion routing for anonymous unicast messages
+//!
+//! Every `Frame` carries the sender and recipient of a message in
+//! clear text, which means that all nodes on a route learn who is
+//! talking to whom.  An anonymous message is instead sent through a
+//! path of relays (other libqaul users), and wrapped in one layer of
+//! encryption per hop.  Each relay can only remove its own layer,
+//! which reveals the next hop, but not the sender or the recipient.
+//!
+//! The innermost layer contains the real sender and the payload,
+//! encrypted end-to-end the same way as any other unicast message,
+//! so that the recipient can still verify who sent it.
+
+use crate::{
+    error::{Error, Result},
+    qaul::Identity,
+    security::{Keypair, Sec},
+    users::UserStore,
+};
+use rand::seq::SliceRandom;
+use ratman::{netmod::Recipient, Message as RatMessage, TimePair};
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of relays an anonymous message is sent through
+pub(crate) const HOPS: usize = 3;
+
+/// A single layer of an onion, sealed to the node that removes it
+#[derive(Debug, Serialize, Deserialize)]
+enum Layer {
+    /// Forward the remaining layers to the next hop
+    Relay { next: Identity, data: Vec<u8> },
+    /// The last layer, containing the encrypted message payload
+    Deliver { sender: Identity, data: Vec<u8> },
+}
+
+/// The result of removing a layer from an incoming message
+pub(crate) enum Peeled {
+    /// A message that needs to be forwarded to another relay
+    Relay(RatMessage),
+    /// A message that is addressed to a local user
+    Message(RatMessage),
+}
+
+pub(crate) struct Onion;
+
+impl Onion {
+    /// Choose a random relay path for an anonymous message
+    ///
+    /// Neither the sender nor the recipient can be a relay.  If there
+    /// are fewer than `HOPS` candidates, all of them are used, but at
+    /// least one is required.
+    pub(crate) fn path<I>(known: I, sender: Identity, recipient: Identity) -> Result<Vec<Identity>>
+    where
+        I: IntoIterator<Item = Identity>,
+    {
+        let mut relays: Vec<_> = known
+            .into_iter()
+            .filter(|id| *id != sender && *id != recipient)
+            .collect();
+
+        if relays.is_empty() {
+            return Err(Error::NoRoute);
+        }
+
+        relays.shuffle(&mut rand::thread_rng());
+        relays.truncate(HOPS);
+        Ok(relays)
+    }
+
+    /// Wrap an encrypted payload into one layer per relay
+    ///
+    /// Returns the first relay, which the resulting data needs to be
+    /// sent to.
+    pub(crate) fn wrap(
+        sender: Identity,
+        recipient: Identity,
+        payload: Vec<u8>,
+        relays: &[Identity],
+    ) -> (Identity, Vec<u8>) {
+        let deliver = Layer::Deliver {
+            sender,
+            data: payload,
+        };
+
+        relays.iter().rev().fold(
+            (recipient, Self::seal(recipient, &deliver)),
+            |(next, data), relay| (*relay, Self::seal(*relay, &Layer::Relay { next, data })),
+        )
+    }
+
+    /// Remove one layer from an incoming message
+    ///
+    /// Messages that aren't part of an onion are returned unchanged.
+    /// When the last layer is removed, the message is rewritten to
+    /// look like it came from the original sender.
+    pub(crate) async fn peel(msg: RatMessage, store: &UserStore) -> Peeled {
+        let local = match msg.recipient {
+            Recipient::User(id) => id,
+            Recipient::Flood => return Peeled::Message(msg),
+        };
+
+        let keypair = store.get_key(local).await;
+        match Self::open(&keypair, &msg.payload) {
+            Some(Layer::Relay { next, data }) => Peeled::Relay(RatMessage {
+                id: Identity::random(),
+                sender: local,
+                recipient: Recipient::User(next),
+                payload: data,
+                timesig: TimePair::sending(),
+                sign: vec![],
+            }),
+            Some(Layer::Deliver { sender, data }) => Peeled::Message(RatMessage {
+                sender,
+                payload: data,
+                ..msg
+            }),
+            None => Peeled::Message(msg),
+        }
+    }
+
+    fn seal(to: Identity, layer: &Layer) -> Vec<u8> {
+        Sec::seal_anonymous(to, &bincode::serialize(layer).unwrap())
+    }
+
+    fn open(keypair: &Keypair, data: &[u8]) -> Option<Layer> {
+        Sec::open_anonymous(keypair, data)
+            .ok()
+            .and_then(|vec| bincode::deserialize(&vec).ok())
+    }
+}
+
+#[async_std::test]
+async fn peel_layers() {
+    let sec = Sec::new();
+    let sender = sec.generate().await;
+    let recipient = sec.generate().await;
+    let r1 = sec.generate().await;
+    let r2 = sec.generate().await;
+
+    let payload = Sec::encrypt(sender.keypair, recipient.id, &b"ACAB".to_vec());
+    let (first, data) = Onion::wrap(sender.id, recipient.id, payload, &[r1.id, r2.id]);
+    assert_eq!(first, r1.id);
+
+    // Relays can't remove each other's layers
+    assert!(Onion::open(&r2.keypair, &data).is_none());
+
+    let data = match Onion::open(&r1.keypair, &data) {
+        Some(Layer::Relay { next, data }) if next == r2.id => data,
+        l => panic!("unexpected layer: {:?}", l),
+    };
+    let data = match Onion::open(&r2.keypair, &data) {
+        Some(Layer::Relay { next, data }) if next == recipient.id => data,
+        l => panic!("unexpected layer: {:?}", l),
+    };
+    match Onion::open(&recipient.keypair, &data) {
+        Some(Layer::Deliver { sender: s, data }) => {
+            assert_eq!(s, sender.id);
+            let plain = Sec::decrypt(recipient.keypair, s, &data).unwrap();
+            assert_eq!(plain, b"ACAB");
+        }
+        l => panic!("unexpected layer: {:?}", l),
+    }
+}
+
+#[test]
+fn relay_path() {
+    let (sender, recipient) = (Identity::random(), Identity::random());
+    assert_eq!(
+        Onion::path(vec![sender, recipient], sender, recipient),
+        Err(Error::NoRoute)
+    );
+
+    let known: Vec<_> = (0..10).map(|_| Identity::random()).collect();
+    let path = Onion::path(
+        known.iter().cloned().chain(vec![sender, recipient]),
+        sender,
+        recipient,
+    )
+    .unwrap();
+    assert_eq!(path.len(), HOPS);
+    assert!(path.iter().all(|id| known.contains(id)));
+}
@@ -2,7 +2,7 @@
 
 use crate::{
     helpers::{QueryResult, Subscription, Tagged},
-    messages::{Message, Mode, MsgQuery, MsgRef},
+    messages::{Message, Mode, MsgQuery, MsgRef, SigTrust},
     services::Service,
     Identity,
 };
@@ -12,7 +12,8 @@ use alexandria::{
     Library, Session, GLOBAL,
 };
 use async_std::sync::Arc;
-use tracing::trace;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{trace, warn};
 
 pub(crate) const TAG_FLOOD: &'static str = "libqaul._int.flood";
 pub(crate) const TAG_UNREAD: &'static str = "libqaul._int.unread";
@@ -34,11 +35,20 @@ fn service_tag(s: String) -> Tag {
 #[derive(Clone)]
 pub(crate) struct MsgStore {
     inner: Arc<Library>,
+    drop_invalid: Arc<AtomicBool>,
 }
 
 impl MsgStore {
     pub(crate) fn new(inner: Arc<Library>) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            drop_invalid: Default::default(),
+        }
+    }
+
+    /// Set whether remote messages with invalid signatures are dropped
+    pub(crate) fn set_drop_invalid(&self, drop: bool) {
+        self.drop_invalid.store(drop, Ordering::Relaxed);
     }
 
     /// Insert a message that was sent locally
@@ -58,7 +68,7 @@ impl MsgStore {
                 tags.insert(Tag::empty(TAG_FLOOD));
                 GLOBAL
             }
-            Mode::Std(_) => Session::Id(user),
+            Mode::Std(_) | Mode::Anonymous(_) => Session::Id(user),
         };
 
         self.inner
@@ -74,6 +84,11 @@ impl MsgStore {
     /// retrieved via the "unread messages" query.
     #[tracing::instrument(skip(self, msg), level = "trace")]
     pub(crate) async fn insert_remote(&self, recipient: Option<Identity>, msg: MsgRef) {
+        if msg.sign == SigTrust::Invalid && self.drop_invalid.load(Ordering::Relaxed) {
+            warn!("Dropping message `{}` with an invalid signature", msg.id);
+            return;
+        }
+
         let mut tags = msg.tags.clone().merge(Tag::empty(TAG_UNREAD));
         tags.insert(sender_tag(msg.sender));
         tags.insert(service_tag(msg.associator.clone()));
@@ -165,6 +180,43 @@ impl MsgStore {
         QueryResult::new(glb)
     }
 
+    /// Get all messages that are stored in a user's session
+    ///
+    /// Flooded messages live in the global store, and aren't included.
+    pub(crate) async fn all_local(&self, user: Identity) -> Vec<Message> {
+        match self
+            .inner
+            .query(Session::Id(user), Query::tags().subset(Message::tag()))
+            .await
+        {
+            Ok(AQResult::Single(rec)) => vec![rec.into()],
+            Ok(AQResult::Many(vec)) => vec.into_iter().map(|rec| rec.into()).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Insert messages from a backup into a user's session
+    ///
+    /// Messages keep the tags they were stored with, and ones that
+    /// exist already are skipped.
+    pub(crate) async fn restore(&self, user: Identity, msgs: Vec<Message>) {
+        for msg in msgs {
+            if self.probe_id(user, msg.id).await {
+                continue;
+            }
+
+            self.inner
+                .batch(
+                    Session::Id(user),
+                    msg_path(msg.id),
+                    msg.tags.clone(),
+                    msg.diff(),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
     pub(crate) async fn subscribe(
         &self,
         user: Identity,
@@ -205,13 +257,14 @@ mod harness {
         Builder,
     };
     use async_std::sync::Arc;
-    use tempfile::tempdir;
+    use tempfile::{tempdir, TempDir};
 
     pub(super) use crate::error::Result;
 
     pub(super) struct Test {
         pub(super) store: MsgStore,
         usr: UserStore,
+        _dir: TempDir,
     }
 
     pub(super) fn init() -> Test {
@@ -224,6 +277,7 @@ mod harness {
         Test {
             usr: UserStore::new(Arc::clone(&lib)),
             store: MsgStore::new(lib),
+            _dir: dir,
         }
     }
 
@@ -318,3 +372,35 @@ async fn simple_received() -> harness::Result<()> {
     assert_eq!(result.take(1).await?.len(), 1);
     Ok(())
 }
+
+#[async_std::test]
+async fn drop_invalid() -> harness::Result<()> {
+    use crate::messages::generator::MsgBuilder;
+
+    let state = harness::init();
+    let id = Identity::random();
+    harness::make_user(&state, id).await;
+
+    let forged = || {
+        let msg = MsgBuilder::new()
+            .with_sender(id)
+            .with_sign(SigTrust::Invalid)
+            .generate();
+        Arc::new(msg)
+    };
+
+    // Invalid messages are kept and marked by default
+    state.store.insert_remote(Some(id), forged()).await;
+    state.store.set_drop_invalid(true);
+    state.store.insert_remote(Some(id), forged()).await;
+
+    let msgs = state
+        .store
+        .query(id, Service::God, MsgQuery::new().sender(id))
+        .await
+        .all()
+        .await?;
+    assert_eq!(msgs.len(), 1);
+    assert_eq!(msgs[0].sign, SigTrust::Invalid);
+    Ok(())
+}
@@ -6,6 +6,7 @@ use crate::{
     auth::AuthStore,
     contacts::ContactStore,
     discover::Discovery,
+    error::{Error, Result},
     messages::MsgStore,
     security::Sec,
     services::ServiceRegistry,
@@ -14,7 +15,7 @@ use crate::{
 
 use alexandria::{Builder, Library};
 use ratman::Router;
-use std::{path::Path, sync::Arc};
+use std::{fs, path::Path, sync::Arc};
 use tracing::{error, info};
 
 /// An atomic reference counted pointer to a running libqaul instance
@@ -41,7 +42,8 @@ pub type QaulRef = Arc<Qaul>;
 /// A bootstrapping procedure should thus look as follows:
 ///
 /// 1. RATMAN + netmod initialisation
-/// 2. `libqaul` startup (this struct, call `new(...)`)
+/// 2. `libqaul` startup (this struct, call `new(...)`, or `open(...)`
+///    to keep state on disk)
 /// 3. Initialise services with a `libqaul` instance reference
 /// 4. Your application is now ready for use
 #[derive(Clone)]
@@ -91,7 +93,7 @@ impl Qaul {
             users: UserStore::new(Arc::clone(&store)),
             announcer: Announcer::new(),
             auth: AuthStore::new(),
-            contacts: ContactStore::new(),
+            contacts: ContactStore::new(Arc::clone(&store)),
             messages: MsgStore::new(Arc::clone(&store)),
             services: ServiceRegistry::new(Arc::clone(&store)),
             sec: Arc::new(Sec::new()),
@@ -114,33 +116,73 @@ impl Qaul {
     /// that the main thread will take over execution of some other
     /// application loop so to enable further API abstractions to hook
     /// into the service API.
+    ///
+    /// All state is kept in memory, and lost when the instance
+    /// stops.  Use `open` to keep it on disk instead.
     #[tracing::instrument(skip(router), level = "info")]
     pub fn new(router: Arc<Router>) -> QaulRef {
-        // let store = Builder::inspect_path(store_path.into(), "").map_or_else(
-        //     |b| match b.build() {
-        //         Ok(s) => {
-        //             info!("Creating new backing store");
-        //             s
-        //         },
-        //         Err(e) => {
-        //             error!("Failed to create backing store: {}", e.to_string());
-        //             std::process::exit(2);
-        //         }
-        //     },
-        //     |s| {
-        //         info!("Loading existing store from disk");
-        //         s
-        //     },
-        // );
-
         let store = Builder::new().build().unwrap();
+        let contacts = ContactStore::new(Arc::clone(&store));
+        Self::start(router, store, AuthStore::new(), contacts)
+    }
 
+    /// Create a new qaul context that keeps its state in a directory
+    ///
+    /// Users, keys, messages and contact books are stored in `path`,
+    /// and loaded again the next time an instance is opened with the
+    /// same path.  Login tokens are not stored, which means that
+    /// users need to log in again after a restart, before they can
+    /// send and receive messages.
+    ///
+    /// Data that doesn't belong to a single user is encrypted with
+    /// `secret`, which can't be empty, and needs to be the same every
+    /// time the directory is opened.  Otherwise `Error::NotAuthorised`
+    /// is returned.
+    ///
+    /// Otherwise this works the same way as `new`.
+    #[tracing::instrument(skip(router, path, secret), level = "info")]
+    pub fn open<P: AsRef<Path>>(router: Arc<Router>, path: P, secret: &str) -> Result<QaulRef> {
+        if secret.is_empty() {
+            return Err(Error::NotAuthorised);
+        }
+
+        let path = path.as_ref();
+        fs::create_dir_all(path).map_err(|_| Error::StorageFault)?;
+
+        let lib = path.join("library");
+        let store = match Library::load(lib.as_path(), secret) {
+            Ok(store) => {
+                info!("Loading existing store from disk");
+                Arc::new(store)
+            }
+            Err(alexandria::error::Error::UnlockFailed { .. }) => return Err(Error::NotAuthorised),
+            Err(_) => Builder::new()
+                .offset(lib.as_path())
+                .root_sec(secret)
+                .build()
+                .map_err(|e| {
+                    error!("Failed to create backing store: {}", e.to_string());
+                    Error::StorageFault
+                })?,
+        };
+
+        let auth = AuthStore::open(path.join("auth"))?;
+        let contacts = ContactStore::open(Arc::clone(&store), path.join("contacts"))?;
+        Ok(Self::start(router, store, auth, contacts))
+    }
+
+    fn start(
+        router: Arc<Router>,
+        store: Arc<Library>,
+        auth: AuthStore,
+        contacts: ContactStore,
+    ) -> QaulRef {
         let q = Arc::new(Self {
             router: Arc::clone(&router),
             users: UserStore::new(Arc::clone(&store)),
             announcer: Announcer::new(),
-            auth: AuthStore::new(),
-            contacts: ContactStore::new(),
+            auth,
+            contacts,
             messages: MsgStore::new(Arc::clone(&store)),
             services: ServiceRegistry::new(Arc::clone(&store)),
             sec: Arc::new(Sec::new()),
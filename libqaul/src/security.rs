@@ -2,14 +2,28 @@
 //!
 //! This code is responsible for creating identities (keys, and
 //! `Identity`), verifying signatures, and encrypting messages
+//!
+//! A user's encryption keys are derived from an Ed25519 signing key.
+//! Because an `Identity` is the encryption public key, anybody can
+//! check that a signature was made by the owner of an `Identity`,
+//! without having to look up any other keys first.
 
 use crate::{
     error::{Error, Result},
     Identity,
 };
 use bincode;
+use libsodium_sys as ffi;
 use serde::{Deserialize, Serialize};
-use sodiumoxide::crypto::box_::{self, Nonce, PublicKey, SecretKey};
+use sodiumoxide::crypto::{
+    box_::{self, Nonce, PublicKey, SecretKey},
+    sealedbox,
+    sign::ed25519 as sign,
+};
+
+// User identities are curve25519 public keys, so libqaul can't be
+// built with one of the smaller `ratman-identity` ID sizes.
+const _: [(); box_::PUBLICKEYBYTES] = [(); ratman::ID_LEN];
 
 /// A near-stateless security handler
 pub(crate) struct Sec {}
@@ -18,6 +32,35 @@ pub(crate) struct Sec {}
 pub(crate) struct Keypair {
     secret: SecretKey,
     public: PublicKey,
+    /// The signing key that the encryption keys were derived from
+    ///
+    /// Users that were created before messages were signed don't
+    /// have one.
+    sign: Option<sign::SecretKey>,
+}
+
+/// The layout of a `Keypair` before users had signing keys
+#[derive(Deserialize)]
+pub(crate) struct LegacyKeypair {
+    secret: SecretKey,
+    public: PublicKey,
+}
+
+impl From<LegacyKeypair> for Keypair {
+    fn from(LegacyKeypair { secret, public }: LegacyKeypair) -> Self {
+        Self {
+            secret,
+            public,
+            sign: None,
+        }
+    }
+}
+
+/// A signature, and the key that made it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Signature {
+    key: sign::PublicKey,
+    sig: sign::Signature,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,6 +70,36 @@ struct CryptoText {
 }
 
 impl Keypair {
+    /// Decode a stored keypair, in either the current or legacy layout
+    pub(crate) fn decode(data: &[u8]) -> Result<Self> {
+        bincode::deserialize(data)
+            .or_else(|_| bincode::deserialize::<LegacyKeypair>(data).map(Into::into))
+            .map_err(|_| Error::InvalidPayload)
+    }
+
+    /// The `Identity` that belongs to this keypair
+    pub(crate) fn id(&self) -> Identity {
+        Identity::from_bytes(self.public.as_ref())
+    }
+
+    /// Check if this keypair has a signing key
+    pub(crate) fn can_sign(&self) -> bool {
+        self.sign.is_some()
+    }
+
+    /// Sign a piece of data, if this keypair is able to
+    pub(crate) fn sign(&self, data: &[u8]) -> Option<Signature> {
+        self.sign.as_ref().map(|sk| Signature {
+            key: sk.public_key(),
+            sig: sign::sign_detached(data, sk),
+        })
+    }
+
+    /// The secret half, for key agreement
+    pub(crate) fn secret(&self) -> &SecretKey {
+        &self.secret
+    }
+
     fn swap_pub(&mut self, id: Identity) {
         self.public = PublicKey::from_slice(id.as_ref()).unwrap();
     }
@@ -47,6 +120,23 @@ impl Keypair {
     }
 }
 
+/// Convert an Ed25519 public key to the matching curve25519 key
+fn curve_pk(key: &sign::PublicKey) -> Option<PublicKey> {
+    let mut pk = [0; box_::PUBLICKEYBYTES];
+    match unsafe { ffi::crypto_sign_ed25519_pk_to_curve25519(pk.as_mut_ptr(), key.0.as_ptr()) } {
+        0 => Some(PublicKey(pk)),
+        _ => None,
+    }
+}
+
+/// Convert an Ed25519 secret key to the matching curve25519 key
+fn curve_sk(key: &sign::SecretKey) -> SecretKey {
+    let mut sk = [0; box_::SECRETKEYBYTES];
+    // This can't fail for a secret key
+    unsafe { ffi::crypto_sign_ed25519_sk_to_curve25519(sk.as_mut_ptr(), key.0.as_ptr()) };
+    SecretKey(sk)
+}
+
 /// A keypair, and Identity
 pub(crate) struct KeyId {
     pub keypair: Keypair,
@@ -70,14 +160,26 @@ impl Sec {
 
     /// Generate an Id and keypair for a new user
     pub(crate) async fn generate(&self) -> KeyId {
-        let (public, secret) = box_::gen_keypair();
+        let (sign_pk, sign_sk) = sign::gen_keypair();
+        let public = curve_pk(&sign_pk).unwrap();
+        let secret = curve_sk(&sign_sk);
         let id = Identity::from_bytes(public.as_ref());
         KeyId {
-            keypair: Keypair { public, secret },
+            keypair: Keypair {
+                public,
+                secret,
+                sign: Some(sign_sk),
+            },
             id,
         }
     }
 
+    /// Check that a signature was made by `sender` over `data`
+    pub(crate) fn verify(sender: Identity, data: &[u8], sig: &Signature) -> bool {
+        sign::verify_detached(&sig.sig, data, &sig.key)
+            && curve_pk(&sig.key).map_or(false, |pk| pk.as_ref() == sender.as_ref())
+    }
+
     /// Decrypt a payload from a friend
     pub(crate) fn decrypt(mut pair: Keypair, friend: Identity, enc: &Vec<u8>) -> Result<Vec<u8>> {
         pair.swap_pub(friend);
@@ -89,10 +191,25 @@ impl Sec {
         pair.swap_pub(friend);
         pair.seal(data)
     }
+
+    /// Encrypt a payload without revealing who sent it
+    ///
+    /// Unlike `encrypt`, the recipient can't verify who sealed the
+    /// payload, so it's only useful to wrap data that is
+    /// authenticated in some other way.
+    pub(crate) fn seal_anonymous(to: Identity, data: &[u8]) -> Vec<u8> {
+        let public = PublicKey::from_slice(to.as_ref()).unwrap();
+        sealedbox::seal(data, &public)
+    }
+
+    /// Decrypt a payload created via `seal_anonymous`
+    pub(crate) fn open_anonymous(pair: &Keypair, data: &[u8]) -> Result<Vec<u8>> {
+        sealedbox::open(data, &pair.public, &pair.secret).map_err(|_| Error::InvalidPayload)
+    }
 }
 
 #[async_std::test]
-async fn single_ratchet() {
+async fn static_box() {
     let sec = Sec::new();
     let a = sec.generate().await;
     let b = sec.generate().await;
@@ -102,3 +219,42 @@ async fn single_ratchet() {
     let acab = Sec::decrypt(b.keypair, a.id, &encrypted).unwrap();
     assert!(acab == plaintext);
 }
+
+#[async_std::test]
+async fn anonymous_seal() {
+    let sec = Sec::new();
+    let a = sec.generate().await;
+    let b = sec.generate().await;
+
+    let sealed = Sec::seal_anonymous(a.id, b"ACAB");
+    assert_eq!(Sec::open_anonymous(&a.keypair, &sealed).unwrap(), b"ACAB");
+    assert!(Sec::open_anonymous(&b.keypair, &sealed).is_err());
+}
+
+#[async_std::test]
+async fn signatures() {
+    let sec = Sec::new();
+    let a = sec.generate().await;
+    let b = sec.generate().await;
+
+    let sig = a.keypair.sign(b"ACAB").unwrap();
+    assert!(Sec::verify(a.id, b"ACAB", &sig));
+    assert!(!Sec::verify(a.id, b"ACAC", &sig));
+
+    // A valid signature by somebody else
+    assert!(!Sec::verify(b.id, b"ACAB", &sig));
+}
+
+#[async_std::test]
+async fn legacy_keypair() {
+    let (public, secret) = box_::gen_keypair();
+    let old = bincode::serialize(&(secret, public)).unwrap();
+
+    let pair = Keypair::decode(&old).unwrap();
+    assert_eq!(pair.id(), Identity::from_bytes(public.as_ref()));
+    assert!(!pair.can_sign());
+
+    let new = Sec::new().generate().await.keypair;
+    let decoded = Keypair::decode(&bincode::serialize(&new).unwrap()).unwrap();
+    assert!(decoded.can_sign());
+}
@@ -4,6 +4,7 @@ use crate::{
     error::{Error, Result},
     messages::MsgRef,
     users::UserAuth,
+    Identity,
 };
 use alexandria::Library;
 use async_std::sync::{Arc, RwLock};
@@ -44,6 +45,17 @@ where
 pub enum ServiceEvent {
     Open(UserAuth),
     Close(UserAuth),
+    /// A verified contact of `user` seems to have re-created their
+    /// account, and now uses the identity `new` instead of `old`
+    ///
+    /// Identities are keys, so this can't be known for sure: `new`
+    /// has the same display name as `old`.  Users should compare
+    /// safety numbers before trusting the new identity.
+    KeyChanged {
+        user: Identity,
+        old: Identity,
+        new: Identity,
+    },
 }
 
 pub(crate) type Listener = Arc<dyn Fn(ServiceEvent) + Send + Sync>;
@@ -88,6 +100,15 @@ impl ServiceRegistry {
             .for_each(|(_, fun)| fun(ServiceEvent::Close(auth.clone())));
     }
 
+    /// Send an event to all services that a contact's key changed
+    pub(crate) async fn key_changed(&self, user: Identity, old: Identity, new: Identity) {
+        self.notify
+            .read()
+            .await
+            .iter()
+            .for_each(|(_, fun)| fun(ServiceEvent::KeyChanged { user, old, new }));
+    }
+
     pub(crate) async fn register<F: 'static>(&self, name: String, listen: F) -> Result<()>
     where
         F: Fn(ServiceEvent) + Send + Sync,
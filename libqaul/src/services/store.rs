@@ -1,4 +1,4 @@
-use crate::{error::Result, Identity};
+use crate::{error::Result, users::ServiceData, Identity};
 use alexandria::{
     query::{Query, QueryResult},
     utils::{Path, Tag, TagSet},
@@ -109,8 +109,10 @@ fn gen_path(serv: &String, name: &String) -> Path {
     Path::from(format!("/service/{}:{}", serv, name))
 }
 
+const TAG_SERVICE: &str = "libqaul._int.service";
+
 fn tag_service(serv: &String) -> Tag {
-    Tag::new("libqaul._int.service", serv.as_bytes().to_vec())
+    Tag::new(TAG_SERVICE, serv.as_bytes().to_vec())
 }
 
 const TAG_METADATA: &'static str = "libqaul._int.metadata";
@@ -196,4 +198,36 @@ impl MetadataStore {
             Err(_) => vec![],
         }
     }
+
+    /// Get all metadata maps a user has stored, for all services
+    pub(crate) async fn all(&self, user: Identity) -> Vec<ServiceData> {
+        let sess = Session::Id(user);
+        let recs = match self
+            .inner
+            .query(sess, Query::tags().subset(Tag::empty(TAG_METADATA)))
+            .await
+        {
+            Ok(QueryResult::Single(rec)) => vec![rec],
+            Ok(QueryResult::Many(vec)) => vec,
+            Err(_) => vec![],
+        };
+
+        recs.into_iter()
+            .filter_map(|rec| {
+                let tags = rec.header.tags.clone();
+                let service = tags
+                    .iter()
+                    .find(|t| t.key == TAG_SERVICE)
+                    .map(|t| String::from_utf8_lossy(&t.val).into_owned())?;
+                let map: MetadataMap = rec.into();
+
+                Some(ServiceData {
+                    service,
+                    name: map.name().clone(),
+                    map: map.map,
+                    tags,
+                })
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,96 @@
+//! Contact book database wrappers
+
+use super::Conv;
+use crate::{contacts::ContactEntry, Identity};
+use alexandria::{record::Record, utils::Diff};
+
+const UID: &'static str = "id";
+const NICK: &'static str = "nick";
+const TRUST: &'static str = "trust";
+const MET: &'static str = "met";
+const LOC: &'static str = "location";
+const NOTES: &'static str = "notes";
+const VERIFIED: &'static str = "verified";
+
+/// A contact entry, along with the contact it's about
+pub(crate) struct ContactWrap(pub(crate) Identity, pub(crate) ContactEntry);
+
+impl From<&Record> for ContactWrap {
+    fn from(rec: &Record) -> Self {
+        let kv = rec.kv();
+
+        ContactWrap(
+            Conv::id(kv.get(UID).unwrap()),
+            ContactEntry {
+                nick: kv.get(NICK).map(Conv::string),
+                trust: Conv::i8(kv.get(TRUST).unwrap()),
+                met: Conv::bool(kv.get(MET).unwrap()),
+                location: kv.get(LOC).map(Conv::string),
+                notes: kv.get(NOTES).map(Conv::string),
+                // Only stored for verified contacts
+                verified: kv.get(VERIFIED).is_some(),
+            },
+        )
+    }
+}
+
+/// Generate the diff for a single optional text field
+fn opt_diff(key: &str, prev: &Option<String>, new: &Option<String>) -> Option<Diff> {
+    match (prev, new) {
+        (Some(p), Some(n)) if p != n => Some(Diff::map().update(key, n.as_str())),
+        (None, Some(n)) => Some(Diff::map().insert(key, n.as_str())),
+        (Some(_), None) => Some(Diff::map().delete(key)),
+        _ => None,
+    }
+}
+
+impl ContactWrap {
+    /// Generate the first insert diff based on an empty record
+    pub(crate) fn init_diff(&self) -> Vec<Diff> {
+        let ContactWrap(id, ref entry) = *self;
+        let mut v = vec![
+            Diff::map().insert(UID, id.as_bytes().to_vec()),
+            Diff::map().insert(TRUST, entry.trust),
+            Diff::map().insert(MET, entry.met),
+        ];
+
+        if let Some(ref nick) = entry.nick {
+            v.push(Diff::map().insert(NICK, nick.as_str()));
+        }
+        if let Some(ref loc) = entry.location {
+            v.push(Diff::map().insert(LOC, loc.as_str()));
+        }
+        if let Some(ref notes) = entry.notes {
+            v.push(Diff::map().insert(NOTES, notes.as_str()));
+        }
+        if entry.verified {
+            v.push(Diff::map().insert(VERIFIED, true));
+        }
+
+        v
+    }
+
+    /// Generate a diffset based on the previous version of the entry
+    pub(crate) fn gen_diffset(&self, prev: &ContactEntry) -> Vec<Diff> {
+        let entry = &self.1;
+        let mut v = vec![];
+
+        if entry.trust != prev.trust {
+            v.push(Diff::map().update(TRUST, entry.trust));
+        }
+        if entry.met != prev.met {
+            v.push(Diff::map().update(MET, entry.met));
+        }
+
+        v.extend(opt_diff(NICK, &prev.nick, &entry.nick));
+        v.extend(opt_diff(LOC, &prev.location, &entry.location));
+        v.extend(opt_diff(NOTES, &prev.notes, &entry.notes));
+
+        match (prev.verified, entry.verified) {
+            (false, true) => v.push(Diff::map().insert(VERIFIED, true)),
+            (true, false) => v.push(Diff::map().delete(VERIFIED)),
+            _ => {}
+        }
+        v
+    }
+}
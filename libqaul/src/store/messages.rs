@@ -1,13 +1,14 @@
 //! Handling message interaction with Alexandria
 
 use super::Conv;
-use crate::{messages::Message, helpers::Tagged};
+use crate::{messages::{Message, SigTrust}, helpers::Tagged};
 use alexandria::{record::RecordRef, utils::{Diff, Tag}};
 
 const MID: &'static str = "id";
 const SENDER: &'static str = "sender";
 const ASSOC: &'static str = "associate";
 const SIGN: &'static str = "sign";
+const VERIFIED: &'static str = "verified";
 const PLOAD: &'static str = "payload";
 
 impl From<RecordRef> for Message {
@@ -20,6 +21,12 @@ impl From<RecordRef> for Message {
             associator: Conv::string(kv.get(ASSOC).unwrap()),
             tags: rec.header.tags.clone(),
             payload: Conv::binvec(kv.get(PLOAD).unwrap()),
+            // Messages stored before they were signed don't have this
+            sign: kv
+                .get(SIGN)
+                .map(Conv::sig_trust)
+                .unwrap_or(SigTrust::Unverified),
+            verified: kv.get(VERIFIED).map(Conv::bool).unwrap_or(false),
         }
     }
 }
@@ -32,6 +39,15 @@ impl Message {
             Diff::map().insert(SENDER, self.sender.as_bytes().to_vec()),
             Diff::map().insert(ASSOC, self.associator.as_str()),
             Diff::map().insert(PLOAD, self.payload.clone()),
+            Diff::map().insert(
+                SIGN,
+                match self.sign {
+                    SigTrust::Trusted => "trusted",
+                    SigTrust::Unverified => "unverified",
+                    SigTrust::Invalid => "invalid",
+                },
+            ),
+            Diff::map().insert(VERIFIED, self.verified),
         ]
     }
 }
@@ -6,6 +6,9 @@
 //! type yields in a diff that the storage system can then apply, and
 //! reading a data type from a record.
 
+mod contacts;
+pub(crate) use contacts::ContactWrap;
+
 mod messages;
 mod services;
 
@@ -72,6 +75,27 @@ impl Conv {
         }
     }
 
+    pub(self) fn i8(v: &Value) -> i8 {
+        match v {
+            Value::I8(i) => *i,
+            v => panic!("Invalid conversion: {:?} -> i8", v),
+        }
+    }
+
+    pub(self) fn u64(v: &Value) -> u64 {
+        match v {
+            Value::U64(u) => *u,
+            v => panic!("Invalid conversion: {:?} -> u64", v),
+        }
+    }
+
+    pub(self) fn bool(v: &Value) -> bool {
+        match v {
+            Value::Bool(b) => *b,
+            v => panic!("Invalid conversion: {:?} -> bool", v),
+        }
+    }
+
     pub(self) fn binvec(v: &Value) -> Vec<u8> {
         match v {
             Value::Vec(v) => v.clone(),
@@ -14,6 +14,7 @@ use std::collections::BTreeMap;
 
 const KPAIR: &'static str = "keypair";
 const UID: &'static str = "id";
+const VERSION: &'static str = "version";
 const D_NAME: &'static str = "display_name";
 const R_NAME: &'static str = "real_name";
 const BIO: &'static str = "bio";
@@ -32,7 +33,7 @@ impl KeyWrap {
 impl From<&Record> for KeyWrap {
     fn from(rec: &Record) -> Self {
         KeyWrap(
-            bincode::deserialize(
+            Keypair::decode(
                 rec.kv()
                     .get(KPAIR)
                     .map(|v| match v {
@@ -53,6 +54,8 @@ impl From<&Record> for UserProfile {
 
         Self {
             id: Conv::id(kv.get(UID).unwrap()),
+            // Not stored before the first change
+            version: kv.get(VERSION).map(Conv::u64).unwrap_or(0),
             display_name: kv.get(D_NAME).map(|v| Conv::string(v)),
             real_name: kv.get(R_NAME).map(|v| Conv::string(v)),
             bio: kv
@@ -73,6 +76,10 @@ impl UserProfile {
     pub(crate) fn init_diff(&self) -> Vec<Diff> {
         let mut v = vec![Diff::map().insert(UID, self.id.as_bytes().to_vec())];
 
+        if self.version > 0 {
+            v.push(Diff::map().insert(VERSION, self.version));
+        }
+
         if let Some(ref d_name) = self.display_name {
             v.push(Diff::map().insert(D_NAME, d_name.clone()));
         }
@@ -107,40 +114,75 @@ impl UserProfile {
     }
 
     /// Diff based on how a `UserUpdate` applies to a `UserProfile`
-    pub(crate) fn gen_diff(&self, update: UserUpdate) -> Diff {
+    ///
+    /// Returns `None` if the update doesn't change anything.
+    pub(crate) fn gen_diff(&self, update: UserUpdate) -> Option<Diff> {
         use UserUpdate::*;
 
-        match update {
+        fn services(set: impl Iterator<Item = String>) -> Vec<Value> {
+            set.map(Into::into).collect()
+        }
+
+        Some(match update {
             // Update data if it was previously set
             DisplayName(Some(name)) if self.display_name.is_some() => {
                 Diff::map().update(D_NAME, name)
             }
             RealName(Some(name)) if self.real_name.is_some() => Diff::map().update(R_NAME, name),
             SetBioLine(key, val) if self.bio.contains_key(&key) => {
-                Diff::map().nested(D_NAME, Diff::map().update(key, val))
+                Diff::map().nested(BIO, Diff::map().update(key, val))
             }
-            RemoveBioLine(key) if self.display_name.is_some() => {
-                Diff::map().nested(D_NAME, Diff::map().delete(key))
-            }
-            AddService(service) if self.services.contains(&service) => unimplemented!(),
-            RemoveService(service) if self.services.contains(&service) => unimplemented!(),
+            AvatarData(Some(data)) if self.avatar.is_some() => Diff::map().update(AVI, data),
 
             // Insert if it wasn't
             DisplayName(Some(name)) => Diff::map().insert(D_NAME, name),
             RealName(Some(name)) => Diff::map().insert(R_NAME, name),
             SetBioLine(key, val) => Diff::map().nested(BIO, Diff::map().insert(key, val)),
-            RemoveBioLine(key) => Diff::map().nested(BIO, Diff::map().delete(key)),
-            AddService(_) => unimplemented!(),
-            RemoveService(_) => unimplemented!(),
+            AvatarData(Some(data)) => Diff::map().insert(AVI, data),
 
             // Delete if set to None
-            DisplayName(None) => Diff::map().delete(D_NAME),
-            RealName(None) => Diff::map().delete(R_NAME),
+            DisplayName(None) if self.display_name.is_some() => Diff::map().delete(D_NAME),
+            RealName(None) if self.real_name.is_some() => Diff::map().delete(R_NAME),
+            RemoveBioLine(key) if self.bio.contains_key(&key) => {
+                Diff::map().nested(BIO, Diff::map().delete(key))
+            }
+            AvatarData(None) if self.avatar.is_some() => Diff::map().delete(AVI),
+
+            // Services are stored as a list, which is replaced as a whole
+            AddService(service) if !self.services.contains(&service) => Diff::map().update(
+                SERV,
+                services(self.services.iter().cloned().chain(Some(service))),
+            ),
+            RemoveService(service) if self.services.contains(&service) => Diff::map().update(
+                SERV,
+                services(self.services.iter().filter(|s| **s != service).cloned()),
+            ),
+
+            // Everything else is already in the right state
+            _ => return None,
+        })
+    }
+
+    /// Generate a diffset based on the previous version of the profile
+    pub(crate) fn gen_diffset(&self, prev: &UserProfile) -> Vec<Diff> {
+        let mut curr = prev.clone();
+        let mut v: Vec<_> = prev
+            .generate_updates(self.clone())
+            .into_iter()
+            .filter_map(|update| {
+                let diff = curr.gen_diff(update.clone());
+                update.apply_to(&mut curr);
+                diff
+            })
+            .collect();
 
-            // Avatars are a little special
-            AvatarData(Some(data)) => Diff::map().delete(AVI).insert(AVI, data),
-            AvatarData(None) => Diff::map().delete(BIO),
+        match (prev.version, self.version) {
+            (p, n) if p == n => {}
+            (0, n) => v.push(Diff::map().insert(VERSION, n)),
+            (_, 0) => v.push(Diff::map().delete(VERSION)),
+            (_, n) => v.push(Diff::map().update(VERSION, n)),
         }
+        v
     }
 }
 
@@ -157,6 +199,7 @@ fn persist_user_profile() {
 
     let profile = UserProfile {
         id: Identity::random(),
+        version: 0,
         display_name: Some("spacekookie".into()),
         real_name: Some("Katharina Fey".into()),
         bio: {
@@ -186,3 +229,42 @@ fn persist_user_profile() {
     })
     .unwrap();
 }
+
+#[test]
+fn profile_diffset() {
+    use crate::Identity;
+    use alexandria::{
+        query::{Query, QueryResult},
+        utils::{Path, TagSet},
+        Builder, GLOBAL,
+    };
+
+    let lib = Builder::new().build().unwrap();
+    let mut profile = UserProfile::new(Identity::random());
+    profile.display_name = Some("alice".into());
+    profile.bio.insert("location".into(), "Berlin".into());
+    let path = Path::from(format!("/users:{}", profile.id));
+
+    let mut new = profile.clone();
+    new.version = 3;
+    new.display_name = None;
+    new.real_name = Some("Alice".into());
+    new.bio.clear();
+    new.bio.insert("languages".into(), "de, en".into());
+    new.services.insert("net.qaul.chat".into());
+    new.avatar = Some(vec![1, 2, 3]);
+
+    async_std::task::block_on(async {
+        lib.batch(GLOBAL, path.clone(), TagSet::empty(), profile.init_diff())
+            .await
+            .unwrap();
+        for diff in new.gen_diffset(&profile) {
+            lib.update(GLOBAL, path.clone(), diff).await.unwrap();
+        }
+
+        match lib.query(GLOBAL, Query::Path(path)).await.unwrap() {
+            QueryResult::Single(rec) => assert_eq!(UserProfile::from(&*rec), new),
+            _ => unreachable!(),
+        }
+    });
+}
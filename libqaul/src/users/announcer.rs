@@ -1,7 +1,14 @@
 //! A user profile change announcer
+//!
+//! Every local user regularly floods the version of their profile,
+//! along with a hash of it.  Peers that have an older (or different)
+//! copy of the profile ask for the new one with a direct message.
+//! Avatars are left out of these replies, and only fetched if the
+//! avatar actually changed.
 
 use crate::{
-    messages::{Envelope, MsgUtils, RatMessageProto},
+    crypto::SignedPrekey,
+    messages::{Envelope, Message, MsgUtils, RatMessageProto, SigTrust},
     users::{UserProfile, UserStore},
     Identity,
 };
@@ -9,8 +16,50 @@ use async_std::{
     sync::{Arc, RwLock},
     task,
 };
-use ratman::{Message, Recipient, Router};
+use ratman::{Recipient, Router};
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::generichash;
 use std::{collections::BTreeSet, time::Duration};
+use tracing::{debug, warn};
+
+const ASSOCIATOR: &str = "libqaul._int.announcer";
+
+/// Messages exchanged to keep user profiles in sync
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum Announcement {
+    /// The current profile version of a user, flooded to the network
+    Version {
+        version: u64,
+        /// The hash of the complete profile, including the avatar
+        hash: Vec<u8>,
+        /// The prekey other users can start encrypted sessions with,
+        /// which users without a signing key don't have
+        prekey: Option<SignedPrekey>,
+    },
+    /// Ask a user for their profile
+    Request {
+        /// Whether the avatar should be included
+        avatar: bool,
+    },
+    /// A user profile, sent in reply to a `Request`
+    Profile {
+        profile: UserProfile,
+        /// The hash of the avatar, which is only included in the
+        /// profile if it was asked for
+        avatar: Option<Vec<u8>>,
+    },
+}
+
+fn hash(data: &[u8]) -> Vec<u8> {
+    let mut state = generichash::State::new(32, None).unwrap();
+    state.update(data).unwrap();
+    state.finalize().unwrap().as_ref().to_vec()
+}
+
+/// Hash a complete user profile
+fn profile_hash(profile: &UserProfile) -> Vec<u8> {
+    hash(&bincode::serialize(profile).unwrap())
+}
 
 pub(crate) struct Announcer {
     active: RwLock<BTreeSet<Identity>>,
@@ -23,10 +72,162 @@ impl Announcer {
         })
     }
 
-    /// Check if a message is a user profile
-    pub(crate) fn check_message(msg: &Message) -> Option<UserProfile> {
-        let payload = MsgUtils::extract_simple_payload(&msg)?;
-        bincode::deserialize(&payload).ok()
+    /// Check if a message is a profile announcement
+    pub(crate) fn check_message(msg: &Message) -> Option<Announcement> {
+        if msg.associator != ASSOCIATOR {
+            return None;
+        }
+        bincode::deserialize(&msg.payload).ok()
+    }
+
+    /// Send an announcement from a local user
+    async fn send(
+        store: &UserStore,
+        router: &Router,
+        sender: Identity,
+        recipient: Recipient,
+        ann: &Announcement,
+    ) {
+        let proto = RatMessageProto {
+            env: Envelope {
+                id: Identity::random(),
+                sender,
+                associator: ASSOCIATOR.into(),
+                payload: bincode::serialize(ann).unwrap(),
+                tags: vec![],
+            },
+            recipient,
+            relays: vec![],
+        };
+
+        if let Err(e) = MsgUtils::send(store, router, proto).await {
+            warn!("Failed to send profile announcement: {:?}", e);
+        }
+    }
+
+    /// Handle an announcement received from the network
+    ///
+    /// `recipient` is the local user a direct message was sent to,
+    /// and `None` for flooded messages.  Only the user themselves
+    /// can change their profile, so versions and profiles are only
+    /// accepted with a trusted signature by the sender.  Returns the
+    /// sender's profile if it was changed.
+    pub(crate) async fn handle(
+        &self,
+        router: &Router,
+        store: &UserStore,
+        msg: &Message,
+        recipient: Option<Identity>,
+        ann: Announcement,
+    ) -> Option<UserProfile> {
+        let sender = msg.sender;
+        if msg.sign == SigTrust::Invalid {
+            warn!("Dropping forged announcement for `{}`", sender);
+            return None;
+        }
+
+        match (ann, recipient) {
+            (Announcement::Version { .. }, _) | (Announcement::Profile { .. }, _)
+                if msg.sign != SigTrust::Trusted =>
+            {
+                warn!("Dropping unsigned announcement for `{}`", sender)
+            }
+            (
+                Announcement::Version {
+                    version,
+                    hash,
+                    prekey,
+                },
+                None,
+            ) => {
+                if let Some(prekey) = prekey {
+                    if let Err(e) = store.crypto().add_prekey(sender, prekey).await {
+                        warn!("Ignoring prekey of `{}`: {:?}", sender, e);
+                    }
+                }
+
+                let outdated = match store.get(sender).await {
+                    Ok(curr) => {
+                        curr.version < version
+                            || (curr.version == version && profile_hash(&curr) != hash)
+                    }
+                    Err(_) => true,
+                };
+
+                // Any user that is online can ask for the profile
+                let local = self.active.read().await.iter().next().cloned();
+                if let (true, Some(local)) = (outdated, local) {
+                    debug!("Requesting new profile of `{}`", sender);
+                    let req = Announcement::Request { avatar: false };
+                    Self::send(store, router, local, Recipient::User(sender), &req).await;
+                }
+            }
+            (Announcement::Request { avatar }, Some(local)) => {
+                let mut profile = match store.get(local).await {
+                    Ok(profile) => profile,
+                    Err(_) => return None,
+                };
+                let hash = profile.avatar.as_deref().map(hash);
+                if !avatar {
+                    profile.avatar = None;
+                }
+
+                let reply = Announcement::Profile {
+                    profile,
+                    avatar: hash,
+                };
+                Self::send(store, router, local, Recipient::User(sender), &reply).await;
+            }
+            (
+                Announcement::Profile {
+                    mut profile,
+                    avatar,
+                },
+                Some(local),
+            ) if profile.id == sender => {
+                let curr = store.get(sender).await.ok().and_then(|p| p.avatar);
+
+                // Keep the avatar we have if it didn't change, and ask
+                // for the new one otherwise
+                if profile.avatar.is_none() && avatar.is_some() {
+                    if curr.as_deref().map(hash) != avatar {
+                        let req = Announcement::Request { avatar: true };
+                        Self::send(store, router, local, Recipient::User(sender), &req).await;
+                    }
+                    profile.avatar = curr;
+                }
+
+                match store.update_remote(profile.clone()).await {
+                    Ok(true) => return Some(profile),
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to store profile of `{}`: {:?}", sender, e),
+                }
+            }
+            _ => warn!("Dropping malformed announcement by `{}`", sender),
+        }
+        None
+    }
+
+    /// Flood the current profile version of a local user
+    ///
+    /// Returns `false` if the user's session was closed.
+    pub(crate) async fn announce(store: &UserStore, router: &Router, id: Identity) -> bool {
+        let keypair = match store.find_key(id).await {
+            Some(keypair) => keypair,
+            None => return false,
+        };
+        let (profile, prekey) = match (store.get(id).await, store.crypto().prekey(&keypair).await) {
+            (Ok(profile), Ok(prekey)) => (profile, prekey),
+            _ => return false,
+        };
+        let ann = Announcement::Version {
+            version: profile.version,
+            hash: profile_hash(&profile),
+            prekey,
+        };
+
+        Self::send(store, router, id, Recipient::Flood, &ann).await;
+        true
     }
 
     pub(crate) async fn online(
@@ -42,21 +243,9 @@ impl Announcer {
 
         task::spawn(async move {
             while this.active.read().await.contains(&id) {
-                let profile = store.get(id).await.unwrap();
-                let payload = bincode::serialize(&profile).unwrap();
-
-                let proto = RatMessageProto {
-                    env: Envelope {
-                        id: Identity::random(),
-                        sender: id,
-                        associator: "libqaul._int.announcer".into(),
-                        payload,
-                        tags: vec![],
-                    },
-                    recipient: Recipient::Flood,
-                };
-
-                MsgUtils::send(&store, &router, proto).await.unwrap();
+                if !Self::announce(&store, &router, id).await {
+                    break;
+                }
                 task::sleep(Duration::from_secs(30)).await;
             }
         });
@@ -66,3 +255,54 @@ impl Announcer {
         self.active.write().await.remove(&id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MsgId;
+    use alexandria::{utils::TagSet, Builder};
+
+    fn message(sender: Identity, sign: SigTrust, ann: &Announcement) -> Message {
+        Message {
+            id: MsgId::random(),
+            sender,
+            associator: ASSOCIATOR.into(),
+            tags: TagSet::empty(),
+            payload: bincode::serialize(ann).unwrap(),
+            sign,
+            verified: false,
+        }
+    }
+
+    #[async_std::test]
+    async fn trusted_profiles() {
+        let router = Router::new();
+        let store = UserStore::new(Builder::new().build().unwrap());
+        let announcer = Announcer::new();
+        let (local, bob) = (Identity::random(), Identity::random());
+
+        let mut profile = UserProfile::new(bob);
+        profile.display_name = Some("bob".into());
+        profile.version = 1;
+        let ann = || Announcement::Profile {
+            profile: profile.clone(),
+            avatar: None,
+        };
+
+        for sign in vec![SigTrust::Unverified, SigTrust::Invalid] {
+            let msg = message(bob, sign, &ann());
+            let changed = announcer
+                .handle(&router, &store, &msg, Some(local), ann())
+                .await;
+            assert_eq!(changed, None);
+            assert!(store.get(bob).await.is_err());
+        }
+
+        let msg = message(bob, SigTrust::Trusted, &ann());
+        let changed = announcer
+            .handle(&router, &store, &msg, Some(local), ann())
+            .await;
+        assert_eq!(changed, Some(profile.clone()));
+        assert_eq!(store.get(bob).await, Ok(profile));
+    }
+}
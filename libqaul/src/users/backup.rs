@@ -0,0 +1,157 @@
+//! Encrypted user backups
+//!
+//! A backup contains everything that is needed to move a local user
+//! to another device: their keypair, encryption sessions, profile,
+//! contact book and service metadata, and optionally their messages.  It is encrypted
+//! with a key derived from a passphrase, which is independent of the
+//! user's login password.
+
+use crate::{
+    contacts::ContactList,
+    crypto::CryptoState,
+    error::{Error, Result},
+    messages::Message,
+    security::Keypair,
+    users::UserProfile,
+};
+use alexandria::utils::TagSet;
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::{
+    pwhash::argon2id13::{self, Salt},
+    secretbox::{self, Key, Nonce},
+};
+use std::collections::BTreeMap;
+
+/// The current backup format version
+///
+/// Bump this whenever the layout of `Backup` changes, and keep the
+/// ability to read older versions around.
+pub(crate) const VERSION: u16 = 1;
+
+/// A metadata map of a service, with its search tags
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ServiceData {
+    pub(crate) service: String,
+    pub(crate) name: String,
+    pub(crate) map: BTreeMap<String, Vec<u8>>,
+    pub(crate) tags: TagSet,
+}
+
+/// The contents of a backup
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Backup {
+    pub(crate) keypair: Keypair,
+    pub(crate) crypto: CryptoState,
+    pub(crate) profile: UserProfile,
+    pub(crate) contacts: ContactList,
+    pub(crate) services: Vec<ServiceData>,
+    pub(crate) messages: Vec<Message>,
+}
+
+/// The encrypted backup, as it is handed out
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    version: u16,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    data: Vec<u8>,
+}
+
+fn derive_key(passphrase: &str, salt: &Salt) -> Result<Key> {
+    let mut key = Key([0; secretbox::KEYBYTES]);
+    argon2id13::derive_key(
+        &mut key.0,
+        passphrase.as_bytes(),
+        salt,
+        argon2id13::OPSLIMIT_INTERACTIVE,
+        argon2id13::MEMLIMIT_INTERACTIVE,
+    )
+    // This only fails if the memory for it can't be allocated
+    .map_err(|_| Error::CommFault)?;
+    Ok(key)
+}
+
+impl Backup {
+    /// Encode and encrypt a backup with a passphrase
+    pub(crate) fn seal(&self, passphrase: &str) -> Result<Vec<u8>> {
+        let salt = argon2id13::gen_salt();
+        let nonce = secretbox::gen_nonce();
+        let key = derive_key(passphrase, &salt)?;
+
+        Ok(bincode::serialize(&Bundle {
+            version: VERSION,
+            salt: salt.0.to_vec(),
+            nonce: nonce.0.to_vec(),
+            data: secretbox::seal(&bincode::serialize(self)?, &nonce, &key),
+        })?)
+    }
+
+    /// Decrypt and decode a backup
+    ///
+    /// Fails with `NotAuthorised` if the passphrase is wrong, and
+    /// with `InvalidPayload` if the data isn't a backup this version
+    /// of libqaul can read.
+    pub(crate) fn open(data: &[u8], passphrase: &str) -> Result<Self> {
+        let bundle: Bundle = bincode::deserialize(data).map_err(|_| Error::InvalidPayload)?;
+        if bundle.version != VERSION {
+            return Err(Error::InvalidPayload);
+        }
+
+        let salt = Salt::from_slice(&bundle.salt).ok_or(Error::InvalidPayload)?;
+        let nonce = Nonce::from_slice(&bundle.nonce).ok_or(Error::InvalidPayload)?;
+        let key = derive_key(passphrase, &salt)?;
+        let clear =
+            secretbox::open(&bundle.data, &nonce, &key).map_err(|_| Error::NotAuthorised)?;
+        bincode::deserialize(&clear).map_err(|_| Error::InvalidPayload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{security::Sec, Identity};
+
+    async fn backup() -> Backup {
+        let keyd = Sec::new().generate().await;
+        let mut contacts = ContactList::new();
+        contacts.insert(Identity::random(), Default::default());
+
+        Backup {
+            keypair: keyd.keypair,
+            crypto: Default::default(),
+            profile: UserProfile::new(keyd.id),
+            contacts,
+            services: vec![],
+            messages: vec![],
+        }
+    }
+
+    #[async_std::test]
+    async fn seal_and_open() {
+        let b = backup().await;
+        let sealed = b.seal("car horse battery staple").unwrap();
+
+        let opened = Backup::open(&sealed, "car horse battery staple").unwrap();
+        assert_eq!(opened.profile, b.profile);
+        assert_eq!(opened.keypair.id(), b.profile.id);
+        assert_eq!(opened.contacts.len(), 1);
+
+        assert_eq!(
+            Backup::open(&sealed, "wrong passphrase").err(),
+            Some(Error::NotAuthorised)
+        );
+    }
+
+    #[async_std::test]
+    async fn unknown_version() {
+        let sealed = backup().await.seal("abcdefg").unwrap();
+        let mut bundle: Bundle = bincode::deserialize(&sealed).unwrap();
+        bundle.version += 1;
+        let sealed = bincode::serialize(&bundle).unwrap();
+
+        assert_eq!(
+            Backup::open(&sealed, "abcdefg").err(),
+            Some(Error::InvalidPayload)
+        );
+    }
+}
@@ -1,13 +1,15 @@
 //! Local user and session types
 
 mod announcer;
+mod backup;
 mod profile;
 mod store;
 
-pub(crate) use store::{UserStore, TAG_PROFILE};
 pub(crate) use announcer::Announcer;
+pub(crate) use backup::{Backup, ServiceData};
+pub(crate) use store::{UserStore, TAG_PROFILE};
 
 pub use {
-    crate::api::users::{Token, UserAuth},
+    crate::api::users::{Session, SessionId, Token, UserAuth, UserScope},
     profile::{UserProfile, UserUpdate},
 };
@@ -17,6 +17,10 @@ use std::collections::{BTreeMap, BTreeSet};
 pub struct UserProfile {
     /// A user's network (node) ID
     pub id: Identity,
+    /// Incremented with every change, so that peers know when their
+    /// copy of a profile is out of date
+    #[serde(default)]
+    pub version: u64,
     /// A human readable display-name (like @foobar)
     #[serde(default)]
     pub display_name: Option<String>,
@@ -41,6 +45,7 @@ impl UserProfile {
     pub fn new(id: Identity) -> Self {
         Self {
             id,
+            version: 0,
             display_name: None,
             real_name: None,
             bio: BTreeMap::new(),
@@ -72,34 +77,116 @@ impl UserProfile {
     }
 
     /// Do a fully fuzzy query on names to facilitate searching
-    pub fn fuzzy_query(&self, _query: &str) -> bool {
-        unimplemented!()
+    ///
+    /// See `search_score` for what is considered a match.
+    pub fn fuzzy_query(&self, query: &str) -> bool {
+        self.search_score(query).is_some()
+    }
+
+    /// Rank how well this profile matches a search query
+    ///
+    /// The query is matched against the display and real name, bio
+    /// values and the start of the user's ID, ignoring case.  Words
+    /// only need to contain the letters of the query in order, but
+    /// exact matches rank highest.  An empty query matches every
+    /// profile.
+    pub(crate) fn search_score(&self, query: &str) -> Option<u32> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Some(0);
+        }
+
+        let names = self.display_name.iter().chain(self.real_name.iter());
+        let names = names.filter_map(|name| field_score(name, &query).map(|s| s * 2));
+        let bio = self.bio.values().filter_map(|val| field_score(val, &query));
+        names.chain(bio).chain(self.id_score(&query)).max()
     }
 
+    /// Match the start of the hex encoded ID
+    ///
+    /// Short prefixes match too many users to be useful, so at least
+    /// four characters are needed.
+    fn id_score(&self, query: &str) -> Option<u32> {
+        let prefix: String = query
+            .chars()
+            .filter(|c| !matches!(c, '-' | ':' | ' '))
+            .collect();
+        let hex: String = self
+            .id
+            .as_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        match prefix.len() >= 4 && hex.starts_with(&prefix) {
+            true => Some(250),
+            false => None,
+        }
+    }
+
+    /// Generate the updates that turn this profile into `new`
+    ///
+    /// The version isn't a field that can be updated, and has to be
+    /// set separately.
     pub(crate) fn generate_updates(&self, new: Self) -> Vec<UserUpdate> {
         let mut updates = vec![];
         use UserUpdate::*;
 
-        // Update the display name
-        match (self.real_name.clone(), new.real_name.clone()) {
-            (Some(n), Some(m)) if n == m => {}
-            (_, Some(m)) => updates.push(RealName(Some(m))),
-            (Some(_), None) => updates.push(RealName(None)),
-            (_, _) => {}
-        }
+        updates.extend(
+            self.bio
+                .keys()
+                .filter(|key| !new.bio.contains_key(*key))
+                .map(|key| RemoveBioLine(key.clone())),
+        );
+        updates.extend(
+            self.services
+                .difference(&new.services)
+                .map(|s| RemoveService(s.clone())),
+        );
+        updates.extend(
+            new.services
+                .difference(&self.services)
+                .map(|s| AddService(s.clone())),
+        );
 
-        // Update the real name
-        match (self.display_name.clone(), new.display_name.clone()) {
-            (Some(n), Some(m)) if n == m => {}
-            (_, Some(m)) => updates.push(DisplayName(Some(m))),
-            (Some(_), None) => updates.push(DisplayName(None)),
-            (_, _) => {}
+        if self.display_name != new.display_name {
+            updates.push(DisplayName(new.display_name));
+        }
+        if self.real_name != new.real_name {
+            updates.push(RealName(new.real_name));
+        }
+        updates.extend(
+            new.bio
+                .into_iter()
+                .filter(|(key, val)| self.bio.get(key) != Some(val))
+                .map(|(key, val)| SetBioLine(key, val)),
+        );
+        if self.avatar != new.avatar {
+            updates.push(AvatarData(new.avatar));
         }
 
         updates
     }
 }
 
+/// How well a single field matches a lowercase query
+fn field_score(field: &str, query: &str) -> Option<u32> {
+    let field = field.to_lowercase();
+    let mut chars = field.chars();
+
+    if field == query {
+        Some(100)
+    } else if field.starts_with(query) {
+        Some(75)
+    } else if field.contains(query) {
+        Some(50)
+    } else if query.chars().all(|q| chars.any(|c| c == q)) {
+        Some(25)
+    } else {
+        None
+    }
+}
+
 /// All the ways a UserData can change, as individual events.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum UserUpdate {
@@ -130,7 +217,7 @@ impl UserUpdate {
                 data.bio.insert(k, v);
             }
             RemoveBioLine(k) => {
-                data.services.remove(&k);
+                data.bio.remove(&k);
             }
             AddService(k) => {
                 data.services.insert(k);
@@ -142,3 +229,47 @@ impl UserUpdate {
         }
     }
 }
+
+#[test]
+fn updates_roundtrip() {
+    let mut old = UserProfile::new(Identity::random());
+    old.display_name = Some("alice".into());
+    old.bio.insert("location".into(), "Berlin".into());
+    old.bio.insert("languages".into(), "de".into());
+    old.services.insert("net.qaul.chat".into());
+
+    let mut new = old.clone();
+    new.display_name = None;
+    new.real_name = Some("Alice".into());
+    new.bio.remove("location");
+    new.bio.insert("languages".into(), "de, en".into());
+    new.services.insert("net.qaul.feed".into());
+    new.services.remove("net.qaul.chat");
+    new.avatar = Some(vec![1, 2, 3]);
+
+    let updates = old.generate_updates(new.clone());
+    assert_eq!(updates.len(), 7);
+    let applied = updates.into_iter().fold(old, |p, u| p.apply(u));
+    assert_eq!(applied, new);
+}
+
+#[test]
+fn search_ranking() {
+    let mut alice = UserProfile::new(Identity::random());
+    alice.display_name = Some("Alice".into());
+    alice.bio.insert("location".into(), "Berlin".into());
+
+    assert_eq!(alice.search_score("alice"), Some(200));
+    assert_eq!(alice.search_score(" ALI"), Some(150));
+    assert_eq!(alice.search_score("lic"), Some(100));
+    assert_eq!(alice.search_score("aie"), Some(50));
+    assert_eq!(alice.search_score("berlin"), Some(100));
+    assert_eq!(alice.search_score("bob"), None);
+    assert_eq!(alice.search_score(""), Some(0));
+    assert!(alice.fuzzy_query("alc"));
+
+    // IDs match by their start, like they are shown to users
+    let id = alice.id.to_string();
+    assert_eq!(alice.search_score(&id[..9]), Some(250));
+    assert_eq!(alice.search_score(&id[5..14]), None);
+}
@@ -1,6 +1,7 @@
 //! Store for user profiles
 
 use crate::{
+    crypto::CryptoStore,
     error::{Error, Result},
     qaul::Identity,
     security::{KeyId, Keypair},
@@ -8,12 +9,18 @@ use crate::{
     users::{UserProfile, UserUpdate},
 };
 use alexandria::{
+    error::Error as AlexError,
     query::{Query, QueryResult},
     utils::{Id, Path, Tag, TagSet},
     Library, Session, GLOBAL,
 };
 
-use std::{collections::BTreeSet, sync::Arc};
+use async_std::sync::RwLock;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 pub(crate) const TAG_PROFILE: &'static str = "libqaul.user.profile";
 pub(crate) const TAG_LOCAL: &'static str = "libqaul.user.local";
@@ -31,12 +38,27 @@ fn profile_path(id: Id) -> Path {
 #[derive(Clone)]
 pub(crate) struct UserStore {
     inner: Arc<Library>,
+    crypto: CryptoStore,
+    /// When a message by a remote user was last received
+    ///
+    /// This is only kept in memory, so all users count as unseen
+    /// again after a restart.
+    seen: Arc<RwLock<BTreeMap<Identity, Instant>>>,
 }
 
 impl UserStore {
     /// Create a new type abstraction over an existing Alexandria lib
     pub(crate) fn new(inner: Arc<Library>) -> Self {
-        Self { inner }
+        Self {
+            crypto: CryptoStore::new(Arc::clone(&inner)),
+            inner,
+            seen: Default::default(),
+        }
+    }
+
+    /// Session state for end-to-end encryption between users
+    pub(crate) fn crypto(&self) -> &CryptoStore {
+        &self.crypto
     }
 
     /// Create a new local user
@@ -60,6 +82,69 @@ impl UserStore {
             .await;
     }
 
+    /// Create a local user from a backup
+    ///
+    /// If the user was known as a remote user before, their profile
+    /// is replaced.
+    pub(crate) async fn restore_local(
+        &self,
+        keyid: KeyId,
+        pw: &str,
+        profile: UserProfile,
+    ) -> Result<()> {
+        let KeyId { id, keypair } = keyid;
+        match self.inner.sessions().create(id, pw).await {
+            Ok(_) => {}
+            Err(AlexError::UserAlreadyExists) => return Err(Error::UserExists),
+            Err(_) => return Err(Error::StorageFault),
+        }
+
+        self.inner
+            .insert(
+                Session::Id(id),
+                key_path(id),
+                TagSet::empty(),
+                KeyWrap(keypair).make_diff(),
+            )
+            .await
+            .map_err(|_| Error::StorageFault)?;
+
+        // The profile exists already if the user was seen on the network
+        let _ = self.inner.delete(GLOBAL, profile_path(id)).await;
+        self.inner
+            .batch(
+                GLOBAL,
+                profile_path(id),
+                vec![Tag::empty(TAG_PROFILE), Tag::empty(TAG_LOCAL)],
+                profile.init_diff(),
+            )
+            .await
+            .map_err(|_| Error::StorageFault)?;
+        Ok(())
+    }
+
+    /// Open the storage session of a local user
+    ///
+    /// Sessions of users that were loaded from disk stay locked until
+    /// they log in again.
+    pub(crate) async fn open_local(&self, id: Identity, pw: &str) -> Result<()> {
+        match self.inner.sessions().open(id, pw).await {
+            Ok(_) | Err(AlexError::AlreadyUnlocked { .. }) => Ok(()),
+            Err(_) => Err(Error::NotAuthorised),
+        }
+    }
+
+    /// Change the password of a local user's storage session
+    ///
+    /// This also re-encrypts the user's key and other records.
+    pub(crate) async fn change_pw(&self, id: Identity, old: &str, new: &str) -> Result<()> {
+        match self.inner.sessions().change_pw(id, old, new).await {
+            Ok(_) => Ok(()),
+            Err(AlexError::UnlockFailed { .. }) => Err(Error::NotAuthorised),
+            Err(_) => Err(Error::StorageFault),
+        }
+    }
+
     /// Add an empty user profile for an id
     pub(crate) async fn insert_profile<T: Into<TagSet>>(&self, id: Identity, tags: T) {
         let profile = UserProfile::new(id);
@@ -69,6 +154,33 @@ impl UserStore {
             .unwrap();
     }
 
+    /// Check if a user has a storage session on this device
+    pub(crate) async fn is_local(&self, id: Identity) -> bool {
+        self.inner.sessions().list().await.contains(&id)
+    }
+
+    /// Undo `restore_local` for a user that couldn't be imported
+    ///
+    /// The storage session is destroyed with everything written to it,
+    /// and `prev`, the profile the user was known by as a remote user,
+    /// is put back.
+    pub(crate) async fn remove_local(&self, id: Identity, prev: Option<UserProfile>) {
+        let _ = self.inner.delete(GLOBAL, profile_path(id)).await;
+        let _ = self.inner.sessions().destroy(Session::Id(id)).await;
+
+        if let Some(profile) = prev {
+            let _ = self
+                .inner
+                .batch(
+                    GLOBAL,
+                    profile_path(id),
+                    vec![Tag::empty(TAG_PROFILE)],
+                    profile.init_diff(),
+                )
+                .await;
+        }
+    }
+
     /// Delete the key and profile for a local user
     pub(crate) async fn delete_local(&self, id: Identity) {
         self.get(id).await.unwrap();
@@ -81,26 +193,68 @@ impl UserStore {
         self.inner.delete(GLOBAL, profile_path(id)).await.unwrap();
     }
 
+    /// Write the changes between two versions of a profile
+    async fn save(&self, prev: &UserProfile, new: &UserProfile) -> Result<()> {
+        for diff in new.gen_diffset(prev) {
+            self.inner
+                .update(GLOBAL, profile_path(new.id), diff)
+                .await
+                .map_err(|_| Error::StorageFault)?;
+        }
+        Ok(())
+    }
+
     /// Modify a single user inside the store in-place
+    ///
+    /// Every change that does something increments the version of
+    /// the profile.
     pub(crate) async fn modify(&self, id: Identity, modifier: UserUpdate) -> Result<()> {
         let curr = self.get(id).await?;
-        let diff = curr.gen_diff(modifier);
-        self.inner
-            .update(GLOBAL, profile_path(id), diff)
-            .await
-            .unwrap();
+        let mut new = curr.clone().apply(modifier);
+        if new != curr {
+            new.version += 1;
+            self.save(&curr, &new).await?;
+        }
         Ok(())
     }
 
+    /// Replace the profile of a remote user with one they sent
+    ///
+    /// Profiles that are older than the one already stored are
+    /// ignored.  Returns whether the profile was changed.
+    pub(crate) async fn update_remote(&self, profile: UserProfile) -> Result<bool> {
+        let curr = match self.get(profile.id).await {
+            Ok(curr) => curr,
+            Err(_) => {
+                self.insert_profile(profile.id, vec![Tag::empty(TAG_PROFILE)])
+                    .await;
+                UserProfile::new(profile.id)
+            }
+        };
+
+        if profile.version < curr.version || profile == curr {
+            return Ok(false);
+        }
+        self.save(&curr, &profile).await?;
+        Ok(true)
+    }
+
     /// Don't call this on non-local users please
     pub(crate) async fn get_key(&self, id: Identity) -> Keypair {
+        self.find_key(id)
+            .await
+            .expect("Local encryption key not known!")
+    }
+
+    /// Get the key of a local user, if their session is open
+    pub(crate) async fn find_key(&self, id: Identity) -> Option<Keypair> {
         match self
             .inner
             .query(Session::Id(id), Query::Path(key_path(id)))
             .await
         {
-            Ok(QueryResult::Single(rec)) => KeyWrap::from(&*rec).0,
-            _ => panic!("Local encryption key not known!"),
+            Ok(QueryResult::Single(rec)) => Some(KeyWrap::from(&*rec).0),
+            _ => None,
         }
     }
 
@@ -117,6 +271,22 @@ impl UserStore {
         }
     }
 
+    /// Remember that a user was just seen on the network
+    pub(crate) async fn mark_seen(&self, id: Identity) {
+        self.seen.write().await.insert(id, Instant::now());
+    }
+
+    /// Get all users that were seen within some time
+    pub(crate) async fn seen_within(&self, dur: Duration) -> BTreeSet<Identity> {
+        self.seen
+            .read()
+            .await
+            .iter()
+            .filter(|(_, time)| time.elapsed() <= dur)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
     /// Get all locally available users
     pub(crate) async fn all_local(&self) -> Vec<UserProfile> {
         match self
@@ -223,6 +393,37 @@ async fn update_user() {
 
     let after = store.get(id).await.unwrap();
     assert_eq!(after.display_name, Some("spacekookie".into()));
+    assert_eq!(after.version, 1);
+
+    // Updates that don't change anything keep the version
+    let update = UserUpdate::RemoveBioLine("location".into());
+    store.modify(id, update).await.unwrap();
+    assert_eq!(store.get(id).await.unwrap().version, 1);
+}
+
+#[async_std::test]
+async fn update_remote_user() {
+    let store = harness::setup();
+    let id = Identity::random();
+
+    let mut profile = UserProfile::new(id);
+    profile.version = 2;
+    profile.display_name = Some("alice".into());
+    profile.services.insert("net.qaul.chat".into());
+    assert_eq!(store.update_remote(profile.clone()).await, Ok(true));
+    assert_eq!(store.get(id).await.unwrap(), profile);
+
+    // Older profiles don't replace newer ones
+    let mut old = profile.clone();
+    old.version = 1;
+    old.display_name = None;
+    assert_eq!(store.update_remote(old).await, Ok(false));
+    assert_eq!(store.get(id).await.unwrap(), profile);
+
+    profile.version = 3;
+    profile.avatar = Some(vec![1, 2, 3]);
+    assert_eq!(store.update_remote(profile.clone()).await, Ok(true));
+    assert_eq!(store.get(id).await.unwrap(), profile);
 }
 
 #[async_std::test]
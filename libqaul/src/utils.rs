@@ -1,6 +1,9 @@
 //! General utility module
 
+use crate::error::{self, Error};
 use rand::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, io::ErrorKind, path::Path};
 
 /// Generate some secure random data into an allocated slice
 pub(crate) fn random(len: usize) -> Vec<u8> {
@@ -14,6 +17,29 @@ pub(crate) fn random(len: usize) -> Vec<u8> {
         })
 }
 
+/// Load a value from a file, or its default if the file doesn't exist
+pub(crate) fn load_file<T>(path: &Path) -> error::Result<T>
+where
+    T: DeserializeOwned + Default,
+{
+    match fs::read(path) {
+        Ok(data) => Ok(bincode::deserialize(&data)?),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(_) => Err(Error::StorageFault),
+    }
+}
+
+/// Replace the contents of a file with an encoded value
+///
+/// The data is written to a temporary file first, so that the old
+/// value is kept if writing fails half-way through.
+pub(crate) fn sync_file<T: Serialize>(path: &Path, val: &T) -> error::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bincode::serialize(val)?)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|_| Error::StorageFault)
+}
+
 pub(crate) trait IterUtils<E>: Iterator + Sized
 where
     E: Copy,
@@ -0,0 +1,265 @@
+//! libqaul contact book tests
+
+use libqaul::{
+    contacts::{ContactEntry, ContactQuery, Proof},
+    error::Error,
+    helpers::TagSet,
+    messages::{Message, SigTrust},
+    Identity, Qaul,
+};
+use ratman::Router;
+use std::collections::BTreeMap;
+
+#[async_std::test]
+async fn modify_and_query() {
+    let q = Qaul::new(Router::new());
+    let auth = q.users().create("abcdefg").await.unwrap();
+    let (alice, bob, eve) = (Identity::random(), Identity::random(), Identity::random());
+
+    assert_eq!(q.contacts().all(auth.clone()).await.unwrap(), vec![]);
+    assert_eq!(
+        q.contacts().get(auth.clone(), &alice).await,
+        Err(Error::NoContact)
+    );
+
+    let contacts = q.contacts();
+    contacts
+        .modify(auth.clone(), &alice, |c| {
+            c.nick = Some("alice".into());
+            c.trust = 100;
+            c.met = true;
+        })
+        .await
+        .unwrap();
+    contacts
+        .modify(auth.clone(), &bob, |c| {
+            c.nick = Some("bob".into());
+            c.trust = 90;
+            c.location = Some("Berlin".into());
+        })
+        .await
+        .unwrap();
+    contacts
+        .modify(auth.clone(), &eve, |c| c.trust = -50)
+        .await
+        .unwrap();
+
+    let mut all = contacts.all(auth.clone()).await.unwrap();
+    all.sort();
+    let mut expected = vec![alice, bob, eve];
+    expected.sort();
+    assert_eq!(all, expected);
+
+    let trusted = ContactQuery::Trust { val: 100, fuz: 20 };
+    let mut found = contacts.query(auth.clone(), trusted.clone()).await.unwrap();
+    found.sort();
+    let mut expected = vec![alice, bob];
+    expected.sort();
+    assert_eq!(found, expected);
+
+    let query = ContactQuery::And(vec![
+        trusted,
+        ContactQuery::Or(vec![
+            ContactQuery::Met(true),
+            ContactQuery::Location("Paris".into()),
+        ]),
+    ]);
+    assert_eq!(
+        contacts.query(auth.clone(), query).await.unwrap(),
+        vec![alice]
+    );
+
+    // Fields can be changed and removed again
+    contacts
+        .modify(auth.clone(), &bob, |c| {
+            c.nick = None;
+            c.location = Some("Paris".into());
+            c.trust = 91;
+        })
+        .await
+        .unwrap();
+    let entry = contacts.get(auth.clone(), &bob).await.unwrap();
+    assert_eq!(entry.nick, None);
+    assert_eq!(entry.location, Some("Paris".into()));
+    assert_eq!(entry.trust, 91);
+    assert_eq!(
+        contacts
+            .query(auth.clone(), ContactQuery::Nick("bob".into()))
+            .await
+            .unwrap(),
+        vec![]
+    );
+}
+
+#[async_std::test]
+async fn books_are_per_user() {
+    let q = Qaul::new(Router::new());
+    let a = q.users().create("abcdefg").await.unwrap();
+    let b = q.users().create("hijklmn").await.unwrap();
+    let friend = Identity::random();
+
+    q.contacts()
+        .modify(a.clone(), &friend, |c| c.met = true)
+        .await
+        .unwrap();
+
+    assert_eq!(q.contacts().all(b.clone()).await.unwrap(), vec![]);
+    assert_eq!(
+        q.contacts().get(b.clone(), &friend).await,
+        Err(Error::NoContact)
+    );
+
+    // A logged out user can't access their contacts
+    q.users().logout(a.clone()).await.unwrap();
+    assert!(q.contacts().all(a).await.is_err());
+}
+
+#[async_std::test]
+async fn migrate_contact_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let q = Qaul::open(Router::new(), dir.path(), "root secret").unwrap();
+    let auth = q.users().create("abcdefg").await.unwrap();
+    let friend = Identity::random();
+
+    // Contact books used to be stored in a file next to the library
+    let mut books: BTreeMap<Identity, BTreeMap<Identity, ContactEntry>> = BTreeMap::new();
+    books.entry(auth.0).or_default().insert(
+        friend,
+        ContactEntry {
+            nick: Some("friend".into()),
+            ..Default::default()
+        },
+    );
+    let file = dir.path().join("contacts");
+    std::fs::write(&file, bincode::serialize(&books).unwrap()).unwrap();
+
+    // Log out first, so this instance doesn't write the session again
+    q.users().logout(auth.clone()).await.unwrap();
+    let q = Qaul::open(Router::new(), dir.path(), "root secret").unwrap();
+    let auth = q.users().login(auth.0, "abcdefg").await.unwrap();
+    assert_eq!(
+        q.contacts().get(auth.clone(), &friend).await.unwrap().nick,
+        Some("friend".into())
+    );
+    assert!(!file.exists());
+
+    // And the contacts are now kept in the library
+    q.users().logout(auth.clone()).await.unwrap();
+    let q = Qaul::open(Router::new(), dir.path(), "root secret").unwrap();
+    let auth = q.users().login(auth.0, "abcdefg").await.unwrap();
+    assert_eq!(q.contacts().all(auth).await.unwrap(), vec![friend]);
+}
+
+#[async_std::test]
+async fn verify_safety_number() {
+    let q = Qaul::new(Router::new());
+    let alice = q.users().create("abcdefg").await.unwrap();
+    let bob = q.users().create("hijklmn").await.unwrap();
+    let contacts = q.contacts();
+
+    let number = contacts.safety_number(alice.clone(), &bob.0).await.unwrap();
+    assert_eq!(
+        contacts
+            .safety_number(bob.clone(), &alice.0)
+            .await
+            .unwrap()
+            .digits,
+        number.digits
+    );
+
+    // Proofs for somebody else are rejected
+    let eve = Identity::random();
+    assert_eq!(
+        contacts
+            .verify(alice.clone(), &eve, Proof::Compared(number.to_string()))
+            .await,
+        Err(Error::BadProof)
+    );
+    assert_eq!(
+        contacts
+            .verify(bob.clone(), &eve, Proof::Scanned(number.qr.clone()))
+            .await,
+        Err(Error::BadProof)
+    );
+
+    // Bob scans the code on Alice's screen, Alice compares the digits
+    contacts
+        .verify(bob.clone(), &alice.0, Proof::Scanned(number.qr.clone()))
+        .await
+        .unwrap();
+    contacts
+        .verify(alice.clone(), &bob.0, Proof::Compared(number.to_string()))
+        .await
+        .unwrap();
+    assert!(contacts.get(bob.clone(), &alice.0).await.unwrap().verified);
+    assert_eq!(
+        contacts
+            .query(alice.clone(), ContactQuery::Verified(true))
+            .await
+            .unwrap(),
+        vec![bob.0]
+    );
+
+    // Verification can be removed, but not added without a proof
+    contacts
+        .modify(alice.clone(), &bob.0, |c| c.verified = false)
+        .await
+        .unwrap();
+    contacts
+        .modify(alice.clone(), &eve, |c| c.verified = true)
+        .await
+        .unwrap();
+    assert_eq!(
+        contacts
+            .query(alice.clone(), ContactQuery::Verified(true))
+            .await
+            .unwrap(),
+        vec![]
+    );
+}
+
+#[async_std::test]
+async fn verified_messages() {
+    let q = Qaul::new(Router::new());
+    let alice = q.users().create("abcdefg").await.unwrap();
+    let bob = q.users().create("hijklmn").await.unwrap();
+
+    let msg = |sign| Message {
+        id: Identity::random(),
+        sender: bob.0,
+        associator: "net.qaul.testing".into(),
+        tags: TagSet::empty(),
+        payload: vec![1, 3, 1, 2],
+        sign,
+        verified: false,
+    };
+    assert!(!q
+        .messages()
+        .verified(alice.clone(), &msg(SigTrust::Trusted))
+        .await
+        .unwrap());
+
+    let number = q
+        .contacts()
+        .safety_number(alice.clone(), &bob.0)
+        .await
+        .unwrap();
+    q.contacts()
+        .verify(alice.clone(), &bob.0, Proof::Compared(number.digits))
+        .await
+        .unwrap();
+
+    let messages = q.messages();
+    assert!(messages
+        .verified(alice.clone(), &msg(SigTrust::Trusted))
+        .await
+        .unwrap());
+    assert!(!messages
+        .verified(alice.clone(), &msg(SigTrust::Unverified))
+        .await
+        .unwrap());
+    assert!(!messages
+        .verified(alice, &msg(SigTrust::Invalid))
+        .await
+        .unwrap());
+}
@@ -63,6 +63,55 @@ async fn send_one() {
     assert_eq!(msg.id, id);
 }
 
+#[async_std::test]
+async fn send_verified() {
+    use libqaul::contacts::Proof;
+
+    let net = harness::init().await;
+    let auth_a = net.a().users().create("abc").await.unwrap();
+    let auth_b = net.b().users().create("abc").await.unwrap();
+    zzz(millis(2000)).await;
+
+    let recv = |id| {
+        let b = Arc::clone(net.b());
+        let auth_b = auth_b.clone();
+        harness::timeout(sec5(), async move {
+            loop {
+                let all = b
+                    .messages()
+                    .query(auth_b.clone(), "net.qaul.testing", MsgQuery::new())
+                    .await
+                    .unwrap()
+                    .all()
+                    .await
+                    .unwrap();
+
+                match all.into_iter().find(|msg| msg.id == id) {
+                    Some(msg) => break msg,
+                    None => harness::zzz(millis(20)).await,
+                }
+            }
+        })
+    };
+
+    let id = send_simple(net.a(), &auth_a, auth_b.0).await;
+    assert!(!recv(id).await.unwrap().verified);
+
+    // Once B verified A, messages from A are marked as verified
+    let contacts = net.b().contacts();
+    let number = contacts
+        .safety_number(auth_b.clone(), &auth_a.0)
+        .await
+        .unwrap();
+    contacts
+        .verify(auth_b.clone(), &auth_a.0, Proof::Compared(number.digits))
+        .await
+        .unwrap();
+
+    let id = send_simple(net.a(), &auth_a, auth_b.0).await;
+    assert!(recv(id).await.unwrap().verified);
+}
+
 #[async_std::test]
 async fn send_three() {
     let net = harness::init().await;
@@ -143,3 +192,31 @@ async fn grouped_send_ids() {
 
     assert_eq!(msg.id, id_type.consume());
 }
+
+#[async_std::test]
+async fn anonymous_needs_relays() {
+    use libqaul::error::Error;
+
+    let net = harness::init().await;
+    let auth_a = net.a().users().create("abc").await.unwrap();
+    let auth_b = net.b().users().create("abc").await.unwrap();
+
+    zzz(millis(2000)).await;
+
+    // The recipient can't relay its own message, and nobody else is
+    // around
+    let res = net
+        .a()
+        .messages()
+        .send(
+            auth_a,
+            Mode::Anonymous(auth_b.0),
+            IdType::unique(),
+            "net.qaul.testing",
+            TagSet::empty(),
+            vec![1, 3, 1, 2],
+        )
+        .await;
+
+    assert_eq!(res, Err(Error::NoRoute));
+}
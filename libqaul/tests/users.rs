@@ -1,7 +1,7 @@
 //! libqaul user tests
 
 mod harness;
-use harness::{sec5, sec10};
+use harness::{sec10, sec5};
 
 use libqaul::users::UserUpdate;
 
@@ -31,31 +31,53 @@ async fn user_delete() {
     assert_eq!(net.a().users().list().await.len(), 0);
 }
 
-#[ignore]
 #[async_std::test]
 async fn modify_user() {
+    use libqaul::users::UserProfile;
+    use std::sync::Arc;
+
     let net = harness::init().await;
 
     // Create a user
     let auth_a = net.a().users().create("abcdefg").await.unwrap();
+    let _auth_b = net.b().users().create("abcdefg").await.unwrap();
     assert_eq!(net.a().users().list().await.len(), 1);
 
-    net.a()
-        .users()
-        .update(
-            auth_a.clone(),
-            UserUpdate::DisplayName(Some("spacekookie".to_owned())),
-        )
+    let users = net.a().users();
+    for update in [
+        UserUpdate::DisplayName(Some("spacekookie".to_owned())),
+        UserUpdate::SetBioLine("location".into(), "Berlin".into()),
+        UserUpdate::AddService("net.qaul.chat".into()),
+        UserUpdate::AvatarData(Some(vec![1, 2, 3])),
+    ] {
+        users.update(auth_a.clone(), update).await.unwrap();
+    }
+    let profile = users.get(auth_a.0).await.unwrap();
+    assert_eq!(profile.version, 4);
+
+    // Wait until node B fetched the profile, and then the avatar
+    let sync = |expected: UserProfile| {
+        let b = Arc::clone(net.b());
+        harness::timeout(sec10(), async move {
+            while b.users().get(expected.id).await.ok() != Some(expected.clone()) {
+                harness::zzz(harness::millis(20)).await;
+            }
+        })
+    };
+    sync(profile).await.unwrap();
+
+    // Removing things from a profile reaches peers too
+    users
+        .update(auth_a.clone(), UserUpdate::RemoveBioLine("location".into()))
         .await
         .unwrap();
-
-    harness::zzz(sec10()).await;
-    harness::zzz(sec10()).await;
-    harness::zzz(sec10()).await;
-    harness::zzz(sec10()).await;
-
-    let profile = net.b().users().get(auth_a.0).await.unwrap();
-    assert_eq!(profile.display_name, Some("spacekookie".to_owned()));
+    users
+        .update(auth_a.clone(), UserUpdate::AvatarData(None))
+        .await
+        .unwrap();
+    let profile = users.get(auth_a.0).await.unwrap();
+    assert!(profile.bio.is_empty() && profile.avatar.is_none());
+    sync(profile).await.unwrap();
 }
 
 #[async_std::test]
@@ -81,9 +103,16 @@ async fn change_pw() {
     let auth = net.a().users().create("abcdefg").await.unwrap();
     assert_eq!(net.a().users().list().await.len(), 1);
 
+    assert!(net
+        .a()
+        .users()
+        .change_pw(auth.clone(), "wrong password", "new and better password")
+        .await
+        .is_err());
     net.a()
         .users()
-        .change_pw(auth, "new and better password")
+        .change_pw(auth, "abcdefg", "new and better password")
+        .await
         .unwrap();
 }
 
@@ -112,15 +141,22 @@ async fn login_changed_pw() {
     let id = auth.0;
     assert_eq!(net.a().users().list().await.len(), 1);
 
-    net.a()
+    let other = net.a().users().login(id, "abcdefg").await.unwrap();
+    let auth = net
+        .a()
         .users()
-        .change_pw(auth.clone(), "new and better password")
+        .change_pw(auth.clone(), "abcdefg", "new and better password")
+        .await
         .unwrap();
 
+    // Other sessions are logged out
+    assert!(net.a().users().is_authenticated(other).await.is_err());
+
     // Yield user session
     net.a().users().logout(auth).await.unwrap();
 
     // Login again
+    assert!(net.a().users().login(id, "abcdefg").await.is_err());
     net.a()
         .users()
         .login(id, "new and better password")
@@ -128,6 +164,359 @@ async fn login_changed_pw() {
         .unwrap();
 }
 
+#[async_std::test]
+async fn logout_everywhere() {
+    let net = harness::init().await;
+
+    // Create a user and a second session
+    let auth = net.a().users().create("abcdefg").await.unwrap();
+    let web = net
+        .a()
+        .users()
+        .login_client(auth.0, "abcdefg", "web")
+        .await
+        .unwrap();
+
+    let sessions = net.a().users().sessions(auth.clone()).unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions
+        .iter()
+        .any(|s| s.client == Some("web".into()) && !s.current));
+
+    // Log out every session from the web client
+    net.a().users().logout_all(web.clone()).await.unwrap();
+    assert!(net.a().users().is_authenticated(auth).await.is_err());
+    assert!(net.a().users().is_authenticated(web).await.is_err());
+}
+
+#[async_std::test]
+async fn reopen_from_disk() {
+    use libqaul::{error::Error, Qaul};
+    use ratman::Router;
+
+    let dir = tempfile::tempdir().unwrap();
+    let q = Qaul::open(Router::new(), dir.path(), "root secret").unwrap();
+
+    let auth = q.users().create("abcdefg").await.unwrap();
+    let friend = q.users().create("hijklmn").await.unwrap().0;
+    q.contacts()
+        .modify(auth.clone(), &friend, |c| c.nick = Some("friend".into()))
+        .await
+        .unwrap();
+
+    // The directory can only be opened with the same secret
+    assert_eq!(
+        Qaul::open(Router::new(), dir.path(), "").err(),
+        Some(Error::NotAuthorised)
+    );
+    assert_eq!(
+        Qaul::open(Router::new(), dir.path(), "wrong secret").err(),
+        Some(Error::NotAuthorised)
+    );
+
+    // A second instance on the same directory sees the same users
+    let q = Qaul::open(Router::new(), dir.path(), "root secret").unwrap();
+    assert_eq!(q.users().list().await.len(), 2);
+    assert_eq!(
+        q.users().is_authenticated(auth.clone()).await,
+        Err(Error::NotAuthorised)
+    );
+    assert!(q.users().login(auth.0, "wrong password").await.is_err());
+
+    let auth = q.users().login(auth.0, "abcdefg").await.unwrap();
+    let contact = q.contacts().get(auth.clone(), &friend).await.unwrap();
+    assert_eq!(contact.nick, Some("friend".into()));
+
+    q.users().logout(auth.clone()).await.unwrap();
+    q.users().login(auth.0, "abcdefg").await.unwrap();
+}
+
+#[async_std::test]
+async fn reopen_changed_pw() {
+    use libqaul::Qaul;
+    use ratman::Router;
+
+    let dir = tempfile::tempdir().unwrap();
+    let q = Qaul::open(Router::new(), dir.path(), "root secret").unwrap();
+    let auth = q.users().create("abcdefg").await.unwrap();
+    q.users()
+        .change_pw(auth.clone(), "abcdefg", "hijklmn")
+        .await
+        .unwrap();
+
+    // Neither the pw hash, nor the stored keys accept the old password
+    let q = Qaul::open(Router::new(), dir.path(), "root secret").unwrap();
+    assert!(q.users().login(auth.0, "abcdefg").await.is_err());
+    q.users().login(auth.0, "hijklmn").await.unwrap();
+}
+
+#[async_std::test]
+async fn export_import() {
+    use libqaul::{error::Error, helpers::TagSet, services::MetadataMap, Qaul};
+    use ratman::Router;
+
+    let q = Qaul::new(Router::new());
+    q.services()
+        .register("net.qaul.test", |_| {})
+        .await
+        .unwrap();
+    let auth = q.users().create("abcdefg").await.unwrap();
+    let friend = q.users().create("hijklmn").await.unwrap().0;
+
+    q.users()
+        .update(
+            auth.clone(),
+            UserUpdate::DisplayName(Some("alice".to_owned())),
+        )
+        .await
+        .unwrap();
+    q.contacts()
+        .modify(auth.clone(), &friend, |c| c.nick = Some("friend".into()))
+        .await
+        .unwrap();
+    let meta = MetadataMap::new("settings").add("theme", vec![1]);
+    q.services()
+        .save(auth.clone(), "net.qaul.test", meta.clone(), TagSet::empty())
+        .await
+        .unwrap();
+
+    let backup = q
+        .users()
+        .export(auth.clone(), "backup passphrase", true)
+        .await
+        .unwrap();
+
+    // Move the user to a new device
+    let q = Qaul::new(Router::new());
+    q.services()
+        .register("net.qaul.test", |_| {})
+        .await
+        .unwrap();
+    assert_eq!(
+        q.users().import(&backup, "wrong passphrase").await,
+        Err(Error::NotAuthorised)
+    );
+
+    let imported = q
+        .users()
+        .import(&backup, "backup passphrase")
+        .await
+        .unwrap();
+    assert_eq!(imported.0, auth.0);
+    assert_eq!(
+        q.users().get(auth.0).await.unwrap().display_name,
+        Some("alice".to_owned())
+    );
+    assert_eq!(
+        q.contacts()
+            .get(imported.clone(), &friend)
+            .await
+            .unwrap()
+            .nick,
+        Some("friend".into())
+    );
+    assert_eq!(
+        q.services()
+            .query(imported.clone(), "net.qaul.test", TagSet::empty())
+            .await
+            .unwrap(),
+        vec![meta]
+    );
+
+    // The passphrase is the new login password
+    q.users().logout(imported).await.unwrap();
+    q.users().login(auth.0, "backup passphrase").await.unwrap();
+    assert_eq!(
+        q.users().import(&backup, "backup passphrase").await,
+        Err(Error::UserExists)
+    );
+}
+
+#[async_std::test]
+async fn import_remote_user() {
+    use std::{sync::Arc, time::Duration};
+    let net = harness::init().await;
+
+    let auth = net.a().users().create("abcdefg").await.unwrap();
+    let backup = net
+        .a()
+        .users()
+        .export(auth.clone(), "backup passphrase", false)
+        .await
+        .unwrap();
+
+    // Wait until node B knows the user as a remote user
+    harness::timeout(sec10(), async {
+        let b = Arc::clone(net.b());
+        while b.users().get(auth.0).await.is_err() {
+            harness::zzz(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let imported = net
+        .b()
+        .users()
+        .import(&backup, "backup passphrase")
+        .await
+        .unwrap();
+    assert_eq!(imported.0, auth.0);
+    assert_eq!(net.b().users().list().await.len(), 1);
+
+    // The user stays local, while node A keeps announcing it
+    harness::zzz(Duration::from_secs(3)).await;
+    net.b().users().logout(imported).await.unwrap();
+    net.b()
+        .users()
+        .login(auth.0, "backup passphrase")
+        .await
+        .unwrap();
+}
+
+#[async_std::test]
+async fn import_keeps_sessions() {
+    use libqaul::{
+        helpers::TagSet,
+        messages::{IdType, Mode, MsgQuery},
+        users::UserAuth,
+        Qaul,
+    };
+    use netmod_mem::MemMod;
+    use ratman::Router;
+    use std::sync::Arc;
+
+    let net = harness::init().await;
+    let alice = net.a().users().create("abcdefg").await.unwrap();
+    let bob = net.b().users().create("abcdefg").await.unwrap();
+    harness::zzz(harness::millis(2000)).await;
+
+    let send = |q: Arc<Qaul>, from: UserAuth, to| async move {
+        q.messages()
+            .send(
+                from,
+                Mode::Std(to),
+                IdType::unique(),
+                "net.qaul.testing",
+                TagSet::empty(),
+                vec![1, 3, 1, 2],
+            )
+            .await
+            .unwrap();
+    };
+    let recv = |q: Arc<Qaul>, user: UserAuth| async move {
+        q.messages()
+            .query(user, "net.qaul.testing", MsgQuery::new())
+            .await
+            .unwrap()
+            .all()
+            .await
+            .unwrap()
+            .len()
+    };
+
+    // Bob and Alice have a session, which Bob already got a reply on
+    send(Arc::clone(net.b()), bob.clone(), alice.0).await;
+    send(Arc::clone(net.a()), alice.clone(), bob.0).await;
+    harness::timeout(sec10(), async {
+        while recv(Arc::clone(net.b()), bob.clone()).await == 0 {
+            harness::zzz(harness::millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // Alice moves to a new device next to Bob
+    let backup = net
+        .a()
+        .users()
+        .export(alice.clone(), "backup passphrase", false)
+        .await
+        .unwrap();
+    net.a().users().delete(alice.clone()).await.unwrap();
+
+    let (ma, mb) = MemMod::make_pair();
+    net.b.0.add_endpoint(mb).await;
+    let router = Router::new();
+    router.add_endpoint(ma).await;
+    let c = Qaul::new(router);
+    let alice = c
+        .users()
+        .import(&backup, "backup passphrase")
+        .await
+        .unwrap();
+
+    // Bob can still reach her
+    harness::timeout(sec10(), async {
+        while recv(Arc::clone(&c), alice.clone()).await == 0 {
+            send(Arc::clone(net.b()), bob.clone(), alice.0).await;
+            harness::zzz(harness::millis(500)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[async_std::test]
+async fn verified_key_changed() {
+    use libqaul::{contacts::Proof, services::ServiceEvent};
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    let net = harness::init().await;
+    let alice = net.a().users().create("abcdefg").await.unwrap();
+    let bob = net.b().users().create("abcdefg").await.unwrap();
+    let name = UserUpdate::DisplayName(Some("bob".into()));
+    net.b()
+        .users()
+        .update(bob.clone(), name.clone())
+        .await
+        .unwrap();
+
+    let changes = Arc::new(Mutex::new(vec![]));
+    let events = Arc::clone(&changes);
+    net.a()
+        .services()
+        .register("net.qaul.testing", move |event| {
+            if let ServiceEvent::KeyChanged { user, old, new } = event {
+                events.lock().unwrap().push((user, old, new));
+            }
+        })
+        .await
+        .unwrap();
+
+    // Wait for node A to know Bob's name, and verify him
+    harness::timeout(sec10(), async {
+        let a = Arc::clone(net.a());
+        while a.users().get(bob.0).await.ok().and_then(|p| p.display_name) != Some("bob".into()) {
+            harness::zzz(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let contacts = net.a().contacts();
+    let number = contacts.safety_number(alice.clone(), &bob.0).await.unwrap();
+    contacts
+        .verify(alice.clone(), &bob.0, Proof::Compared(number.digits))
+        .await
+        .unwrap();
+
+    // Bob re-creates his account
+    let new = net.b().users().create("hijklmn").await.unwrap();
+    net.b().users().update(new.clone(), name).await.unwrap();
+
+    harness::timeout(sec10(), async {
+        while changes.lock().unwrap().is_empty() {
+            harness::zzz(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(*changes.lock().unwrap(), vec![(alice.0, bob.0, new.0)]);
+}
+
 #[async_std::test]
 async fn get_user_profile() {
     use libqaul::users::UserProfile;
@@ -142,6 +531,7 @@ async fn get_user_profile() {
         profile,
         UserProfile {
             id: auth.0,
+            version: 0,
             display_name: None,
             real_name: None,
             bio: Default::default(),
@@ -209,3 +599,174 @@ async fn simple_network_announce_reverse() {
     .await
     .unwrap();
 }
+
+#[async_std::test]
+async fn search_users() {
+    use libqaul::users::UserScope;
+    use std::sync::Arc;
+
+    let net = harness::init().await;
+    let alice = net.a().users().create("abcdefg").await.unwrap();
+    let bob = net.a().users().create("abcdefg").await.unwrap();
+    let carol = net.b().users().create("abcdefg").await.unwrap();
+
+    let users = net.a().users();
+    users
+        .update(alice.clone(), UserUpdate::DisplayName(Some("Alice".into())))
+        .await
+        .unwrap();
+    users
+        .update(
+            alice.clone(),
+            UserUpdate::SetBioLine("location".into(), "Berlin".into()),
+        )
+        .await
+        .unwrap();
+    users
+        .update(bob.clone(), UserUpdate::DisplayName(Some("Bob".into())))
+        .await
+        .unwrap();
+
+    // Wait for both profiles to reach node B
+    let b = Arc::clone(net.b());
+    harness::timeout(sec10(), async {
+        while b.users().get(bob.0).await.map(|p| p.version).unwrap_or(0) < 1
+            || b.users().get(alice.0).await.map(|p| p.version).unwrap_or(0) < 2
+        {
+            harness::zzz(harness::millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let search = |query: &'static str, scope| {
+        let (b, carol) = (Arc::clone(&b), carol.clone());
+        async move {
+            b.users()
+                .search(carol, query, scope)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|p| p.id)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(search("ALI", UserScope::All).await, vec![alice.0]);
+    assert_eq!(search("bb", UserScope::All).await, vec![bob.0]);
+
+    // A matching name ranks higher than a matching bio line
+    assert_eq!(search("b", UserScope::All).await, vec![bob.0, alice.0]);
+
+    // Carol was never seen on the network, only the users on node A
+    let mut seen = search("", UserScope::Recent).await;
+    seen.sort();
+    let mut expected = vec![alice.0, bob.0];
+    expected.sort();
+    assert_eq!(seen, expected);
+
+    b.contacts()
+        .modify(carol.clone(), &alice.0, |c| c.met = true)
+        .await
+        .unwrap();
+    assert_eq!(search("", UserScope::Contacts).await, vec![alice.0]);
+    assert_eq!(search("bob", UserScope::Contacts).await, vec![]);
+}
+
+#[async_std::test]
+async fn search_forged() {
+    use libqaul::{
+        helpers::Tag,
+        messages::{MsgQuery, SigTrust},
+        users::UserScope,
+        Identity, Qaul,
+    };
+    use netmod_mem::MemMod;
+    use ratman::{Message, Recipient, Router, TimePair};
+    use std::sync::Arc;
+
+    let (ma, mb) = MemMod::make_pair();
+    let a = Router::new();
+    a.add_endpoint(ma).await;
+    let b = Router::new();
+    b.add_endpoint(mb).await;
+
+    // Node B learns about Alice while she is online
+    let dir = tempfile::tempdir().unwrap();
+    let q = Qaul::open(b, dir.path(), "root secret").unwrap();
+    let alice = Identity::random();
+    a.add_user(alice).await.unwrap();
+    a.online(alice).await.unwrap();
+    harness::timeout(sec10(), async {
+        while q.users().get(alice).await.is_err() {
+            harness::zzz(harness::millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+    a.del_user(alice, false).await.unwrap();
+
+    // A new instance on the same library hasn't seen her yet
+    let (ma, mc) = MemMod::make_pair();
+    a.add_endpoint(ma).await;
+    let c = Router::new();
+    c.add_endpoint(mc).await;
+    let q = Qaul::open(c, dir.path(), "root secret").unwrap();
+    let carol = q.users().create("abcdefg").await.unwrap();
+
+    let search = |scope| {
+        let (q, carol) = (Arc::clone(&q), carol.clone());
+        async move {
+            q.users()
+                .search(carol, "", scope)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|p| p.id)
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(search(UserScope::All).await, vec![alice]);
+    assert_eq!(search(UserScope::Recent).await, vec![]);
+
+    // Anyone can flood an unsigned message in her name
+    let env = (
+        Identity::random(),
+        alice,
+        "net.qaul.testing",
+        vec![1u8, 3, 1, 2],
+        Vec::<Tag>::new(),
+    );
+    let payload = bincode::serialize(&(bincode::serialize(&env).unwrap(), None::<()>)).unwrap();
+    a.send(Message {
+        id: Identity::random(),
+        sender: alice,
+        recipient: Recipient::Flood,
+        payload,
+        timesig: TimePair::sending(),
+        sign: vec![],
+    })
+    .await
+    .unwrap();
+
+    let msgs = harness::timeout(sec5(), async {
+        loop {
+            let all = q
+                .messages()
+                .query(carol.clone(), "net.qaul.testing", MsgQuery::new())
+                .await
+                .unwrap()
+                .all()
+                .await
+                .unwrap();
+            if !all.is_empty() {
+                break all;
+            }
+            harness::zzz(harness::millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(msgs[0].sign, SigTrust::Invalid);
+    assert_eq!(search(UserScope::Recent).await, vec![]);
+}
@@ -0,0 +1,66 @@
+//! Mapping between MAC addresses and peer IDs
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    sync::Mutex,
+};
+
+/// A hardware (MAC) address
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Mac(pub [u8; 6]);
+
+impl Mac {
+    /// The ethernet broadcast address
+    pub const BROADCAST: Mac = Mac([0xFF; 6]);
+}
+
+impl Display for Mac {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let m = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            m[0], m[1], m[2], m[3], m[4], m[5]
+        )
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    ids: BTreeMap<Mac, u16>,
+    macs: BTreeMap<u16, Mac>,
+}
+
+/// Assigns stable IDs to the MAC addresses frames are received from
+///
+/// This table is used from the receiving thread, which is why it
+/// doesn't use an async lock.
+#[derive(Default)]
+pub(crate) struct AddrTable {
+    inner: Mutex<Inner>,
+}
+
+impl AddrTable {
+    /// Get the ID of a MAC address, assigning a new one if needed
+    pub(crate) fn id(&self, mac: Mac) -> u16 {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(id) = inner.ids.get(&mac) {
+            return *id;
+        }
+
+        let id = inner.ids.len() as u16;
+        inner.ids.insert(mac, id);
+        inner.macs.insert(id, mac);
+        debug!("New peer {} ({})", id, mac);
+        id
+    }
+
+    pub(crate) fn mac(&self, id: u16) -> Option<Mac> {
+        self.inner.lock().unwrap().macs.get(&id).cloned()
+    }
+
+    pub(crate) fn all(&self) -> Vec<Mac> {
+        self.inner.lock().unwrap().macs.values().cloned().collect()
+    }
+}
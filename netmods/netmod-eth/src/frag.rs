@@ -0,0 +1,166 @@
+//! Splitting frames into ethernet sized packets
+//!
+//! Every packet starts with a five byte header: the format version,
+//! a big endian `u16` sequence number which is shared by all packets
+//! of a frame, the index of the packet, and the number of packets
+//! that make up the frame.
+
+use crate::Mac;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    u8,
+};
+
+/// The current packet format version
+pub(crate) const VERSION: u8 = 1;
+
+/// The size of the packet header
+pub(crate) const HEADER: usize = 5;
+
+/// The number of incomplete frames that are kept around
+const PENDING: usize = 64;
+
+/// Split a serialised frame into packets that fit a link MTU
+///
+/// Returns `None` if the frame needs more than 255 packets.
+pub(crate) fn split(seq: u16, data: &[u8], mtu: usize) -> Option<Vec<Vec<u8>>> {
+    let chunks: Vec<_> = data.chunks(mtu - HEADER).collect();
+    if chunks.len() > u8::MAX as usize {
+        return None;
+    }
+
+    let count = chunks.len() as u8;
+    let seq = seq.to_be_bytes();
+    Some(
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(idx, chunk)| {
+                let mut pkt = Vec::with_capacity(HEADER + chunk.len());
+                pkt.extend_from_slice(&[VERSION, seq[0], seq[1], idx as u8, count]);
+                pkt.extend_from_slice(chunk);
+                pkt
+            })
+            .collect(),
+    )
+}
+
+struct Partial {
+    parts: Vec<Option<Vec<u8>>>,
+    missing: usize,
+}
+
+/// Collect packets until a frame is complete
+///
+/// Packets are grouped by their sender and sequence number.  Only a
+/// limited number of frames can be incomplete at the same time, and
+/// the oldest ones are dropped when new frames start arriving.
+#[derive(Default)]
+pub(crate) struct Reassembler {
+    pending: BTreeMap<(Mac, u16), Partial>,
+    order: VecDeque<(Mac, u16)>,
+}
+
+impl Reassembler {
+    /// Add a packet, and return the frame data if it completed one
+    pub(crate) fn push(&mut self, src: Mac, pkt: &[u8]) -> Option<Vec<u8>> {
+        if pkt.len() < HEADER || pkt[0] != VERSION {
+            warn!("Dropping invalid packet from {}", src);
+            return None;
+        }
+
+        let seq = u16::from_be_bytes([pkt[1], pkt[2]]);
+        let (idx, count) = (pkt[3] as usize, pkt[4] as usize);
+        let data = &pkt[HEADER..];
+        if idx >= count {
+            warn!("Dropping invalid packet from {}", src);
+            return None;
+        }
+
+        if count == 1 {
+            return Some(data.to_vec());
+        }
+
+        let key = (src, seq);
+        if !self.pending.contains_key(&key) {
+            if self.order.len() == PENDING {
+                let old = self.order.pop_front().unwrap();
+                self.pending.remove(&old);
+            }
+            self.order.push_back(key);
+        }
+
+        let partial = self.pending.entry(key).or_insert_with(|| Partial {
+            parts: vec![None; count],
+            missing: count,
+        });
+
+        // A sequence number was re-used before the frame completed
+        if partial.parts.len() != count {
+            *partial = Partial {
+                parts: vec![None; count],
+                missing: count,
+            };
+        }
+
+        if partial.parts[idx].is_none() {
+            partial.parts[idx] = Some(data.to_vec());
+            partial.missing -= 1;
+        }
+
+        if partial.missing == 0 {
+            self.order.retain(|k| *k != key);
+            let partial = self.pending.remove(&key).unwrap();
+            Some(partial.parts.into_iter().flatten().flatten().collect())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const A: Mac = Mac([2, 0, 0, 0, 0, 1]);
+    const B: Mac = Mac([2, 0, 0, 0, 0, 2]);
+
+    #[test]
+    fn split_and_reassemble() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let pkts = split(7, &data, 105).unwrap();
+        assert_eq!(pkts.len(), 10);
+        assert!(pkts.iter().all(|p| p.len() <= 105));
+
+        // Out of order, with a duplicate, interleaved with another sender
+        let mut r = Reassembler::default();
+        let single = split(7, b"hello", 105).unwrap();
+        for (i, p) in pkts.iter().enumerate().rev() {
+            if i == 4 {
+                assert_eq!(r.push(B, &single[0]), Some(b"hello".to_vec()));
+                assert_eq!(r.push(A, &pkts[9]), None);
+            }
+            let res = r.push(A, p);
+            assert_eq!(res.is_some(), i == 0);
+            if let Some(res) = res {
+                assert_eq!(res, data);
+            }
+        }
+    }
+
+    #[test]
+    fn oversized() {
+        assert!(split(0, &[0; 300], 6).is_none());
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let mut r = Reassembler::default();
+        let first = split(0, &[1; 20], 15).unwrap();
+        r.push(A, &first[0]);
+        for seq in 1..=PENDING as u16 {
+            r.push(A, &split(seq, &[1; 20], 15).unwrap()[0]);
+        }
+        assert_eq!(r.push(A, &first[1]), None);
+    }
+}
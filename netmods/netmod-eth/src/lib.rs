@@ -0,0 +1,184 @@
+//! netmod-eth sends frames directly over ethernet
+//!
+//! All other LAN drivers need an IP configuration, which isn't
+//! always available on links that mesh nodes share.  This endpoint
+//! instead uses a raw packet socket, and sends frames as ethernet
+//! packets with a custom EtherType (`0x88B5`, one of the EtherTypes
+//! reserved for local experiments).
+//!
+//! Frames flooded by the router are sent to the ethernet broadcast
+//! address.  Every MAC address that frames are received from is
+//! assigned a peer ID, which the router then uses to reply to it
+//! directly.  Frames that don't fit into a single packet (with the
+//! MTU of the interface) are split into several, and reassembled on
+//! the other side.
+//!
+//! Packet sockets require the `CAP_NET_RAW` capability.
+//!
+//! ```no_run
+//! # async_std::task::block_on(async {
+//! use netmod_eth::Endpoint;
+//! let ep = Endpoint::open("eth0").unwrap();
+//! # let router = ratman::Router::new();
+//! router.add_endpoint(ep).await;
+//! # });
+//! ```
+
+#[macro_use]
+extern crate tracing;
+
+mod addrs;
+mod frag;
+mod socket;
+
+use addrs::AddrTable;
+pub use addrs::Mac;
+use frag::Reassembler;
+use socket::Socket;
+
+use async_std::{
+    future, io,
+    sync::{channel, Arc, Receiver},
+    task,
+};
+use async_trait::async_trait;
+use netmod::{Endpoint as EndpointExt, Error, Frame, Result, Target};
+use std::{
+    fs,
+    sync::atomic::{AtomicU16, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// The EtherType used for all packets
+pub const ETHERTYPE: u16 = 0x88B5;
+
+/// The maximum delay before receiving is retried after an error
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// An endpoint on an ethernet interface
+pub struct Endpoint {
+    sock: Arc<Socket>,
+    addrs: Arc<AddrTable>,
+    mtu: usize,
+    seq: AtomicU16,
+    rx: Receiver<(Frame, Target)>,
+}
+
+impl Endpoint {
+    /// Open an endpoint on a network interface
+    ///
+    /// The packet size is taken from the MTU of the interface.  A
+    /// thread is spawned to receive packets, which runs for as long
+    /// as the endpoint exists.  Receive errors are retried with an
+    /// increasing delay.
+    pub fn open(iface: &str) -> io::Result<Arc<Self>> {
+        let mtu = fs::read_to_string(format!("/sys/class/net/{}/mtu", iface))
+            .ok()
+            .and_then(|mtu| mtu.trim().parse().ok())
+            .unwrap_or(1500);
+        Self::open_with_mtu(iface, mtu)
+    }
+
+    /// Open an endpoint with a specific packet size
+    pub fn open_with_mtu(iface: &str, mtu: usize) -> io::Result<Arc<Self>> {
+        if mtu <= frag::HEADER {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "mtu too small"));
+        }
+
+        let sock = Arc::new(Socket::bind(iface, ETHERTYPE)?);
+        sock.set_timeout(Duration::from_millis(500))?;
+        let addrs = Arc::new(AddrTable::default());
+        let (tx, rx) = channel(32);
+
+        let weak = Arc::downgrade(&sock);
+        let table = Arc::clone(&addrs);
+        let mut buf = vec![0; mtu.max(1500)];
+        thread::spawn(move || {
+            let mut frags = Reassembler::default();
+            let mut backoff = None;
+            while let Some(sock) = weak.upgrade() {
+                let (len, src) = match sock.recv_from(&mut buf) {
+                    Ok(Some(recv)) => recv,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        // The interface might come back (for example
+                        // after a cable was replugged), so keep trying
+                        let delay = backoff.map_or(Duration::from_secs(1), |d: Duration| {
+                            (d * 2).min(MAX_BACKOFF)
+                        });
+                        error!("Failed to receive packet, retrying in {:?}: {}", delay, e);
+                        drop(sock);
+                        thread::sleep(delay);
+                        backoff = Some(delay);
+                        continue;
+                    }
+                };
+                drop(sock);
+                backoff = None;
+
+                if let Some(data) = frags.push(src, &buf[..len]) {
+                    match bincode::deserialize(&data) {
+                        Ok(f) => task::block_on(tx.send((f, Target::Single(table.id(src))))),
+                        Err(_) => warn!("Failed to decode frame from {}", src),
+                    }
+                }
+            }
+        });
+
+        info!("Opened ethernet endpoint on {}", iface);
+        Ok(Arc::new(Self {
+            sock,
+            addrs,
+            mtu,
+            seq: AtomicU16::new(0),
+            rx,
+        }))
+    }
+
+    /// Get the MAC addresses of all peers that frames were received from
+    pub fn peers(&self) -> Vec<Mac> {
+        self.addrs.all()
+    }
+}
+
+#[async_trait]
+impl EndpointExt for Endpoint {
+    fn size_hint(&self) -> usize {
+        self.mtu - frag::HEADER
+    }
+
+    /// Send a frame to a peer, or broadcast it
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConnectionLost` for peers that this endpoint hasn't
+    /// received any frames from, or if the packet can't be sent, and
+    /// `FrameTooLarge` if the frame needs too many packets.
+    async fn send(&self, frame: Frame, target: Target) -> Result<()> {
+        let mac = match target {
+            Target::Flood => Mac::BROADCAST,
+            Target::Single(id) => self.addrs.mac(id).ok_or(Error::ConnectionLost)?,
+        };
+
+        let data = bincode::serialize(&frame).map_err(|_| Error::FrameTooLarge)?;
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let pkts = frag::split(seq, &data, self.mtu).ok_or(Error::FrameTooLarge)?;
+
+        let sock = Arc::clone(&self.sock);
+        task::spawn_blocking(move || pkts.iter().try_for_each(|pkt| sock.send_to(mac, pkt)))
+            .await
+            .map_err(|e| {
+                error!("Failed to send packet to {}: {}", mac, e);
+                Error::ConnectionLost
+            })
+    }
+
+    async fn next(&self) -> Result<(Frame, Target)> {
+        match self.rx.recv().await {
+            Some(ft) => Ok(ft),
+            // The receiving thread only stops once the endpoint is gone
+            None => future::pending().await,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! A packet socket bound to a single interface
+
+use crate::Mac;
+use std::{ffi::CString, io, mem, time::Duration};
+
+/// A raw `AF_PACKET` socket for one EtherType
+///
+/// The socket is of type `SOCK_DGRAM`, meaning that the kernel
+/// builds and strips ethernet headers, and only packets with the
+/// EtherType of the socket are received.
+pub(crate) struct Socket {
+    fd: libc::c_int,
+    ifindex: libc::c_int,
+    ethertype: u16,
+}
+
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        ret => Ok(ret),
+    }
+}
+
+impl Socket {
+    pub(crate) fn bind(iface: &str, ethertype: u16) -> io::Result<Self> {
+        let name = CString::new(iface)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid interface name"))?;
+        let ifindex = match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+            0 => return Err(io::Error::last_os_error()),
+            idx => idx as libc::c_int,
+        };
+
+        let fd = check(unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                ethertype.to_be() as libc::c_int,
+            )
+        })?;
+        let sock = Self {
+            fd,
+            ifindex,
+            ethertype,
+        };
+
+        let addr = sock.addr(Mac::BROADCAST);
+        check(unsafe {
+            libc::bind(
+                fd,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        })?;
+        Ok(sock)
+    }
+
+    fn addr(&self, mac: Mac) -> libc::sockaddr_ll {
+        let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = self.ethertype.to_be();
+        addr.sll_ifindex = self.ifindex;
+        addr.sll_halen = 6;
+        addr.sll_addr[..6].copy_from_slice(&mac.0);
+        addr
+    }
+
+    /// Set a timeout for `recv_from`, after which it returns `None`
+    pub(crate) fn set_timeout(&self, timeout: Duration) -> io::Result<()> {
+        let tv = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        check(unsafe {
+            libc::setsockopt(
+                self.fd,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &tv as *const _ as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        })
+        .map(|_| ())
+    }
+
+    pub(crate) fn send_to(&self, mac: Mac, data: &[u8]) -> io::Result<()> {
+        let addr = self.addr(mac);
+        check(unsafe {
+            libc::sendto(
+                self.fd,
+                data.as_ptr() as *const libc::c_void,
+                data.len(),
+                0,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            ) as libc::c_int
+        })
+        .map(|_| ())
+    }
+
+    /// Receive a packet that was sent by another host
+    ///
+    /// Returns `None` if the timeout expired.  Packets sent by this
+    /// host (which packet sockets also see) are skipped.
+    pub(crate) fn recv_from(&self, buf: &mut [u8]) -> io::Result<Option<(usize, Mac)>> {
+        loop {
+            let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            let mut len = mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+            let ret = unsafe {
+                libc::recvfrom(
+                    self.fd,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                    &mut addr as *mut _ as *mut libc::sockaddr,
+                    &mut len,
+                )
+            };
+
+            if ret < 0 {
+                let e = io::Error::last_os_error();
+                return match e.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Ok(None),
+                    io::ErrorKind::Interrupted => continue,
+                    _ => Err(e),
+                };
+            }
+
+            if addr.sll_pkttype == libc::PACKET_OUTGOING {
+                continue;
+            }
+
+            let mut mac = [0; 6];
+            mac.copy_from_slice(&addr.sll_addr[..6]);
+            return Ok(Some((ret as usize, Mac(mac))));
+        }
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
@@ -0,0 +1,87 @@
+//! Tests between two network namespaces, connected by a veth pair
+//!
+//! Creating namespaces requires root, which is why these tests are
+//! ignored by default.  Run them with `cargo test -p netmod-eth --
+//! --ignored`.
+
+use netmod::{Endpoint as _, Frame, Target};
+use netmod_eth::Endpoint;
+use ratman::{Identity, Router};
+use std::{fs::File, os::unix::io::AsRawFd, process::Command, sync::Arc, thread};
+
+fn ip(args: &[&str]) {
+    let status = Command::new("ip").args(args).status().unwrap();
+    assert!(status.success(), "ip {:?} failed", args);
+}
+
+/// Two namespaces with the interface `veth0` in each
+struct Link(&'static str, &'static str);
+
+impl Link {
+    fn new(a: &'static str, b: &'static str) -> Self {
+        ip(&["netns", "add", a]);
+        ip(&["netns", "add", b]);
+        let link = Self(a, b);
+        ip(&[
+            "link", "add", "veth0", "netns", a, "type", "veth", "peer", "name", "veth0", "netns", b,
+        ]);
+        ip(&["-n", a, "link", "set", "veth0", "up"]);
+        ip(&["-n", b, "link", "set", "veth0", "up"]);
+        link
+    }
+
+    /// Open an endpoint in a namespace
+    ///
+    /// The packet socket stays in the namespace it was created in,
+    /// so only the thread creating it needs to switch.
+    fn open(ns: &'static str) -> Arc<Endpoint> {
+        thread::spawn(move || {
+            let f = File::open(format!("/var/run/netns/{}", ns)).unwrap();
+            assert_eq!(unsafe { libc::setns(f.as_raw_fd(), libc::CLONE_NEWNET) }, 0);
+            Endpoint::open("veth0").unwrap()
+        })
+        .join()
+        .unwrap()
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        ip(&["netns", "del", self.0]);
+        ip(&["netns", "del", self.1]);
+    }
+}
+
+#[async_std::test]
+#[ignore]
+async fn fragmented_frames() {
+    let _link = Link::new("eth-frag-a", "eth-frag-b");
+    let a = Link::open("eth-frag-a");
+    let b = Link::open("eth-frag-b");
+
+    let frame = Frame::inline_flood(Identity::random(), vec![0xAB; 6000]);
+    a.send(frame.clone(), Target::Flood).await.unwrap();
+    let (f, t) = b.next().await.unwrap();
+    assert_eq!(f, frame);
+    assert_eq!(b.peers().len(), 1);
+
+    // Reply to the sender directly
+    let frame = Frame::dummy();
+    b.send(frame.clone(), t).await.unwrap();
+    assert_eq!(a.next().await.unwrap().0, frame);
+}
+
+#[async_std::test]
+#[ignore]
+async fn announce_and_discover() {
+    let _link = Link::new("eth-disc-a", "eth-disc-b");
+    let r1 = Router::new();
+    let r2 = Router::new();
+    r1.add_endpoint(Link::open("eth-disc-a")).await;
+    r2.add_endpoint(Link::open("eth-disc-b")).await;
+
+    let u2 = Identity::random();
+    r2.add_user(u2).await.unwrap();
+    r2.online(u2).await.unwrap();
+    assert_eq!(r1.discover().await, u2);
+}
@@ -1,29 +1,45 @@
-use async_std::sync::{channel, Receiver, Sender};
+use crate::link::{Link, LinkConfig};
+use async_std::sync::{channel, Arc, Mutex, Receiver, Sender};
+use clockctrl::Clock;
 use ratman_netmod::Frame;
+use std::time::Duration;
 
 /// A simple I/O wrapper around channels
 pub(crate) struct Io {
     pub out: Sender<Frame>,
     pub inc: Receiver<Frame>,
+    /// Link state, shared with the other end of the connection
+    pub link: Arc<Link>,
+    /// Point in time until which the outgoing direction is busy
+    pub busy: Mutex<Duration>,
 }
 
 impl Io {
-    pub(crate) fn make_pair() -> (Io, Io) {
+    pub(crate) fn make_pair_with(cfg: LinkConfig, cap: usize, clock: Clock) -> (Io, Io) {
         // On order to handle backpressure on the runtime we use
-        // bounded channels here via crossbeam.  This way a channel
-        // will be able to hold only 1 frame before it will be woken
-        // to deliver them (if it was parked).  Potentially we might
-        // want to make this a configurable.
-        let (a_to_b, b_from_a) = channel(1);
-        let (b_to_a, a_from_b) = channel(1);
+        // bounded channels here.  This way a channel will be able to
+        // hold only `cap` frames (1 for direct links) before it will
+        // be woken to deliver them (if it was parked).
+        let (a_to_b, b_from_a) = channel(cap);
+        let (b_to_a, a_from_b) = channel(cap);
+        let link = Arc::new(Link::new(cfg, clock));
         let a = Io {
             out: a_to_b,
             inc: a_from_b,
+            link: Arc::clone(&link),
+            busy: Mutex::new(Duration::from_secs(0)),
         };
         let b = Io {
             out: b_to_a,
             inc: b_from_a,
+            link,
+            busy: Mutex::new(Duration::from_secs(0)),
         };
         return (a, b);
     }
+
+    /// Send a frame through the link
+    pub(crate) async fn send(&self, frame: Frame) {
+        self.link.transmit(&self.out, &self.busy, frame).await
+    }
 }
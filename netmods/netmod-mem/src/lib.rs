@@ -2,6 +2,12 @@
 //!
 //! This aims to make testing any structure that binds against
 //! `netmod` easier and reproducable.
+//!
+//! Links are perfect by default, but can be configured to simulate
+//! latency, jitter, frame loss and limited bandwidth via a
+//! [`LinkConfig`](struct.LinkConfig.html).  Links can also be
+//! partitioned and healed at runtime, to simulate nodes moving out
+//! of range of each other.
 
 #![doc(html_favicon_url = "https://qaul.net/favicon.ico")]
 #![doc(html_logo_url = "https://qaul.net/img/qaul_icon-128.png")]
@@ -11,6 +17,7 @@ use async_std::{
     task,
 };
 use async_trait::async_trait;
+use clockctrl::Clock;
 use ratman_netmod::{Endpoint, Error as NetError, Frame, Result as NetResult, Target};
 
 /// An input/output pair of `mpsc::channel`s.
@@ -18,8 +25,12 @@ use ratman_netmod::{Endpoint, Error as NetError, Frame, Result as NetResult, Tar
 /// This is the actual mechanism by which data is moved around between `MemMod`s in
 /// different places.
 pub(crate) mod io;
-// Simulated transmission media.
-// pub mod media;
+/// Simulated link characteristics
+mod link;
+/// Simulated transmission media.
+pub mod media;
+
+pub use link::LinkConfig;
 
 /// Represents a single netmod endpoint that can connect to exactly one other, either
 /// as a 1-to-1 link between libqaul instances or as a link into a transmission
@@ -44,6 +55,21 @@ impl MemMod {
         (a, b)
     }
 
+    /// Create two already-paired `MemMod`s with simulated link properties
+    pub fn make_pair_with(cfg: LinkConfig) -> (Arc<Self>, Arc<Self>) {
+        Self::make_pair_with_clock(cfg, Clock::system())
+    }
+
+    /// Create two already-paired `MemMod`s, using a custom clock
+    ///
+    /// The clock is used to delay frames, which makes it possible to
+    /// use simulated links inside a `clockctrl::Simulation`.
+    pub fn make_pair_with_clock(cfg: LinkConfig, clock: Clock) -> (Arc<Self>, Arc<Self>) {
+        let (a, b) = (MemMod::new(), MemMod::new());
+        a.link_with_clock(&b, cfg, clock);
+        (a, b)
+    }
+
     /// Return `true` if the MemMod is linked to another one or
     /// `false` otherwise.
     pub fn linked(&self) -> bool {
@@ -56,20 +82,85 @@ impl MemMod {
     ///
     /// Panics if this MemMod, or the other one, is already linked.
     pub fn link(&self, pair: &MemMod) {
+        self.link_with(pair, LinkConfig::default());
+    }
+
+    /// Establish a 1-to-1 link with simulated link properties
+    ///
+    /// # Panics
+    ///
+    /// Panics if this MemMod, or the other one, is already linked.
+    pub fn link_with(&self, pair: &MemMod, cfg: LinkConfig) {
+        self.link_with_clock(pair, cfg, Clock::system());
+    }
+
+    /// Establish a 1-to-1 link with simulated link properties, and a
+    /// custom clock
+    ///
+    /// # Panics
+    ///
+    /// Panics if this MemMod, or the other one, is already linked.
+    pub fn link_with_clock(&self, pair: &MemMod, cfg: LinkConfig, clock: Clock) {
         if self.linked() || pair.linked() {
             panic!("Attempted to link an already linked MemMod.");
         }
-        let (my_io, their_io) = io::Io::make_pair();
+        let (my_io, their_io) = io::Io::make_pair_with(cfg, 1, clock);
 
         self.set_io_async(my_io);
         pair.set_io_async(their_io);
     }
 
+    /// Change the properties of the current link at runtime
+    ///
+    /// Because the link state is shared, this affects both ends of
+    /// the connection.  Does nothing if the MemMod isn't linked.
+    pub async fn configure(&self, cfg: LinkConfig) {
+        if let Some(ref io) = *self.io.read().await {
+            io.link.configure(cfg).await;
+        }
+    }
+
+    /// Get the properties of the current link, if there is one
+    pub async fn config(&self) -> Option<LinkConfig> {
+        self.io.read().await.as_ref().map(|io| io.link.config())
+    }
+
+    /// Temporarily cut the link, in both directions
+    ///
+    /// Unlike `split`, the link is kept alive, and all frames sent
+    /// while partitioned are silently lost.  Frames that were already
+    /// in flight are still delivered.  Call `heal` to restore the
+    /// connection.
+    pub async fn partition(&self) {
+        self.set_partitioned(true).await;
+    }
+
+    /// Restore a previously partitioned link
+    pub async fn heal(&self) {
+        self.set_partitioned(false).await;
+    }
+
+    /// Return `true` if the link is currently partitioned
+    pub async fn partitioned(&self) -> bool {
+        self.io
+            .read()
+            .await
+            .as_ref()
+            .map(|io| io.link.partitioned())
+            .unwrap_or(false)
+    }
+
+    async fn set_partitioned(&self, p: bool) {
+        if let Some(ref io) = *self.io.read().await {
+            io.link.set_partitioned(p);
+        }
+    }
+
     /// Establish a link to an `Io` module
     ///
     /// # Panics
     /// Panics if this MemMod is already linked.
-    pub(crate) fn link_raw(&mut self, io: io::Io) {
+    pub(crate) fn link_raw(&self, io: io::Io) {
         if self.linked() {
             panic!("Attempted to link an already linked MemMod.");
         }
@@ -105,7 +196,10 @@ impl Endpoint for MemMod {
         let io = self.io.read().await;
         match *io {
             None => Err(NetError::NotSupported),
-            Some(ref io) => Ok(io.out.send(frame).await),
+            Some(ref io) => {
+                io.send(frame).await;
+                Ok(())
+            }
         }
     }
 
@@ -0,0 +1,225 @@
+//! Simulated link characteristics
+//!
+//! By default a `MemMod` link is perfect: frames are delivered
+//! instantly, in order, and never lost.  Real networks are not that
+//! kind, and a lot of interesting router behaviour (journaling,
+//! re-sends, frame re-ordering) only shows up when they aren't.  A
+//! `LinkConfig` describes how badly a link should behave.
+
+use async_std::sync::{Mutex, Sender};
+use clockctrl::Clock;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use ratman_netmod::Frame;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+    time::Duration,
+};
+
+/// Describes the behaviour of a simulated link between two `MemMod`s
+///
+/// The default configuration is a perfect link.  All values can be
+/// changed at runtime via `MemMod::configure`.
+///
+/// ```
+/// # use netmod_mem::LinkConfig;
+/// # use std::time::Duration;
+/// let cfg = LinkConfig::default()
+///     .latency(Duration::from_millis(20))
+///     .jitter(Duration::from_millis(5))
+///     .drop_rate(0.1)
+///     .bandwidth(9600)
+///     .seed(1312);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkConfig {
+    /// Base delay applied to every frame
+    pub latency: Duration,
+    /// Upper bound of random delay added on top of `latency`
+    pub jitter: Duration,
+    /// Probability (`0.0` - `1.0`) that a frame is silently lost
+    pub drop_rate: f64,
+    /// Link capacity in bytes per second, `None` being unlimited
+    pub bandwidth: Option<u64>,
+    /// Seed for the random number generator of this link
+    ///
+    /// Two links with the same seed and config will drop and delay
+    /// the same frames.  When `None`, a random seed is chosen.
+    pub seed: Option<u64>,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_millis(0),
+            jitter: Duration::from_millis(0),
+            drop_rate: 0.0,
+            bandwidth: None,
+            seed: None,
+        }
+    }
+}
+
+impl LinkConfig {
+    /// Set the base latency of the link
+    pub fn latency(self, latency: Duration) -> Self {
+        Self { latency, ..self }
+    }
+
+    /// Set the maximum jitter added to the base latency
+    pub fn jitter(self, jitter: Duration) -> Self {
+        Self { jitter, ..self }
+    }
+
+    /// Set the probability of frames getting lost
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rate is not in the range `0.0` to `1.0`
+    pub fn drop_rate(self, drop_rate: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&drop_rate),
+            "Drop rate must be between 0.0 and 1.0"
+        );
+        Self { drop_rate, ..self }
+    }
+
+    /// Limit the link to a number of bytes per second
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bandwidth is 0
+    pub fn bandwidth(self, bytes_per_sec: u64) -> Self {
+        assert_ne!(bytes_per_sec, 0, "Cannot create a link with 0 bandwidth");
+        Self {
+            bandwidth: Some(bytes_per_sec),
+            ..self
+        }
+    }
+
+    /// Seed the random number generator of the link
+    pub fn seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    /// Return `true` if this link doesn't alter traffic at all
+    fn is_perfect(&self) -> bool {
+        self.latency == Duration::from_millis(0)
+            && self.jitter == Duration::from_millis(0)
+            && self.drop_rate == 0.0
+            && self.bandwidth.is_none()
+    }
+}
+
+/// Link state shared between both ends of a connection
+pub(crate) struct Link {
+    cfg: RwLock<LinkConfig>,
+    partitioned: AtomicBool,
+    rng: Mutex<StdRng>,
+    clock: Clock,
+}
+
+impl Link {
+    pub(crate) fn new(cfg: LinkConfig, clock: Clock) -> Self {
+        Self {
+            rng: Mutex::new(Self::make_rng(&cfg)),
+            cfg: RwLock::new(cfg),
+            partitioned: AtomicBool::new(false),
+            clock,
+        }
+    }
+
+    fn make_rng(cfg: &LinkConfig) -> StdRng {
+        match cfg.seed {
+            Some(s) => StdRng::seed_from_u64(s),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// Replace the link configuration
+    ///
+    /// If the new config carries a seed, the random number generator
+    /// is re-seeded as well.
+    pub(crate) async fn configure(&self, cfg: LinkConfig) {
+        if cfg.seed.is_some() {
+            *self.rng.lock().await = Self::make_rng(&cfg);
+        }
+        *self.cfg.write().unwrap() = cfg;
+    }
+
+    pub(crate) fn config(&self) -> LinkConfig {
+        self.cfg.read().unwrap().clone()
+    }
+
+    pub(crate) fn set_partitioned(&self, p: bool) {
+        self.partitioned.store(p, Ordering::SeqCst);
+    }
+
+    pub(crate) fn partitioned(&self) -> bool {
+        self.partitioned.load(Ordering::SeqCst)
+    }
+
+    /// Move a frame across the link, applying its configuration
+    ///
+    /// `busy` tracks when the sending side of the link is done
+    /// transmitting its previous frame (as time elapsed on the link
+    /// clock), which is used to model the bandwidth limit.  Frames
+    /// that are lost are silently dropped, the same way they would be
+    /// on a radio link.
+    pub(crate) async fn transmit(&self, out: &Sender<Frame>, busy: &Mutex<Duration>, frame: Frame) {
+        if self.partitioned() {
+            return;
+        }
+
+        let cfg = self.config();
+        if cfg.is_perfect() {
+            out.send(frame).await;
+            return;
+        }
+
+        // Occupy the link for as long as it takes to push the bytes
+        if let Some(bps) = cfg.bandwidth {
+            let size = bincode::serialized_size(&frame).unwrap_or(0);
+            let tx_time = Duration::from_nanos(size * 1_000_000_000 / bps);
+            let done = {
+                let mut busy = busy.lock().await;
+                let now = self.clock.elapsed();
+                *busy = if *busy > now { *busy } else { now } + tx_time;
+                *busy
+            };
+            self.clock
+                .sleep(done - self.clock.elapsed().min(done))
+                .await;
+        }
+
+        let (lost, delay) = {
+            let mut rng = self.rng.lock().await;
+            let lost = cfg.drop_rate > 0.0 && rng.gen_bool(cfg.drop_rate);
+            let jitter = match cfg.jitter.as_micros() as u64 {
+                0 => 0,
+                max => rng.gen_range(0, max + 1),
+            };
+            (lost, cfg.latency + Duration::from_micros(jitter))
+        };
+
+        if lost || self.partitioned() {
+            return;
+        }
+
+        if delay == Duration::from_millis(0) {
+            out.send(frame).await;
+        } else {
+            let out = out.clone();
+            let clock = self.clock.clone();
+            self.clock.spawn(async move {
+                clock.sleep(delay).await;
+                out.send(frame).await;
+            });
+        }
+    }
+}
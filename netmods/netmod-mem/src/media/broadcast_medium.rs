@@ -1,10 +1,16 @@
 use crate::io::Io;
 use crate::media::TaggedFrame;
-use crate::MemMod;
-use crossbeam_channel::TryRecvError;
-use ratman_netmod::Endpoint;
+use crate::{LinkConfig, MemMod};
+use async_std::sync::{channel, Arc, Sender};
+use clockctrl::Clock;
+use futures::future::FutureExt;
+use ratman_netmod::Frame;
 use std::collections::{BTreeMap, VecDeque};
 
+/// The number of frames an interface can buffer before the medium
+/// starts dropping frames addressed to it.
+const BUFFER_SIZE: usize = 64;
+
 /// A `BroadcastMedium` permits up to 2^32 `MemMod` interfaces to connect and
 /// always sends all messages to all connected interfaces except for the sender.
 // MemMods are assigned a 4-byte tag, and Frames are tagged with the internal ID
@@ -13,6 +19,10 @@ use std::collections::{BTreeMap, VecDeque};
 // the Frame can be transmitted.
 // This is effective but remains lightweight, adding only 8 bytes to each
 // Frame while in "transit".
+//
+// Delivering a frame over a link can take a while (for example when
+// the link has a bandwidth limit), so every interface has a queue of
+// outgoing frames, which a separate task pushes through the link.
 #[derive(Default)]
 pub struct BroadcastMedium {
     /// Records the number of ticks since creation.
@@ -22,10 +32,21 @@ pub struct BroadcastMedium {
     latency: u32,
     /// The last tag used for a MemMod.
     last_tag: u32,
+    /// Link properties applied to newly attached interfaces.
+    link: LinkConfig,
+    /// The clock used to delay frames on attached links.
+    clock: Clock,
     /// The frames currently in transmission.
     buffer: VecDeque<TaggedFrame>,
-    /// The raw `mpsc` transmission interfaces, with their associated tags.
-    interfaces: BTreeMap<u32, Io>,
+    /// The raw transmission interfaces, with their associated tags.
+    interfaces: BTreeMap<u32, Interface>,
+}
+
+/// An interface attached to a `BroadcastMedium`
+struct Interface {
+    io: Arc<Io>,
+    /// Frames waiting to be sent through `io`
+    queue: Sender<Frame>,
 }
 
 impl BroadcastMedium {
@@ -43,6 +64,20 @@ impl BroadcastMedium {
         }
     }
 
+    /// Set the link properties for interfaces attached in the future
+    ///
+    /// Every `MemMod` attached to the medium has its own link into
+    /// it, meaning that drop rates are applied per receiver, and that
+    /// a single `MemMod` can be partitioned from the medium.
+    pub fn set_link_config(&mut self, cfg: LinkConfig) {
+        self.link = cfg;
+    }
+
+    /// Set the clock for interfaces attached in the future
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
     /// Create and return an `Endpoint` (a `MemMod`) connected to this `BroadcastMedium`.
     ///
     /// This `Endpoint` will be assigned a unique ID and can immediately be used to
@@ -64,11 +99,22 @@ impl BroadcastMedium {
     /// b.next().await.expect("Failed to get message at b. Error");
     /// # }
     /// ```
-    pub fn make_netmod(&mut self) -> impl Endpoint {
-        let mut mm = MemMod::new();
-        let (mm_io, my_io) = Io::make_pair();
+    pub fn make_netmod(&mut self) -> Arc<MemMod> {
+        let mm = MemMod::new();
+        let (mm_io, my_io) = Io::make_pair_with(self.link.clone(), BUFFER_SIZE, self.clock.clone());
         mm.link_raw(mm_io);
-        self.interfaces.insert(self.last_tag, my_io);
+
+        let io = Arc::new(my_io);
+        let (queue, frames) = channel(BUFFER_SIZE);
+        let fwd = Arc::clone(&io);
+        self.clock.spawn(async move {
+            while let Some(frame) = frames.recv().await {
+                fwd.send(frame).await;
+            }
+        });
+
+        self.interfaces
+            .insert(self.last_tag, Interface { io, queue });
         self.last_tag += 1;
         mm
     }
@@ -90,31 +136,29 @@ impl BroadcastMedium {
     }
 
     /// Propagate frames in this `BroadcastMedium`.
+    ///
+    /// Frames that can't be delivered because the receiving
+    /// interface's buffer is full are lost.
     pub fn tick(&mut self) {
         let mut disconnected: Vec<u32> = Vec::new();
-        for (tag, io) in &mut self.interfaces {
-            match io.inc.try_recv() {
-                Ok(frame) => {
+        for (tag, iface) in &mut self.interfaces {
+            match iface.io.inc.recv().now_or_never() {
+                Some(Some(frame)) => {
                     self.buffer
                         .push_back(TaggedFrame::new(*tag, self.latency, frame));
                 }
-                Err(e) => match e {
-                    TryRecvError::Empty => (),
-                    TryRecvError::Disconnected => disconnected.push(*tag),
-                },
+                Some(None) => disconnected.push(*tag),
+                None => (),
             }
         }
 
         let mut to_send = 0;
-        self.buffer
-            .iter_mut()
-            .enumerate()
-            .for_each(|(_index, mut frame)| {
-                frame.ttl -= 1;
-                if frame.ttl == 0 {
-                    to_send += 1;
-                }
-            });
+        self.buffer.iter_mut().for_each(|frame| {
+            frame.ttl -= 1;
+            if frame.ttl == 0 {
+                to_send += 1;
+            }
+        });
 
         while to_send > 0 {
             to_send -= 1;
@@ -123,9 +167,10 @@ impl BroadcastMedium {
             );
             self.interfaces
                 .iter()
-                .for_each(|(tag, io)| match io.out.send(frame.frame.clone()) {
-                    Ok(_) => (),
-                    Err(_) => disconnected.push(*tag),
+                .filter(|(tag, iface)| **tag != frame.tag && !iface.queue.is_full())
+                .for_each(|(_, iface)| {
+                    // The queue has space, so this completes right away
+                    let _ = iface.queue.send(frame.frame.clone()).now_or_never();
                 });
         }
 
@@ -1,37 +1,96 @@
-// use async_std;
-// use netmod_mem::media::BroadcastMedium;
-// use ratman_netmod::{Endpoint, Frame, Target};
-
-// #[async_std::test]
-// async fn broadcast_medium_ping_pong() {
-//     let mut medium = BroadcastMedium::with_latency(1);
-//     let a = medium.make_netmod();
-//     let b = medium.make_netmod();
-//     a.send(Frame::dummy(), Target::default())
-//         .await
-//         .expect("Failed to send message from a. Error");
-//     medium.tick();
-//     b.next().await.expect("Failed to get message at b. Error");
-//     b.send(Frame::dummy(), Target::default())
-//         .await
-//         .expect("Failed to send message from b. Error");
-//     medium.tick();
-//     a.next().await.expect("Failed to get message at a. Error");
-// }
-
-// #[async_std::test]
-// async fn broadcast_medium_ping_broadcast() {
-//     let mut medium = BroadcastMedium::with_latency(1);
-//     let a = medium.make_netmod();
-//     let b = medium.make_netmod();
-//     let c = medium.make_netmod();
-//     let d = medium.make_netmod();
-
-//     a.send(Frame::dummy(), Target::default())
-//         .await
-//         .expect("Failed to send message from a. Error");
-//     medium.tick();
-//     b.next().await.expect("Failed to get message at b. Error");
-//     c.next().await.expect("Failed to get message at c. Error");
-//     d.next().await.expect("Failed to get message at d. Error");
-// }
+use netmod_mem::{media::BroadcastMedium, LinkConfig};
+use ratman_netmod::{Endpoint, Frame, Target};
+
+#[async_std::test]
+async fn broadcast_medium_ping_pong() {
+    let mut medium = BroadcastMedium::with_latency(1);
+    let a = medium.make_netmod();
+    let b = medium.make_netmod();
+    a.send(Frame::dummy(), Target::default())
+        .await
+        .expect("Failed to send message from a. Error");
+    medium.tick();
+    b.next().await.expect("Failed to get message at b. Error");
+    b.send(Frame::dummy(), Target::default())
+        .await
+        .expect("Failed to send message from b. Error");
+    medium.tick();
+    a.next().await.expect("Failed to get message at a. Error");
+}
+
+#[async_std::test]
+async fn broadcast_medium_ping_broadcast() {
+    let mut medium = BroadcastMedium::with_latency(1);
+    let a = medium.make_netmod();
+    let b = medium.make_netmod();
+    let c = medium.make_netmod();
+    let d = medium.make_netmod();
+
+    a.send(Frame::dummy(), Target::default())
+        .await
+        .expect("Failed to send message from a. Error");
+    medium.tick();
+    b.next().await.expect("Failed to get message at b. Error");
+    c.next().await.expect("Failed to get message at c. Error");
+    d.next().await.expect("Failed to get message at d. Error");
+}
+
+#[async_std::test]
+async fn broadcast_medium_latency() {
+    let mut medium = BroadcastMedium::with_latency(3);
+    let a = medium.make_netmod();
+    let b = medium.make_netmod();
+
+    let f = Frame::dummy();
+    a.send(f.clone(), Target::default()).await.unwrap();
+
+    // The frame is in transit for three ticks
+    medium.tick();
+    medium.tick();
+    assert!(
+        async_std::future::timeout(std::time::Duration::from_millis(50), b.next())
+            .await
+            .is_err()
+    );
+
+    medium.tick();
+    assert_eq!(b.next().await.unwrap().0, f);
+}
+
+#[async_std::test]
+async fn broadcast_medium_partition() {
+    let mut medium = BroadcastMedium::with_latency(1);
+    medium.set_link_config(LinkConfig::default().seed(1312));
+    let a = medium.make_netmod();
+    let b = medium.make_netmod();
+    let c = medium.make_netmod();
+
+    // C can't hear anything while it's partitioned
+    c.partition().await;
+    a.send(Frame::dummy(), Target::default()).await.unwrap();
+    medium.tick();
+    b.next().await.unwrap();
+
+    c.heal().await;
+    let f = Frame::dummy();
+    a.send(f.clone(), Target::default()).await.unwrap();
+    medium.tick();
+    assert_eq!(c.next().await.unwrap().0, f);
+}
+
+#[async_std::test]
+async fn broadcast_medium_bandwidth() {
+    let mut medium = BroadcastMedium::with_latency(1);
+    medium.set_link_config(LinkConfig::default().bandwidth(64 * 1024));
+    let a = medium.make_netmod();
+    let b = medium.make_netmod();
+
+    // Frames are delayed by the bandwidth limit, but not lost
+    for _ in 0..4 {
+        a.send(Frame::dummy(), Target::default()).await.unwrap();
+        medium.tick();
+    }
+    for _ in 0..4 {
+        b.next().await.unwrap();
+    }
+}
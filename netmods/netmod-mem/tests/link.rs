@@ -0,0 +1,111 @@
+//! Tests for simulated link properties
+
+use async_std::future;
+use netmod_mem::{LinkConfig, MemMod};
+use ratman_netmod::{Endpoint, Frame, Target};
+use std::time::{Duration, Instant};
+
+fn millis(m: u64) -> Duration {
+    Duration::from_millis(m)
+}
+
+#[async_std::test]
+async fn latency() {
+    let (a, b) = MemMod::make_pair_with(LinkConfig::default().latency(millis(100)));
+
+    let start = Instant::now();
+    a.send(Frame::dummy(), Target::default()).await.unwrap();
+    b.next().await.unwrap();
+    assert!(start.elapsed() >= millis(100));
+}
+
+#[async_std::test]
+async fn jitter_is_bounded() {
+    let cfg = LinkConfig::default()
+        .latency(millis(10))
+        .jitter(millis(20))
+        .seed(42);
+    let (a, b) = MemMod::make_pair_with(cfg);
+
+    for _ in 0..5 {
+        let start = Instant::now();
+        a.send(Frame::dummy(), Target::default()).await.unwrap();
+        b.next().await.unwrap();
+        let t = start.elapsed();
+        assert!(t >= millis(10));
+        assert!(t < millis(500));
+    }
+}
+
+#[async_std::test]
+async fn drop_everything() {
+    let (a, b) = MemMod::make_pair_with(LinkConfig::default().drop_rate(1.0));
+
+    a.send(Frame::dummy(), Target::default()).await.unwrap();
+    assert!(future::timeout(millis(100), b.next()).await.is_err());
+}
+
+#[async_std::test]
+async fn drop_rate_is_reproducible() {
+    async fn run(seed: u64) -> Vec<bool> {
+        let cfg = LinkConfig::default().drop_rate(0.5).seed(seed);
+        let (a, b) = MemMod::make_pair_with(cfg);
+        let mut received = vec![];
+        for _ in 0..16 {
+            a.send(Frame::dummy(), Target::default()).await.unwrap();
+            received.push(future::timeout(millis(20), b.next()).await.is_ok());
+        }
+        received
+    }
+
+    let first = run(1312).await;
+    assert_eq!(first, run(1312).await);
+    assert!(first.iter().any(|r| *r));
+    assert!(first.iter().any(|r| !*r));
+}
+
+#[async_std::test]
+async fn bandwidth() {
+    // A frame is a little over 100 bytes, so at 1000 bytes/s
+    // sending three takes at least 300ms
+    let (a, b) = MemMod::make_pair_with(LinkConfig::default().bandwidth(1000));
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        a.send(Frame::dummy(), Target::default()).await.unwrap();
+        b.next().await.unwrap();
+    }
+    assert!(start.elapsed() >= millis(300));
+}
+
+#[async_std::test]
+async fn partition_and_heal() {
+    let (a, b) = MemMod::make_pair();
+
+    a.partition().await;
+    assert!(a.partitioned().await);
+    assert!(b.partitioned().await);
+
+    b.send(Frame::dummy(), Target::default()).await.unwrap();
+    assert!(future::timeout(millis(50), a.next()).await.is_err());
+
+    b.heal().await;
+    let f = Frame::dummy();
+    b.send(f.clone(), Target::default()).await.unwrap();
+    assert_eq!(a.next().await.unwrap().0, f);
+}
+
+#[async_std::test]
+async fn configure_at_runtime() {
+    let (a, b) = MemMod::make_pair();
+    assert_eq!(a.config().await, Some(LinkConfig::default()));
+
+    let cfg = LinkConfig::default().latency(millis(50));
+    b.configure(cfg.clone()).await;
+    assert_eq!(a.config().await, Some(cfg));
+
+    let start = Instant::now();
+    a.send(Frame::dummy(), Target::default()).await.unwrap();
+    b.next().await.unwrap();
+    assert!(start.elapsed() >= millis(50));
+}
@@ -0,0 +1,64 @@
+//! The capturing endpoint wrapper
+
+use crate::{Direction, Record, Writer};
+use async_std::{
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use async_trait::async_trait;
+use netmod::{Endpoint, Frame, Result, Target};
+use tracing::error;
+
+/// Record all frames that cross an endpoint
+///
+/// A `Capture` can wrap any other endpoint, and is used in its place
+/// when adding it to a router.  Frames are written to the capture
+/// file before being passed on to the wrapped endpoint (for sending),
+/// or to the router (for receiving).  Failing to write the capture
+/// doesn't affect the traffic itself.
+pub struct Capture<E> {
+    inner: Arc<E>,
+    out: Mutex<Writer>,
+}
+
+impl<E: Endpoint> Capture<E> {
+    /// Wrap an endpoint, writing the capture to a new file
+    pub async fn new<P: AsRef<Path>>(inner: Arc<E>, path: P) -> io::Result<Arc<Self>> {
+        let out = Mutex::new(Writer::create(path).await?);
+        Ok(Arc::new(Self { inner, out }))
+    }
+
+    /// Get access to the wrapped endpoint
+    pub fn inner(&self) -> &Arc<E> {
+        &self.inner
+    }
+
+    async fn record(&self, dir: Direction, target: Target, frame: &Frame) {
+        let rec = Record::now(dir, target, frame.clone());
+        if let Err(e) = self.out.lock().await.write(&rec).await {
+            error!("Failed to write capture: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl<E> Endpoint for Capture<E>
+where
+    E: Endpoint + Send + Sync,
+{
+    fn size_hint(&self) -> usize {
+        self.inner.size_hint()
+    }
+
+    async fn send(&self, frame: Frame, target: Target) -> Result<()> {
+        self.record(Direction::Out, target, &frame).await;
+        self.inner.send(frame, target).await
+    }
+
+    async fn next(&self) -> Result<(Frame, Target)> {
+        let (frame, target) = self.inner.next().await?;
+        self.record(Direction::In, target, &frame).await;
+        Ok((frame, target))
+    }
+}
@@ -0,0 +1,170 @@
+//! The capture file format
+
+use async_std::{
+    fs::File,
+    io::{self, prelude::*, BufReader, BufWriter},
+    path::Path,
+};
+use netmod::{Frame, Target};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Magic bytes at the start of every capture file
+pub(crate) const MAGIC: &[u8; 4] = b"RCAP";
+
+/// The current version of the capture format
+pub const VERSION: u16 = 1;
+
+/// The largest record that is read from, or written to a capture
+///
+/// This is well above the size of any frame a driver sends, and
+/// mostly protects against allocating huge buffers for a corrupted
+/// length prefix.
+pub const MAX_RECORD: usize = 65535;
+
+/// The direction a frame was travelling in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    /// Received from the network (returned by `next`)
+    In,
+    /// Sent to the network (passed to `send`)
+    Out,
+}
+
+/// A single frame that crossed a captured endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// Time since the UNIX epoch, in microseconds
+    pub time: u64,
+    /// Which way the frame was going
+    pub dir: Direction,
+    /// The target the frame was sent to, or received from
+    pub target: Target,
+    /// The frame itself
+    pub frame: Frame,
+}
+
+impl Record {
+    /// Create a new record, timestamped with the current time
+    pub fn now(dir: Direction, target: Target, frame: Frame) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        Self {
+            time,
+            dir,
+            target,
+            frame,
+        }
+    }
+
+    /// Get the time of this record as a `SystemTime`
+    pub fn system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(self.time)
+    }
+}
+
+fn invalid<E: std::fmt::Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn too_large(len: usize) -> io::Error {
+    invalid(format!(
+        "record of {} bytes exceeds the maximum of {}",
+        len, MAX_RECORD
+    ))
+}
+
+/// Write records to a capture file
+pub struct Writer {
+    out: BufWriter<File>,
+}
+
+impl Writer {
+    /// Create (or truncate) a capture file and write the header
+    pub async fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path).await?);
+        out.write_all(MAGIC).await?;
+        out.write_all(&VERSION.to_be_bytes()).await?;
+        out.write_all(&[0, 0]).await?;
+        out.flush().await?;
+        Ok(Self { out })
+    }
+
+    /// Append a record
+    ///
+    /// Every record is flushed immediately, so that a capture is
+    /// usable even if the process that wrote it crashed.
+    pub async fn write(&mut self, rec: &Record) -> io::Result<()> {
+        let data = bincode::serialize(rec).map_err(invalid)?;
+        if data.len() > MAX_RECORD {
+            return Err(too_large(data.len()));
+        }
+
+        self.out
+            .write_all(&(data.len() as u32).to_be_bytes())
+            .await?;
+        self.out.write_all(&data).await?;
+        self.out.flush().await
+    }
+}
+
+/// Read records from a capture file
+pub struct Reader {
+    inp: BufReader<File>,
+}
+
+impl Reader {
+    /// Open a capture file and check its header
+    pub async fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut inp = BufReader::new(File::open(path).await?);
+        let mut header = [0; 8];
+        inp.read_exact(&mut header).await?;
+
+        if &header[..4] != MAGIC {
+            return Err(invalid("not a ratman capture file"));
+        }
+
+        match u16::from_be_bytes([header[4], header[5]]) {
+            VERSION => Ok(Self { inp }),
+            v => Err(invalid(format!("unsupported capture version {}", v))),
+        }
+    }
+
+    /// Read the next record, returning `None` at the end of the file
+    ///
+    /// A record that was only partially written (for example because
+    /// the capturing process crashed) is treated as the end of file.
+    /// Records larger than [`MAX_RECORD`] are rejected.
+    ///
+    /// [`MAX_RECORD`]: constant.MAX_RECORD.html
+    pub async fn next(&mut self) -> io::Result<Option<Record>> {
+        let mut len = [0; 4];
+        match self.inp.read_exact(&mut len).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_RECORD {
+            return Err(too_large(len));
+        }
+
+        let mut data = vec![0; len];
+        match self.inp.read_exact(&mut data).await {
+            Ok(()) => bincode::deserialize(&data).map(Some).map_err(invalid),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read all remaining records
+    pub async fn read_all(mut self) -> io::Result<Vec<Record>> {
+        let mut records = vec![];
+        while let Some(rec) = self.next().await? {
+            records.push(rec);
+        }
+        Ok(records)
+    }
+}
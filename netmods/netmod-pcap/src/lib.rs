@@ -0,0 +1,56 @@
+//! A netmod wrapper to capture and replay frame traffic
+//!
+//! Debugging a routing issue that only shows up in the field is
+//! hard, because the traffic that caused it is gone by the time
+//! anyone looks at it.  This crate provides a [`Capture`] endpoint,
+//! which wraps any other endpoint and records every `(Frame, Target)`
+//! that crosses it, and a [`Replay`] endpoint, which plays such a
+//! capture back into a router.
+//!
+//! ```no_run
+//! # async_std::task::block_on(async {
+//! use netmod_pcap::{Capture, Replay, Timing};
+//! # let (ep, _) = netmod_mem::MemMod::make_pair();
+//! # let router = ratman::Router::new();
+//!
+//! // Record everything going through `ep`
+//! let cap = Capture::new(ep, "session.rcap").await.unwrap();
+//! router.add_endpoint(cap).await;
+//!
+//! // ... and later, feed the recorded traffic into another router
+//! let replay = Replay::open("session.rcap", Timing::Original).await.unwrap();
+//! # });
+//! ```
+//!
+//! ## File format
+//!
+//! A capture file starts with an 8 byte header:
+//!
+//! | Bytes | Content                                   |
+//! |-------|-------------------------------------------|
+//! | 0..4  | Magic bytes: `RCAP`                       |
+//! | 4..6  | Format version, big endian (currently 1)  |
+//! | 6..8  | Reserved, set to 0                        |
+//!
+//! Following the header is a sequence of records, each of which is a
+//! big endian `u32` length, followed by that many bytes of
+//! [bincode]-encoded [`Record`]: a timestamp (microseconds since the
+//! UNIX epoch), the direction of the frame, the target, and the frame
+//! itself.  Records are flushed as they are written, and a truncated
+//! last record is ignored when reading.
+//!
+//! [`Capture`]: struct.Capture.html
+//! [`Replay`]: struct.Replay.html
+//! [`Record`]: struct.Record.html
+//! [bincode]: https://docs.rs/bincode
+
+#[macro_use]
+extern crate serde;
+
+mod capture;
+mod format;
+mod replay;
+
+pub use capture::Capture;
+pub use format::{Direction, Reader, Record, Writer, MAX_RECORD, VERSION};
+pub use replay::{Replay, Timing};
@@ -0,0 +1,89 @@
+//! The replaying endpoint
+
+use crate::{Direction, Reader, Record};
+use async_std::{
+    future, io,
+    path::Path,
+    sync::{Arc, Mutex},
+    task,
+};
+use async_trait::async_trait;
+use netmod::{Endpoint, Frame, Result, Target};
+use std::{collections::VecDeque, time::Duration};
+
+/// How a replay paces the frames it plays back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Play back all frames as fast as they are polled
+    Instant,
+    /// Keep the time between frames that was recorded
+    Original,
+}
+
+/// An endpoint that plays back a capture
+///
+/// Only frames that were received by the captured endpoint
+/// (`Direction::In`) are played back, which puts a router that uses
+/// a `Replay` into the same position as the router that was
+/// captured.  Frames sent to a `Replay` are discarded.  Once all
+/// frames were played back, the endpoint stays silent.
+pub struct Replay {
+    records: Mutex<VecDeque<Record>>,
+    last: Mutex<Option<u64>>,
+    timing: Timing,
+}
+
+impl Replay {
+    /// Load a capture file for playback
+    pub async fn open<P: AsRef<Path>>(path: P, timing: Timing) -> io::Result<Arc<Self>> {
+        let records = Reader::open(path).await?.read_all().await?;
+        Ok(Self::new(records, timing))
+    }
+
+    /// Play back a set of records
+    pub fn new(records: Vec<Record>, timing: Timing) -> Arc<Self> {
+        Arc::new(Self {
+            records: Mutex::new(
+                records
+                    .into_iter()
+                    .filter(|rec| rec.dir == Direction::In)
+                    .collect(),
+            ),
+            last: Mutex::new(None),
+            timing,
+        })
+    }
+
+    /// Return the number of frames that haven't been played back yet
+    pub async fn remaining(&self) -> usize {
+        self.records.lock().await.len()
+    }
+}
+
+#[async_trait]
+impl Endpoint for Replay {
+    fn size_hint(&self) -> usize {
+        0
+    }
+
+    async fn send(&self, _: Frame, _: Target) -> Result<()> {
+        Ok(())
+    }
+
+    async fn next(&self) -> Result<(Frame, Target)> {
+        let rec = match self.records.lock().await.pop_front() {
+            Some(rec) => rec,
+            None => future::pending().await,
+        };
+
+        if self.timing == Timing::Original {
+            let mut last = self.last.lock().await;
+            if let Some(prev) = *last {
+                task::sleep(Duration::from_micros(rec.time.saturating_sub(prev))).await;
+            }
+            *last = Some(rec.time);
+        }
+
+        Ok((rec.frame, rec.target))
+    }
+}
@@ -0,0 +1,68 @@
+use netmod::{Endpoint, Frame, Target};
+use netmod_mem::MemMod;
+use netmod_pcap::{Capture, Direction, Reader, Replay, Timing, MAX_RECORD};
+use ratman::{Identity, Router};
+
+#[async_std::test]
+async fn capture_both_directions() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.rcap");
+
+    let (a, b) = MemMod::make_pair();
+    let a = Capture::new(a, &path).await.unwrap();
+
+    let frame = Frame::dummy();
+    a.send(frame.clone(), Target::Single(1)).await.unwrap();
+    b.next().await.unwrap();
+    b.send(Frame::dummy(), Target::Flood).await.unwrap();
+    a.next().await.unwrap();
+
+    let recs = Reader::open(&path).await.unwrap().read_all().await.unwrap();
+    assert_eq!(recs.len(), 2);
+    assert_eq!(recs[0].dir, Direction::Out);
+    assert_eq!(recs[0].target, Target::Single(1));
+    assert_eq!(recs[0].frame, frame);
+    assert_eq!(recs[1].dir, Direction::In);
+    assert!(recs[0].time <= recs[1].time);
+}
+
+#[async_std::test]
+async fn replay_announcement() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("announce.rcap");
+
+    // Capture the announcement that r1 receives from r2
+    let (a, b) = MemMod::make_pair();
+    let r1 = Router::new();
+    let r2 = Router::new();
+    r1.add_endpoint(Capture::new(a, &path).await.unwrap()).await;
+    r2.add_endpoint(b).await;
+
+    let u2 = Identity::random();
+    r2.add_user(u2).await.unwrap();
+    r2.online(u2).await.unwrap();
+    assert_eq!(r1.discover().await, u2);
+
+    // A fresh router discovers the same user from the capture alone
+    let r3 = Router::new();
+    let replay = Replay::open(&path, Timing::Original).await.unwrap();
+    assert!(replay.remaining().await > 0);
+    r3.add_endpoint(replay).await;
+    assert_eq!(r3.discover().await, u2);
+}
+
+#[async_std::test]
+async fn oversized_record() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.rcap");
+
+    // A valid header, followed by a corrupted length prefix
+    let mut data = b"RCAP\0\x01\0\0".to_vec();
+    data.extend_from_slice(&(MAX_RECORD as u32 + 1).to_be_bytes());
+    data.extend_from_slice(&[0; 64]);
+    std::fs::write(&path, data).unwrap();
+
+    let mut reader = Reader::open(&path).await.unwrap();
+    let e = reader.next().await.unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+}
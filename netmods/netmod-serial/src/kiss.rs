@@ -0,0 +1,168 @@
+//! KISS framing with an HDLC frame check sequence
+//!
+//! A KISS frame is delimited by `FEND` bytes, starts with a command
+//! byte (the upper nibble selects the TNC port, the lower nibble the
+//! command), and escapes any `FEND` or `FESC` in its contents.  Plain
+//! KISS leaves error detection to the TNC, which doesn't help on a
+//! raw serial line, so every data frame carries a CRC-16/X.25 (the
+//! HDLC FCS) over its payload, in little endian, before escaping.
+
+pub(crate) const FEND: u8 = 0xC0;
+pub(crate) const FESC: u8 = 0xDB;
+pub(crate) const TFEND: u8 = 0xDC;
+pub(crate) const TFESC: u8 = 0xDD;
+
+/// The KISS command for a data frame
+const DATA: u8 = 0x00;
+
+/// Compute the CRC-16/X.25 of a byte sequence
+pub(crate) fn crc16(data: &[u8]) -> u16 {
+    !data.iter().fold(0xFFFF, |crc, b| {
+        (0..8).fold(crc ^ *b as u16, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0x8408,
+            _ => crc >> 1,
+        })
+    })
+}
+
+/// Encode a payload into a data frame for a TNC port
+pub(crate) fn encode(port: u8, data: &[u8]) -> Vec<u8> {
+    let crc = crc16(data).to_le_bytes();
+    let mut out = Vec::with_capacity(data.len() + 8);
+    out.push(FEND);
+    out.push(port << 4 | DATA);
+    for b in data.iter().chain(crc.iter()) {
+        match *b {
+            FEND => out.extend_from_slice(&[FESC, TFEND]),
+            FESC => out.extend_from_slice(&[FESC, TFESC]),
+            b => out.push(b),
+        }
+    }
+    out.push(FEND);
+    out
+}
+
+/// Check a decoded frame, and return its payload
+///
+/// Frames for other ports, non-data frames, and frames with a bad
+/// checksum are rejected.
+pub(crate) fn unpack(port: u8, raw: &[u8]) -> Option<&[u8]> {
+    match raw.split_first() {
+        Some((cmd, rest)) if *cmd == port << 4 | DATA && rest.len() >= 2 => {
+            let (data, crc) = rest.split_at(rest.len() - 2);
+            if crc16(data).to_le_bytes() == crc {
+                Some(data)
+            } else {
+                warn!("Dropping frame with invalid checksum");
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// An incremental KISS frame decoder
+///
+/// Bytes are pushed one at a time, and complete (unescaped) frames
+/// are returned as their closing `FEND` is seen.  Frames longer than
+/// `max` bytes are dropped, so that line noise can't make the decoder
+/// buffer without bounds.
+pub(crate) struct Decoder {
+    buf: Vec<u8>,
+    max: usize,
+    escaped: bool,
+    overflow: bool,
+}
+
+impl Decoder {
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            buf: vec![],
+            max,
+            escaped: false,
+            overflow: false,
+        }
+    }
+
+    pub(crate) fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        match byte {
+            FEND => {
+                let overflow = self.overflow;
+                let frame = std::mem::take(&mut self.buf);
+                self.escaped = false;
+                self.overflow = false;
+                match frame.len() {
+                    _ if overflow => {
+                        warn!("Dropping oversized frame");
+                        None
+                    }
+                    0 => None,
+                    _ => Some(frame),
+                }
+            }
+            _ if self.overflow => None,
+            FESC => {
+                self.escaped = true;
+                None
+            }
+            b => {
+                let b = match (self.escaped, b) {
+                    (true, TFEND) => FEND,
+                    (true, TFESC) => FESC,
+                    (_, b) => b,
+                };
+                self.escaped = false;
+
+                if self.buf.len() < self.max {
+                    self.buf.push(b);
+                } else {
+                    self.buf.clear();
+                    self.overflow = true;
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode(port: u8, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut dec = Decoder::new(64);
+        bytes
+            .iter()
+            .filter_map(|b| dec.push(*b))
+            .filter_map(|raw| unpack(port, &raw).map(|d| d.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x906E);
+    }
+
+    #[test]
+    fn escape_roundtrip() {
+        let data = vec![1, FEND, 2, FESC, TFEND, TFESC, FEND, FEND];
+        let enc = encode(3, &data);
+        assert_eq!(enc.iter().filter(|b| **b == FEND).count(), 2);
+        assert_eq!(decode(3, &enc), vec![data]);
+    }
+
+    #[test]
+    fn filter_frames() {
+        let mut enc = encode(1, b"wrong port");
+
+        let mut bad = encode(0, b"bad checksum");
+        bad[4] ^= 0xFF;
+        enc.extend(bad);
+
+        // Line noise without a closing FEND, then an oversized frame
+        enc.extend(&[0x42; 16]);
+        enc.extend(encode(0, &[0x42; 128]));
+        enc.extend(encode(0, b"hello"));
+        assert_eq!(decode(0, &enc), vec![b"hello".to_vec()]);
+    }
+}
@@ -0,0 +1,232 @@
+//! netmod-serial is a serial link driver for Ratman
+//!
+//! Frames are sent over a byte stream with KISS framing, which is
+//! understood by most packet radio and LoRa TNCs.  Because a plain
+//! serial line doesn't detect transmission errors, every frame also
+//! carries an HDLC-style checksum, and corrupted frames are dropped.
+//!
+//! A serial link connects exactly two devices (or one device and a
+//! radio channel), meaning that there's no addressing: all frames
+//! are sent to the other side of the link, whatever their target.
+//!
+//! Frames are bincode encoded by default.  On slow links, the compact
+//! encoding from `netmod::wire` can be enabled with `Config::compact`,
+//! which sends the full frame header only once per sequence.  Both
+//! sides of the link need to use the same encoding.
+//!
+//! An endpoint can be created from a terminal device (a tty, or a
+//! pty for testing), or from any other async byte stream, such as a
+//! tcp connection to a software TNC.
+//!
+//! ```no_run
+//! # async_std::task::block_on(async {
+//! use netmod_serial::Serial;
+//! let ep = Serial::open("/dev/ttyUSB0", 9600).await.unwrap();
+//! # let router = ratman::Router::new();
+//! router.add_endpoint(ep).await;
+//! # });
+//! ```
+
+#[macro_use]
+extern crate tracing;
+
+mod kiss;
+mod tty;
+
+use async_std::{
+    future,
+    io::{self, prelude::*},
+    path::Path,
+    sync::{channel, Arc, Mutex, Receiver},
+    task,
+};
+use async_trait::async_trait;
+use netmod::{wire, Endpoint, Error, Frame, Result, Target};
+use std::fs::File;
+
+/// The largest encoded frame that is accepted from a link
+pub const MAX_FRAME: usize = 65535;
+
+/// Serial link configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// The TNC port to send frames on, from 0 to 15
+    ///
+    /// Frames received on other ports are ignored.
+    pub port: u8,
+    /// The frame size reported to the router
+    ///
+    /// Radio links are slow, and most TNCs have small buffers, so
+    /// frames should be kept small.
+    pub size_hint: usize,
+    /// Use the compact `netmod::wire` encoding instead of bincode
+    pub compact: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: 0,
+            size_hint: 256,
+            compact: false,
+        }
+    }
+}
+
+type Writer = Box<dyn Write + Send + Unpin>;
+
+/// The sending half of a link
+///
+/// The compact encoder is kept next to the writer, so that frames are
+/// written in the order they were encoded in.
+struct Tx {
+    writer: Writer,
+    enc: Option<wire::Encoder>,
+}
+
+/// An endpoint on a serial link
+pub struct Serial {
+    cfg: Config,
+    tx: Mutex<Tx>,
+    inbox: Receiver<Frame>,
+}
+
+impl Serial {
+    /// Open a terminal device with a given baud rate
+    pub async fn open<P: AsRef<Path>>(path: P, baud: u32) -> io::Result<Arc<Self>> {
+        Self::open_with(path, baud, Config::default()).await
+    }
+
+    /// Open a terminal device with a custom link configuration
+    ///
+    /// The device is put into raw mode.
+    pub async fn open_with<P: AsRef<Path>>(
+        path: P,
+        baud: u32,
+        cfg: Config,
+    ) -> io::Result<Arc<Self>> {
+        let path = path.as_ref().to_path_buf();
+        let file = task::spawn_blocking(move || tty::open(path.as_ref(), baud)).await?;
+        Self::from_file(file, cfg)
+    }
+
+    /// Create an endpoint from an already configured device
+    pub fn from_file(file: File, cfg: Config) -> io::Result<Arc<Self>> {
+        let reader = async_std::fs::File::from(file.try_clone()?);
+        let writer = async_std::fs::File::from(file);
+        Ok(Self::new(reader, writer, cfg))
+    }
+
+    /// Create an endpoint from a pair of byte streams
+    ///
+    /// A task is spawned to read frames from `reader`, which runs
+    /// until the stream ends.
+    pub fn new<R, W>(reader: R, writer: W, cfg: Config) -> Arc<Self>
+    where
+        R: Read + Send + Unpin + 'static,
+        W: Write + Send + Unpin + 'static,
+    {
+        assert!(cfg.port < 16, "KISS port must be smaller than 16");
+        let (tx, inbox) = channel(32);
+
+        task::spawn(async move {
+            let mut reader = reader;
+            let mut dec = kiss::Decoder::new(MAX_FRAME);
+            let mut wire = if cfg.compact {
+                Some(wire::Decoder::new())
+            } else {
+                None
+            };
+            let mut buf = [0; 1024];
+            loop {
+                let len = match reader.read(&mut buf).await {
+                    Ok(0) => break,
+                    Ok(len) => len,
+                    Err(e) => {
+                        error!("Failed to read from serial link: {}", e);
+                        break;
+                    }
+                };
+
+                for raw in buf[..len].iter().filter_map(|b| dec.push(*b)) {
+                    let frame = kiss::unpack(cfg.port, &raw).and_then(|data| match wire {
+                        Some(ref mut wire) => wire
+                            .decode(data)
+                            .map_err(|e| trace!("Failed to decode frame: {}", e))
+                            .ok(),
+                        None => bincode::deserialize::<Frame>(data).ok(),
+                    });
+                    match frame {
+                        Some(f) => tx.send(f).await,
+                        None => trace!("Ignoring invalid frame"),
+                    }
+                }
+            }
+            debug!("Serial link closed");
+        });
+
+        Arc::new(Self {
+            cfg,
+            tx: Mutex::new(Tx {
+                writer: Box::new(writer),
+                enc: if cfg.compact {
+                    Some(wire::Encoder::new())
+                } else {
+                    None
+                },
+            }),
+            inbox,
+        })
+    }
+}
+
+#[async_trait]
+impl Endpoint for Serial {
+    fn size_hint(&self) -> usize {
+        self.cfg.size_hint
+    }
+
+    /// Send a frame to the other side of the link
+    ///
+    /// # Errors
+    ///
+    /// Returns `FrameTooLarge` for frames that the other side would
+    /// drop, and `ConnectionLost` if the link can't be written to.
+    async fn send(&self, frame: Frame, _: Target) -> Result<()> {
+        // The command byte and checksum are part of the frame too, and
+        // the compact encoding is never larger than bincode
+        match bincode::serialized_size(&frame) {
+            Ok(size) if size as usize + 3 <= MAX_FRAME => {}
+            _ => return Err(Error::FrameTooLarge),
+        }
+
+        let mut tx = self.tx.lock().await;
+        let data = match tx.enc {
+            Some(ref mut enc) => enc.encode(&frame),
+            None => bincode::serialize(&frame).map_err(|_| Error::FrameTooLarge)?,
+        };
+
+        let writer = &mut tx.writer;
+        let res = async {
+            writer
+                .write_all(&kiss::encode(self.cfg.port, &data))
+                .await?;
+            writer.flush().await
+        };
+        res.await.map_err(|e| {
+            error!("Failed to write to serial link: {}", e);
+            Error::ConnectionLost
+        })
+    }
+
+    /// Wait for the next frame from the other side of the link
+    ///
+    /// Once the link is closed no more frames arrive, but the router
+    /// keeps polling, so this never returns instead of erroring.
+    async fn next(&self) -> Result<(Frame, Target)> {
+        match self.inbox.recv().await {
+            Some(f) => Ok((f, Target::default())),
+            None => future::pending().await,
+        }
+    }
+}
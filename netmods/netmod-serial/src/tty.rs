@@ -0,0 +1,60 @@
+//! Terminal device setup
+
+use async_std::io;
+use nix::sys::termios::{self, BaudRate, ControlFlags, SetArg, SpecialCharacterIndices};
+use std::{
+    fs::{File, OpenOptions},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::Path,
+};
+
+fn baud_rate(baud: u32) -> io::Result<BaudRate> {
+    Ok(match baud {
+        1200 => BaudRate::B1200,
+        2400 => BaudRate::B2400,
+        4800 => BaudRate::B4800,
+        9600 => BaudRate::B9600,
+        19200 => BaudRate::B19200,
+        38400 => BaudRate::B38400,
+        57600 => BaudRate::B57600,
+        115_200 => BaudRate::B115200,
+        230_400 => BaudRate::B230400,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported baud rate {}", baud),
+            ))
+        }
+    })
+}
+
+fn nix_err(e: nix::Error) -> io::Error {
+    match e.as_errno() {
+        Some(errno) => io::Error::from_raw_os_error(errno as i32),
+        None => io::Error::new(io::ErrorKind::InvalidInput, e),
+    }
+}
+
+/// Open a terminal device in raw mode
+///
+/// Line editing, echo and all character translations are disabled,
+/// so that the device is a plain 8-bit byte stream.
+pub(crate) fn open(path: &Path, baud: u32) -> io::Result<File> {
+    let speed = baud_rate(baud)?;
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(path)?;
+
+    let fd = file.as_raw_fd();
+    let mut t = termios::tcgetattr(fd).map_err(nix_err)?;
+    termios::cfmakeraw(&mut t);
+    termios::cfsetspeed(&mut t, speed).map_err(nix_err)?;
+    t.control_flags |= ControlFlags::CLOCAL | ControlFlags::CREAD;
+    t.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+    t.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+    termios::tcsetattr(fd, SetArg::TCSANOW, &t).map_err(nix_err)?;
+
+    Ok(file)
+}
@@ -0,0 +1,85 @@
+//! End-to-end tests over a pseudo terminal
+//!
+//! The slave side of the pty is opened like a real tty device, while
+//! the master side stands in for the TNC on the other end.
+
+use netmod::{Endpoint, Frame, Recipient, SeqBuilder, Target};
+use netmod_serial::{Config, Serial};
+use nix::pty;
+use ratman::{Identity, Router};
+use std::{fs::File, os::unix::io::FromRawFd, sync::Arc};
+
+async fn pty_pair(cfg: Config) -> (Arc<Serial>, Arc<Serial>, File) {
+    let pty = pty::openpty(None, None).unwrap();
+    let path = std::fs::read_link(format!("/proc/self/fd/{}", pty.slave)).unwrap();
+
+    let (master, slave) = unsafe { (File::from_raw_fd(pty.master), File::from_raw_fd(pty.slave)) };
+    let a = Serial::from_file(master, cfg).unwrap();
+    let b = Serial::open_with(&path, 115_200, cfg).await.unwrap();
+    (a, b, slave)
+}
+
+#[async_std::test]
+async fn frame_roundtrip() {
+    let (a, b, _slave) = pty_pair(Config::default()).await;
+
+    let frame = Frame::dummy();
+    a.send(frame.clone(), Target::Flood).await.unwrap();
+    assert_eq!(b.next().await.unwrap().0, frame);
+
+    let frame = Frame::dummy();
+    b.send(frame.clone(), Target::Single(3)).await.unwrap();
+    assert_eq!(a.next().await.unwrap().0, frame);
+}
+
+#[async_std::test]
+async fn compact_sequence() {
+    let cfg = Config {
+        compact: true,
+        ..Config::default()
+    };
+    let (a, b, _slave) = pty_pair(cfg).await;
+
+    let seq = SeqBuilder::new(
+        Identity::random(),
+        Recipient::User(Identity::random()),
+        Identity::random(),
+    )
+    .add(vec![1; 64])
+    .add(vec![2; 64])
+    .add(vec![3; 16])
+    .build();
+
+    for f in seq.iter().cloned() {
+        a.send(f, Target::default()).await.unwrap();
+    }
+    for f in seq {
+        assert_eq!(b.next().await.unwrap().0, f);
+    }
+}
+
+#[async_std::test]
+async fn announce_and_discover() {
+    let (a, b, _slave) = pty_pair(Config::default()).await;
+
+    let r1 = Router::new();
+    let r2 = Router::new();
+    r1.add_endpoint(a).await;
+    r2.add_endpoint(b).await;
+
+    let u2 = Identity::random();
+    r2.add_user(u2).await.unwrap();
+    r2.online(u2).await.unwrap();
+    assert_eq!(r1.discover().await, u2);
+}
+
+#[async_std::test]
+async fn closed_link_pends() {
+    use async_std::{future, io};
+    use std::time::Duration;
+
+    // The reader ends immediately, like a device that was unplugged
+    let s = Serial::new(io::empty(), io::sink(), Config::default());
+    let next = future::timeout(Duration::from_millis(100), s.next());
+    assert!(next.await.is_err());
+}
@@ -12,14 +12,14 @@ pub use error::{Error, Result};
 
 pub(crate) use io::IoPair;
 pub(crate) use peer::{DstAddr, Peer, PeerState, SourceAddr};
-pub(crate) use proto::{Packet, PacketBuilder};
+pub use proto::{Packet, PacketBuilder};
 pub(crate) use ptr::AtomPtr;
 pub(crate) use routes::Routes;
 pub(crate) use server::{LockedStream, Server};
 
 use async_std::sync::Arc;
 use async_trait::async_trait;
-use netmod::{self, Endpoint as EndpointExt, Frame, Target};
+use netmod::{self, Endpoint as EndpointExt, Frame, Handshake, Target};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use tracing::{error, info, trace};
@@ -69,9 +69,25 @@ impl Endpoint {
     /// Create a new endpoint on an interface and port
     #[tracing::instrument(level = "info")]
     pub async fn new(addr: &str, port: u16, name: &str, mode: Mode) -> Result<Arc<Self>> {
+        Self::with_handshake(addr, port, name, mode, Handshake::current()).await
+    }
+
+    /// Create a new endpoint with a custom protocol handshake
+    ///
+    /// Peers with an incompatible handshake are rejected when they
+    /// connect.  This is mostly useful to test networks with mixed
+    /// protocol versions.
+    #[tracing::instrument(level = "info")]
+    pub async fn with_handshake(
+        addr: &str,
+        port: u16,
+        name: &str,
+        mode: Mode,
+        handshake: Handshake,
+    ) -> Result<Arc<Self>> {
         info!("Initialising Tcp backend");
 
-        let routes = Routes::new(port);
+        let routes = Routes::new(port, handshake);
         let server = Server::new(Arc::clone(&routes), addr, port, mode).await?;
 
         server.run();
@@ -97,7 +113,7 @@ impl Endpoint {
     pub async fn add_peers(&self, peers: Vec<String>) -> Result<()> {
         for p in peers.into_iter() {
             if &p == "" && continue {}
-            
+
             let mut parts: Vec<_> = p.split(|x| x == ' ').collect();
             let _type = parts.get(1);
             let peer = match parts[0].parse().ok() {
@@ -113,11 +129,15 @@ impl Endpoint {
                 _ => LinkType::Bidirect,
             };
 
-            trace!("Adding peer: {} ({})", peer, match t {
-                LinkType::Limited => "limited",
-                LinkType::Bidirect => "",
-            });
-            
+            trace!(
+                "Adding peer: {} ({})",
+                peer,
+                match t {
+                    LinkType::Limited => "limited",
+                    LinkType::Bidirect => "",
+                }
+            );
+
             self.routes.add_via_dst(peer, t).await;
         }
 
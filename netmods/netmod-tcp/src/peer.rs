@@ -37,9 +37,10 @@ use async_std::{
 };
 use bincode::serialize;
 use byteorder::{BigEndian, ByteOrder};
+use netmod::Handshake;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::{net::SocketAddr, time::Duration};
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
 /// Utility module to generate monotonic peer IDs
 mod id {
@@ -120,7 +121,7 @@ impl Peer {
     /// worker that will try to establish a connection to the peer,
     /// exiting until `stop()` is called on this peer
     #[tracing::instrument(level = "trace")]
-    pub(crate) fn open(dst: DstAddr, port: u16, _type: LinkType) -> Arc<Self> {
+    pub(crate) fn open(dst: DstAddr, port: u16, _type: LinkType, proto: Handshake) -> Arc<Self> {
         let p = Arc::new(Self {
             id: id::next(),
             dst: Some(dst),
@@ -131,7 +132,11 @@ impl Peer {
 
         // Start sender loop and send a hello
         Arc::clone(&p).run_io_sender(port, _type);
-        task::block_on(async { Arc::clone(&p).send(Packet::Hello { port, _type }).await });
+        task::block_on(async {
+            Arc::clone(&p)
+                .send(Packet::Hello { port, _type, proto })
+                .await
+        });
 
         return p;
     }
@@ -228,6 +233,14 @@ impl Peer {
                     match pb.parse().await {
                         Ok(_) => match pb.build() {
                             Some(Packet::Ack) => trace!("Received an ACK."),
+                            Some(Packet::Reject(proto)) => {
+                                warn!(
+                                    "Peer `{:?}` only accepts protocol versions {} to {}; disconnecting",
+                                    self.dst, proto.min_version, proto.version
+                                );
+                                std::mem::swap(&mut *s, &mut None);
+                                self.stop();
+                            }
                             _ => error!("Invalid data (only ACKs)!"),
                         },
                         _ => {
@@ -262,7 +275,8 @@ impl Peer {
     /// This function will try sending a packet, initialising the
     /// output stream if it doesn't yet exist
     async fn send_or_introduce(self: &Arc<Self>, p: Packet, port: u16, _type: LinkType) {
-        loop {
+        // A stopped peer won't connect again, so the packet is dropped
+        while self.alive() {
             if { self.sender.get_ref().read().await.is_some() } {
                 // Send the packet and re-run the loop if we failed to send
                 match self.send_packet(&p).await {
@@ -1,18 +1,22 @@
 //! TCP internal protocol used to share connection state
+//!
+//! The same length-prefixed packet format is used by other stream
+//! based netmods (such as `netmod-unix`), which only send `Frame`
+//! packets.
 
 use crate::LinkType;
-use async_std::{
-    io::{self, prelude::ReadExt},
-    net::TcpStream,
-};
+use async_std::io::{self, prelude::ReadExt, Read};
 use bincode::{deserialize, serialize};
 use byteorder::{BigEndian, ByteOrder};
-use netmod::Frame;
+use netmod::{Frame, Handshake};
 use serde::{Deserialize, Serialize};
 
-/// An internally used packet format
+/// The packet format used on a stream
+///
+/// On the wire, every packet is prefixed with its length as a big
+/// endian `u64`.
 #[derive(Debug, Serialize, Deserialize)]
-pub(crate) enum Packet {
+pub enum Packet {
     /// A general keep alive message
     ///
     /// Because tcp assumes a client-server architecture, an incoming
@@ -21,16 +25,37 @@ pub(crate) enum Packet {
     /// network we create reverse connections.  When establishing a
     /// connection, the hello message contains the port which is
     /// swapped into the source address to connect to.
-    Hello { port: u16, _type: LinkType },
+    ///
+    /// The hello also carries the protocol handshake of the sender.
+    /// Peers that don't send one are treated as `Handshake::LEGACY`.
+    Hello {
+        port: u16,
+        _type: LinkType,
+        proto: Handshake,
+    },
     /// Response to a Hello on the sending stream
     Ack,
     /// An actual data packet
     Frame(Frame),
+    /// Response to a Hello with an incompatible protocol version
+    ///
+    /// The connection is closed after sending it.
+    Reject(Handshake),
+}
+
+/// The hello packet sent before handshakes were introduced
+///
+/// Because new fields are appended at the end, older peers can parse
+/// a new hello (ignoring the handshake), but the other way around
+/// needs this fallback.
+#[derive(Deserialize)]
+enum LegacyPacket {
+    Hello { port: u16, _type: LinkType },
 }
 
 impl Packet {
     /// Serialises the packet into a length prepended data stream
-    pub(crate) fn serialize(&self) -> Vec<u8> {
+    pub fn serialize(&self) -> Vec<u8> {
         let mut vec = serialize(self).unwrap();
         let mut buf = vec![0; 8];
         BigEndian::write_u64(&mut buf, vec.len() as u64);
@@ -39,20 +64,20 @@ impl Packet {
     }
 }
 
-/// A utility to read packets from an incoming TCP stream
-pub(crate) struct PacketBuilder<'s> {
-    stream: &'s mut TcpStream,
+/// A utility to read packets from an incoming stream
+pub struct PacketBuilder<'s, R> {
+    stream: &'s mut R,
     data: Option<Vec<u8>>,
 }
 
-impl<'s> PacketBuilder<'s> {
+impl<'s, R: Read + Unpin> PacketBuilder<'s, R> {
     /// Create a new frame builder from a stream
-    pub(crate) fn new(stream: &'s mut TcpStream) -> Self {
+    pub fn new(stream: &'s mut R) -> Self {
         Self { stream, data: None }
     }
 
     /// Parse incoming data and initialise the builder
-    pub(crate) async fn parse(&mut self) -> io::Result<()> {
+    pub async fn parse(&mut self) -> io::Result<()> {
         let mut len_buf = [0; 8];
         self.stream.read_exact(&mut len_buf).await?;
         let len = BigEndian::read_u64(&len_buf);
@@ -64,7 +89,17 @@ impl<'s> PacketBuilder<'s> {
     }
 
     /// Consume the builder and maybe return a frame
-    pub(crate) fn build(self) -> Option<Packet> {
-        self.data.and_then(|vec| deserialize(&vec).ok())
+    pub fn build(self) -> Option<Packet> {
+        self.data.and_then(|vec| {
+            deserialize(&vec)
+                .ok()
+                .or_else(|| match deserialize(&vec).ok()? {
+                    LegacyPacket::Hello { port, _type } => Some(Packet::Hello {
+                        port,
+                        _type,
+                        proto: Handshake::LEGACY,
+                    }),
+                })
+        })
     }
 }
@@ -13,6 +13,7 @@
 
 use crate::{DstAddr, LinkType, LockedStream, Peer, SourceAddr};
 use async_std::sync::{Arc, RwLock};
+use netmod::Handshake;
 use std::collections::BTreeMap;
 use tracing::{trace, warn};
 
@@ -21,6 +22,8 @@ use tracing::{trace, warn};
 pub(crate) struct Routes {
     /// Store which port this instance is listening to
     port: u16,
+    /// The protocol handshake sent to peers
+    proto: Handshake,
     /// A map of all the peers known to this system
     peers: Arc<RwLock<BTreeMap<usize, Arc<Peer>>>>,
     /// Map source addresses to peer ID
@@ -31,13 +34,19 @@ pub(crate) struct Routes {
 
 impl Routes {
     /// Create a new empty routes table
-    pub(crate) fn new(port: u16) -> Arc<Self> {
+    pub(crate) fn new(port: u16, proto: Handshake) -> Arc<Self> {
         Arc::new(Self {
             port,
+            proto,
             ..Self::default()
         })
     }
 
+    /// Get the protocol handshake sent to peers
+    pub(crate) fn proto(&self) -> Handshake {
+        self.proto
+    }
+
     pub(crate) async fn stop_all(self: &Arc<Self>) {
         for (_, peer) in self.peers.read().await.iter() {
             peer.stop();
@@ -80,7 +89,7 @@ impl Routes {
     /// This function is called when adding a peer via the static set
     /// of peers to connect to.
     pub(crate) async fn add_via_dst(self: &Arc<Self>, dst: DstAddr, _type: LinkType) -> usize {
-        let p = Peer::open(dst.clone(), self.port, _type);
+        let p = Peer::open(dst.clone(), self.port, _type, self.proto);
         let id = p.id;
 
         self.peers.write().await.insert(id, p);
@@ -208,7 +217,7 @@ impl Routes {
             }
             // If no such peer exists, we create one with SRC and DST addresses
             None => {
-                let p = Peer::open(dst, port, LinkType::Bidirect);
+                let p = Peer::open(dst, port, LinkType::Bidirect, self.proto);
                 p.set_src(src);
                 if let Some(s) = stream {
                     p.set_stream(s).await;
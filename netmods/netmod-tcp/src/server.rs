@@ -10,7 +10,7 @@ use async_std::{
     sync::{Arc, RwLock},
     task,
 };
-use netmod::{Frame, Target};
+use netmod::{Frame, Handshake, Target};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use tracing::{debug, error, info, trace, warn};
@@ -152,7 +152,11 @@ impl Server {
             use PeerState::*;
             match (peer.state(), f) {
                 (_, Frame(f)) => self.handle_frame(peer.id, f).await,
-                (state, Hello { port, _type }) => {
+                (state, Hello { port, _type, proto }) => {
+                    if !self.compatible(&src_addr, proto, &stream).await {
+                        break;
+                    }
+
                     self.handle_hello(peer.id, state, &src_addr, port, _type, Arc::clone(&stream))
                         .await
                 }
@@ -165,6 +169,27 @@ impl Server {
         info!("Exiting connetion work-loop; was there a connection drop?");
     }
 
+    /// Check the protocol handshake of a peer that said hello
+    ///
+    /// Incompatible peers are sent a `Reject`, after which the
+    /// connection is closed.
+    async fn compatible(&self, src: &SourceAddr, proto: Handshake, stream: &LockedStream) -> bool {
+        let local = self.routes.proto();
+        if local.negotiate(&proto).is_some() {
+            return true;
+        }
+
+        warn!(
+            "Rejecting peer {}: it speaks protocol versions {} to {}, we speak {} to {}",
+            src, proto.min_version, proto.version, local.min_version, local.version
+        );
+        let mut stream = stream.write().await;
+        if let Some(ref mut s) = *stream {
+            let _ = s.write_all(&Packet::Reject(local).serialize()).await;
+        }
+        false
+    }
+
     /// Handle an incoming frame message
     async fn handle_frame(self: &Arc<Self>, peer_id: usize, p: Frame) {
         self.incoming.tx.send((p, peer_id)).await;
@@ -240,6 +265,7 @@ impl Server {
                 peer.send(Packet::Hello {
                     port: s._port,
                     _type: peer.link_type(),
+                    proto: s.routes.proto(),
                 })
                 .await;
             }
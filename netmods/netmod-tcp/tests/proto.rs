@@ -0,0 +1,62 @@
+//! Packet compatibility with peers on other protocol versions
+
+use async_std::io::Cursor;
+use bincode::serialize;
+use byteorder::{BigEndian, ByteOrder};
+use netmod::Handshake;
+use netmod_tcp::{LinkType, Packet, PacketBuilder};
+use serde::Serialize;
+
+/// The hello sent by peers that predate the handshake
+#[derive(Serialize)]
+enum OldPacket {
+    Hello { port: u16, _type: LinkType },
+}
+
+async fn read(buf: Vec<u8>) -> Option<Packet> {
+    let mut stream = Cursor::new(buf);
+    let mut pb = PacketBuilder::new(&mut stream);
+    pb.parse().await.unwrap();
+    pb.build()
+}
+
+#[async_std::test]
+async fn legacy_hello() {
+    let mut data = serialize(&OldPacket::Hello {
+        port: 9000,
+        _type: LinkType::Bidirect,
+    })
+    .unwrap();
+    let mut buf = vec![0; 8];
+    BigEndian::write_u64(&mut buf, data.len() as u64);
+    buf.append(&mut data);
+
+    match read(buf).await {
+        Some(Packet::Hello { port, _type, proto }) => {
+            assert_eq!(port, 9000);
+            assert_eq!(_type, LinkType::Bidirect);
+            assert_eq!(proto, Handshake::LEGACY);
+        }
+        p => panic!("unexpected packet: {:?}", p),
+    }
+}
+
+#[async_std::test]
+async fn hello_and_reject() {
+    let proto = Handshake::current();
+    let hello = Packet::Hello {
+        port: 9000,
+        _type: LinkType::Limited,
+        proto,
+    };
+
+    match read(hello.serialize()).await {
+        Some(Packet::Hello { proto: p, .. }) => assert_eq!(p, proto),
+        p => panic!("unexpected packet: {:?}", p),
+    }
+
+    match read(Packet::Reject(proto).serialize()).await {
+        Some(Packet::Reject(p)) => assert_eq!(p, proto),
+        p => panic!("unexpected packet: {:?}", p),
+    }
+}
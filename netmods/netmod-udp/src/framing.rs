@@ -1,6 +1,6 @@
 //! UDP overlay protocol and framing
 
-use netmod::{Frame, Target};
+use netmod::{Frame, Handshake, Target};
 use serde::{Deserialize, Serialize};
 
 /// A framing device to encapsulate the UDP overlay protocol
@@ -14,16 +14,37 @@ use serde::{Deserialize, Serialize};
 /// what internal ID they are represented by.  All other routing is
 /// then done via Ratman and the netmod API which considers target
 /// state.
+///
+/// Announcements and replies carry the protocol handshake of the
+/// sender, so that incompatible endpoints can ignore each other.
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) enum Envelope {
     /// Announcing an endpoint via multicast
-    Announce,
+    Announce(Handshake),
     /// Reply to an announce
-    Reply,
+    Reply(Handshake),
     /// A raw data frame
     Data(Vec<u8>),
 }
 
+/// The envelope format before handshakes were introduced
+#[derive(Deserialize)]
+enum LegacyEnvelope {
+    Announce,
+    Reply,
+    Data(Vec<u8>),
+}
+
+impl From<LegacyEnvelope> for Envelope {
+    fn from(env: LegacyEnvelope) -> Self {
+        match env {
+            LegacyEnvelope::Announce => Self::Announce(Handshake::LEGACY),
+            LegacyEnvelope::Reply => Self::Reply(Handshake::LEGACY),
+            LegacyEnvelope::Data(vec) => Self::Data(vec),
+        }
+    }
+}
+
 impl Envelope {
     pub(crate) fn frame(f: &Frame) -> Vec<u8> {
         let inner = bincode::serialize(f).unwrap();
@@ -31,29 +52,24 @@ impl Envelope {
         bincode::serialize(&env).unwrap()
     }
 
-    pub(crate) fn get_frame(&self) -> Frame {
+    pub(crate) fn get_frame(&self) -> Option<Frame> {
         match self {
-            Self::Data(ref vec) => bincode::deserialize(vec).unwrap(),
-            _ => unreachable!(),
+            Self::Data(ref vec) => bincode::deserialize(vec).ok(),
+            _ => None,
         }
     }
 
-    pub(crate) fn announce() -> Vec<u8> {
-        let env = Envelope::Announce;
-        bincode::serialize(&env).unwrap()
-    }
-
-    pub(crate) fn reply() -> Vec<u8> {
-        let env = Envelope::Reply;
-        bincode::serialize(&env).unwrap()
-    }
-
     pub(crate) fn as_bytes(&self) -> Vec<u8> {
         bincode::serialize(self).unwrap()
     }
 
-    pub(crate) fn from_bytes(vec: &Vec<u8>) -> Self {
-        bincode::deserialize(&vec).unwrap()
+    /// Parse an envelope, including ones sent by legacy endpoints
+    pub(crate) fn from_bytes(buf: &[u8]) -> Option<Self> {
+        bincode::deserialize(buf).ok().or_else(|| {
+            bincode::deserialize::<LegacyEnvelope>(buf)
+                .ok()
+                .map(Into::into)
+        })
     }
 }
 
@@ -15,8 +15,8 @@ pub(crate) use framing::{Envelope, FrameExt};
 
 use async_std::{sync::Arc, task};
 use async_trait::async_trait;
-use netmod::{Endpoint as EndpointExt, Frame, Recipient, Result, Target};
-use std::net::ToSocketAddrs;
+use netmod::{Endpoint as EndpointExt, Frame, Handshake, Recipient, Result, Target};
+use std::net::{Ipv4Addr, ToSocketAddrs};
 
 #[derive(Clone)]
 pub struct Endpoint {
@@ -27,10 +27,27 @@ pub struct Endpoint {
 impl Endpoint {
     /// Create a new endpoint and spawn a dispatch task
     pub fn spawn(port: u16) -> Arc<Self> {
+        Self::spawn_on(Ipv4Addr::UNSPECIFIED, port)
+    }
+
+    /// Create a new endpoint on a specific interface
+    ///
+    /// The socket is always bound to all interfaces (otherwise it
+    /// wouldn't receive any multicast traffic), but only joins the
+    /// multicast group on the interface with the provided address.
+    pub fn spawn_on(addr: Ipv4Addr, port: u16) -> Arc<Self> {
+        Self::with_handshake(addr, port, Handshake::current())
+    }
+
+    /// Create a new endpoint with a custom protocol handshake
+    ///
+    /// Announcements from endpoints with an incompatible handshake
+    /// are ignored, so they never become peers.
+    pub fn with_handshake(addr: Ipv4Addr, port: u16, handshake: Handshake) -> Arc<Self> {
         task::block_on(async move {
             let addrs = Arc::new(AddrTable::new());
             Arc::new(Self {
-                socket: Socket::with_port(port, Arc::clone(&addrs)).await,
+                socket: Socket::with_addr(addr, port, Arc::clone(&addrs), handshake).await,
                 addrs,
             })
         })
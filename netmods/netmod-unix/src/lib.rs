@@ -0,0 +1,168 @@
+//! netmod-unix connects routers on the same host
+//!
+//! Running many routers in separate processes on one machine (for
+//! example in CI) with netmod-tcp means managing a port for each of
+//! them.  This endpoint instead connects routers via unix domain
+//! sockets, or via any pair of pipes: stdin and stdout make it
+//! possible to connect two routers over `ssh`, or any other program
+//! that forwards a byte stream.
+//!
+//! Frames are sent with the same length-prefixed packet format that
+//! netmod-tcp uses.  Each connection is a peer, which frames can be
+//! sent to directly, or flooded to along with all other peers.
+//!
+//! ```no_run
+//! # async_std::task::block_on(async {
+//! use netmod_unix::Endpoint;
+//!
+//! let a = Endpoint::new();
+//! a.listen("/tmp/ratman-a.sock").await.unwrap();
+//!
+//! let b = Endpoint::new();
+//! b.connect("/tmp/ratman-a.sock").await.unwrap();
+//! # });
+//! ```
+
+#[macro_use]
+extern crate tracing;
+
+mod peer;
+use peer::{Peers, Shared};
+
+use async_std::{
+    io::{self, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    prelude::*,
+    sync::{channel, Arc, Receiver, Sender},
+    task,
+};
+use async_trait::async_trait;
+use netmod::{Endpoint as EndpointExt, Error, Frame, Result, Target};
+
+/// An endpoint for unix domain sockets and pipes
+pub struct Endpoint {
+    peers: Arc<Peers>,
+    tx: Sender<(Frame, Target)>,
+    rx: Receiver<(Frame, Target)>,
+}
+
+impl Endpoint {
+    /// Create an endpoint without any connections
+    pub fn new() -> Arc<Self> {
+        let (tx, rx) = channel(32);
+        Arc::new(Self {
+            peers: Default::default(),
+            tx,
+            rx,
+        })
+    }
+
+    /// Accept connections on a socket path
+    ///
+    /// The socket file is created by this function, and must not
+    /// exist yet.  A task is spawned to accept connections, which
+    /// runs for as long as the endpoint exists.
+    pub async fn listen<P: AsRef<Path>>(self: &Arc<Self>, path: P) -> io::Result<()> {
+        let listener = UnixListener::bind(path).await?;
+        let this = Arc::downgrade(self);
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(stream) = incoming.next().await {
+                let this = match this.upgrade() {
+                    Some(this) => this,
+                    None => break,
+                };
+
+                match stream {
+                    Ok(stream) => {
+                        let id = this.add_unix(stream).await;
+                        debug!("Accepted connection from peer {}", id);
+                    }
+                    Err(e) => warn!("Failed to accept connection: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Connect to an endpoint that is listening on a socket path
+    ///
+    /// Returns the ID of the new peer.
+    pub async fn connect<P: AsRef<Path>>(&self, path: P) -> io::Result<u16> {
+        let stream = UnixStream::connect(path).await?;
+        Ok(self.add_unix(stream).await)
+    }
+
+    /// Use stdin and stdout as a connection to a peer
+    ///
+    /// No other output may be written to stdout after calling this
+    /// function, so make sure that logs are written to stderr.
+    pub async fn stdio(&self) -> u16 {
+        self.add_stream(io::stdin(), io::stdout()).await
+    }
+
+    /// Add a connection from a pair of byte streams
+    ///
+    /// Returns the ID of the new peer.
+    pub async fn add_stream<R, W>(&self, reader: R, writer: W) -> u16
+    where
+        R: Read + Send + Unpin + 'static,
+        W: Write + Send + Unpin + 'static,
+    {
+        self.peers.add(reader, writer, self.tx.clone()).await
+    }
+
+    async fn add_unix(&self, stream: UnixStream) -> u16 {
+        let stream = Arc::new(stream);
+        self.add_stream(Shared(Arc::clone(&stream)), Shared(stream))
+            .await
+    }
+
+    /// Return the number of currently connected peers
+    pub async fn peers(&self) -> usize {
+        self.peers.len().await
+    }
+}
+
+#[async_trait]
+impl EndpointExt for Endpoint {
+    fn size_hint(&self) -> usize {
+        0
+    }
+
+    /// Send a frame to one peer, or all of them
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConnectionLost` if a single peer isn't connected, or
+    /// the connection fails while sending.  Peers that fail while
+    /// flooding are removed silently.
+    async fn send(&self, frame: Frame, target: Target) -> Result<()> {
+        match target {
+            Target::Flood => {
+                for (id, peer) in self.peers.all().await {
+                    if let Err(e) = peer.send(frame.clone()).await {
+                        debug!("Removing peer {}: {}", id, e);
+                        self.peers.remove(id).await;
+                    }
+                }
+                Ok(())
+            }
+            Target::Single(id) => {
+                let peer = self.peers.get(id).await.ok_or(Error::ConnectionLost)?;
+                peer.send(frame).await.map_err(|e| {
+                    debug!("Removing peer {}: {}", id, e);
+                    Error::ConnectionLost
+                })
+            }
+        }
+    }
+
+    async fn next(&self) -> Result<(Frame, Target)> {
+        match self.rx.recv().await {
+            Some(ft) => Ok(ft),
+            None => Err(Error::ConnectionLost),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Peer connection handling
+
+use async_std::{
+    io::{prelude::*, Read, Write},
+    os::unix::net::UnixStream,
+    pin::Pin,
+    sync::{Arc, Mutex, RwLock, Sender},
+    task::{self, Context, Poll},
+};
+use netmod::{Frame, Target};
+use netmod_tcp::{Packet, PacketBuilder};
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::atomic::{AtomicU16, Ordering},
+};
+
+type Writer = Box<dyn Write + Send + Unpin>;
+
+/// A unix stream that is shared between a reader and a writer
+pub(crate) struct Shared(pub(crate) Arc<UnixStream>);
+
+impl Read for Shared {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut &*self.0).poll_read(cx, buf)
+    }
+}
+
+impl Write for Shared {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut &*self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut &*self.0).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut &*self.0).poll_close(cx)
+    }
+}
+
+/// The writing half of a connection
+pub(crate) struct Peer {
+    writer: Mutex<Writer>,
+}
+
+impl Peer {
+    pub(crate) async fn send(&self, frame: Frame) -> io::Result<()> {
+        let mut w = self.writer.lock().await;
+        w.write_all(&Packet::Frame(frame).serialize()).await?;
+        w.flush().await
+    }
+}
+
+/// A table of connected peers
+#[derive(Default)]
+pub(crate) struct Peers {
+    ctr: AtomicU16,
+    map: RwLock<BTreeMap<u16, Arc<Peer>>>,
+}
+
+impl Peers {
+    /// Add a connection and spawn a task that reads from it
+    ///
+    /// Incoming frames are passed to `inbox`, with the peer ID as
+    /// their target.  The peer is removed when its stream ends.
+    pub(crate) async fn add<R, W>(
+        self: &Arc<Self>,
+        mut reader: R,
+        writer: W,
+        inbox: Sender<(Frame, Target)>,
+    ) -> u16
+    where
+        R: Read + Send + Unpin + 'static,
+        W: Write + Send + Unpin + 'static,
+    {
+        let id = self.ctr.fetch_add(1, Ordering::Relaxed);
+        let peer = Arc::new(Peer {
+            writer: Mutex::new(Box::new(writer)),
+        });
+        self.map.write().await.insert(id, peer);
+
+        let peers = Arc::clone(self);
+        task::spawn(async move {
+            loop {
+                let mut pb = PacketBuilder::new(&mut reader);
+                if let Err(e) = pb.parse().await {
+                    debug!("Connection to peer {} closed: {}", id, e);
+                    break;
+                }
+
+                match pb.build() {
+                    Some(Packet::Frame(f)) => inbox.send((f, Target::Single(id))).await,
+                    Some(p) => trace!("Ignoring packet: {:?}", p),
+                    None => warn!("Failed to decode packet from peer {}", id),
+                }
+            }
+            peers.remove(id).await;
+        });
+
+        id
+    }
+
+    pub(crate) async fn get(&self, id: u16) -> Option<Arc<Peer>> {
+        self.map.read().await.get(&id).cloned()
+    }
+
+    pub(crate) async fn all(&self) -> Vec<(u16, Arc<Peer>)> {
+        self.map
+            .read()
+            .await
+            .iter()
+            .map(|(id, p)| (*id, Arc::clone(p)))
+            .collect()
+    }
+
+    pub(crate) async fn remove(&self, id: u16) {
+        self.map.write().await.remove(&id);
+    }
+
+    pub(crate) async fn len(&self) -> usize {
+        self.map.read().await.len()
+    }
+}
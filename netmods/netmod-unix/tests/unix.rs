@@ -0,0 +1,50 @@
+use async_std::os::unix::net::UnixStream;
+use netmod::{Endpoint as _, Frame, Target};
+use netmod_unix::Endpoint;
+use ratman::{Identity, Router};
+
+#[async_std::test]
+async fn socket_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.sock");
+
+    let a = Endpoint::new();
+    a.listen(&path).await.unwrap();
+    let b = Endpoint::new();
+    let id = b.connect(&path).await.unwrap();
+
+    let frame = Frame::dummy();
+    b.send(frame.clone(), Target::Single(id)).await.unwrap();
+    let (f, t) = a.next().await.unwrap();
+    assert_eq!(f, frame);
+    assert_eq!(a.peers().await, 1);
+
+    // Replies go to the peer that a frame was received from
+    let frame = Frame::dummy();
+    a.send(frame.clone(), t).await.unwrap();
+    assert_eq!(b.next().await.unwrap(), (frame, Target::Single(id)));
+
+    assert!(b.send(Frame::dummy(), Target::Single(7)).await.is_err());
+}
+
+#[async_std::test]
+async fn pipe_routers() {
+    // Two one-way pipes, like stdin and stdout of an ssh session
+    let (a_out, b_in) = UnixStream::pair().unwrap();
+    let (b_out, a_in) = UnixStream::pair().unwrap();
+
+    let a = Endpoint::new();
+    a.add_stream(a_in, a_out).await;
+    let b = Endpoint::new();
+    b.add_stream(b_in, b_out).await;
+
+    let r1 = Router::new();
+    let r2 = Router::new();
+    r1.add_endpoint(a).await;
+    r2.add_endpoint(b).await;
+
+    let u2 = Identity::random();
+    r2.add_user(u2).await.unwrap();
+    r2.online(u2).await.unwrap();
+    assert_eq!(r1.discover().await, u2);
+}
@@ -0,0 +1,284 @@
+//! A websocket netmod to connect routers through http proxies
+//!
+//! Some networks only allow http traffic on well-known ports.  This
+//! endpoint sends frames as binary websocket messages, which most
+//! http proxies pass through, and which can be served behind a
+//! regular web server.
+//!
+//! An endpoint can accept connections (server mode), connect to a
+//! list of peers (client mode), or both.  Connections to peers are
+//! re-established automatically when they fail, with an increasing
+//! delay between attempts.
+//!
+//! Clients can connect through an http proxy, which tunnels the
+//! connection with the `CONNECT` method (see [`Endpoint::with_proxy`]).
+//!
+//! ```no_run
+//! # async_std::task::block_on(async {
+//! use netmod_ws::Endpoint;
+//!
+//! let server = Endpoint::new();
+//! server.listen("0.0.0.0", 9080).await.unwrap();
+//!
+//! let client = Endpoint::new();
+//! client.add_peers(vec!["ws://example.com:9080".into()]).unwrap();
+//!
+//! let proxied = Endpoint::with_proxy("proxy.example.com:3128").unwrap();
+//! proxied.add_peers(vec!["ws://example.com:443".into()]).unwrap();
+//! # });
+//! ```
+//!
+//! [`Endpoint::with_proxy`]: struct.Endpoint.html#method.with_proxy
+
+#[macro_use]
+extern crate tracing;
+
+mod peer;
+mod proxy;
+use peer::Peers;
+
+use async_std::{
+    io,
+    net::TcpListener,
+    sync::{channel, Arc, Receiver, Sender},
+    task,
+};
+use async_trait::async_trait;
+use async_tungstenite::tungstenite::{http::Uri, Error as WsError};
+use netmod::{Endpoint as EndpointExt, Error, Frame, Result, Target};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// The delay before the first reconnection attempt
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The maximum delay between reconnection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+fn scheme_supported(uri: &Uri) -> bool {
+    match uri.scheme_str() {
+        Some("ws") => true,
+        Some("wss") => cfg!(feature = "tls"),
+        _ => false,
+    }
+}
+
+/// Connect to a peer, and run the connection until it fails
+async fn connect(
+    url: &str,
+    proxy: Option<&str>,
+    id: u16,
+    peers: &Peers,
+    inbox: &Sender<(Frame, Target)>,
+) -> std::result::Result<(), WsError> {
+    let proxy = match proxy {
+        Some(proxy) => proxy,
+        None => {
+            let (ws, _) = async_tungstenite::async_std::connect_async(url).await?;
+            debug!("Connected to peer {} ({})", id, url);
+            peers.run(id, ws, inbox).await;
+            return Ok(());
+        }
+    };
+
+    // Urls are validated when peers are added
+    let uri: Uri = url.parse().unwrap();
+    let port = match (uri.port_u16(), uri.scheme_str()) {
+        (Some(port), _) => port,
+        (None, Some("wss")) => 443,
+        (None, _) => 80,
+    };
+    let stream = proxy::connect(proxy, uri.host().unwrap(), port).await?;
+
+    #[cfg(feature = "tls")]
+    let (ws, _) = async_tungstenite::async_tls::client_async_tls(url, stream).await?;
+    #[cfg(not(feature = "tls"))]
+    let (ws, _) = async_tungstenite::client_async(url, stream).await?;
+
+    debug!("Connected to peer {} ({}) via {}", id, url, proxy);
+    peers.run(id, ws, inbox).await;
+    Ok(())
+}
+
+/// A websocket endpoint
+pub struct Endpoint {
+    peers: Arc<Peers>,
+    proxy: Option<String>,
+    running: Arc<AtomicBool>,
+    tx: Sender<(Frame, Target)>,
+    rx: Receiver<(Frame, Target)>,
+}
+
+impl Endpoint {
+    fn create(proxy: Option<String>) -> Arc<Self> {
+        let (tx, rx) = channel(32);
+        Arc::new(Self {
+            peers: Default::default(),
+            proxy,
+            running: Arc::new(AtomicBool::new(true)),
+            tx,
+            rx,
+        })
+    }
+
+    /// Create an endpoint without any connections
+    pub fn new() -> Arc<Self> {
+        Self::create(None)
+    }
+
+    /// Create an endpoint that connects to peers via an http proxy
+    ///
+    /// `proxy` is the `host:port` of the proxy, which needs to allow
+    /// the `CONNECT` method to the ports of all peers.  Incoming
+    /// connections are not affected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if the proxy address has no port.
+    pub fn with_proxy(proxy: &str) -> io::Result<Arc<Self>> {
+        if !proxy::valid(proxy) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid proxy address `{}`", proxy),
+            ));
+        }
+
+        Ok(Self::create(Some(proxy.into())))
+    }
+
+    /// Accept websocket connections on an address and port
+    pub async fn listen(&self, addr: &str, port: u16) -> io::Result<()> {
+        let listener = TcpListener::bind((addr, port)).await?;
+        info!("Accepting websocket connections on {}:{}", addr, port);
+
+        let peers = Arc::clone(&self.peers);
+        let running = Arc::clone(&self.running);
+        let inbox = self.tx.clone();
+        task::spawn(async move {
+            while let Ok((stream, src)) = listener.accept().await {
+                if !running.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let peers = Arc::clone(&peers);
+                let inbox = inbox.clone();
+                task::spawn(async move {
+                    match async_tungstenite::accept_async(stream).await {
+                        Ok(ws) => {
+                            let id = peers.next_id();
+                            debug!("Accepted peer {} from {}", id, src);
+                            peers.run(id, ws, &inbox).await;
+                        }
+                        Err(e) => debug!("Websocket handshake with {} failed: {}", src, e),
+                    }
+                });
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Connect to a set of peers
+    ///
+    /// Peers are websocket urls (`ws://host:port/path`, or `wss://`
+    /// with the `tls` feature enabled).  Each peer
+    /// spawns a task that keeps a connection to it open, until the
+    /// endpoint is stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` (without adding any peers) if one of
+    /// the urls can't be parsed.
+    pub fn add_peers(&self, peers: Vec<String>) -> io::Result<()> {
+        for url in peers.iter() {
+            match url.parse::<Uri>() {
+                Ok(ref uri) if uri.host().is_some() && scheme_supported(uri) => {}
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid peer url `{}`", url),
+                    ))
+                }
+            }
+        }
+
+        for url in peers {
+            let id = self.peers.next_id();
+            let peers = Arc::clone(&self.peers);
+            let proxy = self.proxy.clone();
+            let running = Arc::clone(&self.running);
+            let inbox = self.tx.clone();
+            task::spawn(async move {
+                let mut backoff = MIN_BACKOFF;
+                while running.load(Ordering::Relaxed) {
+                    match connect(&url, proxy.as_deref(), id, &peers, &inbox).await {
+                        Ok(()) => {
+                            debug!("Lost connection to peer {} ({})", id, url);
+                            backoff = MIN_BACKOFF;
+                        }
+                        Err(e) => trace!("Failed to connect to {}: {}", url, e),
+                    }
+
+                    task::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Return the number of currently connected peers
+    pub async fn peers(&self) -> usize {
+        self.peers.len().await
+    }
+
+    /// Stop accepting connections, and reconnecting to peers
+    ///
+    /// Existing connections are kept open until they fail.
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl EndpointExt for Endpoint {
+    fn size_hint(&self) -> usize {
+        0
+    }
+
+    /// Send a frame to one peer, or all of them
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConnectionLost` if a single peer isn't currently
+    /// connected, or the connection fails while sending.
+    async fn send(&self, frame: Frame, target: Target) -> Result<()> {
+        match target {
+            Target::Flood => {
+                for (id, peer) in self.peers.all().await {
+                    if let Err(e) = peer.send(&frame).await {
+                        debug!("Failed to send to peer {}: {}", id, e);
+                    }
+                }
+                Ok(())
+            }
+            Target::Single(id) => {
+                let peer = self.peers.get(id).await.ok_or(Error::ConnectionLost)?;
+                peer.send(&frame).await.map_err(|e| {
+                    debug!("Failed to send to peer {}: {}", id, e);
+                    Error::ConnectionLost
+                })
+            }
+        }
+    }
+
+    async fn next(&self) -> Result<(Frame, Target)> {
+        match self.rx.recv().await {
+            Some(ft) => Ok(ft),
+            None => Err(Error::ConnectionLost),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Peer connection handling
+
+use async_std::sync::{Mutex, RwLock, Sender};
+use async_tungstenite::{
+    tungstenite::{Error as WsError, Message},
+    WebSocketStream,
+};
+use futures::{
+    io::{AsyncRead, AsyncWrite},
+    prelude::*,
+};
+use netmod::{Frame, Target};
+use std::{
+    collections::BTreeMap,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+};
+
+type WsSink = Pin<Box<dyn Sink<Message, Error = WsError> + Send>>;
+
+/// The sending half of a websocket connection
+pub(crate) struct Peer {
+    sink: Mutex<WsSink>,
+}
+
+impl Peer {
+    pub(crate) async fn send(&self, frame: &Frame) -> Result<(), WsError> {
+        let data = bincode::serialize(frame).unwrap();
+        self.sink.lock().await.send(Message::Binary(data)).await
+    }
+}
+
+/// A table of connected peers
+#[derive(Default)]
+pub(crate) struct Peers {
+    ctr: AtomicU16,
+    map: RwLock<BTreeMap<u16, Arc<Peer>>>,
+}
+
+impl Peers {
+    /// Reserve a new peer ID
+    ///
+    /// Peers that are reconnected keep their ID, so that routes via
+    /// them stay valid.
+    pub(crate) fn next_id(&self) -> u16 {
+        self.ctr.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Run a connection until it is closed
+    ///
+    /// Incoming frames are passed to `inbox`, with the peer ID as
+    /// their target.
+    pub(crate) async fn run<S>(
+        &self,
+        id: u16,
+        ws: WebSocketStream<S>,
+        inbox: &Sender<(Frame, Target)>,
+    ) where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (sink, mut stream) = ws.split();
+        let peer = Arc::new(Peer {
+            sink: Mutex::new(Box::pin(sink)),
+        });
+        self.map.write().await.insert(id, peer);
+
+        while let Some(msg) = stream.next().await {
+            match msg {
+                Ok(Message::Binary(data)) => match bincode::deserialize(&data) {
+                    Ok(frame) => inbox.send((frame, Target::Single(id))).await,
+                    Err(_) => warn!("Failed to decode frame from peer {}", id),
+                },
+                Ok(Message::Close(_)) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    debug!("Connection to peer {} failed: {}", id, e);
+                    break;
+                }
+            }
+        }
+
+        self.remove(id).await;
+    }
+
+    pub(crate) async fn get(&self, id: u16) -> Option<Arc<Peer>> {
+        self.map.read().await.get(&id).cloned()
+    }
+
+    pub(crate) async fn all(&self) -> Vec<(u16, Arc<Peer>)> {
+        self.map
+            .read()
+            .await
+            .iter()
+            .map(|(id, p)| (*id, Arc::clone(p)))
+            .collect()
+    }
+
+    pub(crate) async fn remove(&self, id: u16) {
+        self.map.write().await.remove(&id);
+    }
+
+    pub(crate) async fn len(&self) -> usize {
+        self.map.read().await.len()
+    }
+}
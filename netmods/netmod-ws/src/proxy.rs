@@ -0,0 +1,60 @@
+//! Tunnelling connections through http proxies
+
+use async_std::{
+    io::{self, prelude::*},
+    net::TcpStream,
+};
+
+/// The largest proxy response header that is accepted
+const MAX_HEADER: usize = 8192;
+
+/// Check that a proxy address has the form `host:port`
+pub(crate) fn valid(proxy: &str) -> bool {
+    match proxy.rfind(':') {
+        Some(idx) => idx > 0 && proxy[idx + 1..].parse::<u16>().is_ok(),
+        None => false,
+    }
+}
+
+/// Open a tcp connection to `host:port` via an http proxy
+///
+/// This uses the `CONNECT` method, so the proxy never sees the
+/// websocket handshake, and the connection can use tls end-to-end.
+pub(crate) async fn connect(proxy: &str, host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy).await?;
+    let req = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n\r\n",
+        host = host,
+        port = port
+    );
+    stream.write_all(req.as_bytes()).await?;
+
+    // The response is read one byte at a time, so that no data the
+    // peer sends after it is consumed
+    let mut head = vec![];
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEADER {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "proxy response is too long",
+            ));
+        }
+
+        match stream.read(&mut byte).await? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => head.push(byte[0]),
+        }
+    }
+
+    // Only the status code matters: "HTTP/1.1 200 Connection established"
+    let status = String::from_utf8_lossy(&head);
+    let status = status.lines().next().unwrap_or("");
+    match status.split(' ').nth(1) {
+        Some("200") => Ok(stream),
+        _ => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("proxy refused connection: {}", status),
+        )),
+    }
+}
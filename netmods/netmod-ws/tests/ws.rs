@@ -0,0 +1,108 @@
+use async_std::{
+    io::{self, prelude::*},
+    net::{TcpListener, TcpStream},
+    task,
+};
+use netmod::{Endpoint as _, Frame, Target};
+use netmod_ws::Endpoint;
+use ratman::{Identity, Router};
+use std::{sync::Arc, time::Duration};
+
+async fn connected(ep: &Arc<Endpoint>) {
+    while ep.peers().await == 0 {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[async_std::test]
+async fn client_server() {
+    let server = Endpoint::new();
+    server.listen("127.0.0.1", 19180).await.unwrap();
+    let client = Endpoint::new();
+    client
+        .add_peers(vec!["ws://127.0.0.1:19180".into()])
+        .unwrap();
+    connected(&client).await;
+
+    let frame = Frame::dummy();
+    client.send(frame.clone(), Target::Flood).await.unwrap();
+    let (f, t) = server.next().await.unwrap();
+    assert_eq!(f, frame);
+
+    let frame = Frame::dummy();
+    server.send(frame.clone(), t).await.unwrap();
+    assert_eq!(client.next().await.unwrap(), (frame, Target::Single(0)));
+
+    assert!(client.add_peers(vec!["http://127.0.0.1".into()]).is_err());
+}
+
+#[async_std::test]
+async fn reconnect() {
+    // The server only comes up after the client started connecting
+    let client = Endpoint::new();
+    client
+        .add_peers(vec!["ws://127.0.0.1:19181".into()])
+        .unwrap();
+    task::sleep(Duration::from_millis(100)).await;
+    assert_eq!(client.peers().await, 0);
+
+    let server = Endpoint::new();
+    server.listen("127.0.0.1", 19181).await.unwrap();
+
+    let r1 = Router::new();
+    let r2 = Router::new();
+    r1.add_endpoint(client).await;
+    r2.add_endpoint(server).await;
+
+    let u2 = Identity::random();
+    r2.add_user(u2).await.unwrap();
+    r2.online(u2).await.unwrap();
+    assert_eq!(r1.discover().await, u2);
+}
+
+/// A minimal http proxy that only supports `CONNECT`
+async fn proxy() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    task::spawn(async move {
+        while let Ok((mut client, _)) = listener.accept().await {
+            let mut head = vec![];
+            let mut byte = [0];
+            while !head.ends_with(b"\r\n\r\n") {
+                client.read_exact(&mut byte).await.unwrap();
+                head.push(byte[0]);
+            }
+
+            let head = String::from_utf8(head).unwrap();
+            let target = head.split(' ').nth(1).unwrap();
+            let server = TcpStream::connect(target).await.unwrap();
+            client
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await
+                .unwrap();
+
+            let (mut cr, mut cw) = (client.clone(), client);
+            let (mut sr, mut sw) = (server.clone(), server);
+            task::spawn(async move { io::copy(&mut cr, &mut sw).await });
+            task::spawn(async move { io::copy(&mut sr, &mut cw).await });
+        }
+    });
+    addr
+}
+
+#[async_std::test]
+async fn http_proxy() {
+    let server = Endpoint::new();
+    server.listen("127.0.0.1", 19182).await.unwrap();
+    let client = Endpoint::with_proxy(&proxy().await).unwrap();
+    client
+        .add_peers(vec!["ws://127.0.0.1:19182".into()])
+        .unwrap();
+    connected(&client).await;
+
+    let frame = Frame::dummy();
+    client.send(frame.clone(), Target::Flood).await.unwrap();
+    assert_eq!(server.next().await.unwrap().0, frame);
+
+    assert!(Endpoint::with_proxy("127.0.0.1").is_err());
+}
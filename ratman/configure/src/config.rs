@@ -1,20 +1,51 @@
+//! Network configuration types
 //!
+//! A `Network` is a set of endpoints, each with a unique ID, a type
+//! and a set of parameters, and a set of patches that describe how
+//! endpoints are connected.  See the crate root for the file format.
 
-use async_std::task::block_on;
-use ratman::Router;
-use std::collections::BTreeMap;
-use std::{net::SocketAddr, ops::Deref, sync::Arc};
+use crate::{
+    error::{Error, Result},
+    key::{self, Key, Keyed},
+    mtu::{self, Mtu},
+};
+use async_std::sync::Arc;
+use netmod_mem::MemMod;
+use netmod_pcap::{Capture, Replay, Timing};
+use ratman::{netmod::Endpoint as EndpointExt, Router};
+use serde::{
+    de::{self, Deserializer, Unexpected, Visitor},
+    Deserialize,
+};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+    ops::Deref,
+    path::PathBuf,
+};
 
 pub type Id = usize;
 
+fn default_udp_port() -> u16 {
+    9000
+}
+
+fn default_baud() -> u32 {
+    9600
+}
+
 /// A wrapper type for parameters that are required for an endpoint
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", content = "params", rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
 pub enum Params {
     /// Virtual testing endpoint purely in memory
     ///
     /// Because it is only used to connect with one other endpoint in
     /// memory no parameters are required to make a network of virtual
-    /// endpoints work.
+    /// endpoints work.  A virtual endpoint must always be patched to
+    /// another virtual endpoint.
     Virtual,
     /// Internet tcp overlay endpoint
     ///
@@ -24,28 +55,118 @@ pub enum Params {
     Tcp {
         addr: String,
         port: u16,
+        #[serde(default)]
         peers: Vec<String>,
+        #[serde(default)]
         dynamic: bool,
     },
     /// Purely local udp broadcast endpoint
     ///
+    /// `addr` selects the interface to join the multicast group on.
     /// Because of how multicast works on Linux, all udp modules in a
     /// network need to be running on the same port.  This means that
     /// two udp endpoints can't be running on the same computer at the
     /// same time for testing purposes, without network namespaces.
-    LocalUpd { addr: String },
+    LocalUdp {
+        addr: String,
+        #[serde(default = "default_udp_port")]
+        port: u16,
+    },
+    /// Raw ethernet endpoint, for links without IP configuration
+    ///
+    /// Requires the `CAP_NET_RAW` capability.
+    Ethernet { iface: String },
+    /// Websocket endpoint for networks behind http proxies
+    ///
+    /// Accepts connections on `listen` (if set), and connects to the
+    /// websocket urls in `peers`, reconnecting when they fail.  With
+    /// `proxy` (`host:port`), peers are connected to through an http
+    /// proxy.
+    Websocket {
+        #[serde(default)]
+        listen: Option<SocketAddr>,
+        #[serde(default)]
+        peers: Vec<String>,
+        #[serde(default)]
+        proxy: Option<String>,
+    },
+    /// Unix domain socket and pipe endpoint for routers on one host
+    ///
+    /// The endpoint accepts connections on `listen` (if set), and
+    /// connects to all socket paths in `peers`.  With `stdio`, stdin
+    /// and stdout are used as an additional connection.
+    Unix {
+        #[serde(default)]
+        listen: Option<PathBuf>,
+        #[serde(default)]
+        peers: Vec<PathBuf>,
+        #[serde(default)]
+        stdio: bool,
+    },
+    /// Serial link to another device or a KISS TNC
+    ///
+    /// `path` is the terminal device to open, and `port` the TNC port
+    /// to send frames on.  With `compact`, frames are sent with the
+    /// compact wire encoding, which both sides need to enable.
+    Serial {
+        path: PathBuf,
+        #[serde(default = "default_baud")]
+        baud: u32,
+        #[serde(default)]
+        port: u8,
+        #[serde(default)]
+        compact: bool,
+    },
+    /// Play back a capture made with the `capture` option
+    ///
+    /// Frames are played back with the delays they were recorded
+    /// with, unless `instant` is set.  Frames sent to this endpoint
+    /// are discarded.
+    Replay {
+        path: PathBuf,
+        #[serde(default)]
+        instant: bool,
+    },
     /// Android wifi direct support
     #[cfg(feature = "android")]
     WifiDirect,
 }
 
+impl Params {
+    /// The type name, as used in configuration files
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Virtual => "virtual",
+            Self::Tcp { .. } => "tcp",
+            Self::LocalUdp { .. } => "local-udp",
+            Self::Ethernet { .. } => "ethernet",
+            Self::Websocket { .. } => "websocket",
+            Self::Unix { .. } => "unix",
+            Self::Serial { .. } => "serial",
+            Self::Replay { .. } => "replay",
+            #[cfg(feature = "android")]
+            Self::WifiDirect => "wifi-direct",
+        }
+    }
+}
+
 /// Configuration for a single endpoint
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
 pub struct Endpoint {
     /// A unique ID for this endpoint
-    pub id: usize,
+    pub id: Id,
     /// Type and required parameter set for initialisation
+    #[serde(flatten)]
     pub params: Params,
+    /// Maximum size of a frame sent via this endpoint, in bytes
+    #[serde(default)]
+    pub mtu: Option<usize>,
+    /// Record all frames crossing this endpoint to a file
+    #[serde(default)]
+    pub capture: Option<PathBuf>,
+    /// Encrypt all frames sent via this endpoint with a pre-shared key
+    #[serde(default)]
+    pub key: Option<Key>,
 }
 
 /// A network endpoint patch type
@@ -57,16 +178,91 @@ pub struct Endpoint {
 /// hooking up the internal memory channel of `netmod-mem`.
 ///
 /// Use the `External` type to use the endpoint to configure against
-/// an external target (meaning actual network traffic).
-#[derive(Clone, Debug)]
+/// an external target (meaning actual network traffic).  Endpoints
+/// without a patch are external.
+#[derive(Clone, Debug, PartialEq)]
 pub enum Patch {
     Internal(Id),
     External,
 }
 
+impl<'de> Deserialize<'de> for Patch {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> std::result::Result<Self, D::Error> {
+        struct PatchVisitor;
+
+        impl<'de> Visitor<'de> for PatchVisitor {
+            type Value = Patch;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "\"external\" or an endpoint id")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> std::result::Result<Patch, E> {
+                match s {
+                    "external" => Ok(Patch::External),
+                    _ => Err(E::invalid_value(Unexpected::Str(s), &self)),
+                }
+            }
+
+            fn visit_u64<E: de::Error>(self, id: u64) -> std::result::Result<Patch, E> {
+                Ok(Patch::Internal(id as Id))
+            }
+
+            fn visit_i64<E: de::Error>(self, id: i64) -> std::result::Result<Patch, E> {
+                match id {
+                    id if id >= 0 => Ok(Patch::Internal(id as Id)),
+                    _ => Err(E::invalid_value(Unexpected::Signed(id), &self)),
+                }
+            }
+        }
+
+        de.deserialize_any(PatchVisitor)
+    }
+}
+
+/// The network description as it appears in a configuration file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RawNetwork {
+    endpoints: Vec<Endpoint>,
+    #[serde(default)]
+    patches: BTreeMap<String, Patch>,
+}
+
+impl RawNetwork {
+    /// Index endpoints and patches by ID, and validate the result
+    pub(crate) fn into_network(self) -> Result<Network> {
+        let mut endpoints = BTreeMap::new();
+        for ep in self.endpoints {
+            let id = ep.id;
+            if endpoints.insert(id, ep).is_some() {
+                return Err(Error::invalid(id, "duplicate endpoint id"));
+            }
+        }
+
+        let patches = self
+            .patches
+            .into_iter()
+            .map(|(k, v)| match k.parse() {
+                Ok(id) => Ok((id, v)),
+                Err(_) => Err(Error::Invalid {
+                    id: None,
+                    msg: format!("invalid patch key `{}`: must be an endpoint id", k),
+                }),
+            })
+            .collect::<Result<_>>()?;
+
+        let net = Network { endpoints, patches };
+        net.validate()?;
+        Ok(net)
+    }
+}
+
 /// A set of endpoints to connect to various networks
 ///
-/// The list of endpoints defines
+/// The list of endpoints defines which drivers a router will be
+/// using, while the patches define which of them are connected to
+/// each other internally.
 #[derive(Clone, Debug, Default)]
 pub struct Network {
     /// Set of endpoints for this network backend
@@ -80,48 +276,386 @@ impl Network {
         Self::default()
     }
 
+    /// Check that this network can be turned into a router
+    ///
+    /// This checks endpoint parameters, and makes sure that all
+    /// patches are between existing, compatible endpoints, and don't
+    /// contradict each other.
+    pub fn validate(&self) -> Result<()> {
+        for (id, ep) in self.endpoints.iter() {
+            if ep.id != *id {
+                return Err(Error::invalid(
+                    *id,
+                    format!("indexed as endpoint {}", ep.id),
+                ));
+            }
+
+            if let Some(mtu) = ep.mtu {
+                // Keys are applied before the mtu is checked
+                let min = mtu::min_mtu() + ep.key.as_ref().map_or(0, |_| key::OVERHEAD);
+                if mtu < min {
+                    return Err(Error::invalid(
+                        *id,
+                        format!("mtu must be at least {} bytes", min),
+                    ));
+                }
+            }
+
+            match ep.params {
+                Params::Tcp { ref peers, .. } => {
+                    for p in peers {
+                        // Peers can carry an optional link type suffix
+                        let addr = p.split(' ').next().unwrap_or("");
+                        if addr.parse::<SocketAddr>().is_err() {
+                            return Err(Error::invalid(*id, format!("invalid peer `{}`", p)));
+                        }
+                    }
+                }
+                Params::LocalUdp { ref addr, .. } if addr.parse::<Ipv4Addr>().is_err() => {
+                    return Err(Error::invalid(*id, format!("invalid address `{}`", addr)));
+                }
+                Params::Websocket {
+                    ref peers,
+                    ref proxy,
+                    ..
+                } => {
+                    for p in peers {
+                        if !p.starts_with("ws://") && !p.starts_with("wss://") {
+                            return Err(Error::invalid(*id, format!("invalid peer `{}`", p)));
+                        }
+                    }
+
+                    let port = proxy.as_ref().map(|p| p.rsplit(':').next().unwrap());
+                    if let Some(Err(_)) = port.map(str::parse::<u16>) {
+                        return Err(Error::invalid(
+                            *id,
+                            format!("invalid proxy `{}`", proxy.as_ref().unwrap()),
+                        ));
+                    }
+                }
+                Params::Serial { port, .. } if port > 15 => {
+                    return Err(Error::invalid(*id, "serial port must be between 0 and 15"));
+                }
+                _ => {}
+            }
+        }
+
+        for (id, patch) in self.patches.iter() {
+            let ep = self.endpoints.get(id).ok_or_else(|| Error::Invalid {
+                id: None,
+                msg: format!("patch for unknown endpoint {}", id),
+            })?;
+
+            let other = match patch {
+                Patch::Internal(other) => *other,
+                Patch::External => continue,
+            };
+
+            let target = self.endpoints.get(&other).ok_or_else(|| {
+                Error::invalid(*id, format!("patched to unknown endpoint {}", other))
+            })?;
+
+            if other == *id {
+                return Err(Error::invalid(*id, "can't be patched to itself"));
+            }
+
+            match (&ep.params, &target.params) {
+                (Params::Virtual, Params::Virtual) => {}
+                (Params::Tcp { .. }, Params::Tcp { .. }) => {}
+                (Params::Virtual, _) | (Params::Tcp { .. }, _) => {
+                    return Err(Error::invalid(
+                        *id,
+                        format!(
+                            "can't patch {} endpoint to {} endpoint {}",
+                            ep.params.kind(),
+                            target.params.kind(),
+                            other
+                        ),
+                    ));
+                }
+                (params, _) => {
+                    return Err(Error::invalid(
+                        *id,
+                        format!("{} endpoints can't be patched internally", params.kind()),
+                    ));
+                }
+            }
+
+            match self.patches.get(&other) {
+                Some(Patch::Internal(back)) if back == id => {}
+                None => {}
+                Some(_) => {
+                    return Err(Error::invalid(
+                        *id,
+                        format!("conflicting patch on endpoint {}", other),
+                    ))
+                }
+            }
+        }
+
+        let stdio = self
+            .endpoints
+            .values()
+            .filter(|ep| match ep.params {
+                Params::Unix { stdio, .. } => stdio,
+                _ => false,
+            })
+            .nth(1);
+        if let Some(ep) = stdio {
+            return Err(Error::invalid(ep.id, "only one endpoint can use stdio"));
+        }
+
+        let pairs = self.internal_pairs();
+        for (id, ep) in self
+            .endpoints
+            .iter()
+            .filter(|(_, ep)| ep.params == Params::Virtual)
+        {
+            match pairs.iter().filter(|(a, b)| a == id || b == id).count() {
+                0 => return Err(Error::invalid(*id, "virtual endpoints must be patched")),
+                1 => {}
+                _ => {
+                    return Err(Error::invalid(
+                        ep.id,
+                        "virtual endpoints can only be patched once",
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get all internal patches, as pairs of (lower, higher) IDs
+    fn internal_pairs(&self) -> Vec<(Id, Id)> {
+        let mut pairs: Vec<_> = self
+            .patches
+            .iter()
+            .filter_map(|(a, p)| match p {
+                Patch::Internal(b) => Some((*a.min(b), *a.max(b))),
+                Patch::External => None,
+            })
+            .collect();
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+
     /// Consume the `Network` instance to initialise a Router
-    pub fn into_router(self) -> Arc<Router> {
-        let _p = self.patches;
+    ///
+    /// The network is validated first, and no endpoints are created
+    /// if it is invalid.
+    pub async fn into_router(self) -> Result<Arc<Router>> {
+        self.build().await.map(|(router, _)| router)
+    }
 
-        self.endpoints
-            .into_iter()
-            .fold(Router::new(), |router, (_, ep)| {
-                match ep.params {
-                    // FIXME: Figure out what the udp module actually needs
-                    Params::LocalUpd { addr: _ } => {
-                        use netmod_udp::Endpoint;
-                        let ep = Endpoint::spawn(9000);
-                        block_on(async { router.add_endpoint(ep).await });
+    /// Initialise a Router, and return handles to endpoints that
+    /// need to be driven by the caller
+    pub async fn build(self) -> Result<(Arc<Router>, Handles)> {
+        self.validate()?;
+
+        let mut mems = BTreeMap::new();
+        let mut local_peers: BTreeMap<Id, Vec<String>> = BTreeMap::new();
+        for (a, b) in self.internal_pairs() {
+            match (&self.endpoints[&a].params, &self.endpoints[&b].params) {
+                (Params::Tcp { port: pa, .. }, Params::Tcp { port: pb, .. }) => {
+                    let entry = local_peers.entry(a).or_default();
+                    entry.push(format!("127.0.0.1:{}", pb));
+                    let entry = local_peers.entry(b).or_default();
+                    entry.push(format!("127.0.0.1:{}", pa));
+                }
+                _ => {
+                    let (ma, mb) = MemMod::make_pair();
+                    mems.insert(a, ma);
+                    mems.insert(b, mb);
+                }
+            }
+        }
+
+        let router = Router::new();
+        #[allow(unused_mut)]
+        let mut handles = Handles::default();
+
+        for (id, ep) in self.endpoints {
+            match ep.params.clone() {
+                Params::Virtual => {
+                    let mm = mems.remove(&id).unwrap();
+                    add_endpoint(&router, &ep, mm).await?;
+                }
+                Params::Tcp {
+                    addr,
+                    port,
+                    mut peers,
+                    dynamic,
+                } => {
+                    use netmod_tcp::{Endpoint, Mode};
+                    let mode = if dynamic { Mode::Dynamic } else { Mode::Static };
+                    let tcp = Endpoint::new(&addr, port, "qauld", mode)
+                        .await
+                        .map_err(|e| Error::Init {
+                            id,
+                            msg: e.to_string(),
+                        })?;
+
+                    peers.extend(local_peers.remove(&id).unwrap_or_default());
+                    tcp.add_peers(peers).await.map_err(|e| Error::Init {
+                        id,
+                        msg: e.to_string(),
+                    })?;
+                    add_endpoint(&router, &ep, tcp).await?;
+                }
+                Params::LocalUdp { addr, port } => {
+                    use netmod_udp::Endpoint;
+                    let udp = Endpoint::spawn_on(addr.parse().unwrap(), port);
+                    add_endpoint(&router, &ep, udp).await?;
+                }
+                Params::Ethernet { iface } => {
+                    let eth = netmod_eth::Endpoint::open(&iface).map_err(|e| Error::Init {
+                        id,
+                        msg: format!("failed to open {}: {}", iface, e),
+                    })?;
+                    add_endpoint(&router, &ep, eth).await?;
+                }
+                Params::Websocket {
+                    listen,
+                    peers,
+                    proxy,
+                } => {
+                    let init = |e| Error::Init {
+                        id,
+                        msg: format!("{}", e),
+                    };
+                    let ws = match proxy {
+                        Some(proxy) => netmod_ws::Endpoint::with_proxy(&proxy).map_err(init)?,
+                        None => netmod_ws::Endpoint::new(),
+                    };
+                    if let Some(addr) = listen {
+                        ws.listen(&addr.ip().to_string(), addr.port())
+                            .await
+                            .map_err(init)?;
+                    }
+                    ws.add_peers(peers).map_err(init)?;
+                    add_endpoint(&router, &ep, ws).await?;
+                }
+                Params::Unix {
+                    listen,
+                    peers,
+                    stdio,
+                } => {
+                    let unix = netmod_unix::Endpoint::new();
+                    let init = |e| Error::Init {
+                        id,
+                        msg: format!("{}", e),
+                    };
+                    if let Some(path) = listen {
+                        unix.listen(path).await.map_err(init)?;
+                    }
+                    for peer in peers {
+                        unix.connect(peer).await.map_err(init)?;
                     }
-                    Params::Tcp {
-                        addr,
+                    if stdio {
+                        unix.stdio().await;
+                    }
+                    add_endpoint(&router, &ep, unix).await?;
+                }
+                Params::Serial {
+                    path,
+                    baud,
+                    port,
+                    compact,
+                } => {
+                    use netmod_serial::{Config, Serial};
+                    let cfg = Config {
                         port,
-                        peers,
-                        dynamic,
-                    } => {
-                        use netmod_tcp::{Endpoint, Mode};
-                        block_on(async {
-                            let ep = Endpoint::new(
-                                &addr,
-                                port,
-                                "qauld",
-                                if dynamic { Mode::Dynamic } else { Mode::Static },
-                            )
+                        compact,
+                        ..Config::default()
+                    };
+                    let serial =
+                        Serial::open_with(&path, baud, cfg)
                             .await
-                            .unwrap();
-                            ep.add_peers(peers).await.unwrap();
-                            router.add_endpoint(ep).await;
-                        });
-                    }
-                    _ => unimplemented!(),
+                            .map_err(|e| Error::Init {
+                                id,
+                                msg: format!("failed to open {}: {}", path.display(), e),
+                            })?;
+                    add_endpoint(&router, &ep, serial).await?;
                 }
+                Params::Replay { path, instant } => {
+                    let timing = if instant {
+                        Timing::Instant
+                    } else {
+                        Timing::Original
+                    };
+                    let replay = Replay::open(path, timing).await.map_err(|e| Error::Init {
+                        id,
+                        msg: format!("failed to open capture: {}", e),
+                    })?;
+                    add_endpoint(&router, &ep, replay).await?;
+                }
+                #[cfg(feature = "android")]
+                Params::WifiDirect => {
+                    let wd = netmod_wd::WdMod::new();
+                    add_endpoint(&router, &ep, Arc::clone(&wd)).await?;
+                    handles.wifi_direct.insert(id, wd);
+                }
+            }
+        }
 
-                router
-            })
+        Ok((router, handles))
     }
 }
 
+/// Wrap an endpoint according to its options, and add it to a router
+///
+/// Frames are encrypted before their size is checked, and captures
+/// record frames as they are sent over the link.
+async fn add_endpoint<E>(router: &Router, ep: &Endpoint, inner: Arc<E>) -> Result<()>
+where
+    E: EndpointExt + Send + Sync + 'static,
+{
+    match ep.capture {
+        Some(ref path) => {
+            let cap = Capture::new(inner, path).await.map_err(|e| Error::Init {
+                id: ep.id,
+                msg: format!("failed to create capture: {}", e),
+            })?;
+            add_limited(router, ep, cap).await
+        }
+        None => add_limited(router, ep, inner).await,
+    }
+    Ok(())
+}
+
+async fn add_limited<E>(router: &Router, ep: &Endpoint, inner: Arc<E>)
+where
+    E: EndpointExt + Send + Sync + 'static,
+{
+    match ep.mtu {
+        Some(mtu) => add_keyed(router, ep, Mtu::new(inner, mtu)).await,
+        None => add_keyed(router, ep, inner).await,
+    }
+}
+
+async fn add_keyed<E>(router: &Router, ep: &Endpoint, inner: Arc<E>)
+where
+    E: EndpointExt + Send + Sync + 'static,
+{
+    match ep.key {
+        Some(ref key) => router.add_endpoint(Keyed::new(inner, key)).await,
+        None => router.add_endpoint(inner).await,
+    };
+}
+
+/// Endpoints that need to be driven from outside the router
+///
+/// Most endpoints do their own I/O, but some are fed by a platform
+/// specific driver stack.
+#[derive(Default)]
+pub struct Handles {
+    /// WiFi Direct endpoints, fed by the android driver stack
+    #[cfg(feature = "android")]
+    pub wifi_direct: BTreeMap<Id, Arc<netmod_wd::WdMod>>,
+}
+
 impl Deref for Network {
     type Target = BTreeMap<usize, Endpoint>;
     fn deref(&self) -> &Self::Target {
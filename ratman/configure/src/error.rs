@@ -0,0 +1,92 @@
+//! Configuration error handling
+
+use crate::config::Id;
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Errors that can occur while loading or applying a configuration
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The configuration file could not be read
+    Io(String),
+    /// The configuration is malformed
+    ///
+    /// The position (line and column, both starting at 1) is
+    /// available for most syntax and type errors, but not all.
+    Parse {
+        pos: Option<(usize, usize)>,
+        msg: String,
+    },
+    /// The configuration is well-formed, but describes an invalid
+    /// network, for example a patch to an endpoint that doesn't
+    /// exist.
+    Invalid { id: Option<Id>, msg: String },
+    /// An endpoint failed to initialise
+    Init { id: Id, msg: String },
+}
+
+impl Error {
+    pub(crate) fn invalid<S: Into<String>>(id: Id, msg: S) -> Self {
+        Self::Invalid {
+            id: Some(id),
+            msg: msg.into(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Io(msg) => write!(f, "failed to read configuration: {}", msg),
+            Self::Parse {
+                pos: Some((line, col)),
+                msg,
+            } => write!(f, "line {}, column {}: {}", line, col, msg),
+            Self::Parse { pos: None, msg } => write!(f, "{}", msg),
+            Self::Invalid { id: Some(id), msg } => write!(f, "endpoint {}: {}", id, msg),
+            Self::Invalid { id: None, msg } => write!(f, "{}", msg),
+            Self::Init { id, msg } => write!(f, "endpoint {} failed to start: {}", id, msg),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        let msg = e.to_string();
+        Self::Parse {
+            pos: match e.line() {
+                0 => None,
+                line => Some((line, e.column())),
+            },
+            // serde_json appends the position to the message
+            msg: match msg.rfind(" at line ") {
+                Some(idx) => msg[..idx].to_owned(),
+                None => msg,
+            },
+        }
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        let msg = e.to_string();
+        Self::Parse {
+            pos: e.line_col().map(|(line, col)| (line + 1, col + 1)),
+            msg: match msg.rfind(" at line ") {
+                Some(idx) => msg[..idx].to_owned(),
+                None => msg,
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+/// A `ratman-configure` specific `Result` wrapper
+pub type Result<T> = std::result::Result<T, Error>;
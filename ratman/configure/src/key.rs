@@ -0,0 +1,190 @@
+//! An endpoint wrapper that encrypts frames with a pre-shared key
+
+use async_std::sync::Arc;
+use async_trait::async_trait;
+use ratman::netmod::{Endpoint, Error, Frame, Result, Target};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::de::{self, Deserialize, Deserializer, Unexpected, Visitor};
+use std::fmt;
+
+/// The number of bytes a key adds to the payload of every frame
+pub(crate) const OVERHEAD: usize = NONCE_LEN + 16;
+
+/// A pre-shared key for an endpoint
+///
+/// In a configuration file, a key is written as 64 hex characters
+/// (for example the output of `openssl rand -hex 32`).
+#[derive(Clone, PartialEq)]
+pub struct Key(pub [u8; 32]);
+
+impl Key {
+    /// Parse a key from its hex representation
+    pub fn from_hex(s: &str) -> Option<Self> {
+        if s.len() != 64 || !s.is_ascii() {
+            return None;
+        }
+
+        let mut key = [0; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(Self(key))
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Key(..)")
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> std::result::Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = Key;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a 32 byte key, as 64 hex characters")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> std::result::Result<Key, E> {
+                Key::from_hex(s).ok_or_else(|| E::invalid_value(Unexpected::Str(s), &self))
+            }
+        }
+
+        de.deserialize_str(KeyVisitor)
+    }
+}
+
+/// Encrypt all frames sent via an endpoint with a pre-shared key
+///
+/// The payload of every frame is encrypted and authenticated, along
+/// with its header, which is left readable so that the frame can
+/// still be routed.  Frames that weren't sent with the same key are
+/// dropped on the receiving side, meaning that only routers that know
+/// the key can talk to each other via the endpoint.
+pub(crate) struct Keyed<E: Endpoint> {
+    inner: Arc<E>,
+    key: LessSafeKey,
+    rand: SystemRandom,
+}
+
+impl<E: Endpoint> Keyed<E> {
+    pub(crate) fn new(inner: Arc<E>, key: &Key) -> Arc<Self> {
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key.0).unwrap();
+        Arc::new(Self {
+            inner,
+            key: LessSafeKey::new(key),
+            rand: SystemRandom::new(),
+        })
+    }
+}
+
+/// The frame header, which is authenticated along with the payload
+fn header(f: &Frame) -> Vec<u8> {
+    bincode::serialize(&(&f.sender, &f.recipient, &f.seq)).unwrap()
+}
+
+#[async_trait]
+impl<E> Endpoint for Keyed<E>
+where
+    E: Endpoint + Send + Sync,
+{
+    fn size_hint(&self) -> usize {
+        match self.inner.size_hint() {
+            0 => 0,
+            hint => hint.saturating_sub(OVERHEAD),
+        }
+    }
+
+    async fn send(&self, mut frame: Frame, target: Target) -> Result<()> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rand
+            .fill(&mut nonce)
+            .map_err(|_| Error::NotSupported)?;
+
+        let aad = header(&frame);
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&aad),
+                &mut frame.payload,
+            )
+            .map_err(|_| Error::FrameTooLarge)?;
+
+        frame.payload.splice(0..0, nonce.iter().cloned());
+        self.inner.send(frame, target).await
+    }
+
+    async fn next(&self) -> Result<(Frame, Target)> {
+        loop {
+            let (mut frame, t) = self.inner.next().await?;
+            if frame.payload.len() < OVERHEAD {
+                continue;
+            }
+
+            let mut nonce = [0; NONCE_LEN];
+            nonce.copy_from_slice(&frame.payload[..NONCE_LEN]);
+            let aad = header(&frame);
+            let mut data = frame.payload.split_off(NONCE_LEN);
+
+            // Frames that weren't sent with our key are dropped
+            if let Ok(plain) = self.key.open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&aad),
+                &mut data,
+            ) {
+                let len = plain.len();
+                data.truncate(len);
+                frame.payload = data;
+                return Ok((frame, t));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netmod_mem::MemMod;
+
+    fn key(b: u8) -> Key {
+        Key([b; 32])
+    }
+
+    #[test]
+    fn parse_hex() {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+        let k = Key::from_hex(hex).unwrap();
+        assert_eq!(k.0[1], 0x11);
+        assert_eq!(k.0[31], 0xff);
+
+        assert!(Key::from_hex("0011").is_none());
+        assert!(Key::from_hex(&hex.replace("00", "zz")).is_none());
+    }
+
+    #[async_std::test]
+    async fn roundtrip() {
+        let (a, b) = MemMod::make_pair();
+        let (a, b) = (Keyed::new(a, &key(1)), Keyed::new(b, &key(1)));
+
+        let f = Frame::dummy();
+        a.send(f.clone(), Target::default()).await.unwrap();
+        assert_eq!(b.next().await.unwrap().0, f);
+    }
+
+    #[async_std::test]
+    async fn wrong_key() {
+        let (a, b) = MemMod::make_pair();
+        let (a, b) = (Keyed::new(a, &key(1)), Keyed::new(b, &key(2)));
+
+        a.send(Frame::dummy(), Target::default()).await.unwrap();
+        let next = async_std::future::timeout(std::time::Duration::from_millis(50), b.next());
+        assert!(next.await.is_err());
+    }
+}
@@ -5,14 +5,97 @@
 //! and less repetitive, this library is meant to handle network
 //! module state and initialisation, at runtime, either via a
 //! configuration language parser, or via the pure code API.
+//!
+//! ## Configuration format
+//!
+//! A configuration can be written in either json ([`parse_json`]) or
+//! toml ([`parse_toml`]), with [`load`] picking the format based on
+//! the file extension.  Both share the same structure:
+//!
+//! - `endpoints`: a list of endpoints, each with a unique numeric
+//!   `id`, a `type`, a set of `params` for that type, and optional
+//!   settings that apply to all endpoint types.
+//! - `patches` (optional): a map from endpoint id to either another
+//!   endpoint id (an internal patch), or `"external"` (the default).
+//!
+//! The following endpoint types are available:
+//!
+//! | `type`        | `params`                                              |
+//! |---------------|-------------------------------------------------------|
+//! | `virtual`     | none                                                  |
+//! | `tcp`         | `addr`, `port`, `peers` (`[]`), `dynamic` (`false`)   |
+//! | `local-udp`   | `addr` (interface address), `port` (`9000`)           |
+//! | `ethernet`    | `iface` (interface name)                              |
+//! | `websocket`   | `listen` (`addr:port`), `peers` (urls, `[]`),         |
+//! |               | `proxy` (`host:port`)                                 |
+//! | `unix`        | `listen`, `peers` (`[]`), `stdio` (`false`)           |
+//! | `serial`      | `path` (tty), `baud` (`9600`), `port` (`0`),          |
+//! |               | `compact` (`false`)                                   |
+//! | `replay`      | `path` (capture file), `instant` (`false`)            |
+//! | `wifi-direct` | none (only with the `android` feature)                |
+//!
+//! Options that apply to every endpoint type:
+//!
+//! - `mtu`: the maximum size of a frame sent via this endpoint, in
+//!   bytes.  Larger frames are dropped.  The router doesn't re-slice
+//!   messages for an endpoint, so the mtu can't be smaller than the
+//!   frames it sends itself (a little under 1500 bytes, the exact
+//!   minimum is reported when parsing a configuration with a smaller
+//!   one).
+//! - `key`: a pre-shared key, as 64 hex characters.  All frames sent
+//!   via this endpoint are encrypted with it, and frames from peers
+//!   that don't use the same key are dropped.
+//! - `capture`: a file to record all frames crossing this endpoint
+//!   to (see `netmod-pcap`).  A `replay` endpoint plays such a file
+//!   back.
+//!
+//! Internal patches connect two endpoints in the same router, which
+//! is mostly useful for testing.  Virtual endpoints are connected
+//! via a `netmod-mem` channel, and must always be patched to exactly
+//! one other virtual endpoint.  Tcp endpoints are introduced to each
+//! other via localhost.  Patches only need to be specified in one
+//! direction, but they can't contradict each other.
+//!
+//! ```toml
+//! [[endpoints]]
+//! id = 0
+//! type = "tcp"
+//! mtu = 1500
+//! params = { addr = "0.0.0.0", port = 9000, peers = ["10.0.0.1:9000"], dynamic = true }
+//!
+//! [[endpoints]]
+//! id = 1
+//! type = "local-udp"
+//! params = { addr = "0.0.0.0" }
+//!
+//! [patches]
+//! 0 = "external"
+//! ```
+//!
+//! Syntax and type errors are reported with the line and column they
+//! occured on, while invalid networks (for example a patch to an
+//! endpoint that doesn't exist) are reported with the offending
+//! endpoint id.
+//!
+//! [`parse_json`]: fn.parse_json.html
+//! [`parse_toml`]: fn.parse_toml.html
+//! [`load`]: fn.load.html
 
 mod parser;
-pub use parser::parse_json;
+pub use parser::{load, parse_json, parse_toml};
+
+mod error;
+pub use error::{Error, Result};
+
+mod key;
+pub use key::Key;
+
+mod mtu;
 
 pub mod config;
 
-use config::{Endpoint, Id, Network, Params};
-use std::{collections::BTreeMap, net::SocketAddr};
+use config::{Endpoint, Id, Network, Params, Patch};
+use std::{collections::BTreeMap, net::SocketAddr, path::PathBuf};
 
 /// A rust API builder equivalent of the json parser
 ///
@@ -22,6 +105,7 @@ use std::{collections::BTreeMap, net::SocketAddr};
 pub struct NetBuilder {
     id_ctr: Id,
     endpoints: BTreeMap<Id, Endpoint>,
+    patches: BTreeMap<Id, Patch>,
 }
 
 impl NetBuilder {
@@ -29,53 +113,161 @@ impl NetBuilder {
         Self {
             id_ctr: 0,
             endpoints: BTreeMap::new(),
+            patches: BTreeMap::new(),
         }
     }
 
+    /// Add an endpoint, which is assigned the next free ID
     pub fn endpoint(mut self, epb: EpBuilder) -> Self {
         let (id, ep) = epb.build(&mut self.id_ctr);
         self.endpoints.insert(id, ep);
         self
     }
 
+    /// Patch two endpoints together internally
+    pub fn patch(mut self, a: Id, b: Id) -> Self {
+        self.patches.insert(a, Patch::Internal(b));
+        self.patches.insert(b, Patch::Internal(a));
+        self
+    }
+
     pub fn build(self) -> Network {
         Network {
             endpoints: self.endpoints,
-            patches: Default::default(),
+            patches: self.patches,
         }
     }
 }
 
 pub struct EpBuilder {
     p: Params,
+    mtu: Option<usize>,
+    capture: Option<PathBuf>,
+    key: Option<Key>,
 }
 
 impl EpBuilder {
+    fn new(p: Params) -> Self {
+        Self {
+            p,
+            mtu: None,
+            capture: None,
+            key: None,
+        }
+    }
+
     pub fn virt() -> Self {
-        Self { p: Params::Virtual }
+        Self::new(Params::Virtual)
     }
 
     pub fn tcp(addr: String, port: u16, dynamic: bool) -> Self {
+        Self::new(Params::Tcp {
+            addr,
+            port,
+            peers: vec![],
+            dynamic,
+        })
+    }
+
+    pub fn local_udp(addr: String) -> Self {
+        Self::new(Params::LocalUdp { addr, port: 9000 })
+    }
+
+    pub fn ethernet(iface: String) -> Self {
+        Self::new(Params::Ethernet { iface })
+    }
+
+    pub fn websocket(listen: Option<SocketAddr>, peers: Vec<String>) -> Self {
+        Self::new(Params::Websocket {
+            listen,
+            peers,
+            proxy: None,
+        })
+    }
+
+    pub fn unix(listen: Option<PathBuf>, peers: Vec<PathBuf>) -> Self {
+        Self::new(Params::Unix {
+            listen,
+            peers,
+            stdio: false,
+        })
+    }
+
+    pub fn serial<P: Into<PathBuf>>(path: P, baud: u32) -> Self {
+        Self::new(Params::Serial {
+            path: path.into(),
+            baud,
+            port: 0,
+            compact: false,
+        })
+    }
+
+    pub fn replay<P: Into<PathBuf>>(path: P, instant: bool) -> Self {
+        Self::new(Params::Replay {
+            path: path.into(),
+            instant,
+        })
+    }
+
+    #[cfg(feature = "android")]
+    pub fn wifi_direct() -> Self {
+        Self::new(Params::WifiDirect)
+    }
+
+    /// Set the initial peers of a tcp endpoint
+    ///
+    /// This has no effect on other endpoint types.
+    pub fn peers(mut self, new: Vec<String>) -> Self {
+        if let Params::Tcp { ref mut peers, .. } = self.p {
+            *peers = new;
+        }
+        self
+    }
+
+    /// Connect to the peers of a websocket endpoint via an http proxy
+    ///
+    /// This has no effect on other endpoint types.
+    pub fn proxy(mut self, addr: String) -> Self {
+        if let Params::Websocket { ref mut proxy, .. } = self.p {
+            *proxy = Some(addr);
+        }
+        self
+    }
+
+    /// Use the compact frame encoding on a serial endpoint
+    ///
+    /// This has no effect on other endpoint types.
+    pub fn compact(mut self) -> Self {
+        if let Params::Serial {
+            ref mut compact, ..
+        } = self.p
+        {
+            *compact = true;
+        }
+        self
+    }
+
+    /// Limit the size of frames sent via this endpoint
+    pub fn mtu(self, mtu: usize) -> Self {
         Self {
-            p: Params::Tcp {
-                addr,
-                port,
-                peers: vec![],
-                dynamic,
-            },
+            mtu: Some(mtu),
+            ..self
         }
     }
 
-    pub fn local_udp(addr: String) -> Self {
+    /// Record all frames crossing this endpoint to a file
+    pub fn capture<P: Into<PathBuf>>(self, path: P) -> Self {
         Self {
-            p: Params::LocalUpd { addr },
+            capture: Some(path.into()),
+            ..self
         }
     }
 
-    #[cfg(feature = "android")]
-    pub fn wifi_direct() -> Self {
+    /// Encrypt all frames sent via this endpoint with a pre-shared key
+    pub fn key(self, key: Key) -> Self {
         Self {
-            p: Params::WifiDirect,
+            key: Some(key),
+            ..self
         }
     }
 
@@ -87,6 +279,9 @@ impl EpBuilder {
             Endpoint {
                 id: this,
                 params: self.p,
+                mtu: self.mtu,
+                capture: self.capture,
+                key: self.key,
             },
         )
     }
@@ -0,0 +1,65 @@
+//! An endpoint wrapper that enforces a maximum frame size
+
+use async_std::sync::Arc;
+use async_trait::async_trait;
+use ratman::{
+    netmod::{Endpoint, Error, Frame, Recipient, Result, SeqBuilder, Target},
+    Identity, SLICE_SIZE,
+};
+
+/// The serialised size of the largest frame a router sends
+///
+/// The router doesn't re-slice messages for an endpoint, so an MTU
+/// smaller than this would reject some of its own frames.
+pub(crate) fn min_mtu() -> usize {
+    let id = Identity::random();
+    let frame = SeqBuilder::new(id, Recipient::User(id), id)
+        .add(vec![0; SLICE_SIZE])
+        .add(vec![])
+        .build()
+        .remove(0);
+    bincode::serialized_size(&frame).unwrap() as usize
+}
+
+/// Limit the size of frames sent via an endpoint
+///
+/// Most drivers don't have a hard limit on the frames they can send,
+/// but the link they're sending over might (for example a tunnel
+/// that fragments large packets).  Frames that don't fit are
+/// rejected with `FrameTooLarge`.  Because the router slices all
+/// messages to the same size, this mostly applies to frames that are
+/// forwarded from routers that slice them larger.
+pub(crate) struct Mtu<E: Endpoint> {
+    inner: Arc<E>,
+    mtu: usize,
+}
+
+impl<E: Endpoint> Mtu<E> {
+    pub(crate) fn new(inner: Arc<E>, mtu: usize) -> Arc<Self> {
+        Arc::new(Self { inner, mtu })
+    }
+}
+
+#[async_trait]
+impl<E> Endpoint for Mtu<E>
+where
+    E: Endpoint + Send + Sync,
+{
+    fn size_hint(&self) -> usize {
+        match self.inner.size_hint() {
+            0 => self.mtu,
+            hint => hint.min(self.mtu),
+        }
+    }
+
+    async fn send(&self, frame: Frame, target: Target) -> Result<()> {
+        match bincode::serialized_size(&frame) {
+            Ok(size) if size as usize <= self.mtu => self.inner.send(frame, target).await,
+            _ => Err(Error::FrameTooLarge),
+        }
+    }
+
+    async fn next(&self) -> Result<(Frame, Target)> {
+        self.inner.next().await
+    }
+}
@@ -0,0 +1,295 @@
+//! Configuration parsing and validation tests
+
+use ratman::Identity;
+use ratman_configure::{
+    config::{Params, Patch},
+    parse_json, parse_toml, EpBuilder, Error, Key, NetBuilder,
+};
+
+#[test]
+fn json_and_toml_agree() {
+    let json = r#"{
+      "endpoints": [
+        { "id": 0, "type": "tcp", "mtu": 1500,
+          "params": { "addr": "0.0.0.0", "port": 9000, "peers": ["127.0.0.1:8080"] } },
+        { "id": 1, "type": "local-udp", "params": { "addr": "127.0.0.1", "port": 9100 } },
+        { "id": 2, "type": "virtual" },
+        { "id": 3, "type": "virtual" }
+      ],
+      "patches": { "0": "external", "2": 3 }
+    }"#;
+
+    let toml = r#"
+        [[endpoints]]
+        id = 0
+        type = "tcp"
+        mtu = 1500
+        params = { addr = "0.0.0.0", port = 9000, peers = ["127.0.0.1:8080"] }
+
+        [[endpoints]]
+        id = 1
+        type = "local-udp"
+        params = { addr = "127.0.0.1", port = 9100 }
+
+        [[endpoints]]
+        id = 2
+        type = "virtual"
+
+        [[endpoints]]
+        id = 3
+        type = "virtual"
+
+        [patches]
+        0 = "external"
+        2 = 3
+    "#;
+
+    let (j, t) = (parse_json(json).unwrap(), parse_toml(toml).unwrap());
+    assert_eq!(j.endpoints, t.endpoints);
+    assert_eq!(j.patches, t.patches);
+
+    assert_eq!(j[&0].mtu, Some(1500));
+    assert_eq!(
+        j[&1].params,
+        Params::LocalUdp {
+            addr: "127.0.0.1".into(),
+            port: 9100
+        }
+    );
+    assert_eq!(j.patches[&2], Patch::Internal(3));
+}
+
+#[test]
+fn serial_params() {
+    let net = parse_toml(
+        r#"
+        [[endpoints]]
+        id = 0
+        type = "serial"
+        params = { path = "/dev/ttyUSB0", compact = true }
+    "#,
+    )
+    .unwrap();
+
+    let built = NetBuilder::new()
+        .endpoint(EpBuilder::serial("/dev/ttyUSB0", 9600).compact())
+        .build();
+    assert_eq!(net.endpoints, built.endpoints);
+}
+
+#[test]
+fn parse_errors_have_line_info() {
+    let json = "{\n  \"endpoints\": [\n    { \"id\": 0, \"type\": \"carrier-pigeon\" }\n  ]\n}";
+    match parse_json(json) {
+        Err(Error::Parse {
+            pos: Some((3, _)), ..
+        }) => {}
+        e => panic!("unexpected result: {:?}", e),
+    }
+
+    // Errors in endpoint params point to the endpoint table
+    let toml = "[[endpoints]]\nid = 0\ntype = \"virtual\"\n\n[[endpoints]]\nid = 1\ntype = \"tcp\"\nparams = { addr = \"0.0.0.0\", port = \"nope\" }\n";
+    match parse_toml(toml) {
+        Err(Error::Parse {
+            pos: Some((5, _)), ..
+        }) => {}
+        e => panic!("unexpected result: {:?}", e),
+    }
+}
+
+fn invalid_id(e: &Error) -> Option<usize> {
+    match e {
+        Error::Invalid { id, .. } => *id,
+        e => panic!("unexpected error: {:?}", e),
+    }
+}
+
+#[test]
+fn invalid_networks() {
+    fn err(json: &str) -> Error {
+        parse_json(json).unwrap_err()
+    }
+
+    // Unpatched virtual endpoint
+    let e = err(r#"{ "endpoints": [ { "id": 0, "type": "virtual" } ] }"#);
+    assert_eq!(invalid_id(&e), Some(0));
+
+    // Patch to a missing endpoint
+    let e = err(r#"{ "endpoints": [ { "id": 0, "type": "virtual" } ], "patches": { "0": 1 } }"#);
+    assert_eq!(invalid_id(&e), Some(0));
+
+    // Patch between incompatible types
+    let e = err(r#"{ "endpoints": [
+              { "id": 0, "type": "virtual" },
+              { "id": 1, "type": "local-udp", "params": { "addr": "0.0.0.0" } } ],
+            "patches": { "0": 1 } }"#);
+    assert_eq!(invalid_id(&e), Some(0));
+
+    // Duplicate ids
+    let e = err(
+        r#"{ "endpoints": [ { "id": 0, "type": "virtual" }, { "id": 0, "type": "virtual" } ] }"#,
+    );
+    assert_eq!(invalid_id(&e), Some(0));
+
+    // Contradicting patches
+    let e = err(r#"{ "endpoints": [
+              { "id": 0, "type": "virtual" },
+              { "id": 1, "type": "virtual" },
+              { "id": 2, "type": "virtual" } ],
+            "patches": { "0": 1, "1": 2 } }"#);
+    assert!(invalid_id(&e).is_some());
+
+    // Invalid tcp peer
+    let e = err(r#"{ "endpoints": [
+              { "id": 0, "type": "tcp", "params": { "addr": "0.0.0.0", "port": 9000, "peers": ["nope"] } } ] }"#);
+    assert_eq!(invalid_id(&e), Some(0));
+
+    // Websocket peers are urls
+    let e = err(r#"{ "endpoints": [
+              { "id": 0, "type": "websocket", "params": { "peers": ["127.0.0.1:9080"] } } ] }"#);
+    assert_eq!(invalid_id(&e), Some(0));
+
+    // Proxies need a port
+    let e = err(r#"{ "endpoints": [
+              { "id": 0, "type": "websocket", "params": { "proxy": "proxy.example.com" } } ] }"#);
+    assert_eq!(invalid_id(&e), Some(0));
+
+    // KISS only has 16 ports
+    let e = err(r#"{ "endpoints": [
+              { "id": 0, "type": "serial", "params": { "path": "/dev/ttyUSB0", "port": 16 } } ] }"#);
+    assert_eq!(invalid_id(&e), Some(0));
+
+    // The router doesn't re-slice frames for small mtus
+    let e = err(r#"{ "endpoints": [
+              { "id": 0, "type": "local-udp", "mtu": 1200, "params": { "addr": "0.0.0.0" } } ] }"#);
+    assert_eq!(invalid_id(&e), Some(0));
+
+    // Keys make frames larger, so the mtu has to leave room for them
+    let e = err(&format!(
+        r#"{{ "endpoints": [
+              {{ "id": 0, "type": "local-udp", "mtu": 1400, "key": {:?},
+                 "params": {{ "addr": "0.0.0.0" }} }} ] }}"#,
+        KEY
+    ));
+    assert_eq!(invalid_id(&e), Some(0));
+}
+
+const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+#[test]
+fn endpoint_keys() {
+    let net = parse_toml(&format!(
+        "[[endpoints]]\nid = 0\ntype = \"local-udp\"\nkey = {:?}\nparams = {{ addr = \"0.0.0.0\" }}\n",
+        KEY
+    ))
+    .unwrap();
+    assert_eq!(net[&0].key, Key::from_hex(KEY));
+
+    // Keys need to be exactly 32 bytes
+    let e = parse_json(&format!(
+        r#"{{ "endpoints": [ {{ "id": 0, "type": "virtual", "key": {:?} }} ] }}"#,
+        &KEY[2..]
+    ))
+    .unwrap_err();
+    match e {
+        Error::Parse { .. } => {}
+        e => panic!("unexpected error: {:?}", e),
+    }
+}
+
+#[async_std::test]
+async fn virtual_patch() {
+    let net = NetBuilder::new()
+        .endpoint(EpBuilder::virt())
+        .endpoint(EpBuilder::virt().mtu(4096))
+        .patch(0, 1)
+        .build();
+
+    let router = net.into_router().await.unwrap();
+    let u = Identity::random();
+    router.add_user(u).await.unwrap();
+    router.online(u).await.unwrap();
+
+    // Virtual endpoints can't be left dangling
+    let net = NetBuilder::new().endpoint(EpBuilder::virt()).build();
+    assert!(net.into_router().await.is_err());
+}
+
+#[async_std::test]
+async fn capture_and_replay() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("0.rcap");
+
+    let toml = format!(
+        "[[endpoints]]\nid = 0\ntype = \"virtual\"\ncapture = {:?}\n\n\
+         [[endpoints]]\nid = 1\ntype = \"virtual\"\n\n[patches]\n0 = 1\n",
+        path
+    );
+    let net = parse_toml(&toml).unwrap();
+    assert_eq!(net[&0].capture.as_ref(), Some(&path));
+    net.into_router().await.unwrap();
+    assert!(path.exists());
+
+    let net = NetBuilder::new()
+        .endpoint(EpBuilder::replay(&path, true))
+        .build();
+    net.into_router().await.unwrap();
+
+    // Replaying a capture that doesn't exist fails on startup
+    let net = NetBuilder::new()
+        .endpoint(EpBuilder::replay(dir.path().join("nope.rcap"), true))
+        .build();
+    match net.into_router().await {
+        Err(Error::Init { id: 0, .. }) => {}
+        e => panic!("unexpected result: {:?}", e.map(|_| ())),
+    }
+}
+
+#[async_std::test]
+async fn unix_sockets() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.sock");
+
+    let a = NetBuilder::new()
+        .endpoint(EpBuilder::unix(Some(path.clone()), vec![]))
+        .build();
+    let r1 = a.into_router().await.unwrap();
+
+    let b = parse_json(&format!(
+        r#"{{ "endpoints": [ {{ "id": 0, "type": "unix", "params": {{ "peers": [{:?}] }} }} ] }}"#,
+        path
+    ))
+    .unwrap();
+    let r2 = b.into_router().await.unwrap();
+
+    let u = Identity::random();
+    r2.add_user(u).await.unwrap();
+    r2.online(u).await.unwrap();
+    assert_eq!(r1.discover().await, u);
+
+    // Routers that share a key can talk to each other
+    let path = dir.path().join("b.sock");
+    let key = Key::from_hex(KEY).unwrap();
+    let a = NetBuilder::new()
+        .endpoint(EpBuilder::unix(Some(path.clone()), vec![]).key(key.clone()))
+        .build();
+    let r1 = a.into_router().await.unwrap();
+    let b = NetBuilder::new()
+        .endpoint(EpBuilder::unix(None, vec![path]).key(key))
+        .build();
+    let r2 = b.into_router().await.unwrap();
+
+    let u = Identity::random();
+    r2.add_user(u).await.unwrap();
+    r2.online(u).await.unwrap();
+    assert_eq!(r1.discover().await, u);
+
+    // Only one endpoint can own stdin and stdout
+    let e = parse_json(
+        r#"{ "endpoints": [
+              { "id": 0, "type": "unix", "params": { "stdio": true } },
+              { "id": 1, "type": "unix", "params": { "stdio": true } } ] }"#,
+    )
+    .unwrap_err();
+    assert_eq!(invalid_id(&e), Some(1));
+}
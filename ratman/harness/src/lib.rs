@@ -8,12 +8,19 @@
 //! inputs and outputs, and then initialise your application state
 //! accordingly.  In case you are writing tests for libqaul, use the
 //! iter_mut() to then initialise and store your endpoint state.
+//!
+//! For small tests, `ThreePoint` is usually enough.  Larger networks
+//! (lines, rings, stars, grids and random graphs) can be generated
+//! with a [`NetworkBuilder`](struct.NetworkBuilder.html).
 
 use netmod_mem::MemMod;
 use ratman::Router;
 use std::{sync::Arc, time::Duration};
 use tempfile::{tempdir, TempDir};
 
+mod network;
+pub use network::{Link, Network, NetworkBuilder, Node, Topology};
+
 pub fn temp() -> TempDir {
     tempdir().unwrap()
 }
@@ -0,0 +1,407 @@
+//! Larger generated network topologies
+//!
+//! Some routing issues only show up once a network has more than a
+//! handful of nodes.  This module can generate a variety of common
+//! topologies, made of `Router`s connected via `MemMod` pairs.  Each
+//! node in the network has a single test user that can be marked
+//! online to start announcements, and there are helpers to wait for
+//! the routing tables to converge, and to cut and restore links.
+//!
+//! A network built with a clock from a `Simulation` runs
+//! deterministically: the same seed produces the same topology, the
+//! same lost frames, and the same order of events on every run.
+
+use crate::Initialize;
+use netmod_mem::{LinkConfig, MemMod};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use ratman::{clock::Clock, Identity, Result, Router};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+/// The shape of a generated network
+#[derive(Debug, Clone, PartialEq)]
+pub enum Topology {
+    /// `n` nodes, each connected to the next one
+    Line(usize),
+    /// A line, where the last node is connected to the first
+    Ring(usize),
+    /// One central node (`0`), connected to `n - 1` others
+    Star(usize),
+    /// A `width` x `height` grid, connecting horizontal and vertical
+    /// neighbours
+    Grid(usize, usize),
+    /// An Erdős–Rényi graph: `n` nodes, where every pair is
+    /// connected with probability `p`
+    Random { n: usize, p: f64 },
+    /// A random geometric graph: `n` nodes are placed in a unit
+    /// square, and connected if they are closer than `radius`
+    Geometric { n: usize, radius: f64 },
+}
+
+impl Topology {
+    /// Return the number of nodes in this topology
+    pub fn size(&self) -> usize {
+        match *self {
+            Self::Line(n) | Self::Ring(n) | Self::Star(n) => n,
+            Self::Grid(w, h) => w * h,
+            Self::Random { n, .. } | Self::Geometric { n, .. } => n,
+        }
+    }
+
+    /// Generate the set of edges between node indices
+    ///
+    /// Random topologies are always connected: if the generated graph
+    /// has more than one component, the components are joined by an
+    /// additional edge each.
+    fn edges(&self, rng: &mut StdRng) -> Vec<(usize, usize)> {
+        let edges = match *self {
+            Self::Line(n) => (1..n).map(|i| (i - 1, i)).collect(),
+            Self::Ring(n) if n > 2 => (0..n).map(|i| (i, (i + 1) % n)).collect(),
+            Self::Ring(n) => Self::Line(n).edges(rng),
+            Self::Star(n) => (1..n).map(|i| (0, i)).collect(),
+            Self::Grid(w, h) => {
+                let mut edges = vec![];
+                for y in 0..h {
+                    for x in 0..w {
+                        let i = y * w + x;
+                        if x + 1 < w {
+                            edges.push((i, i + 1));
+                        }
+                        if y + 1 < h {
+                            edges.push((i, i + w));
+                        }
+                    }
+                }
+                edges
+            }
+            Self::Random { n, p } => {
+                let mut edges = vec![];
+                for a in 0..n {
+                    for b in (a + 1)..n {
+                        if rng.gen_bool(p) {
+                            edges.push((a, b));
+                        }
+                    }
+                }
+                edges
+            }
+            Self::Geometric { n, radius } => {
+                let pos: Vec<(f64, f64)> = (0..n).map(|_| (rng.gen(), rng.gen())).collect();
+                let mut edges = vec![];
+                for a in 0..n {
+                    for b in (a + 1)..n {
+                        let (dx, dy) = (pos[a].0 - pos[b].0, pos[a].1 - pos[b].1);
+                        if (dx * dx + dy * dy).sqrt() < radius {
+                            edges.push((a, b));
+                        }
+                    }
+                }
+                edges
+            }
+        };
+
+        connect_components(self.size(), edges)
+    }
+}
+
+/// Join all components of a graph into one
+fn connect_components(n: usize, mut edges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    // A very small union-find, good enough for test-sized networks
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    let mut parents: Vec<usize> = (0..n).collect();
+    for (a, b) in edges.iter() {
+        let (ra, rb) = (root(&mut parents, *a), root(&mut parents, *b));
+        parents[ra] = rb;
+    }
+
+    let mut roots = BTreeSet::new();
+    let mut reps = vec![];
+    for i in 0..n {
+        if roots.insert(root(&mut parents, i)) {
+            reps.push(i);
+        }
+    }
+
+    reps.windows(2).for_each(|w| edges.push((w[0], w[1])));
+    edges
+}
+
+/// A single router in a generated network
+pub struct Node<T> {
+    /// The node name, which is passed to `init_with`
+    pub name: String,
+    /// The router of this node
+    pub router: Arc<Router>,
+    /// A test user, which is registered on the router
+    pub user: Identity,
+    /// Application state attached via `Initialize`
+    pub state: Option<T>,
+}
+
+impl<T> Node<T> {
+    /// Get easy access to the application state
+    ///
+    /// Panics if not initialised
+    pub fn state(&self) -> &T {
+        self.state.as_ref().unwrap()
+    }
+}
+
+/// A connection between two nodes, via a pair of `MemMod`s
+pub struct Link {
+    /// Index of the first node
+    pub a: usize,
+    /// Index of the second node
+    pub b: usize,
+    /// The endpoint attached to node `a`
+    pub mm_a: Arc<MemMod>,
+    /// The endpoint attached to node `b`
+    pub mm_b: Arc<MemMod>,
+}
+
+/// Configure and build a `Network`
+pub struct NetworkBuilder {
+    topo: Topology,
+    link: LinkConfig,
+    seed: u64,
+    clock: Clock,
+}
+
+impl NetworkBuilder {
+    /// Start building a network with a particular topology
+    pub fn new(topo: Topology) -> Self {
+        Self {
+            topo,
+            link: LinkConfig::default(),
+            seed: 0,
+            clock: Clock::system(),
+        }
+    }
+
+    /// Set the link properties used for every link in the network
+    ///
+    /// Each link gets its own random seed, derived from the network
+    /// seed, so that they don't all lose the same frames.  A seed set
+    /// in the provided config is ignored.
+    pub fn link_config(self, link: LinkConfig) -> Self {
+        Self { link, ..self }
+    }
+
+    /// Set the seed used to generate random topologies and users
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Set the clock used by all routers and links
+    ///
+    /// Pass a clock from a `Simulation` to run the whole network
+    /// deterministically, in virtual time.  The network then has to
+    /// be built and driven inside `Simulation::block_on`.
+    pub fn clock(self, clock: Clock) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Create all routers and links
+    pub async fn build<T>(self) -> Network<T> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let edges = self.topo.edges(&mut rng);
+
+        let mut nodes = Vec::with_capacity(self.topo.size());
+        for i in 0..self.topo.size() {
+            let router = Router::with_clock(self.clock.clone());
+            let user = Identity::with_digest(&format!("{}-{}", self.seed, i).into_bytes());
+            router.add_user(user).await.unwrap();
+            nodes.push(Node {
+                name: format!("{}", i),
+                router,
+                user,
+                state: None,
+            });
+        }
+
+        let mut links = Vec::with_capacity(edges.len());
+        for (a, b) in edges {
+            let cfg = self.link.clone().seed(rng.gen());
+            let (mm_a, mm_b) = MemMod::make_pair_with_clock(cfg, self.clock.clone());
+            nodes[a].router.add_endpoint(Arc::clone(&mm_a)).await;
+            nodes[b].router.add_endpoint(Arc::clone(&mm_b)).await;
+            links.push(Link { a, b, mm_a, mm_b });
+        }
+
+        Network {
+            topo: self.topo,
+            clock: self.clock,
+            nodes,
+            links,
+        }
+    }
+}
+
+/// A generated network of routers
+///
+/// ```
+/// # use async_std::task;
+/// # use ratman_harness::{sec10, Network, Topology};
+/// # task::block_on(async {
+/// let net: Network<()> = Network::new(Topology::Ring(8)).await;
+/// net.online().await.unwrap();
+/// assert!(net.converge(sec10()).await);
+/// # });
+/// ```
+pub struct Network<T> {
+    topo: Topology,
+    clock: Clock,
+    nodes: Vec<Node<T>>,
+    links: Vec<Link>,
+}
+
+impl<T> Network<T> {
+    /// Create a network with perfect links and the default seed
+    pub async fn new(topo: Topology) -> Self {
+        NetworkBuilder::new(topo).build().await
+    }
+
+    /// Get the topology this network was built from
+    pub fn topology(&self) -> &Topology {
+        &self.topo
+    }
+
+    /// Get the clock shared by all routers in this network
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Return the number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Return `true` if the network has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Get a node by index
+    ///
+    /// Panics if the index is out of bounds
+    pub fn node(&self, i: usize) -> &Node<T> {
+        &self.nodes[i]
+    }
+
+    /// Get all nodes
+    pub fn nodes(&self) -> &Vec<Node<T>> {
+        &self.nodes
+    }
+
+    /// Get all links
+    pub fn links(&self) -> &Vec<Link> {
+        &self.links
+    }
+
+    /// Get the test users of all nodes
+    pub fn users(&self) -> Vec<Identity> {
+        self.nodes.iter().map(|n| n.user).collect()
+    }
+
+    /// Mark the test user of every node as online
+    pub async fn online(&self) -> Result<()> {
+        for n in self.nodes.iter() {
+            n.router.online(n.user).await?;
+        }
+        Ok(())
+    }
+
+    /// Check if every router currently knows every remote test user
+    pub async fn converged(&self) -> bool {
+        for n in self.nodes.iter() {
+            for u in self.nodes.iter() {
+                if u.user != n.user && n.router.known(u.user).await.is_err() {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Wait until every router knows every test user
+    ///
+    /// Returns `false` if the network didn't converge before the
+    /// timeout was reached.  The timeout is measured on the network
+    /// clock, meaning that it is virtual time in a simulation.
+    pub async fn converge(&self, timeout: Duration) -> bool {
+        let deadline = self.clock.elapsed() + timeout;
+        while !self.converged().await {
+            if self.clock.elapsed() >= deadline {
+                return false;
+            }
+            self.clock.sleep(Duration::from_millis(50)).await;
+        }
+        true
+    }
+
+    /// Cut all links between two nodes
+    pub async fn partition(&self, a: usize, b: usize) {
+        for l in self.between(a, b) {
+            l.mm_a.partition().await;
+        }
+    }
+
+    /// Restore all links between two nodes
+    pub async fn heal(&self, a: usize, b: usize) {
+        for l in self.between(a, b) {
+            l.mm_a.heal().await;
+        }
+    }
+
+    /// Cut all links between a set of nodes and the rest of the network
+    pub async fn split(&self, set: &[usize]) {
+        for l in self.crossing(set) {
+            l.mm_a.partition().await;
+        }
+    }
+
+    /// Cut all links of a single node
+    pub async fn isolate(&self, i: usize) {
+        self.split(&[i]).await;
+    }
+
+    /// Restore every link in the network
+    pub async fn heal_all(&self) {
+        for l in self.links.iter() {
+            l.mm_a.heal().await;
+        }
+    }
+
+    fn between(&self, a: usize, b: usize) -> impl Iterator<Item = &Link> {
+        self.links
+            .iter()
+            .filter(move |l| (l.a == a && l.b == b) || (l.a == b && l.b == a))
+    }
+
+    fn crossing<'s>(&'s self, set: &'s [usize]) -> impl Iterator<Item = &'s Link> {
+        self.links
+            .iter()
+            .filter(move |l| set.contains(&l.a) != set.contains(&l.b))
+    }
+}
+
+impl<T> Initialize<T> for Network<T> {
+    fn init_with<'a, F: Fn(&'a str, Arc<Router>) -> T>(&'a mut self, cb: F) {
+        self.nodes.iter_mut().for_each(|n| {
+            let Node {
+                name,
+                router,
+                state,
+                ..
+            } = n;
+            let name: &'a String = name;
+            *state = Some(cb(name.as_str(), Arc::clone(router)));
+        });
+    }
+}
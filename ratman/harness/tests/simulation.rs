@@ -0,0 +1,106 @@
+//! Tests for running generated networks in a deterministic simulation
+
+use netmod_mem::LinkConfig;
+use ratman::{clock::Simulation, Identity, Message, Recipient, TimePair};
+use ratman_harness::{Network, NetworkBuilder, Topology};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Run a lossy grid, and record when node 0 discovers each user
+fn run(seed: u64) -> (Vec<(Duration, Identity)>, Duration) {
+    let sim = Simulation::new(seed);
+    let clock = sim.clock();
+    let log = Arc::new(Mutex::new(vec![]));
+
+    let cfg = LinkConfig::default()
+        .latency(Duration::from_millis(20))
+        .jitter(Duration::from_millis(30))
+        .drop_rate(0.2);
+
+    sim.block_on(async {
+        let net: Network<()> = NetworkBuilder::new(Topology::Grid(4, 4))
+            .link_config(cfg)
+            .seed(seed)
+            .clock(clock.clone())
+            .build()
+            .await;
+
+        let router = Arc::clone(&net.node(0).router);
+        let (log, c) = (Arc::clone(&log), clock.clone());
+        clock.spawn(async move {
+            loop {
+                let id = router.discover().await;
+                log.lock().unwrap().push((c.elapsed(), id));
+            }
+        });
+
+        net.online().await.unwrap();
+        assert!(net.converge(Duration::from_secs(60)).await);
+    });
+
+    let log = log.lock().unwrap().clone();
+    (log, sim.elapsed())
+}
+
+#[test]
+fn same_seed_same_run() {
+    let (log, time) = run(1312);
+    assert_eq!(log.len(), 15);
+    assert_eq!((log, time), run(1312));
+}
+
+#[test]
+fn simulated_hour() {
+    let sim = Simulation::new(0);
+    let clock = sim.clock();
+    let start = Instant::now();
+
+    sim.block_on(async {
+        let net: Network<()> = NetworkBuilder::new(Topology::Line(4))
+            .clock(clock.clone())
+            .build()
+            .await;
+        net.online().await.unwrap();
+        assert!(net.converge(Duration::from_secs(10)).await);
+        clock.sleep(Duration::from_secs(3600)).await;
+        assert!(net.converged().await);
+    });
+
+    assert!(sim.elapsed() >= Duration::from_secs(3600));
+    assert!(start.elapsed() < Duration::from_secs(60));
+}
+
+/// Send a message across a line after some time, and return its timestamps
+fn timestamps(seed: u64) -> TimePair {
+    let sim = Simulation::new(seed);
+    let clock = sim.clock();
+
+    sim.block_on(async {
+        let net: Network<()> = NetworkBuilder::new(Topology::Line(3))
+            .link_config(LinkConfig::default().latency(Duration::from_millis(20)))
+            .clock(clock.clone())
+            .build()
+            .await;
+        net.online().await.unwrap();
+        assert!(net.converge(Duration::from_secs(10)).await);
+        clock.sleep(Duration::from_secs(60)).await;
+
+        let msg = Message {
+            id: Identity::random(),
+            sender: net.node(0).user,
+            recipient: Recipient::User(net.node(2).user),
+            payload: vec![1, 3, 1, 2],
+            timesig: TimePair::sending(),
+            sign: vec![],
+        };
+        net.node(0).router.send(msg).await.unwrap();
+        net.node(2).router.next().await.timesig
+    })
+}
+
+#[test]
+fn timestamps_follow_the_clock() {
+    assert_eq!(timestamps(7), timestamps(7));
+}
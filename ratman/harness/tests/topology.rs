@@ -0,0 +1,90 @@
+//! Tests for the generated network topologies
+
+use ratman_harness::{sec10, Initialize, Network, NetworkBuilder, Topology};
+use std::time::Duration;
+
+fn degree<T>(net: &Network<T>, i: usize) -> usize {
+    net.links().iter().filter(|l| l.a == i || l.b == i).count()
+}
+
+#[async_std::test]
+async fn shapes() {
+    let line: Network<()> = Network::new(Topology::Line(5)).await;
+    assert_eq!(line.len(), 5);
+    assert_eq!(line.links().len(), 4);
+
+    let ring: Network<()> = Network::new(Topology::Ring(5)).await;
+    assert_eq!(ring.links().len(), 5);
+    assert!((0..5).all(|i| degree(&ring, i) == 2));
+
+    let star: Network<()> = Network::new(Topology::Star(5)).await;
+    assert_eq!(degree(&star, 0), 4);
+    assert!((1..5).all(|i| degree(&star, i) == 1));
+
+    let grid: Network<()> = Network::new(Topology::Grid(3, 4)).await;
+    assert_eq!(grid.len(), 12);
+    assert_eq!(grid.links().len(), 2 * 4 + 3 * 3);
+}
+
+#[async_std::test]
+async fn random_is_seeded_and_connected() {
+    async fn edges(seed: u64) -> Vec<(usize, usize)> {
+        let net: Network<()> = NetworkBuilder::new(Topology::Random { n: 16, p: 0.05 })
+            .seed(seed)
+            .build()
+            .await;
+        net.links().iter().map(|l| (l.a, l.b)).collect()
+    }
+
+    let a = edges(1312).await;
+    assert_eq!(a, edges(1312).await);
+
+    // A connected graph of n nodes has at least n - 1 edges
+    assert!(a.len() >= 15);
+
+    let geo: Network<()> = NetworkBuilder::new(Topology::Geometric { n: 16, radius: 0.1 })
+        .seed(7)
+        .build()
+        .await;
+    assert!(geo.links().len() >= 15);
+}
+
+#[async_std::test]
+async fn initialize() {
+    let mut net = Network::new(Topology::Star(4)).await;
+    net.init_with(|name, _| name.to_string());
+    assert_eq!(net.node(3).state(), "3");
+}
+
+#[async_std::test]
+async fn line_converges() {
+    let net: Network<()> = Network::new(Topology::Line(20)).await;
+    net.online().await.unwrap();
+    assert!(net.converge(sec10()).await);
+}
+
+#[async_std::test]
+async fn cycles_converge() {
+    let ring: Network<()> = Network::new(Topology::Ring(12)).await;
+    ring.online().await.unwrap();
+    assert!(ring.converge(sec10()).await);
+
+    let grid: Network<()> = Network::new(Topology::Grid(5, 5)).await;
+    grid.online().await.unwrap();
+    assert!(grid.converge(sec10()).await);
+}
+
+#[async_std::test]
+async fn partition_and_heal() {
+    let net: Network<()> = Network::new(Topology::Line(4)).await;
+    net.split(&[2, 3]).await;
+    net.online().await.unwrap();
+
+    // The two halves of the network can't see each other
+    assert!(!net.converge(Duration::from_secs(3)).await);
+    assert!(net.node(0).router.known(net.node(1).user).await.is_ok());
+    assert!(net.node(0).router.known(net.node(3).user).await.is_err());
+
+    net.heal_all().await;
+    assert!(net.converge(sec10()).await);
+}
@@ -0,0 +1,122 @@
+//! Alternative string encodings for identities
+//!
+//! The hex encoding used by `Display` is unambiguous, but long, and
+//! doesn't catch typos.  The base32 encoding in this module uses the
+//! Crockford alphabet (no `I`, `L`, `O` or `U`, and decoding is case
+//! insensitive and forgiving of `I`/`L` for `1`, and `O` for `0`),
+//! and appends a two byte checksum to the identity before encoding.
+
+use crate::{ParseError, ID_LEN};
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The number of characters between dashes in the encoded form
+const GROUP: usize = 5;
+
+/// Compute the CRC-16/CCITT-FALSE of a byte sequence
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, b| {
+        (0..8).fold(crc ^ (*b as u16) << 8, |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x1021,
+        })
+    })
+}
+
+fn symbol(c: char) -> Result<u8, ParseError> {
+    let c = match c.to_ascii_uppercase() {
+        'I' | 'L' => '1',
+        'O' => '0',
+        c => c,
+    };
+    ALPHABET
+        .iter()
+        .position(|a| *a as char == c)
+        .map(|pos| pos as u8)
+        .ok_or(ParseError::InvalidCharacter(c))
+}
+
+/// Encode identity bytes, and their checksum
+pub(crate) fn encode(id: &[u8]) -> String {
+    let mut data = id.to_vec();
+    data.extend_from_slice(&crc16(id).to_be_bytes());
+
+    fn push(out: &mut String, sym: u32) {
+        if out.len() % (GROUP + 1) == GROUP {
+            out.push('-');
+        }
+        out.push(ALPHABET[sym as usize & 0x1F] as char);
+    }
+
+    // Bits that weren't encoded yet are kept at the bottom of `acc`
+    let (mut acc, mut bits) = (0u32, 0);
+    let mut out = String::new();
+    for b in data {
+        acc = (acc << 8 | b as u32) & 0xFFFF;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            push(&mut out, acc >> bits);
+        }
+    }
+    if bits > 0 {
+        push(&mut out, acc << (5 - bits));
+    }
+    out
+}
+
+/// Decode a base32 string, and verify its checksum
+///
+/// Dashes and whitespace are ignored.
+pub(crate) fn decode(s: &str) -> Result<[u8; ID_LEN], ParseError> {
+    let (mut acc, mut bits) = (0u32, 0);
+    let mut data = vec![];
+    for c in s.chars().filter(|c| *c != '-' && !c.is_whitespace()) {
+        acc = (acc << 5 | symbol(c)? as u32) & 0xFFFF;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((acc >> bits) as u8);
+        }
+    }
+
+    if data.len() != ID_LEN + 2 {
+        return Err(ParseError::InvalidLength {
+            expected: ID_LEN,
+            found: data.len().saturating_sub(2),
+        });
+    }
+
+    let (id, crc) = data.split_at(ID_LEN);
+    if crc16(id).to_be_bytes() != crc {
+        return Err(ParseError::ChecksumMismatch);
+    }
+
+    let mut buf = [0; ID_LEN];
+    buf.copy_from_slice(id);
+    Ok(buf)
+}
+
+/// Decode the hex encoding used by `Display`
+///
+/// Dashes are ignored, and both upper and lower case are accepted.
+pub(crate) fn decode_hex(s: &str) -> Result<[u8; ID_LEN], ParseError> {
+    let digits = s
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| c.to_digit(16).ok_or(ParseError::InvalidCharacter(c)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if digits.len() != ID_LEN * 2 {
+        return Err(ParseError::InvalidLength {
+            expected: ID_LEN,
+            found: digits.len() / 2,
+        });
+    }
+
+    let mut buf = [0; ID_LEN];
+    for (b, pair) in buf.iter_mut().zip(digits.chunks(2)) {
+        *b = (pair[0] << 4 | pair[1]) as u8;
+    }
+    Ok(buf)
+}
@@ -0,0 +1,33 @@
+//! Identity parsing errors
+
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+};
+
+/// An error that occured while parsing an `Identity`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input decoded to the wrong number of bytes
+    InvalidLength { expected: usize, found: usize },
+    /// The input contained a character that isn't part of the encoding
+    InvalidCharacter(char),
+    /// A base32 encoded identity had a checksum that didn't match
+    ///
+    /// This usually means that it was mistyped.
+    ChecksumMismatch,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidLength { expected, found } => {
+                write!(f, "expected {} bytes, found {}", expected, found)
+            }
+            Self::InvalidCharacter(c) => write!(f, "invalid character `{}`", c),
+            Self::ChecksumMismatch => write!(f, "checksum mismatch"),
+        }
+    }
+}
+
+impl StdError for ParseError {}
@@ -13,12 +13,49 @@
 //! 1. There are no identity collisions
 //! 2. Identities don't change mid-route
 //!
+//! Identities are displayed as hex strings in groups of four digits,
+//! which is also what their `FromStr` implementation parses.  For
+//! sharing them between people, there is a shorter base32 encoding
+//! with a checksum ([`to_base32`]), and for verifying them out of
+//! band, a [`fingerprint`] and its word representation ([`words`]).
+//!
+//! ## Identity sizes
+//!
+//! By default an identity is 32 bytes long, which is the length of an
+//! ed25519 public key.  Every frame carries at least three of them
+//! (sender, recipient and sequence ID), which adds up on constrained
+//! links, so smaller identities can be selected with a feature flag:
+//!
+//! | Feature   | `ID_LEN`                        |
+//! |-----------|---------------------------------|
+//! | (none)    | 32                              |
+//! | `compact` | 16                              |
+//! | `aligned` | the platform word size (4 or 8) |
+//!
+//! All identities in a network share the same length: user
+//! addresses, message IDs and sequence IDs are all `Identity` values.
+//! Nodes built with different sizes can't parse each others' frames,
+//! so every node in a network needs to pick the same variant.  Only
+//! one of the features can be enabled at a time.
+//!
+//! Applications that use public keys as addresses (such as libqaul)
+//! need the default size.
+//!
 //! This crate is part of the qaul.net project.  The docs for this
 //! crate are probably lacking because currently Ratman/ libqaul are
 //! the only users of it.  If you have questions, don't hesitate to
 //! [contact us]!
 //!
 //! [contact us]: https://docs.qaul.net/manual/social/_intro.html
+//! [`to_base32`]: struct.Identity.html#method.to_base32
+//! [`fingerprint`]: struct.Identity.html#method.fingerprint
+//! [`words`]: struct.Identity.html#method.words
+
+mod encoding;
+mod error;
+mod words;
+
+pub use error::ParseError;
 
 use cfg_if;
 use serde::{
@@ -26,21 +63,30 @@ use serde::{
     Deserialize, Serialize, Serializer,
 };
 use std::{
+    convert::TryFrom,
     fmt::{self, Debug, Display, Formatter},
+    str::FromStr,
     string::ToString,
 };
 
 cfg_if::cfg_if! {
-    if #[cfg(features = "aligned")] {
-        use std::mem::size_of,
+    if #[cfg(all(feature = "aligned", feature = "compact"))] {
+        compile_error!("The `aligned` and `compact` features are mutually exclusive");
+    } else if #[cfg(feature = "aligned")] {
         /// Length of the identity buffer to align with platform words
-        pub const ID_LEN: usize = size_of::<usize>();
+        pub const ID_LEN: usize = std::mem::size_of::<usize>();
+    } else if #[cfg(feature = "compact")] {
+        /// Length of the identity buffer, half of an ed25519 pubkey
+        pub const ID_LEN: usize = 16;
     } else {
         /// Length of the identity buffer to align with an ed25519 pubkey
         pub const ID_LEN: usize = 32;
     }
 }
 
+/// The number of bytes an identity fingerprint covers
+pub const FINGERPRINT_LEN: usize = if ID_LEN < 8 { ID_LEN } else { 8 };
+
 /// A generic object identifier
 #[derive(Copy, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
 pub struct Identity([u8; ID_LEN]);
@@ -68,7 +114,7 @@ impl Display for Identity {
 }
 
 impl Identity {
-    /// Create an identity from the first 16 bytes of a vector
+    /// Create an identity from the first `ID_LEN` bytes of a vector
     ///
     /// This function will panic, if the provided vector isn't long
     /// enough, but extra data will simply be discarded.
@@ -91,32 +137,57 @@ impl Identity {
     ///
     /// This function will panic, if the provided slice isn't exactly
     /// the length of the underlying identity implementation (see
-    /// `ID_LEN`)
+    /// `ID_LEN`).  Use `Identity::try_from` for untrusted input.
     pub fn from_bytes(buf: &[u8]) -> Self {
-        assert_eq!(buf.len(), ID_LEN);
-        Self(
-            buf.into_iter()
-                .enumerate()
-                .fold([0; ID_LEN], |mut buf, (i, u)| {
-                    buf[i] = *u;
-                    buf
-                }),
-        )
+        Self::try_from(buf).expect("Identity::from_bytes() called with the wrong length")
     }
 
+    /// Parse an identity that was serialised by `to_string()`
+    ///
+    /// This function will panic on invalid input.
+    #[deprecated(note = "use `str::parse`, which returns an error for invalid input")]
     pub fn from_string(s: &String) -> Self {
-        let v: Vec<u8> = s
-            .split("-")
-            .map(|s| {
-                hex::decode(s).expect(
-                    "Don't call from_string() on input that was not serialised by to_string()!",
-                )
-            })
-            .collect::<Vec<Vec<u8>>>()
-            .into_iter()
-            .flatten()
-            .collect();
-        Self::from_bytes(&v)
+        s.parse()
+            .expect("Don't call from_string() on input that was not serialised by to_string()!")
+    }
+
+    /// Encode this identity as base32, with a checksum
+    ///
+    /// The encoding is about a third shorter than the hex encoding,
+    /// and typos are detected when parsing it.  Both encodings are
+    /// accepted by `FromStr`.
+    pub fn to_base32(&self) -> String {
+        encoding::encode(&self.0)
+    }
+
+    /// Parse an identity encoded with `to_base32`
+    pub fn from_base32(s: &str) -> Result<Self, ParseError> {
+        encoding::decode(s).map(Self)
+    }
+
+    /// A short fingerprint to compare identities out of band
+    ///
+    /// The fingerprint consists of the first groups of the hex
+    /// encoding, so it can also be compared against a full ID.
+    pub fn fingerprint(&self) -> String {
+        self.to_string()
+            .split('-')
+            .take(FINGERPRINT_LEN / 2)
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// Represent the fingerprint as a sequence of words
+    ///
+    /// This is easier to read out to someone (for example on the
+    /// phone) than hex digits.
+    pub fn words(&self) -> String {
+        self.0
+            .iter()
+            .take(FINGERPRINT_LEN)
+            .map(|b| words::WORDS[*b as usize])
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 
     pub fn as_bytes(&self) -> &[u8] {
@@ -161,6 +232,51 @@ impl Identity {
     }
 }
 
+/// Parse the hex encoding used by `Display`, or the base32 encoding
+impl FromStr for Identity {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let hex = match encoding::decode_hex(s) {
+            Ok(buf) => return Ok(Self(buf)),
+            Err(e) => e,
+        };
+
+        match encoding::decode(s) {
+            Ok(buf) => Ok(Self(buf)),
+            // Report errors for the encoding the input looks like
+            Err(_) if s.chars().all(|c| c == '-' || c.is_ascii_hexdigit()) => Err(hex),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl TryFrom<&str> for Identity {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, ParseError> {
+        s.parse()
+    }
+}
+
+/// Create an identity from an exactly length-matched byte slice
+impl TryFrom<&[u8]> for Identity {
+    type Error = ParseError;
+
+    fn try_from(buf: &[u8]) -> Result<Self, ParseError> {
+        if buf.len() != ID_LEN {
+            return Err(ParseError::InvalidLength {
+                expected: ID_LEN,
+                found: buf.len(),
+            });
+        }
+
+        let mut id = [0; ID_LEN];
+        id.copy_from_slice(buf);
+        Ok(Self(id))
+    }
+}
+
 /// Implement RAW `From` binary array
 impl From<[u8; ID_LEN]> for Identity {
     fn from(i: [u8; ID_LEN]) -> Self {
@@ -246,36 +362,11 @@ impl<'de> Deserialize<'de> for Identity {
 
         impl IdentityVisitor {
             fn from_str<E: Error>(v: &str) -> Result<Identity, E> {
-                let v: Vec<u8> = v
-                    .split("-")
-                    .map(|s| hex::decode(s).map_err(|e| E::custom(e)))
-                    // I don't like this way of propagating errors up but the alternative
-                    // is a for loop which i also don't like
-                    .collect::<Result<Vec<Vec<u8>>, E>>()?
-                    .into_iter()
-                    .flatten()
-                    .collect();
-
-                Self::from_bytes(&v)
+                v.parse().map_err(E::custom)
             }
 
             fn from_bytes<E: Error, V: AsRef<[u8]>>(v: V) -> Result<Identity, E> {
-                let v = v.as_ref();
-                if v.len() != ID_LEN {
-                    return Err(E::custom(format!(
-                        "Expected {} bytes, got {}",
-                        ID_LEN,
-                        v.len()
-                    )));
-                }
-
-                Ok(Identity(v.iter().enumerate().take(ID_LEN).fold(
-                    [0; ID_LEN],
-                    |mut buf, (i, u)| {
-                        buf[i] = *u;
-                        buf
-                    },
-                )))
+                Identity::try_from(v.as_ref()).map_err(E::custom)
             }
         }
 
@@ -334,7 +425,7 @@ mod test {
     use serde_json;
 
     #[test]
-    #[cfg(not(features = "aligned"))]
+    #[cfg(not(any(feature = "aligned", feature = "compact")))]
     fn json_serde() {
         let s = b"Yes, we will make total destroy.";
         let i = Identity::truncate(&s.to_vec());
@@ -348,7 +439,7 @@ mod test {
     }
 
     #[test]
-    #[cfg(not(features = "aligned"))]
+    #[cfg(not(any(feature = "aligned", feature = "compact")))]
     fn bincode_serde() {
         let s = b"Yes, we will make total destroy.";
         let i = Identity::truncate(&s.to_vec());
@@ -366,14 +457,103 @@ mod test {
     }
 
     #[test]
-    #[cfg(features = "aligned")]
+    fn serde_roundtrip() {
+        let i = Identity::truncate(&b"Yes, we will make total destroy.".to_vec());
+        let json = serde_json::to_string(&i).unwrap();
+        assert_eq!(serde_json::from_str::<Identity>(&json).unwrap(), i);
+
+        let bin = bincode::serialize(&i).unwrap();
+        assert_eq!(bin.len(), 8 + ID_LEN);
+        assert_eq!(bincode::deserialize::<Identity>(&bin).unwrap(), i);
+    }
+
+    #[test]
+    fn parse_hex() {
+        let i = Identity::truncate(&b"Yes, we will make total destroy.".to_vec());
+        assert_eq!(i.to_string().parse(), Ok(i));
+        assert_eq!(hex::encode(i).parse(), Ok(i));
+        assert_eq!(Identity::try_from(i.to_string().as_str()), Ok(i));
+
+        assert_eq!(
+            "5965-732C".parse::<Identity>(),
+            Err(ParseError::InvalidLength {
+                expected: ID_LEN,
+                found: 4
+            })
+        );
+        assert_eq!(
+            "5965-732U".parse::<Identity>(),
+            Err(ParseError::InvalidCharacter('U'))
+        );
+        assert!(serde_json::from_str::<Identity>("\"not an id\"").is_err());
+    }
+
+    #[test]
+    fn parse_base32() {
+        let i = Identity::truncate(&b"Yes, we will make total destroy.".to_vec());
+        let b32 = i.to_base32();
+        #[cfg(not(any(feature = "aligned", feature = "compact")))]
+        assert_eq!(b32.len(), 65);
+        assert_eq!(Identity::from_base32(&b32), Ok(i));
+        assert_eq!(b32.parse(), Ok(i));
+
+        // Case and commonly confused characters don't matter
+        let sloppy = b32.to_lowercase().replace('0', "o").replace('-', " ");
+        assert_eq!(Identity::from_base32(&sloppy), Ok(i));
+
+        // A single typo is detected
+        let typo: String = b32
+            .chars()
+            .enumerate()
+            .map(|(n, c)| match (n, c) {
+                (7, 'Z') => 'Y',
+                (7, _) => 'Z',
+                (_, c) => c,
+            })
+            .collect();
+        assert_eq!(typo.parse::<Identity>(), Err(ParseError::ChecksumMismatch));
+        assert_eq!(
+            Identity::from_base32("U"),
+            Err(ParseError::InvalidCharacter('U'))
+        );
+    }
+
+    #[test]
+    fn try_from_bytes() {
+        assert!(Identity::try_from(&[0; ID_LEN][..]).is_ok());
+        assert_eq!(
+            Identity::try_from(&[0; 3][..]),
+            Err(ParseError::InvalidLength {
+                expected: ID_LEN,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn fingerprint() {
+        let i = Identity::truncate(&b"Yes, we will make total destroy.".to_vec());
+        assert!("5965-732C-2077-6520".starts_with(&i.fingerprint()));
+        assert!(i.to_string().starts_with(&i.fingerprint()));
+        assert_eq!(i.words().split(' ').count(), FINGERPRINT_LEN);
+        assert_ne!(i.words(), Identity::from([0; ID_LEN]).words());
+    }
+
+    #[test]
+    #[cfg(feature = "aligned")]
+    fn sized() {
+        assert_eq!(crate::ID_LEN, std::mem::size_of::<usize>());
+    }
+
+    #[test]
+    #[cfg(feature = "compact")]
     fn sized() {
-        assert_eq!(crate::ID_LEN, size_of::<usize>());
+        assert_eq!(crate::ID_LEN, 16);
     }
 
     /// This is the default length
     #[test]
-    #[cfg(not(features = "aligned"))]
+    #[cfg(not(any(feature = "aligned", feature = "compact")))]
     fn sized() {
         assert_eq!(crate::ID_LEN, 32);
     }
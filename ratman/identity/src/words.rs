@@ -0,0 +1,35 @@
+//! A word list to read out identity fingerprints
+//!
+//! Every byte maps to one word.  The words are short, common, and
+//! distinct enough to not be confused when read out over a bad
+//! phone line.
+
+pub(crate) const WORDS: [&str; 256] = [
+    "acid", "acorn", "actor", "adobe", "agent", "alarm", "album", "alert", "alien", "alloy",
+    "alpha", "amber", "angel", "ankle", "apple", "apron", "arena", "armor", "arrow", "aspen",
+    "atlas", "atom", "attic", "audio", "autumn", "avenue", "award", "axis", "bacon", "badge",
+    "bagel", "baker", "bamboo", "banjo", "barn", "basil", "basin", "beach", "beacon", "beard",
+    "beetle", "bell", "bench", "berry", "bike", "bison", "blade", "blanket", "blaze", "bloom",
+    "board", "boat", "bonus", "boot", "bottle", "bowl", "brain", "brass", "bread", "brick",
+    "bridge", "brook", "broom", "bucket", "buffalo", "bundle", "butter", "cabin", "cable",
+    "cactus", "camel", "camera", "canal", "candle", "canoe", "canyon", "carbon", "cargo", "carpet",
+    "castle", "cedar", "cello", "chalk", "cherry", "chess", "chief", "chimney", "cider", "circle",
+    "citrus", "clay", "cliff", "clock", "cloud", "clover", "coast", "cobalt", "cocoa", "comet",
+    "copper", "coral", "cotton", "couch", "coyote", "crane", "crater", "crayon", "cricket",
+    "crown", "crystal", "cube", "cymbal", "daisy", "dancer", "delta", "denim", "desert", "diamond",
+    "dingo", "dolphin", "domino", "donkey", "dragon", "drum", "dune", "eagle", "earth", "echo",
+    "eclipse", "elbow", "ember", "emerald", "engine", "falcon", "fable", "feather", "fern",
+    "ferry", "fiddle", "field", "fig", "finch", "flame", "flute", "forest", "fossil", "fountain",
+    "fox", "frost", "galaxy", "garden", "garlic", "gecko", "geyser", "ginger", "glacier", "globe",
+    "goblet", "gold", "gopher", "granite", "grape", "gravel", "guitar", "hammer", "harbor", "harp",
+    "hawk", "hazel", "helmet", "heron", "hickory", "honey", "hornet", "igloo", "iris", "island",
+    "ivory", "jacket", "jaguar", "jasmine", "jelly", "jewel", "jigsaw", "jungle", "kayak",
+    "kettle", "kiwi", "koala", "ladder", "lagoon", "lantern", "laser", "lemon", "lentil", "lily",
+    "lizard", "llama", "lobster", "locket", "lotus", "magnet", "mango", "maple", "marble",
+    "meadow", "melon", "meteor", "mirror", "mitten", "monkey", "moose", "mosaic", "moss", "nectar",
+    "needle", "nickel", "noodle", "nutmeg", "oasis", "ocean", "olive", "onion", "opal", "orbit",
+    "orchid", "otter", "owl", "paddle", "panda", "papaya", "parrot", "peach", "pebble", "pepper",
+    "piano", "pilot", "pine", "planet", "plum", "pocket", "pony", "poppy", "potato", "prism",
+    "pumpkin", "puzzle", "quail", "quartz", "quill", "rabbit", "radar", "radish", "raven", "reef",
+    "ribbon",
+];
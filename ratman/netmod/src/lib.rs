@@ -32,6 +32,28 @@
 //! integrity (resends are up to a user of this interface to
 //! implement, as well as associating sequential frames into a data
 //! set.
+//!
+//! Netmods are free to choose how frames are encoded on the wire.
+//! Most use bincode, but the [`wire`] module provides a more compact
+//! encoding for links where every byte counts.
+//!
+//! ## Versions
+//!
+//! Nodes in a network can't all be upgraded at the same time.  To
+//! detect peers that are too old (or too new) to talk to, netmods
+//! exchange a [`Handshake`] when establishing a link, and reject
+//! incompatible peers.
+//!
+//! ## Padding
+//!
+//! Routers can pad frames to a few fixed sizes, and send cover
+//! traffic, to make it harder to tell what kind of data is being
+//! sent.  The [`pad`] module describes the padding format, and lets
+//! netmods remove padding where it isn't wanted.
+//!
+//! [`wire`]: wire/index.html
+//! [`Handshake`]: struct.Handshake.html
+//! [`pad`]: pad/index.html
 #![allow(warnings)]
 
 #[macro_use]
@@ -41,8 +63,12 @@ mod endpoint;
 mod frame;
 mod result;
 mod seq;
+mod version;
+pub mod pad;
+pub mod wire;
 
 pub use endpoint::Endpoint;
 pub use frame::{Frame, Recipient, Target};
 pub use result::{Error, Result};
 pub use seq::{SeqBuilder, SeqData, SeqId};
+pub use version::Handshake;
@@ -0,0 +1,129 @@
+//! Frame padding and cover traffic
+//!
+//! The size of a frame reveals a lot about what it carries: voice
+//! packets, chat messages and file chunks all look different.  A
+//! router can pad frame payloads to a small set of fixed sizes (see
+//! [`BUCKETS`]), and send cover frames that look like regular traffic,
+//! but are dropped by the receiving router (see [`cover`]).
+//!
+//! Padding is appended to the payload as a single `0x80` byte,
+//! followed by zeros.  The sequence signature of a frame only covers
+//! the unpadded payload, which means that a netmod can remove padding
+//! at any point (for example on a link where every byte is precious)
+//! via [`strip`], without breaking the frame.
+//!
+//! [`BUCKETS`]: constant.BUCKETS.html
+//! [`cover`]: fn.cover.html
+//! [`strip`]: fn.strip.html
+
+use crate::{Frame, Recipient, SeqBuilder};
+use identity::Identity;
+
+/// The payload sizes that padded frames are rounded up to
+///
+/// Payloads larger than the last bucket are rounded up to a multiple
+/// of it.
+pub const BUCKETS: [usize; 3] = [128, 512, 1312];
+
+/// Marks the start of the padding in a payload
+const MARKER: u8 = 0x80;
+
+/// Get the padded size of a payload with `len` bytes
+pub fn bucket(len: usize) -> usize {
+    let min = len + 1;
+    let max = BUCKETS[BUCKETS.len() - 1];
+    BUCKETS
+        .iter()
+        .cloned()
+        .find(|b| *b >= min)
+        .unwrap_or_else(|| (min + max - 1) / max * max)
+}
+
+/// Pad the payload of a frame to the next bucket size
+pub fn pad(frame: &mut Frame) {
+    let size = bucket(frame.payload.len());
+    frame.payload.push(MARKER);
+    frame.payload.resize(size, 0);
+}
+
+/// Remove padding from a frame, if there is any
+///
+/// Returns `false` if the frame payload doesn't match its signature,
+/// with or without padding.  Such frames are broken, and should be
+/// dropped.
+pub fn strip(frame: &mut Frame) -> bool {
+    if frame.seq.sig.verify(&frame.payload) {
+        return true;
+    }
+
+    let len = match frame.payload.iter().rposition(|b| *b != 0) {
+        Some(i) if frame.payload[i] == MARKER => i,
+        _ => return false,
+    };
+
+    if frame.seq.sig.verify(&frame.payload[..len].to_vec()) {
+        frame.payload.truncate(len);
+        true
+    } else {
+        false
+    }
+}
+
+/// Create a cover frame around some random data
+///
+/// The frame is a valid, padded frame, addressed from and to random
+/// users.  Nothing in it sets it apart from a real frame that is
+/// sent to a user who isn't reachable.  Routers treat it exactly like
+/// such a frame: because no router knows the recipient, it is
+/// dropped by the first router that receives it.
+pub fn cover(data: Vec<u8>) -> Frame {
+    let mut frame = SeqBuilder::new(
+        Identity::random(),
+        Recipient::User(Identity::random()),
+        Identity::random(),
+    )
+    .add(data)
+    .build()
+    .remove(0);
+
+    pad(&mut frame);
+    frame
+}
+
+#[test]
+fn buckets() {
+    assert_eq!(bucket(0), 128);
+    assert_eq!(bucket(127), 128);
+    assert_eq!(bucket(128), 512);
+    assert_eq!(bucket(1311), 1312);
+    assert_eq!(bucket(1312), 2624);
+}
+
+#[test]
+fn pad_and_strip() {
+    // Payloads ending in zeros or the marker need to survive
+    for payload in vec![vec![], vec![1, 2, 3], vec![MARKER, 0, 0], vec![0; 600]] {
+        let orig = SeqBuilder::new(Identity::random(), Recipient::Flood, Identity::random())
+            .add(payload)
+            .build()
+            .remove(0);
+
+        let mut f = orig.clone();
+        pad(&mut f);
+        assert!(BUCKETS.contains(&f.payload.len()));
+        assert!(strip(&mut f));
+        assert_eq!(f, orig);
+
+        // Unpadded frames are left alone
+        assert!(strip(&mut f));
+        assert_eq!(f, orig);
+    }
+}
+
+#[test]
+fn cover_frames() {
+    let mut f = cover(vec![1, 2, 3, 4]);
+    assert_eq!(f.payload.len(), 128);
+    assert!(strip(&mut f));
+    assert_eq!(f.payload, vec![1, 2, 3, 4]);
+}
@@ -13,8 +13,8 @@ pub type SeqId = Identity;
 /// An XxHash signature and initialisation seed
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct XxSignature {
-    sig: u64,
-    seed: u64,
+    pub(crate) sig: u64,
+    pub(crate) seed: u64,
 }
 
 impl XxSignature {
@@ -27,7 +27,7 @@ impl XxSignature {
         }
     }
 
-    fn verify(&self, data: &Vec<u8>) -> bool {
+    pub(crate) fn verify(&self, data: &Vec<u8>) -> bool {
         let mut hasher = XxHash64::with_seed(self.seed);
         hasher.write(data);
         hasher.finish() == self.sig
@@ -0,0 +1,134 @@
+//! Protocol versions and capabilities
+
+/// Protocol version and capability information about a node
+///
+/// Netmods exchange a handshake when a link is established, and
+/// Ratman includes one in its announcements.  Two nodes can talk to
+/// each other if there is a version that both of them accept (see
+/// [`negotiate`]).  Nodes that don't send a handshake (because they
+/// were built before it was introduced) are treated as
+/// [`LEGACY`].
+///
+/// The frame format of version 1 is the same as that of version 0,
+/// so by default legacy nodes are still accepted.
+///
+/// [`negotiate`]: struct.Handshake.html#method.negotiate
+/// [`LEGACY`]: struct.Handshake.html#associatedconstant.LEGACY
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handshake {
+    /// The newest protocol version a node speaks
+    pub version: u16,
+    /// The oldest protocol version a node still accepts
+    pub min_version: u16,
+    /// Optional features supported by a node, as a bit set
+    pub caps: u32,
+}
+
+impl Handshake {
+    /// The protocol version implemented by this crate
+    pub const VERSION: u16 = 1;
+
+    /// The oldest protocol version this crate is compatible with
+    pub const MIN_VERSION: u16 = 0;
+
+    /// The node can decode frames in the compact `wire` encoding
+    ///
+    /// None of the netmods that exchange handshakes use the compact
+    /// encoding yet, so this isn't part of the `current` handshake.
+    pub const COMPACT: u32 = 1 << 0;
+
+    /// The node removes `pad`ding from frames, and drops cover frames
+    pub const PADDING: u32 = 1 << 1;
+
+    /// The implied handshake of a node that didn't send one
+    pub const LEGACY: Self = Self {
+        version: 0,
+        min_version: 0,
+        caps: 0,
+    };
+
+    /// The handshake of the current protocol version
+    pub fn current() -> Self {
+        Self {
+            version: Self::VERSION,
+            min_version: Self::MIN_VERSION,
+            caps: Self::PADDING,
+        }
+    }
+
+    /// Find the protocol that two nodes can use to talk to each other
+    ///
+    /// This is the newest version both nodes speak, as long as both of
+    /// them still accept it, and the capabilities that both of them
+    /// support.  Returns `None` if the nodes are incompatible.
+    pub fn negotiate(&self, peer: &Self) -> Option<Self> {
+        let version = self.version.min(peer.version);
+        if version < self.min_version.max(peer.min_version) {
+            return None;
+        }
+
+        Some(Self {
+            version,
+            min_version: version,
+            caps: self.caps & peer.caps,
+        })
+    }
+
+    /// Check if a capability is part of this handshake
+    pub fn supports(&self, cap: u32) -> bool {
+        self.caps & cap == cap
+    }
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self::current()
+    }
+}
+
+#[test]
+fn same_version() {
+    let h = Handshake::current();
+    let link = h.negotiate(&h).unwrap();
+    assert_eq!(link.version, Handshake::VERSION);
+    assert!(link.supports(Handshake::PADDING));
+    assert!(!link.supports(Handshake::COMPACT));
+}
+
+#[test]
+fn older_peer() {
+    let new = Handshake {
+        version: 3,
+        min_version: 2,
+        caps: 0b11,
+    };
+    let old = Handshake {
+        version: 2,
+        min_version: 1,
+        caps: 0b01,
+    };
+
+    let link = new.negotiate(&old).unwrap();
+    assert_eq!(link, old.negotiate(&new).unwrap());
+    assert_eq!(link.version, 2);
+    assert_eq!(link.caps, 0b01);
+    assert!(!link.supports(0b10));
+}
+
+#[test]
+fn incompatible() {
+    let new = Handshake {
+        version: 3,
+        min_version: 3,
+        caps: 0,
+    };
+    let old = Handshake {
+        version: 2,
+        min_version: 0,
+        caps: 0,
+    };
+
+    assert_eq!(new.negotiate(&old), None);
+    assert_eq!(old.negotiate(&new), None);
+    assert!(Handshake::current().negotiate(&Handshake::LEGACY).is_some());
+}
@@ -0,0 +1,440 @@
+//! A compact binary encoding for frames
+//!
+//! By default frames are serialised with bincode, which repeats the
+//! sender, recipient and sequence ID in every frame, and uses fixed
+//! size integers and length prefixes.  On slow links (serial lines,
+//! packet radio, LoRa) these headers can easily be larger than the
+//! payload they carry.
+//!
+//! This module implements a versioned encoding that netmods can opt
+//! into.  Integers are encoded as varints, the payload isn't length
+//! prefixed (the netmod is expected to preserve frame boundaries),
+//! and an [`Encoder`] only sends the full header in the first frame
+//! of a sequence that crosses the link.  Following frames refer back
+//! to it with a short sequence reference, which the [`Decoder`] on
+//! the other side resolves.
+//!
+//! The encoder and decoder keep state about a single link, so a
+//! one-to-many netmod needs a pair for every peer.  Broadcasts can
+//! use the stateless [`encode`] and [`decode`] functions, which only
+//! use full headers.
+//!
+//! ## Format
+//!
+//! | Field     | Size      | Notes                                 |
+//! |-----------|-----------|---------------------------------------|
+//! | header    | 1 byte    | version (upper 4 bits) and flags      |
+//! | reference | varint    | short sequence reference              |
+//! | sender    | `ID_LEN`  | only with the `FULL` flag             |
+//! | recipient | `ID_LEN`  | only with `FULL`, and without `FLOOD` |
+//! | seqid     | `ID_LEN`  | only with the `FULL` flag             |
+//! | num       | varint    | frame number in the sequence          |
+//! | sig       | 16 bytes  | payload signature and seed            |
+//! | next      | 8 bytes   | only with the `NEXT` flag             |
+//! | payload   | remaining |                                       |
+//!
+//! [`Encoder`]: struct.Encoder.html
+//! [`Decoder`]: struct.Decoder.html
+//! [`encode`]: fn.encode.html
+//! [`decode`]: fn.decode.html
+
+use crate::{seq::XxSignature, Frame, Recipient, SeqData, SeqId};
+use identity::{Identity, ID_LEN};
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+};
+
+/// The version of the encoding produced by this module
+pub const VERSION: u8 = 1;
+
+/// The number of sequences an encoder or decoder remembers by default
+pub const DEFAULT_CAPACITY: usize = 64;
+
+const FULL: u8 = 0b0001;
+const NEXT: u8 = 0b0010;
+const FLOOD: u8 = 0b0100;
+
+/// The sequence reference of frames that aren't part of a link's state
+const STATELESS: u64 = 0;
+
+/// Errors that can occur while decoding a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The buffer ended before the frame header did
+    Truncated,
+    /// The frame was encoded with an unknown version
+    Version(u8),
+    /// A short sequence reference that the decoder doesn't know
+    ///
+    /// This happens when the first frame of a sequence was lost, or
+    /// when the other side of the link was restarted.
+    UnknownSequence(u64),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "frame is truncated"),
+            Self::Version(v) => write!(f, "unsupported frame encoding version {}", v),
+            Self::UnknownSequence(r) => write!(f, "unknown sequence reference {}", r),
+        }
+    }
+}
+
+impl StdError for DecodeError {}
+
+/// The part of a frame that is the same for a whole sequence
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    sender: Identity,
+    recipient: Recipient,
+    seqid: SeqId,
+}
+
+/// Encode a frame with its full header
+///
+/// The result can be decoded by [`decode`], or any [`Decoder`].
+///
+/// [`decode`]: fn.decode.html
+/// [`Decoder`]: struct.Decoder.html
+pub fn encode(frame: &Frame) -> Vec<u8> {
+    write(frame, STATELESS, true)
+}
+
+/// Decode a frame that was encoded with its full header
+///
+/// Frames with a short sequence reference are rejected, because there
+/// is no state to resolve them with.
+pub fn decode(buf: &[u8]) -> Result<Frame, DecodeError> {
+    let mut r = Reader(buf);
+    match read_header(&mut r)? {
+        (flags, _, Some(h)) => read_body(&mut r, flags, h),
+        (_, rf, None) => Err(DecodeError::UnknownSequence(rf)),
+    }
+}
+
+/// Encodes frames sent over a single link
+///
+/// The encoder remembers the sequences it has sent a full header for,
+/// up to its capacity.  The decoder on the other side of the link
+/// needs to remember at least as many sequences.
+#[derive(Debug)]
+pub struct Encoder {
+    cap: usize,
+    next: u64,
+    refs: HashMap<SeqId, u64>,
+    order: VecDeque<SeqId>,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl Encoder {
+    /// Create an encoder that remembers the default number of sequences
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an encoder that remembers `cap` sequences
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            cap,
+            next: STATELESS + 1,
+            refs: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Encode a frame, with a full header if this link hasn't seen its
+    /// sequence yet
+    pub fn encode(&mut self, frame: &Frame) -> Vec<u8> {
+        let seqid = frame.seqid();
+        if let Some(rf) = self.refs.get(&seqid) {
+            return write(frame, *rf, false);
+        }
+
+        let rf = self.next;
+        self.next += 1;
+        self.refs.insert(seqid, rf);
+        self.order.push_back(seqid);
+        if self.order.len() > self.cap {
+            let old = self.order.pop_front().unwrap();
+            self.refs.remove(&old);
+        }
+
+        write(frame, rf, true)
+    }
+
+    /// Forget all sequences
+    ///
+    /// Call this when the other side of the link might have lost its
+    /// state, for example after reconnecting.
+    pub fn reset(&mut self) {
+        self.refs.clear();
+        self.order.clear();
+    }
+}
+
+/// Decodes frames received from a single link
+#[derive(Debug)]
+pub struct Decoder {
+    cap: usize,
+    headers: HashMap<u64, Header>,
+    order: VecDeque<u64>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl Decoder {
+    /// Create a decoder that remembers the default number of sequences
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a decoder that remembers `cap` sequences
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            cap,
+            headers: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Decode a frame sent by an `Encoder`, or the `encode` function
+    pub fn decode(&mut self, buf: &[u8]) -> Result<Frame, DecodeError> {
+        let mut r = Reader(buf);
+        let (flags, rf, header) = read_header(&mut r)?;
+        let header = match header {
+            Some(h) if rf == STATELESS => h,
+            Some(h) => {
+                if self.headers.insert(rf, h).is_none() {
+                    self.order.push_back(rf);
+                }
+                if self.order.len() > self.cap {
+                    let old = self.order.pop_front().unwrap();
+                    self.headers.remove(&old);
+                }
+                h
+            }
+            None => *self
+                .headers
+                .get(&rf)
+                .ok_or(DecodeError::UnknownSequence(rf))?,
+        };
+
+        read_body(&mut r, flags, header)
+    }
+}
+
+fn write(frame: &Frame, rf: u64, full: bool) -> Vec<u8> {
+    let mut flags = VERSION << 4;
+    if full {
+        flags |= FULL;
+    }
+    if frame.seq.next.is_some() {
+        flags |= NEXT;
+    }
+    if frame.recipient == Recipient::Flood {
+        flags |= FLOOD;
+    }
+
+    let mut buf = Vec::with_capacity(frame.payload.len() + 32);
+    buf.push(flags);
+    write_varint(&mut buf, rf);
+    if full {
+        buf.extend_from_slice(frame.sender.as_bytes());
+        if let Recipient::User(ref id) = frame.recipient {
+            buf.extend_from_slice(id.as_bytes());
+        }
+        buf.extend_from_slice(frame.seq.seqid.as_bytes());
+    }
+
+    write_varint(&mut buf, frame.seq.num as u64);
+    buf.extend_from_slice(&frame.seq.sig.sig.to_le_bytes());
+    buf.extend_from_slice(&frame.seq.sig.seed.to_le_bytes());
+    if let Some(next) = frame.seq.next {
+        buf.extend_from_slice(&next.to_le_bytes());
+    }
+    buf.extend_from_slice(&frame.payload);
+    buf
+}
+
+fn read_header(r: &mut Reader) -> Result<(u8, u64, Option<Header>), DecodeError> {
+    let flags = r.take(1)?[0];
+    if flags >> 4 != VERSION {
+        return Err(DecodeError::Version(flags >> 4));
+    }
+
+    let rf = r.varint()?;
+    if flags & FULL == 0 {
+        return Ok((flags, rf, None));
+    }
+
+    let sender = r.id()?;
+    let recipient = match flags & FLOOD {
+        0 => Recipient::User(r.id()?),
+        _ => Recipient::Flood,
+    };
+    let seqid = r.id()?;
+    Ok((
+        flags,
+        rf,
+        Some(Header {
+            sender,
+            recipient,
+            seqid,
+        }),
+    ))
+}
+
+fn read_body(r: &mut Reader, flags: u8, h: Header) -> Result<Frame, DecodeError> {
+    let num = u32::try_from(r.varint()?).map_err(|_| DecodeError::Truncated)?;
+    let sig = XxSignature {
+        sig: r.u64()?,
+        seed: r.u64()?,
+    };
+    let next = match flags & NEXT {
+        0 => None,
+        _ => Some(r.u64()?),
+    };
+
+    Ok(Frame {
+        sender: h.sender,
+        recipient: h.recipient,
+        seq: SeqData {
+            num,
+            sig,
+            seqid: h.seqid,
+            next,
+        },
+        payload: r.0.to_vec(),
+    })
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+/// A cursor over the remaining input
+struct Reader<'b>(&'b [u8]);
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, n: usize) -> Result<&'b [u8], DecodeError> {
+        if self.0.len() < n {
+            return Err(DecodeError::Truncated);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut v = 0;
+        for shift in (0..64).step_by(7) {
+            let b = self.take(1)?[0];
+            v |= ((b & 0x7F) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(DecodeError::Truncated)
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    fn id(&mut self) -> Result<Identity, DecodeError> {
+        Ok(Identity::from_bytes(self.take(ID_LEN)?))
+    }
+}
+
+#[cfg(test)]
+fn sequence() -> Vec<Frame> {
+    use crate::SeqBuilder;
+    let sender = Identity::with_digest(&vec![1]);
+    let recp = Identity::with_digest(&vec![2]);
+    SeqBuilder::new(sender, Recipient::User(recp), Identity::random())
+        .add(vec![42; 10])
+        .add(vec![13; 10])
+        .add(vec![37; 5])
+        .build()
+}
+
+#[test]
+fn stateless_roundtrip() {
+    for f in sequence() {
+        assert_eq!(decode(&encode(&f)), Ok(f));
+    }
+
+    let f = Frame::dummy();
+    assert_eq!(decode(&encode(&f)), Ok(f));
+}
+
+#[test]
+fn short_references() {
+    let (mut enc, mut dec) = (Encoder::new(), Decoder::new());
+    let seq = sequence();
+
+    let first = enc.encode(&seq[0]);
+    let second = enc.encode(&seq[1]);
+    assert!(second.len() + 2 * ID_LEN < first.len());
+    assert!(second.len() < bincode::serialized_size(&seq[1]).unwrap() as usize / 2);
+
+    // A short frame can't be decoded without its sequence header
+    assert_eq!(decode(&second), Err(DecodeError::UnknownSequence(1)));
+    assert_eq!(
+        Decoder::new().decode(&second),
+        Err(DecodeError::UnknownSequence(1))
+    );
+
+    assert_eq!(dec.decode(&first), Ok(seq[0].clone()));
+    assert_eq!(dec.decode(&second), Ok(seq[1].clone()));
+    assert_eq!(dec.decode(&enc.encode(&seq[2])), Ok(seq[2].clone()));
+}
+
+#[test]
+fn capacity() {
+    let mut enc = Encoder::with_capacity(1);
+    let (a, b) = (sequence(), sequence());
+
+    assert_eq!(enc.encode(&a[0])[0] & FULL, FULL);
+    assert_eq!(enc.encode(&b[0])[0] & FULL, FULL);
+    // The first sequence was forgotten, so its header is sent again
+    assert_eq!(enc.encode(&a[1])[0] & FULL, FULL);
+    assert_eq!(enc.encode(&a[2])[0] & FULL, 0);
+
+    enc.reset();
+    assert_eq!(enc.encode(&a[2])[0] & FULL, FULL);
+}
+
+#[test]
+fn invalid_input() {
+    let buf = encode(&Frame::dummy());
+    assert_eq!(decode(&[]), Err(DecodeError::Truncated));
+    assert_eq!(decode(&buf[..10]), Err(DecodeError::Truncated));
+
+    let mut other = buf.clone();
+    other[0] = (VERSION + 1) << 4 | other[0] & 0x0F;
+    assert_eq!(decode(&other), Err(DecodeError::Version(VERSION + 1)));
+
+    // Bincode encoded frames start with a length prefix
+    let bin = bincode::serialize(&Frame::dummy()).unwrap();
+    assert!(decode(&bin).is_err());
+}
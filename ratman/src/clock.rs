@@ -5,8 +5,46 @@
 //! crate docs instead.
 //!
 //! [`clockctrl`]: https://docs.rs/clockctrl
+//!
+//! ## Simulation
+//!
+//! All internal tasks of a router are spawned, and all timestamps
+//! taken, via a [`Clock`].  A router created with
+//! [`Router::with_clock`] on a clock from a [`Simulation`] can be
+//! run deterministically: all routers sharing the same simulation
+//! are scheduled on a single thread, in an order decided by the
+//! simulation seed, and with a virtual time that fast-forwards
+//! through idle periods.
+//!
+//! ```
+//! # use ratman::{Router, Identity, clock::Simulation};
+//! # use netmod_mem::MemMod;
+//! let sim = Simulation::new(1312);
+//! let clock = sim.clock();
+//!
+//! let (u1, u2) = sim.block_on(async {
+//!     let (m1, m2) = MemMod::make_pair();
+//!     let r1 = Router::with_clock(clock.clone());
+//!     let r2 = Router::with_clock(clock.clone());
+//!     r1.add_endpoint(m1).await;
+//!     r2.add_endpoint(m2).await;
+//!
+//!     let (u1, u2) = (Identity::random(), Identity::random());
+//!     r1.add_user(u1).await.unwrap();
+//!     r2.add_user(u2).await.unwrap();
+//!     r1.online(u1).await.unwrap();
+//!     r2.online(u2).await.unwrap();
+//!
+//!     assert_eq!(r1.discover().await, u2);
+//!     (u1, u2)
+//! });
+//! ```
+//!
+//! [`Clock`]: struct.Clock.html
+//! [`Router::with_clock`]: ../struct.Router.html#method.with_clock
+//! [`Simulation`]: struct.Simulation.html
 
-pub use clockctrl::{ClockCtrl, Error, Interval, Scheduler, Target};
+pub use clockctrl::{Clock, ClockCtrl, Error, Interval, Scheduler, Simulation, Target};
 
 /// A collection of tasks running inside the Ratman router
 #[derive(Hash, Ord, PartialOrd, Eq, PartialEq)]
@@ -17,4 +55,13 @@ pub enum Tasks {
     Collector,
     /// Main router poll loop checking for new frames
     Switch,
+    /// Sends cover traffic to neighbours
+    ///
+    /// Cover traffic is only sent when this task is clocked via
+    /// [`Router::clock`].  An `Interval::Delay` scales the default
+    /// interval of ten seconds between two rounds, while an
+    /// `Interval::Timed` sends a round every time the timer fires.
+    ///
+    /// [`Router::clock`]: ../struct.Router.html#method.clock
+    Cover,
 }
@@ -17,11 +17,8 @@
 //! getting access to the state manager to ask for more work, and then
 //! making themselves redundant by handing in their finished messages.
 
-use crate::Message;
-use async_std::{
-    sync::{Arc, Mutex},
-    task,
-};
+use crate::{clock::Clock, Message};
+use async_std::sync::{Arc, Mutex};
 use netmod::{Frame, SeqId};
 use std::collections::BTreeMap;
 use tracing_futures::Instrument;
@@ -38,14 +35,16 @@ pub(self) use worker::Worker;
 pub(crate) struct Collector {
     state: Arc<State>,
     workers: Locked<BTreeMap<SeqId, Arc<Worker>>>,
+    clock: Clock,
 }
 
 impl Collector {
     /// Create a new collector
-    pub(crate) fn new() -> Arc<Self> {
+    pub(crate) fn new(clock: Clock) -> Arc<Self> {
         Arc::new(Self {
             state: Arc::new(State::new()),
             workers: Default::default(),
+            clock,
         })
     }
 
@@ -58,7 +57,14 @@ impl Collector {
 
         let mut map = self.workers.lock().await;
         if !map.contains_key(&seq) {
-            map.insert(seq, Arc::new(Worker::new(seq, Arc::clone(&self.state))));
+            map.insert(
+                seq,
+                Arc::new(Worker::new(
+                    seq,
+                    Arc::clone(&self.state),
+                    self.clock.clone(),
+                )),
+            );
         }
     }
 
@@ -70,7 +76,14 @@ impl Collector {
 
         let mut map = self.workers.lock().await;
         if !map.contains_key(&seq) {
-            map.insert(seq, Arc::new(Worker::new(seq, Arc::clone(&self.state))));
+            map.insert(
+                seq,
+                Arc::new(Worker::new(
+                    seq,
+                    Arc::clone(&self.state),
+                    self.clock.clone(),
+                )),
+            );
             drop(map);
 
             // This function tries to re-lock!
@@ -109,7 +122,7 @@ impl Collector {
             Arc::clone(&map.get(&seq).unwrap())
         };
 
-        task::spawn(
+        self.clock.spawn(
             async move {
                 info!("Spawning worker");
 
@@ -128,6 +141,9 @@ impl Collector {
 #[cfg(test)]
 use crate::Identity;
 
+#[cfg(test)]
+use async_std::task;
+
 #[test]
 fn queue_one() {
     use crate::{Slicer, TimePair};
@@ -151,7 +167,7 @@ fn queue_one() {
     let seqid = id;
 
     task::block_on(async move {
-        let c = Collector::new();
+        let c = Collector::new(Clock::system());
 
         // There is one queued frame
         c.queue(seqid, frame).await;
@@ -189,7 +205,7 @@ fn queue_many() {
     assert_eq!(len, 9);
 
     task::block_on(async move {
-        let c = Collector::new();
+        let c = Collector::new(Clock::system());
 
         for f in seq {
             c.queue(seqid, f).await;
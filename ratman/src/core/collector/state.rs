@@ -3,14 +3,43 @@ use crate::Message;
 
 use async_notify::Notify;
 use async_std::{
-    future::{self, Future},
-    pin::Pin,
-    sync::Arc,
-    task::Poll,
+    future,
+    task::{Poll, Waker},
 };
 use netmod::{Frame, SeqId};
 use std::collections::{BTreeMap, VecDeque};
 
+/// Register the current task to be woken, then release the lock
+///
+/// The waker has to be registered while the lock is still held,
+/// otherwise a notification could slip in between releasing the lock
+/// and going to sleep, and be lost.  The lock guard is consumed by
+/// the `register` function, and the future completes the next time
+/// the task is woken.
+async fn park<G, F>(guard: G, register: F)
+where
+    F: FnOnce(G, &Waker),
+{
+    let mut state = Some((guard, register));
+    future::poll_fn(move |ctx| match state.take() {
+        Some((guard, register)) => {
+            register(guard, ctx.waker());
+            Poll::Pending
+        }
+        None => Poll::Ready(()),
+    })
+    .await
+}
+
+/// Replace any previously registered waker on a `Notify`
+///
+/// A `Notify` only keeps the first waker it is given, which might
+/// belong to a task that has stopped polling a long time ago.
+fn replace_waker<T>(not: &mut Notify<T>, waker: &Waker) {
+    Notify::clear_waker(not);
+    Notify::register_waker(not, waker);
+}
+
 /// Local frame collector state holder
 #[derive(Default)]
 pub(super) struct State {
@@ -27,48 +56,39 @@ impl State {
     /// Poll for completed messages from the outside world
     #[tracing::instrument(skip(self), level = "trace")]
     pub(super) async fn completed(&self) -> Message {
-        let done = Arc::clone(&self.done);
-        future::poll_fn(|ctx| {
-            let lock = &mut done.lock();
-            match unsafe { Pin::new_unchecked(lock).poll(ctx) } {
-                Poll::Ready(ref mut not) => match not.pop_front() {
-                    Some(f) => {
-                        info!("Received new message for local service");
-                        Poll::Ready(f)
-                    }
-                    None => {
-                        if !Notify::has_waker(not) {
-                            trace!("No new frames; registering waker");
-                        }
+        loop {
+            let mut done = self.done.lock().await;
 
-                        Notify::register_waker(not, ctx.waker());
-                        Poll::Pending
-                    }
-                },
-                _ => Poll::Pending,
+            // Only take a mutable reference when there's something to
+            // take: any mutable access wakes the registered waker, which
+            // would be this task, and keep it spinning
+            if !done.is_empty() {
+                info!("Received new message for local service");
+                return done.pop_front().unwrap();
             }
-        })
-        .await
+
+            trace!("No new frames; registering waker");
+            park(done, |mut done, waker| replace_waker(&mut *done, waker)).await;
+        }
     }
 
     /// Poll for new work on a particular frame sequence
     pub(super) async fn get(&self, seq: &SeqId) -> Frame {
-        let incoming = Arc::clone(&self.incoming);
-        future::poll_fn(|ctx| {
-            let lock = &mut incoming.lock();
-            match unsafe { Pin::new_unchecked(lock).poll(ctx) } {
-                Poll::Ready(ref mut map) => match map.get_mut(seq) {
-                    Some(ref mut vec) if vec.len() > 0 => Poll::Ready(vec.pop_front().unwrap()),
-                    Some(ref mut vec) => {
-                        Notify::register_waker(vec, ctx.waker());
-                        Poll::Pending
-                    }
-                    None => unimplemented!(), // No work queue _should_ never happen
-                },
-                _ => Poll::Pending,
+        loop {
+            let mut map = self.incoming.lock().await;
+            match map.get_mut(seq) {
+                Some(ref mut vec) if vec.len() > 0 => return vec.pop_front().unwrap(),
+                Some(_) => {}
+                None => unimplemented!(), // No work queue _should_ never happen
             }
-        })
-        .await
+
+            park(map, |mut map, waker| {
+                map.get_mut(seq)
+                    .map(|vec| replace_waker(vec, waker))
+                    .unwrap_or_default()
+            })
+            .await;
+        }
     }
 
     /// Yield a finished message to the state
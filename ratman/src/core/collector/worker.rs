@@ -1,7 +1,7 @@
 //! The collector worker
 
 use super::{Locked, State};
-use crate::{Message, Payload};
+use crate::{clock::Clock, Message, Payload};
 use async_std::sync::Arc;
 use netmod::{Frame, SeqBuilder, SeqId};
 
@@ -13,14 +13,17 @@ pub(super) struct Worker {
     buf: Locked<Vec<Frame>>,
     /// Collector reference for control flow
     parent: Arc<State>,
+    /// Clock used to timestamp received messages
+    clock: Clock,
 }
 
 impl Worker {
     /// Create a new collector task for a collector parent
-    pub(super) fn new(seq: SeqId, parent: Arc<State>) -> Self {
+    pub(super) fn new(seq: SeqId, parent: Arc<State>, clock: Clock) -> Self {
         Self {
             seq,
             parent,
+            clock,
             buf: Default::default(),
         }
     }
@@ -33,7 +36,7 @@ impl Worker {
         let mut buf = self.buf.lock().await;
 
         info!("Joining frames");
-        if let Some(msg) = join_frames(&mut buf, frame) {
+        if let Some(msg) = join_frames(&mut buf, frame, &self.clock) {
             self.parent.finish(msg).await;
             None
         } else {
@@ -43,7 +46,7 @@ impl Worker {
 }
 
 /// Utility function that uses the SeqBuilder to rebuild Sequence
-fn join_frames(buf: &mut Vec<Frame>, new: Frame) -> Option<Message> {
+fn join_frames(buf: &mut Vec<Frame>, new: Frame, clock: &Clock) -> Option<Message> {
     // Insert the frame
     buf.push(new);
 
@@ -69,8 +72,8 @@ fn join_frames(buf: &mut Vec<Frame>, new: Frame) -> Option<Message> {
         } = bincode::deserialize(&layered).unwrap();
 
         // Update the received timestamp in the message
-        timesig.receive();
-        
+        timesig.receive_at(clock.now().into());
+
         Some(Message {
             id,
             sender,
@@ -109,7 +112,7 @@ fn join_frame_simple() {
     // The function expects a filling buffer
     let mut buf = vec![];
 
-    assert!(join_frames(&mut buf, seq.remove(0)) == None);
-    assert!(join_frames(&mut buf, seq.remove(1)) == None); // Insert out of order
-    assert!(join_frames(&mut buf, seq.remove(0)).is_some());
+    assert!(join_frames(&mut buf, seq.remove(0), &Clock::system()) == None);
+    assert!(join_frames(&mut buf, seq.remove(1), &Clock::system()) == None); // Insert out of order
+    assert!(join_frames(&mut buf, seq.remove(0), &Clock::system()).is_some());
 }
@@ -0,0 +1,105 @@
+//! Cover traffic between neighbours
+
+use crate::{
+    clock::Clock,
+    core::{DriverMap, RouteTable},
+};
+use async_std::sync::Arc;
+use clockctrl::Scheduler;
+use netmod::{pad, Handshake, Target};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// The average time between two rounds of cover frames
+///
+/// This is scaled by the `Interval::Delay` of the cover task, unless
+/// it's clocked in some other way.
+pub(crate) const INTERVAL: Duration = Duration::from_secs(10);
+
+/// Sends cover frames to all neighbours
+///
+/// In each round, every endpoint is sent one cover frame with a
+/// random amount of data, which is then padded like a regular frame
+/// (see `pad::cover`).  Endpoints with neighbours that don't support
+/// padding are skipped, because no real frames are padded for them
+/// either.
+pub(crate) struct Cover {
+    drivers: Arc<DriverMap>,
+    routes: Arc<RouteTable>,
+    clock: Clock,
+    running: AtomicBool,
+}
+
+impl Cover {
+    pub(crate) fn new(drivers: Arc<DriverMap>, routes: Arc<RouteTable>, clock: Clock) -> Arc<Self> {
+        Arc::new(Self {
+            drivers,
+            routes,
+            clock,
+            running: AtomicBool::new(false),
+        })
+    }
+
+    /// Start sending cover traffic, clocked by the given scheduler
+    ///
+    /// Cover traffic can only be started once, later schedulers are
+    /// ignored.
+    pub(crate) fn run(self: Arc<Self>, sched: Scheduler) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            warn!("Cover traffic is already running");
+            return;
+        }
+
+        let clock = self.clock.clone();
+        clock.spawn(async move {
+            loop {
+                match sched {
+                    Scheduler::Internal(ref b) => {
+                        b.wait().await;
+                    }
+                    // Rounds are jittered, so that they can't be told
+                    // apart by their timing alone
+                    Scheduler::External { delay, .. } => {
+                        let wait = INTERVAL
+                            .mul_f32(delay)
+                            .mul_f32(self.random(50..150) as f32 / 100.0);
+                        self.clock.sleep(wait).await;
+                    }
+                }
+
+                self.round().await;
+            }
+        });
+    }
+
+    /// Send one cover frame to every endpoint
+    async fn round(&self) {
+        let max = pad::BUCKETS[pad::BUCKETS.len() - 1];
+        for (id, ep) in self.drivers.get_all().await.into_iter() {
+            if !self
+                .routes
+                .link_supports(id as u8, None, Handshake::PADDING)
+                .await
+            {
+                continue;
+            }
+
+            let mut data = vec![0; self.random(0..max)];
+            self.clock.fill_bytes(&mut data);
+
+            trace!("Sending cover frame");
+            if ep.send(pad::cover(data), Target::Flood).await.is_err() {
+                warn!("Failed to send cover frame");
+            }
+        }
+    }
+
+    /// Get a random number in a range, via the clock
+    fn random(&self, range: std::ops::Range<usize>) -> usize {
+        let mut buf = [0; 8];
+        self.clock.fill_bytes(&mut buf);
+        range.start + u64::from_le_bytes(buf) as usize % (range.end - range.start)
+    }
+}
@@ -1,21 +1,56 @@
 //! Asynchronous Ratman routing core
 
 use crate::{
+    clock::Clock,
     core::{DriverMap, EpTargetPair, RouteTable},
-    Message, Result, Slicer,
+    Message, Result, Slicer, SLICE_SIZE,
 };
-use async_std::{sync::Arc, task};
-use netmod::{Frame, Recipient, Target};
+use async_std::sync::Arc;
+use netmod::{pad, Frame, Handshake, Recipient, Target};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub(crate) struct Dispatch {
     routes: Arc<RouteTable>,
     drivers: Arc<DriverMap>,
+    clock: Clock,
+    /// Pad all outgoing frames
+    padding: AtomicBool,
 }
 
 impl Dispatch {
     /// Create a new frame dispatcher
-    pub(crate) fn new(routes: Arc<RouteTable>, drivers: Arc<DriverMap>) -> Arc<Self> {
-        Arc::new(Self { routes, drivers })
+    pub(crate) fn new(routes: Arc<RouteTable>, drivers: Arc<DriverMap>, clock: Clock) -> Arc<Self> {
+        Arc::new(Self {
+            routes,
+            drivers,
+            clock,
+            padding: AtomicBool::new(false),
+        })
+    }
+
+    /// Enable or disable frame padding
+    pub(crate) fn set_padding(&self, on: bool) {
+        self.padding.store(on, Ordering::Relaxed);
+    }
+
+    /// Apply padding to a frame sent via an endpoint, if it's enabled
+    ///
+    /// Frames are only padded for links on which all routers support
+    /// padding (with `target` set to `None` for flooded frames), and
+    /// only if they fit into the largest bucket.  Larger frames were
+    /// sliced by a router without padding, and already have the size
+    /// of a full frame.
+    async fn prepare(&self, mut frame: Frame, ep: usize, target: Option<Target>) -> Frame {
+        if self.padding.load(Ordering::Relaxed)
+            && frame.payload.len() < pad::BUCKETS[pad::BUCKETS.len() - 1]
+            && self
+                .routes
+                .link_supports(ep as u8, target, Handshake::PADDING)
+                .await
+        {
+            pad::pad(&mut frame);
+        }
+        frame
     }
 
     pub(crate) async fn send_msg(&self, msg: Message) -> Result<()> {
@@ -26,13 +61,24 @@ impl Dispatch {
         // to the interface we're broadcasting on and we potentially
         // need a way to re-slice, or combine frames that we encounter
         // for better transmission metrics
-        let frames = Slicer::slice(1312, msg);
+        //
+        // Padded frames need one byte for the padding marker, so they
+        // still fit into the largest bucket.
+        let max = if self.padding.load(Ordering::Relaxed) {
+            pad::BUCKETS[pad::BUCKETS.len() - 1] - 1
+        } else {
+            SLICE_SIZE
+        };
+        let frames = Slicer::slice(max, msg);
 
-        frames.into_iter().fold(Ok(()), |res, f| match (res, r) {
-            (Ok(()), Recipient::User(_)) => task::block_on(async move { self.send_one(f).await }),
-            (Ok(()), Recipient::Flood) => task::block_on(async move { self.flood(f).await }),
-            (res, _) => res,
-        })
+        for f in frames {
+            match r {
+                Recipient::User(_) => self.send_one(f).await?,
+                Recipient::Flood => self.flood(f).await?,
+            }
+        }
+
+        Ok(())
     }
 
     /// Dispatch a single frame across the network
@@ -47,13 +93,16 @@ impl Dispatch {
             .unwrap();
 
         let ep = self.drivers.get(epid as usize).await;
+        let frame = self.prepare(frame, epid as usize, Some(trgt)).await;
         Ok(ep.send(frame, trgt).await?)
     }
 
     pub(crate) async fn flood(&self, frame: Frame) -> Result<()> {
-        for ep in self.drivers.get_all().await.into_iter() {
-            let f = frame.clone();
-            ep.send(f, Target::Flood).await.unwrap();
+        for (id, ep) in self.drivers.get_all().await.into_iter() {
+            let f = self.prepare(frame.clone(), id, None).await;
+            if let Err(e) = ep.send(f, Target::Flood).await {
+                warn!("Failed to flood frame: {}", e);
+            }
         }
 
         Ok(())
@@ -61,9 +110,13 @@ impl Dispatch {
 
     /// Reflood a message to the network, except the previous interface
     pub(crate) async fn reflood(&self, frame: Frame, ep: usize) {
-        for ep in self.drivers.get_without(ep).await.into_iter() {
-            let f = frame.clone();
-            task::spawn(async move { ep.send(f, Target::Flood).await.unwrap() });
+        for (id, ep) in self.drivers.get_without(ep).await.into_iter() {
+            let f = self.prepare(frame.clone(), id, None).await;
+            self.clock.spawn(async move {
+                if let Err(e) = ep.send(f, Target::Flood).await {
+                    warn!("Failed to reflood frame: {}", e);
+                }
+            });
         }
     }
 }
@@ -56,24 +56,25 @@ impl DriverMap {
         })
     }
 
-    /// Get access to all endpoints wrapped in Arc
-    pub(crate) async fn get_all(&self) -> Vec<Arc<Ep>> {
+    /// Get access to all endpoints wrapped in Arc, with their IDs
+    pub(crate) async fn get_all(&self) -> Vec<(usize, Arc<Ep>)> {
         let map = self.map.read().await;
         map.iter()
-            .filter_map(|ep| match ep {
-                EpWrap::Used(ref ep) => Some(Arc::clone(ep)),
+            .enumerate()
+            .filter_map(|(i, ep)| match ep {
+                EpWrap::Used(ref ep) => Some((i, Arc::clone(ep))),
                 _ => None,
             })
             .collect()
     }
 
     /// Get all endpoints, except for the one provided via the ID
-    pub(crate) async fn get_without(&self, not: usize) -> Vec<Arc<Ep>> {
+    pub(crate) async fn get_without(&self, not: usize) -> Vec<(usize, Arc<Ep>)> {
         let map = self.map.read().await;
         map.iter()
             .enumerate()
             .filter_map(|(i, ep)| match ep {
-                EpWrap::Used(ref ep) if i != not => Some(Arc::clone(ep)),
+                EpWrap::Used(ref ep) if i != not => Some((i, Arc::clone(ep))),
                 _ => None,
             })
             .collect()
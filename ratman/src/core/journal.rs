@@ -1,17 +1,43 @@
+use crate::clock::Clock;
 use async_std::sync::{Arc, RwLock};
 use netmod::{Frame, SeqId};
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeSet, VecDeque},
+    time::Duration,
+};
+
+/// How long a flooded frame is remembered
+///
+/// Users are announced every few seconds, so without an expiry the
+/// journal would grow for as long as the router runs.  A frame that
+/// comes back after this time is flooded again.
+pub(crate) const TTL: Duration = Duration::from_secs(60);
+
+/// A frame in a sequence
+type FrameId = (SeqId, u32);
+
+#[derive(Default)]
+struct Pages {
+    known: BTreeSet<FrameId>,
+    /// When frames were first seen, oldest first
+    order: VecDeque<(Duration, FrameId)>,
+}
 
 /// Remote frame journal
 pub(crate) struct Journal {
     /// Keeps track of known frames to do reflood
-    known: RwLock<BTreeSet<SeqId>>,
+    ///
+    /// All frames of a message share a sequence ID, so frames are
+    /// identified by their number in the sequence as well.
+    known: RwLock<Pages>,
+    clock: Clock,
 }
 
 impl Journal {
-    pub(crate) fn new() -> Arc<Self> {
+    pub(crate) fn new(clock: Clock) -> Arc<Self> {
         Arc::new(Self {
             known: Default::default(),
+            clock,
         })
     }
 
@@ -23,13 +49,69 @@ impl Journal {
     /// Add a new frame to the known set
     pub(crate) async fn queue(&self, _: Frame) {}
 
-    /// Save a FrameID in the known journal page
-    pub(crate) async fn save(&self, fid: &SeqId) {
-        self.known.write().await.insert(fid.clone());
+    /// Save a frame in the known journal page
+    ///
+    /// Frames older than `TTL` are forgotten at the same time.
+    pub(crate) async fn save(&self, f: &Frame) {
+        let now = self.clock.elapsed();
+        let mut pages = self.known.write().await;
+
+        while let Some((seen, id)) = pages.order.front().cloned() {
+            if now - seen < TTL {
+                break;
+            }
+            pages.order.pop_front();
+            pages.known.remove(&id);
+        }
+
+        let id = (f.seq.seqid, f.seq.num);
+        if pages.known.insert(id) {
+            pages.order.push_back((now, id));
+        }
+    }
+
+    /// Checks if a frame has not been seen before
+    pub(crate) async fn unknown(&self, f: &Frame) -> bool {
+        !self
+            .known
+            .read()
+            .await
+            .known
+            .contains(&(f.seq.seqid, f.seq.num))
     }
 
-    /// Checks if a frame ID has not been seen before
-    pub(crate) async fn unknown(&self, fid: &SeqId) -> bool {
-        !self.known.read().await.contains(fid)
+    #[cfg(test)]
+    async fn len(&self) -> usize {
+        self.known.read().await.known.len()
     }
 }
+
+#[test]
+fn expire_frames() {
+    use crate::{clock::Simulation, Identity};
+    use netmod::{Recipient, SeqBuilder};
+
+    let sim = Simulation::new(1312);
+    let clock = sim.clock();
+    let announce = Duration::from_secs(2);
+
+    sim.block_on(async move {
+        let j = Journal::new(clock.clone());
+        let id = Identity::random();
+
+        // Announce a user for ten minutes
+        for _ in 0..300 {
+            let f = SeqBuilder::new(id, Recipient::Flood, Identity::random())
+                .add(vec![1, 3, 1, 2])
+                .build()
+                .remove(0);
+            assert!(j.unknown(&f).await);
+            j.save(&f).await;
+            assert!(!j.unknown(&f).await);
+            clock.sleep(announce).await;
+        }
+
+        let max = (TTL.as_secs() / announce.as_secs()) as usize;
+        assert!(j.len().await <= max);
+    });
+}
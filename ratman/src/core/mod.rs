@@ -5,6 +5,7 @@
 //! instead the core has been split into several parts.
 
 mod collector;
+mod cover;
 mod dispatch;
 mod drivers;
 mod journal;
@@ -12,15 +13,17 @@ mod routes;
 mod switch;
 
 pub(self) use collector::Collector;
+pub(self) use cover::Cover;
 pub(self) use dispatch::Dispatch;
 pub(self) use drivers::DriverMap;
 pub(self) use journal::Journal;
 pub(self) use routes::{EpTargetPair, RouteTable, RouteType};
 pub(self) use switch::Switch;
 
-use crate::{Endpoint, Error, Identity, Message, Result};
+use crate::{clock::Clock, Endpoint, Error, Identity, Message, Result};
 use async_std::sync::Arc;
-use netmod::Frame;
+use clockctrl::Scheduler;
+use netmod::{Frame, Handshake};
 
 /// The Ratman routing core interface
 ///
@@ -28,22 +31,25 @@ use netmod::Frame;
 /// be delivered at that time (delay-tolerance).
 pub(crate) struct Core {
     collector: Arc<Collector>,
+    cover: Arc<Cover>,
     dispatch: Arc<Dispatch>,
     _journal: Arc<Journal>,
     routes: Arc<RouteTable>,
     switch: Arc<Switch>,
     drivers: Arc<DriverMap>,
+    clock: Clock,
 }
 
 impl Core {
     /// Initialises, but doesn't run the routing core
-    pub(crate) fn init() -> Self {
+    pub(crate) fn init(clock: Clock, proto: Handshake) -> Self {
         let drivers = DriverMap::new();
-        let routes = RouteTable::new();
-        let _journal = Journal::new();
+        let routes = RouteTable::new(clock.clone());
+        let _journal = Journal::new(clock.clone());
 
-        let dispatch = Dispatch::new(Arc::clone(&routes), Arc::clone(&drivers));
-        let collector = Collector::new();
+        let dispatch = Dispatch::new(Arc::clone(&routes), Arc::clone(&drivers), clock.clone());
+        let collector = Collector::new(clock.clone());
+        let cover = Cover::new(Arc::clone(&drivers), Arc::clone(&routes), clock.clone());
 
         let switch = Switch::new(
             Arc::clone(&routes),
@@ -51,6 +57,8 @@ impl Core {
             Arc::clone(&dispatch),
             Arc::clone(&collector),
             Arc::clone(&drivers),
+            clock.clone(),
+            proto,
         );
 
         // Dispatch the runners
@@ -58,15 +66,22 @@ impl Core {
         Arc::clone(&_journal).run();
 
         Self {
+            cover,
             dispatch,
             routes,
             collector,
             _journal,
             switch,
             drivers,
+            clock,
         }
     }
 
+    /// Get access to the clock that drives this core
+    pub(crate) fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
     /// Asynchronously send a Message
     pub(crate) async fn send(&self, msg: Message) -> Result<()> {
         self.dispatch.send_msg(msg).await
@@ -81,6 +96,16 @@ impl Core {
         self.dispatch.flood(f).await
     }
 
+    /// Enable or disable padding for all outgoing frames
+    pub(crate) fn padding(&self, on: bool) {
+        self.dispatch.set_padding(on);
+    }
+
+    /// Start sending cover traffic on all endpoints
+    pub(crate) fn cover(&self, sched: Scheduler) {
+        Arc::clone(&self.cover).run(sched);
+    }
+
     /// Poll for the incoming Message
     pub(crate) async fn next(&self) -> Message {
         self.collector.completed().await
@@ -91,10 +116,10 @@ impl Core {
         if local {
             self.routes.local(id).await
         } else {
-            self.routes
-                .resolve(id)
-                .await
-                .map_or(Err(Error::NoUser), |_| Ok(()))
+            match self.routes.reachable(id).await {
+                Some(RouteType::Remote(_)) => Ok(()),
+                _ => Err(Error::NoUser),
+            }
         }
     }
 
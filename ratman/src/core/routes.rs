@@ -1,12 +1,12 @@
 //! Routing table module
 
-use crate::{Error, IoPair, Result};
-use async_std::{
-    sync::{channel, Arc, Mutex},
-    task,
-};
+use crate::{clock::Clock, Error, IoPair, Result};
+use async_std::sync::{channel, Arc, Mutex};
 use std::collections::BTreeMap;
-use {identity::Identity, netmod::Target};
+use {
+    identity::Identity,
+    netmod::{Handshake, Target},
+};
 
 /// A netmod endpoint ID and an endpoint target ID
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,14 +26,19 @@ pub(crate) enum RouteType {
 /// changes, but these are not carried between sessions.
 pub(crate) struct RouteTable {
     routes: Arc<Mutex<BTreeMap<Identity, RouteType>>>,
+    /// The handshakes that remote users were announced with
+    protos: Mutex<BTreeMap<Identity, Handshake>>,
     new: IoPair<Identity>,
+    clock: Clock,
 }
 
 impl RouteTable {
-    pub(crate) fn new() -> Arc<Self> {
+    pub(crate) fn new(clock: Clock) -> Arc<Self> {
         Arc::new(Self {
             routes: Default::default(),
+            protos: Default::default(),
             new: channel(1),
+            clock,
         })
     }
 
@@ -41,14 +46,26 @@ impl RouteTable {
     ///
     /// If the Id was not previously known to the router, it is queued
     /// to the `new` set which can be polled by calling `discovered().await`.
-    pub(crate) async fn update(self: &Arc<Self>, if_: u8, t: Target, id: Identity) {
+    pub(crate) async fn update(
+        self: &Arc<Self>,
+        if_: u8,
+        t: Target,
+        id: Identity,
+        proto: Handshake,
+    ) {
         let mut tbl = self.routes.lock().await;
         let route = RouteType::Remote(EpTargetPair(if_, t));
 
+        // Announcements for a local user never replace its route
+        if let Some(RouteType::Local) = tbl.get(&id) {
+            return;
+        }
+        self.protos.lock().await.insert(id, proto);
+
         // Only "announce" a new user if it was not known before
         if tbl.insert(id, route).is_none() {
             let s = Arc::clone(&self);
-            task::spawn(async move { s.new.0.send(id).await });
+            self.clock.spawn(async move { s.new.0.send(id).await });
         }
     }
 
@@ -58,10 +75,18 @@ impl RouteTable {
     }
 
     /// Track a local ID in the routes table
+    ///
+    /// A remote route to the same ID is replaced, which happens when
+    /// an identity is moved to this device.
     pub(crate) async fn add_local(&self, id: Identity) -> Result<()> {
-        match self.routes.lock().await.insert(id, RouteType::Local) {
-            Some(_) => Err(Error::DuplicateUser),
-            None => Ok(()),
+        let mut tbl = self.routes.lock().await;
+        match tbl.get(&id) {
+            Some(RouteType::Local) => Err(Error::DuplicateUser),
+            _ => {
+                tbl.insert(id, RouteType::Local);
+                self.protos.lock().await.remove(&id);
+                Ok(())
+            }
         }
     }
 
@@ -75,6 +100,7 @@ impl RouteTable {
 
     /// Delete an entry from the routing table
     pub(crate) async fn delete(&self, id: Identity) -> Result<()> {
+        self.protos.lock().await.remove(&id);
         match self.routes.lock().await.remove(&id) {
             Some(_) => Ok(()),
             None => Err(Error::NoUser),
@@ -99,6 +125,36 @@ impl RouteTable {
         }
     }
 
+    /// Check if all routers behind a link support a capability
+    ///
+    /// A link is an endpoint and a target on it, or the whole endpoint
+    /// if `target` is `None` (for frames that are flooded on it).
+    /// This is decided by the handshakes of the users currently routed
+    /// via the link, so a link on which no user was announced yet
+    /// doesn't support anything.
+    pub(crate) async fn link_supports(&self, if_: u8, target: Option<Target>, cap: u32) -> bool {
+        let routes = self.routes.lock().await;
+        let protos = self.protos.lock().await;
+
+        let users = routes.iter().filter_map(|(id, route)| match route {
+            RouteType::Remote(EpTargetPair(ep, t))
+                if *ep == if_ && target.map_or(true, |target| target == *t) =>
+            {
+                Some(id)
+            }
+            _ => None,
+        });
+
+        let mut any = false;
+        for id in users {
+            if !protos.get(id).map_or(false, |p| p.supports(cap)) {
+                return false;
+            }
+            any = true;
+        }
+        any
+    }
+
     /// Check if an ID is reachable via currently known routes
     pub(crate) async fn reachable(&self, id: Identity) -> Option<RouteType> {
         self.routes.lock().await.get(&id).cloned()
@@ -1,12 +1,12 @@
-use async_std::{
-    sync::{channel, Arc},
-    task,
-};
-use netmod::Recipient;
+use async_std::sync::{channel, Arc, Mutex};
+use netmod::{pad, Handshake, Recipient};
+use std::collections::BTreeSet;
 
 use crate::{
+    clock::Clock,
     core::{Collector, Dispatch, DriverMap, Journal, RouteTable, RouteType},
-    IoPair, Protocol,
+    protocol::Announce,
+    Identity, IoPair, Protocol,
 };
 
 /// A frame switch inside Ratman to route packets and signals
@@ -24,6 +24,12 @@ pub(crate) struct Switch {
     dispatch: Arc<Dispatch>,
     collector: Arc<Collector>,
     drivers: Arc<DriverMap>,
+    clock: Clock,
+
+    /// The protocol handshake of this router
+    proto: Handshake,
+    /// Users whose routers were found to be incompatible
+    rejected: Mutex<BTreeSet<Identity>>,
 
     /// Control channel to start new endpoints
     ctrl: IoPair<usize>,
@@ -37,6 +43,8 @@ impl Switch {
         dispatch: Arc<Dispatch>,
         collector: Arc<Collector>,
         drivers: Arc<DriverMap>,
+        clock: Clock,
+        proto: Handshake,
     ) -> Arc<Self> {
         Arc::new(Self {
             routes,
@@ -44,6 +52,9 @@ impl Switch {
             dispatch,
             collector,
             drivers,
+            clock,
+            proto,
+            rejected: Mutex::new(BTreeSet::new()),
             ctrl: channel(1),
         })
     }
@@ -55,10 +66,11 @@ impl Switch {
 
     /// Dispatches a long-running task to run the switching logic
     pub(crate) fn run(self: Arc<Self>) {
-        task::spawn(async move {
+        let clock = self.clock.clone();
+        clock.spawn(async move {
             while let Some(i) = self.ctrl.1.recv().await {
                 let switch = Arc::clone(&self);
-                task::spawn(switch.run_inner(i));
+                self.clock.spawn(switch.run_inner(i));
             }
         });
     }
@@ -66,21 +78,31 @@ impl Switch {
     async fn run_inner(self: Arc<Self>, id: usize) {
         let ep = self.drivers.get(id).await;
         loop {
-            let (f, t) = match ep.next().await {
+            let (mut f, t) = match ep.next().await {
                 Ok(f) => f,
                 _ => continue,
             };
 
             trace!("Receiving frame...");
 
+            // Padding is only re-applied when the frame is sent on.
+            // Frames that don't match their signature are broken
+            // beyond repair.
+            if !pad::strip(&mut f) {
+                trace!("Dropping invalid frame");
+                continue;
+            }
+
             // Switch the traffic to the appropriate place
             use {Recipient::*, RouteType::*};
             match f.recipient {
                 Flood => {
-                    let seqid = f.seq.seqid;
-                    if self.journal.unknown(&seqid).await {
-                        if let Some(sender) = Protocol::is_announce(&f) {
-                            self.routes.update(id as u8, t, sender).await;
+                    if self.journal.unknown(&f).await {
+                        self.journal.save(&f).await;
+                        if let Some(ann) = Protocol::is_announce(&f) {
+                            if self.compatible(&ann).await {
+                                self.routes.update(id as u8, t, ann.id, ann.proto).await;
+                            }
                         } else {
                             self.collector.queue_and_spawn(f.seqid(), f.clone()).await;
                         }
@@ -90,11 +112,36 @@ impl Switch {
                 }
                 User(id) => match self.routes.reachable(id).await {
                     Some(Local) => self.collector.queue_and_spawn(f.seqid(), f).await,
-                    Some(Remote(_)) => self.dispatch.send_one(f).await.unwrap(),
+                    Some(Remote(_)) => {
+                        if let Err(e) = self.dispatch.send_one(f).await {
+                            warn!("Failed to forward frame: {:?}", e);
+                        }
+                    }
                     None => self.journal.queue(f).await,
                 },
             }
+        }
+    }
+
+    /// Check if an announced user's router speaks a common protocol
+    ///
+    /// Announcements of incompatible routers are still reflooded, but
+    /// their users aren't added to the routing table.
+    async fn compatible(&self, ann: &Announce) -> bool {
+        if self.proto.negotiate(&ann.proto).is_some() {
+            return true;
+        }
 
+        if self.rejected.lock().await.insert(ann.id) {
+            warn!(
+                "Ignoring user `{}`: their router speaks protocol versions {} to {}, we speak {} to {}",
+                ann.id,
+                ann.proto.min_version,
+                ann.proto.version,
+                self.proto.min_version,
+                self.proto.version
+            );
         }
+        false
     }
 }
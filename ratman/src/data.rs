@@ -1,3 +1,4 @@
+use crate::clock::Clock;
 use chrono::{DateTime, Utc};
 use identity::Identity;
 use netmod::Recipient;
@@ -20,16 +21,26 @@ pub struct TimePair {
 
 impl TimePair {
     /// A utility function to create a new sending timestamp
+    ///
+    /// Inside a simulation, the time is taken from the simulation
+    /// clock (see `Clock::current`).
     pub fn sending() -> Self {
-        Self {
-            sent: Utc::now(),
-            recv: None,
-        }
+        Self::sending_at(Clock::current().now().into())
+    }
+
+    /// Create a sending timestamp for a particular point in time
+    pub fn sending_at(sent: DateTime<Utc>) -> Self {
+        Self { sent, recv: None }
     }
 
     /// Update the received time in a timestamp locally received
     pub fn receive(&mut self) {
-        self.recv = Some(Utc::now());
+        self.receive_at(Clock::current().now().into());
+    }
+
+    /// Update the received time to a particular point in time
+    pub(crate) fn receive_at(&mut self, recv: DateTime<Utc>) {
+        self.recv = Some(recv);
     }
 
     /// A test function to strip the recv-time
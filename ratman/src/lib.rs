@@ -156,10 +156,17 @@ pub use crate::{
 pub use identity::{Identity, ID_LEN};
 pub use netmod;
 
+/// The largest payload of a frame sent by a router, in bytes
+///
+/// Messages are sliced into frames of this size, regardless of the
+/// endpoint they are sent on.  Endpoints need to be able to send
+/// frames of this size, either directly or by splitting them up.
+pub const SLICE_SIZE: usize = 1312;
+
 use crate::core::Core;
 use async_std::sync::{Arc, Receiver, Sender};
-use clock::{ClockCtrl, Tasks};
-use netmod::Endpoint;
+use clock::{Clock, ClockCtrl, Scheduler, Tasks};
+use netmod::{Endpoint, Handshake};
 
 /// Primary async ratman router handle
 ///
@@ -179,8 +186,29 @@ impl Router {
     /// state, which means that all routing tables are lost when the
     /// router is stopped.
     pub fn new() -> Arc<Self> {
-        let proto = Protocol::new();
-        let inner = Arc::new(Core::init());
+        Self::with_clock(Clock::system())
+    }
+
+    /// Create a new and empty message router on a custom clock
+    ///
+    /// All internal tasks are spawned via this clock, and all
+    /// timestamps are taken from it.  Check the [`clock`] module docs
+    /// on how to run routers in a deterministic simulation.
+    ///
+    /// [`clock`]: clock/index.html
+    pub fn with_clock(clock: Clock) -> Arc<Self> {
+        Self::with_handshake(clock, Handshake::current())
+    }
+
+    /// Create a new and empty message router with a custom handshake
+    ///
+    /// The handshake is sent with every announcement, and users
+    /// announced by routers with an incompatible handshake are
+    /// ignored.  This is mostly useful to test how networks of mixed
+    /// router versions behave.
+    pub fn with_handshake(clock: Clock, handshake: Handshake) -> Arc<Self> {
+        let proto = Protocol::new(handshake);
+        let inner = Arc::new(Core::init(clock, handshake));
 
         Arc::new(Self { inner, proto })
     }
@@ -247,18 +275,73 @@ impl Router {
     }
 
     /// Check the local routing table for a user ID
+    ///
+    /// Returns `Error::NoUser` if the ID isn't known, or belongs to a
+    /// local user.
     pub async fn known(&self, id: Identity) -> Result<()> {
         self.inner.known(id, false).await
     }
 
+    /// Check if a user ID was added to this router as a local user
+    ///
+    /// Returns `Error::NoUser` if the ID isn't known, or belongs to a
+    /// remote user.
+    pub async fn local(&self, id: Identity) -> Result<()> {
+        self.inner.known(id, true).await
+    }
+
     /// Check for newly discovered users on the network
     pub async fn discover(&self) -> Identity {
         self.inner.discover().await
     }
 
+    /// Pad all frames sent by this router to a few fixed sizes
+    ///
+    /// This makes it harder to tell what kind of data is being sent
+    /// (for example voice, or a file transfer) from the sizes of the
+    /// frames on the network.  Padding is removed again by the next
+    /// router on the path.  Routers that don't support padding (see
+    /// `Handshake::PADDING`) can't read padded messages addressed to
+    /// them, but still forward them.
+    ///
+    /// Check the `netmod::pad` module for details.
+    pub fn padding(&self, on: bool) {
+        self.inner.padding(on);
+    }
+
     /// Register a manual clock controller object for internal tasks
-    pub fn clock(&self, _cc: ClockCtrl<Tasks>) -> Result<()> {
-        unimplemented!()
+    ///
+    /// Currently only the `Tasks::Cover` target is used, which enables
+    /// cover traffic: random padded frames that are sent to all
+    /// neighbours that support padding, and dropped by them.  Calling
+    /// this more than once doesn't start a second cover task.  Cover
+    /// traffic hides when, and how much real traffic is sent, at the
+    /// cost of bandwidth and battery, which can be bounded by the
+    /// clock settings.  An `Interval::Delay` needs to be positive and
+    /// finite, otherwise `Error::InitFailed` is returned.
+    ///
+    /// ```
+    /// # use ratman::{Router, clock::{ClockCtrl, Interval, Tasks}};
+    /// # use std::time::Duration;
+    /// let r = Router::new();
+    /// r.padding(true);
+    ///
+    /// let mut cc = ClockCtrl::new();
+    /// cc.setup(Tasks::Cover)
+    ///     .set(Interval::Timed(Duration::from_secs(30)));
+    /// r.clock(cc).unwrap();
+    /// ```
+    pub fn clock(&self, mut cc: ClockCtrl<Tasks>) -> Result<()> {
+        match cc.start(Tasks::Cover) {
+            Ok(Scheduler::External { delay, .. }) if !(delay > 0.0 && delay.is_finite()) => {
+                return Err(Error::InitFailed)
+            }
+            Ok(sched) => self.inner.cover(sched),
+            Err(clock::Error::NoTarget) => {}
+            Err(_) => return Err(Error::InitFailed),
+        }
+
+        Ok(())
     }
 
     /// Dispatch a message into a network
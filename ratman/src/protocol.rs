@@ -7,17 +7,19 @@
 //!
 //! - `Announce` is sent when a node comes online
 //! - `Sync` is a reply to an `Announce`, only omitted when `no_sync` is set
+//!
+//! Announcements carry the protocol `Handshake` of the sending
+//! router.  Routers that were built before this was introduced send
+//! the same announcement without it, which is still accepted.
 
 use crate::{
+    clock::Clock,
     error::{Error, Result},
     Core,
 };
-use async_std::{
-    sync::{Arc, Mutex},
-    task,
-};
-use identity::Identity;
-use netmod::Frame;
+use async_std::sync::{Arc, Mutex};
+use identity::{Identity, ID_LEN};
+use netmod::{Frame, Handshake, Recipient, SeqBuilder};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::BTreeMap,
@@ -29,18 +31,44 @@ use std::{
 #[derive(Debug, Serialize, Deserialize)]
 enum ProtoPayload {
     /// A network-wide announcement message
+    Announce {
+        id: Identity,
+        no_sync: bool,
+        proto: Handshake,
+    },
+}
+
+/// The protocol payload layout before handshakes were introduced
+///
+/// New fields are only ever appended, which older routers ignore.
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+enum LegacyPayload {
     Announce { id: Identity, no_sync: bool },
 }
 
+/// An announcement received from the network
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Announce {
+    /// The user that is being announced
+    pub(crate) id: Identity,
+    /// The protocol handshake of the announcing router
+    pub(crate) proto: Handshake,
+}
+
 /// Provide a builder API to construct different types of Messages
 #[derive(Default)]
 pub(crate) struct Protocol {
     online: Mutex<BTreeMap<Identity, Arc<AtomicBool>>>,
+    proto: Handshake,
 }
 
 impl Protocol {
-    pub(crate) fn new() -> Arc<Self> {
-        Default::default()
+    pub(crate) fn new(proto: Handshake) -> Arc<Self> {
+        Arc::new(Self {
+            proto,
+            ..Default::default()
+        })
     }
 
     /// Dispatch a task to announce a user periodically
@@ -57,11 +85,14 @@ impl Protocol {
         map.insert(id, Arc::clone(&b));
         drop(map);
 
-        task::spawn(async move {
+        let clock = core.clock().clone();
+        clock.clone().spawn(async move {
             loop {
                 trace!("Sending announcement `{}`", id);
-                core.raw_flood(Self::announce(id)).await.unwrap();
-                task::sleep(Duration::from_secs(2)).await;
+                core.raw_flood(Self::announce(id, self.proto, &clock))
+                    .await
+                    .unwrap();
+                clock.sleep(Duration::from_secs(2)).await;
 
                 if !b.load(Ordering::Relaxed) && break {}
             }
@@ -84,24 +115,73 @@ impl Protocol {
     }
 
     /// Try to parse a frame as an announcement
-    pub(crate) fn is_announce(f: &Frame) -> Option<Identity> {
+    pub(crate) fn is_announce(f: &Frame) -> Option<Announce> {
         let Frame { ref payload, .. } = f;
 
-        bincode::deserialize(payload)
-            .map(|p| match p {
-                ProtoPayload::Announce { id, .. } => id,
-            })
-            .ok()
+        match bincode::deserialize(payload) {
+            Ok(ProtoPayload::Announce { id, proto, .. }) => Some(Announce { id, proto }),
+            Err(_) => bincode::deserialize(payload)
+                .map(|p| match p {
+                    LegacyPayload::Announce { id, .. } => Announce {
+                        id,
+                        proto: Handshake::LEGACY,
+                    },
+                })
+                .ok(),
+        }
     }
 
     /// Build an announcement message for a user
-    fn announce(sender: Identity) -> Frame {
+    ///
+    /// The sequence ID is generated via the clock, so that it is
+    /// reproducible in a simulation.
+    fn announce(sender: Identity, proto: Handshake, clock: &Clock) -> Frame {
         let payload = bincode::serialize(&ProtoPayload::Announce {
             id: sender,
             no_sync: true,
+            proto,
         })
         .unwrap();
 
-        Frame::inline_flood(sender, payload)
+        let mut seqid = [0; ID_LEN];
+        clock.fill_bytes(&mut seqid);
+
+        SeqBuilder::new(sender, Recipient::Flood, seqid.into())
+            .add(payload)
+            .build()
+            .remove(0)
+    }
+}
+
+#[test]
+fn legacy_announce() {
+    #[derive(Serialize, Deserialize)]
+    enum OldPayload {
+        Announce { id: Identity, no_sync: bool },
+    }
+
+    let id = Identity::random();
+    let clock = Clock::system();
+    let proto = Handshake::current();
+    let new = Protocol::announce(id, proto, &clock);
+    assert_eq!(Protocol::is_announce(&new), Some(Announce { id, proto }));
+
+    let payload = bincode::serialize(&OldPayload::Announce { id, no_sync: true }).unwrap();
+    let old = SeqBuilder::new(id, Recipient::Flood, Identity::random())
+        .add(payload)
+        .build()
+        .remove(0);
+    assert_eq!(
+        Protocol::is_announce(&old),
+        Some(Announce {
+            id,
+            proto: Handshake::LEGACY
+        })
+    );
+
+    // Older routers can still read new announcements
+    let parsed: OldPayload = bincode::deserialize(&new.payload).unwrap();
+    match parsed {
+        OldPayload::Announce { id: parsed, .. } => assert_eq!(parsed, id),
     }
 }
@@ -53,3 +53,75 @@ async fn announce_and_discover() -> Result<()> {
     assert_eq!(r1.discover().await, u3);
     Ok(())
 }
+
+/// Flood messages that span several frames share one sequence ID, but
+/// every frame needs to be passed on
+#[async_std::test]
+async fn flood_large_message() -> Result<()> {
+    use async_std::future::timeout;
+    use ratman::{Message, MsgId, Recipient, TimePair};
+    use std::time::Duration;
+
+    let mm1 = MemMod::new();
+    let mm2_1 = MemMod::new();
+    let mm2_3 = MemMod::new();
+    let mm3 = MemMod::new();
+    mm1.link(&mm2_1);
+    mm2_3.link(&mm3);
+
+    let r1 = Router::new();
+    let r2 = Router::new();
+    let r3 = Router::new();
+    r1.add_endpoint(mm1).await;
+    r2.add_endpoint(mm2_1).await;
+    r2.add_endpoint(mm2_3).await;
+    r3.add_endpoint(mm3).await;
+
+    let u1 = Identity::random();
+    r1.add_user(u1).await?;
+    let u3 = Identity::random();
+    r3.add_user(u3).await?;
+
+    let msg = Message {
+        id: MsgId::random(),
+        sender: u1,
+        recipient: Recipient::Flood,
+        payload: vec![7; 4000],
+        timesig: TimePair::sending(),
+        sign: vec![],
+    };
+    r1.send(msg.clone()).await?;
+
+    let recv = timeout(Duration::from_secs(5), r3.next()).await.unwrap();
+    assert_eq!(recv.payload, msg.payload);
+    Ok(())
+}
+
+/// A user that moves to a router which only knew it as remote becomes
+/// local there, and later announcements don't route it away again
+#[async_std::test]
+async fn local_replaces_remote() -> Result<()> {
+    let (mm1, mm2) = MemMod::make_pair();
+    let r1 = Router::new();
+    let r2 = Router::new();
+    r1.add_endpoint(mm1).await;
+    r2.add_endpoint(mm2).await;
+
+    let u1 = Identity::random();
+    r1.add_user(u1).await?;
+    r1.online(u1).await?;
+
+    assert_eq!(r2.discover().await, u1);
+    assert!(r2.known(u1).await.is_ok());
+    assert!(r2.local(u1).await.is_err());
+
+    r2.add_user(u1).await?;
+    assert!(r2.local(u1).await.is_ok());
+    assert!(r2.known(u1).await.is_err());
+    assert!(r2.add_user(u1).await.is_err());
+
+    // r1 keeps announcing the user
+    task::sleep(std::time::Duration::from_secs(3)).await;
+    assert!(r2.local(u1).await.is_ok());
+    Ok(())
+}
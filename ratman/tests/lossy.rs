@@ -0,0 +1,72 @@
+//! Announcements over bad links
+//!
+//! Real networks lose frames, deliver them late, and out of order.
+//! Because announcements are repeated periodically, users should
+//! still be discovered eventually, even if a lot of them are lost on
+//! the way.
+
+use async_std::future;
+use netmod_mem::{LinkConfig, MemMod};
+use ratman::{Identity, Result, Router};
+use std::time::Duration;
+
+#[async_std::test]
+async fn announce_over_lossy_links() -> Result<()> {
+    let cfg = LinkConfig::default()
+        .latency(Duration::from_millis(10))
+        .jitter(Duration::from_millis(20))
+        .drop_rate(0.3)
+        .seed(1312);
+
+    let (mm1, mm2_1) = MemMod::make_pair_with(cfg.clone());
+    let (mm2_3, mm3) = MemMod::make_pair_with(cfg.seed(1337));
+
+    let r1 = Router::new();
+    let r2 = Router::new();
+    let r3 = Router::new();
+
+    r1.add_endpoint(mm1).await;
+    r2.add_endpoint(mm2_1).await;
+    r2.add_endpoint(mm2_3).await;
+    r3.add_endpoint(mm3).await;
+
+    let u1 = Identity::random();
+    r1.add_user(u1).await?;
+
+    let u3 = Identity::random();
+    r3.add_user(u3).await?;
+
+    r1.online(u1).await?;
+    r3.online(u3).await?;
+
+    assert_eq!(r1.discover().await, u3);
+    Ok(())
+}
+
+#[async_std::test]
+async fn announce_after_heal() -> Result<()> {
+    let (mm1, mm2) = MemMod::make_pair();
+    mm1.partition().await;
+
+    let r1 = Router::new();
+    let r2 = Router::new();
+    r1.add_endpoint(mm1.clone()).await;
+    r2.add_endpoint(mm2).await;
+
+    let u1 = Identity::random();
+    r1.add_user(u1).await?;
+    let u2 = Identity::random();
+    r2.add_user(u2).await?;
+
+    r1.online(u1).await?;
+    r2.online(u2).await?;
+
+    // Nothing can be discovered while the link is down
+    assert!(future::timeout(Duration::from_millis(100), r1.discover())
+        .await
+        .is_err());
+
+    mm1.heal().await;
+    assert_eq!(r1.discover().await, u2);
+    Ok(())
+}
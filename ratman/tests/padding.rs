@@ -0,0 +1,219 @@
+//! Frame padding and cover traffic
+//!
+//! A router with padding enabled rounds all frame payloads up to a
+//! few fixed sizes, and a router clocked with `Tasks::Cover` sends
+//! cover frames to its neighbours.  Both only happen on links to
+//! routers that announced the `PADDING` capability, and neither may
+//! change what the receiving router delivers.
+
+use async_std::{future::timeout, sync::Mutex, task};
+use async_trait::async_trait;
+use netmod_mem::MemMod;
+use ratman::{
+    clock::{Clock, ClockCtrl, Interval, Tasks},
+    netmod::{pad, Endpoint, Frame, Handshake, Result as NetResult, Target},
+    Error, Identity, Message, MsgId, Recipient, Result, Router, TimePair,
+};
+use std::{sync::Arc, time::Duration};
+
+/// An endpoint that keeps a copy of every frame it receives
+struct Tap {
+    inner: Arc<MemMod>,
+    seen: Mutex<Vec<Frame>>,
+}
+
+impl Tap {
+    fn new(inner: Arc<MemMod>) -> Arc<Self> {
+        Arc::new(Self {
+            inner,
+            seen: Default::default(),
+        })
+    }
+
+    async fn seen(&self) -> Vec<Frame> {
+        self.seen.lock().await.clone()
+    }
+}
+
+#[async_trait]
+impl Endpoint for Tap {
+    fn size_hint(&self) -> usize {
+        self.inner.size_hint()
+    }
+
+    async fn send(&self, frame: Frame, target: Target) -> NetResult<()> {
+        self.inner.send(frame, target).await
+    }
+
+    async fn next(&self) -> NetResult<(Frame, Target)> {
+        let (f, t) = self.inner.next().await?;
+        self.seen.lock().await.push(f.clone());
+        Ok((f, t))
+    }
+}
+
+/// Check if a frame carries padding that can be removed
+fn padded(f: &Frame) -> bool {
+    let mut stripped = f.clone();
+    pad::strip(&mut stripped) && stripped.payload.len() < f.payload.len()
+}
+
+fn message(sender: Identity, recp: Identity, len: usize) -> Message {
+    Message {
+        id: MsgId::random(),
+        sender,
+        recipient: Recipient::User(recp),
+        payload: vec![42; len],
+        timesig: TimePair::sending(),
+        sign: vec![],
+    }
+}
+
+fn cover_every(dur: Duration) -> ClockCtrl<Tasks> {
+    let mut cc = ClockCtrl::new();
+    cc.setup(Tasks::Cover).set(Interval::Timed(dur));
+    cc
+}
+
+/// Connect a padding router to a peer with the given handshake
+///
+/// All frames the peer receives from the padding router are tapped.
+async fn link(peer: Handshake) -> (Arc<Router>, Arc<Router>, Arc<Tap>) {
+    let (m1, m2) = MemMod::make_pair();
+    let r1 = Router::new();
+    let r2 = Router::with_handshake(Clock::system(), peer);
+    let tap = Tap::new(m2);
+    r1.padding(true);
+    r1.add_endpoint(m1).await;
+    r2.add_endpoint(Arc::clone(&tap)).await;
+    (r1, r2, tap)
+}
+
+fn legacy() -> Handshake {
+    Handshake {
+        caps: 0,
+        ..Handshake::current()
+    }
+}
+
+#[async_std::test]
+async fn padded_frames() -> Result<()> {
+    let (r1, r2, tap) = link(Handshake::current()).await;
+
+    // Nothing is padded before the peer announced that it can strip
+    // padding again
+    let u1 = Identity::random();
+    r1.add_user(u1).await?;
+    r1.online(u1).await?;
+    let _ = r2.discover().await;
+    assert!(!tap.seen().await.iter().any(padded));
+
+    let u2 = Identity::random();
+    r2.add_user(u2).await?;
+    r2.online(u2).await?;
+    assert_eq!(r1.discover().await, u2);
+
+    // Spans several frames, the last of which is mostly padding
+    let msg = message(u1, u2, 3000);
+    r1.send(msg.clone()).await?;
+
+    let recv = timeout(Duration::from_secs(5), r2.next()).await.unwrap();
+    assert_eq!(recv.payload, msg.payload);
+    assert!(tap.seen().await.iter().any(padded));
+    Ok(())
+}
+
+#[async_std::test]
+async fn legacy_peer() -> Result<()> {
+    let (r1, r2, tap) = link(legacy()).await;
+
+    let (u1, u2) = (Identity::random(), Identity::random());
+    r1.add_user(u1).await?;
+    r2.add_user(u2).await?;
+    r1.online(u1).await?;
+    r2.online(u2).await?;
+    assert_eq!(r1.discover().await, u2);
+
+    // A router without padding support gets every frame as it is
+    let msg = message(u1, u2, 4000);
+    r1.send(msg.clone()).await?;
+
+    let recv = timeout(Duration::from_secs(5), r2.next()).await.unwrap();
+    assert_eq!(recv.payload, msg.payload);
+    assert!(!tap.seen().await.iter().any(padded));
+    Ok(())
+}
+
+#[async_std::test]
+async fn cover_traffic() -> Result<()> {
+    let (r1, r2, tap) = link(Handshake::current()).await;
+    r1.clock(cover_every(Duration::from_millis(20)))?;
+
+    let u2 = Identity::random();
+    r2.add_user(u2).await?;
+    r2.online(u2).await?;
+    assert_eq!(r1.discover().await, u2);
+    task::sleep(Duration::from_millis(500)).await;
+
+    // Cover frames are valid padded frames, which the peer drops
+    let seen = tap.seen().await;
+    assert!(!seen.is_empty());
+    assert!(seen.iter().all(padded));
+    assert!(timeout(Duration::from_millis(200), r2.next())
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[async_std::test]
+async fn no_cover_for_legacy_peers() -> Result<()> {
+    let (r1, r2, tap) = link(legacy()).await;
+    r1.clock(cover_every(Duration::from_millis(20)))?;
+
+    let u2 = Identity::random();
+    r2.add_user(u2).await?;
+    r2.online(u2).await?;
+    assert_eq!(r1.discover().await, u2);
+    task::sleep(Duration::from_millis(500)).await;
+
+    assert!(tap.seen().await.is_empty());
+    Ok(())
+}
+
+#[async_std::test]
+async fn cover_is_dropped() -> Result<()> {
+    let (r1, r2, _) = link(Handshake::current()).await;
+    r2.padding(true);
+    r1.clock(cover_every(Duration::from_millis(20)))?;
+
+    let (u1, u2) = (Identity::random(), Identity::random());
+    r1.add_user(u1).await?;
+    r2.add_user(u2).await?;
+    r1.online(u1).await?;
+    r2.online(u2).await?;
+    assert_eq!(r1.discover().await, u2);
+
+    // Only the real message is delivered
+    let msg = message(u1, u2, 64);
+    r1.send(msg.clone()).await?;
+    let recv = timeout(Duration::from_secs(5), r2.next()).await.unwrap();
+    assert_eq!(recv.payload, msg.payload);
+    assert!(timeout(Duration::from_millis(500), r2.next())
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[async_std::test]
+async fn invalid_cover_delay() {
+    let r = Router::new();
+    for delay in vec![0.0, -1.0, std::f32::NAN, std::f32::INFINITY] {
+        let mut cc = ClockCtrl::new();
+        cc.setup(Tasks::Cover).set(Interval::Delay(delay));
+        assert!(matches!(r.clock(cc), Err(Error::InitFailed)));
+    }
+
+    let mut cc = ClockCtrl::new();
+    cc.setup(Tasks::Cover).set(Interval::Delay(0.5));
+    assert!(r.clock(cc).is_ok());
+}
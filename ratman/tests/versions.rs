@@ -0,0 +1,60 @@
+//! Routers with different protocol versions on the same network
+//!
+//! Every router sends its protocol `Handshake` with its announcements.
+//! Routers that have a protocol version in common discover each
+//! other's users, while users of incompatible routers are ignored.
+
+use async_std::future::timeout;
+use netmod_mem::MemMod;
+use ratman::{clock::Clock, netmod::Handshake, Identity, Result, Router};
+use std::{sync::Arc, time::Duration};
+
+/// Connect two routers and announce a user on each of them
+async fn pair(
+    a: Handshake,
+    b: Handshake,
+) -> Result<(Arc<Router>, Identity, Arc<Router>, Identity)> {
+    let (m1, m2) = MemMod::make_pair();
+    let r1 = Router::with_handshake(Clock::system(), a);
+    let r2 = Router::with_handshake(Clock::system(), b);
+    r1.add_endpoint(m1).await;
+    r2.add_endpoint(m2).await;
+
+    let (u1, u2) = (Identity::random(), Identity::random());
+    r1.add_user(u1).await?;
+    r2.add_user(u2).await?;
+    r1.online(u1).await?;
+    r2.online(u2).await?;
+    Ok((r1, u1, r2, u2))
+}
+
+#[async_std::test]
+async fn older_compatible_router() -> Result<()> {
+    let newer = Handshake {
+        version: Handshake::VERSION + 1,
+        min_version: Handshake::VERSION,
+        caps: 0,
+    };
+    let (r1, u1, r2, u2) = pair(newer, Handshake::current()).await?;
+
+    assert_eq!(r1.discover().await, u2);
+    assert_eq!(r2.discover().await, u1);
+    Ok(())
+}
+
+#[async_std::test]
+async fn incompatible_router() -> Result<()> {
+    let newer = Handshake {
+        version: Handshake::VERSION + 1,
+        min_version: Handshake::VERSION + 1,
+        caps: 0,
+    };
+    let (r1, _, r2, u2) = pair(newer, Handshake::current()).await?;
+
+    // Announcements are sent every two seconds
+    let wait = Duration::from_secs(3);
+    assert!(timeout(wait, r1.discover()).await.is_err());
+    assert!(timeout(wait, r2.discover()).await.is_err());
+    assert!(r1.known(u2).await.is_err());
+    Ok(())
+}
@@ -1,17 +1,23 @@
 use crate::{
-    core::{Session, SessionsApi},
+    core::{Session, SessionsApi, GLOBAL},
+    crypto::{asym::KeyPair, CipherText, Encrypter},
     delta::{DeltaBuilder, DeltaType},
     dir::Dirs,
-    error::Result,
+    error::{Error, Result},
     meta::{tags::TagCache, users::UserTable},
     query::{Query, QueryIterator, QueryResult, SetQuery, SubHub, Subscription},
+    record::Record,
     store::Store,
     utils::{Diff, Id, Path, TagSet},
+    wire::Encoder,
 };
 use async_std::sync::{Arc, RwLock};
-use std::fmt::Debug;
+use std::{fmt::Debug, path::Path as StdPath};
 use tracing::info;
 
+/// Used to derive the key for the global scope from the root secret
+pub(crate) const ROOT_SALT: &str = "alexandria.root";
+
 /// In-memory representation of an alexandria database
 ///
 /// Refer to [`Builder`][builder] to configure and initialise an alexandria
@@ -19,8 +25,10 @@ use tracing::info;
 ///
 /// [builder]: struct.Builder.html
 pub struct Library {
-    // /// The main management path
-    // pub(crate) root: Dirs,
+    /// The main management path, if the library is persistent
+    pub(crate) root: Option<Dirs>,
+    /// The key for records in the global scope
+    pub(crate) key: Arc<KeyPair>,
     /// Table with encrypted user metadata
     pub(crate) users: RwLock<UserTable>,
     /// Cache of tag/path mappings
@@ -34,17 +42,216 @@ pub struct Library {
 impl Library {
     /// Internally called setup function
     pub(crate) fn init(self) -> Result<Self> {
-        // self.root.scaffold()?;
+        // A new library is empty, but writing it out right away means
+        // that it can be loaded again, even if nothing is ever added
+        if let Some(ref root) = self.root {
+            root.scaffold()?;
+            root.write(root.users(), &UserTable::new().snapshot()?)?;
+
+            let check = ROOT_SALT.as_bytes().to_vec();
+            root.write(root.root_check(), &self.key.seal(&check)?.encode()?)?;
+        }
+
         Ok(self)
     }
 
     /// Load and re-initialise a previous database session from disk
-    pub fn load<'tmp, P, S>(_: P, _: S) -> Result<Self>
+    ///
+    /// The `root_sec` needs to be the same secret that was given to
+    /// the [`Builder`][builder] when the library was created.  All
+    /// sessions start out closed, and their records are only loaded
+    /// when they are opened again.
+    ///
+    /// [builder]: struct.Builder.html#method.root_sec
+    pub fn load<'tmp, P, S>(offset: P, root_sec: S) -> Result<Self>
     where
-        P: Into<&'tmp Path>,
+        P: Into<&'tmp StdPath>,
         S: Into<String>,
     {
-        unimplemented!()
+        let p: &StdPath = offset.into();
+        let root = Dirs::new(p);
+        if !root.exists() {
+            return Err(Error::InitFailed {
+                offset: p.to_string_lossy().into(),
+            });
+        }
+
+        let users = match root.read(root.users())? {
+            Some(data) => UserTable::load(&data)?,
+            None => UserTable::new(),
+        };
+
+        // The root secret is checked up front, even if there are no
+        // records in the global scope that would fail to decrypt
+        let key = Arc::new(KeyPair::from_pw(&root_sec.into(), ROOT_SALT));
+        if let Some(data) = root.read(root.root_check())? {
+            let check: Result<Vec<u8>> = key.open(&CipherText::decode(&data)?);
+            if check.ok().as_deref() != Some(ROOT_SALT.as_bytes()) {
+                return Err(Error::UnlockFailed { id: "root".into() });
+            }
+        }
+
+        let mut store = Store::new();
+        let mut tag_cache = TagCache::new();
+        let paths = store
+            .restore(GLOBAL, &root.read_dir(root.session(GLOBAL))?, &key)
+            .map_err(|_| Error::UnlockFailed { id: "root".into() })?;
+        Self::cache_tags(&mut tag_cache, GLOBAL, paths)?;
+
+        info!("Loaded library from `{}`", p.display());
+        Ok(Self {
+            root: Some(root),
+            key,
+            users: RwLock::new(users),
+            tag_cache: RwLock::new(tag_cache),
+            store: RwLock::new(store),
+            subs: SubHub::new(),
+        })
+    }
+
+    /// Write a single record of a session to disk
+    ///
+    /// Each record is kept in its own file, so only the record that
+    /// was changed needs to be written.  If there is no record at the
+    /// path anymore, its file is removed.  This doesn't do anything
+    /// for libraries without an `offset`.
+    pub(crate) async fn sync(&self, store: &Store, id: Session, path: &Path) -> Result<()> {
+        let root = match self.root {
+            Some(ref root) => root,
+            None => return Ok(()),
+        };
+
+        let key = match id.id() {
+            Some(id) => self.users.read().await.key(id)?,
+            None => Arc::clone(&self.key),
+        };
+
+        let file = Dirs::record(root.session(id), path);
+        match store.snapshot(id, path, key)? {
+            Some(data) => root.write(file, &data),
+            None => root.remove(file),
+        }
+    }
+
+    /// Write a changed record to disk, or undo the change
+    ///
+    /// `prev` is the record as it was before the change, and is put
+    /// back into the store if it can't be written, so that memory and
+    /// disk don't diverge.
+    async fn commit(
+        &self,
+        store: &mut Store,
+        id: Session,
+        path: &Path,
+        prev: Option<Arc<Record>>,
+    ) -> Result<()> {
+        let res = self.sync(store, id, path).await;
+        if res.is_err() {
+            store.reset(id, path, prev);
+        }
+        res
+    }
+
+    /// Remove a single record of a session from disk
+    pub(crate) fn unsync(&self, id: Session, path: &Path) -> Result<()> {
+        match self.root {
+            Some(ref root) => root.remove(Dirs::record(root.session(id), path)),
+            None => Ok(()),
+        }
+    }
+
+    /// Change the password and record key of an open user session
+    ///
+    /// All records of the session are written again with the new
+    /// key, into a staging directory that replaces the old records
+    /// once the user table was written.  If that fails, the previous
+    /// keys are restored.
+    pub(crate) async fn rekey(&self, id: Id, old: &str, new: &str) -> Result<()> {
+        // Hold on to the store so nothing is written with the old key
+        let store = self.store.write().await;
+        let mut users = self.users.write().await;
+        let prev = users.rekey(id, old, new)?;
+
+        let root = match self.root {
+            Some(ref root) => root,
+            None => return Ok(()),
+        };
+
+        let session = Session::Id(id);
+        let staging = root.staging(session);
+        let res = root
+            .remove_dir(staging.clone())
+            .and_then(|_| store.snapshot_all(session, users.key(id)?))
+            .and_then(|records| {
+                records.into_iter().try_for_each(|(path, data)| {
+                    root.write(Dirs::record(staging.clone(), &path), &data)
+                })
+            })
+            .and_then(|_| self.sync_users(&users));
+
+        match res {
+            Ok(()) => root.replace_dir(staging, root.session(session)),
+            Err(e) => {
+                users.set_keys(id, prev)?;
+                self.sync_users(&users)?;
+                root.remove_dir(staging)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Write the user table to disk
+    pub(crate) fn sync_users(&self, users: &UserTable) -> Result<()> {
+        match self.root {
+            Some(ref root) => root.write(root.users(), &users.snapshot()?),
+            None => Ok(()),
+        }
+    }
+
+    /// Load the records of a session that was just opened
+    pub(crate) async fn load_session(&self, id: Session) -> Result<()> {
+        let root = match self.root {
+            Some(ref root) => root,
+            None => return Ok(()),
+        };
+
+        let mut store = self.store.write().await;
+        if store.loaded(id) {
+            return Ok(());
+        }
+
+        // If a password change was interrupted after the user table
+        // was written, the re-encrypted records are still staged
+        let key = self.users.read().await.key(id.id().unwrap())?;
+        let paths = match store.restore(id, &root.read_dir(root.session(id))?, &key) {
+            Err(e) if root.staging(id).exists() => {
+                let paths = store
+                    .restore(id, &root.read_dir(root.staging(id))?, &key)
+                    .map_err(|_| e)?;
+                root.replace_dir(root.staging(id), root.session(id))?;
+                paths
+            }
+            res => res?,
+        };
+        Self::cache_tags(&mut *self.tag_cache.write().await, id, paths)
+    }
+
+    /// Remove the records of a session from disk
+    pub(crate) fn remove_session(&self, id: Session) -> Result<()> {
+        match self.root {
+            Some(ref root) => root
+                .remove_dir(root.session(id))
+                .and_then(|_| root.remove_dir(root.staging(id))),
+            None => Ok(()),
+        }
+    }
+
+    fn cache_tags(tc: &mut TagCache, id: Session, paths: Vec<(Path, TagSet)>) -> Result<()> {
+        paths.into_iter().fold(Ok(()), |res, (path, tags)| {
+            tags.iter().fold(res, |res, t| {
+                res.and_then(|_| tc.insert(id, path.clone(), t.clone()))
+            })
+        })
     }
 
     /// Load the database sessions API scope
@@ -75,6 +282,7 @@ impl Library {
             tags.clone(),
             data.into_iter().map(|d| d.into()).collect(),
         )?;
+        self.commit(&mut store, id, &path, None).await?;
         drop(store);
 
         let mut tc = self.tag_cache.write().await;
@@ -109,6 +317,7 @@ impl Library {
 
         let mut store = self.store.write().await;
         let rec_id = store.insert(&mut db, id, &path, tags.clone(), data.into())?;
+        self.commit(&mut store, id, &path, None).await?;
         drop(store);
 
         let mut tc = self.tag_cache.write().await;
@@ -132,7 +341,13 @@ impl Library {
 
         let mut db = DeltaBuilder::new(id, DeltaType::Delete);
 
+        // The record is removed from disk first, so that it's not
+        // deleted from memory only.  It might still be held by an
+        // iterator, but it's gone as far as the disk is concerned.
         let mut store = self.store.write().await;
+        if store.record(id, &path).is_some() {
+            self.unsync(id, &path)?;
+        }
         store.destroy(&mut db, id, &path)?;
         drop(store);
 
@@ -160,7 +375,9 @@ impl Library {
         let mut db = DeltaBuilder::new(id, DeltaType::Update);
 
         let mut store = self.store.write().await;
+        let prev = store.record(id, &path);
         store.update(&mut db, id, &path, diff.into())?;
+        self.commit(&mut store, id, &path, prev).await?;
         drop(store);
 
         self.subs.queue(db.make()).await;
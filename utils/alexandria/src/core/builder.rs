@@ -1,6 +1,8 @@
 use crate::{
+    core::api::ROOT_SALT,
+    crypto::asym::KeyPair,
     dir::Dirs,
-    error::Result,
+    error::{Error, Result},
     meta::{tags::TagCache, users::UserTable},
     query::SubHub,
     store::Store,
@@ -28,10 +30,14 @@ use std::{path::Path, result::Result as StdResult};
 /// # drop(lib);
 /// # Ok(()) }
 /// ```
+///
+/// A library that is built without an `offset` only lives in memory.
 #[derive(Default)]
 pub struct Builder {
     /// The main offset path
     offset: Option<String>,
+    /// The secret for the root namespace
+    root_sec: Option<String>,
 }
 
 impl Builder {
@@ -43,36 +49,25 @@ impl Builder {
     ///
     /// If no library exists at the path yet (or the path doesn't
     /// exist), the `Err(_)` variant is a new builder with an
-    /// initialised `offset` that can then be used to create a new
-    /// database.
-    pub fn inspect_path<'tmp, P, S>(offset: P, _: S) -> StdResult<Arc<Library>, Self>
+    /// initialised `offset` and root secret that can then be used to
+    /// create a new database.
+    ///
+    /// The same is true if a library exists, but can't be loaded
+    /// (for example because the root secret is wrong).  Building
+    /// this builder will then fail, instead of overwriting the
+    /// existing library.
+    pub fn inspect_path<'tmp, P, S>(offset: P, root_sec: S) -> StdResult<Arc<Library>, Self>
     where
         P: Into<&'tmp Path>,
         S: Into<String>,
     {
         let p: &Path = offset.into();
+        let root_sec = root_sec.into();
 
-        // If the path doesn't exist it can't be a database
-        if !p.exists() {
-            return Err(Self::new().offset(p));
+        match Library::load(p, root_sec.as_str()) {
+            Ok(lib) => Ok(Arc::new(lib)),
+            Err(_) => Err(Self::new().offset(p).root_sec(root_sec)),
         }
-
-        // TODO: Check for a magic file here
-        // TODO: load database with provided root secret
-
-        let root = Dirs::new(p);
-        let users = RwLock::new(UserTable::new());
-        let tag_cache = RwLock::new(TagCache::new());
-
-        let store = RwLock::new(Store::new());
-        let subs = SubHub::new();
-        Ok(Arc::new(Library {
-            // root,
-            users,
-            tag_cache,
-            store,
-            subs,
-        }))
     }
 
     /// Specify a normal path offset
@@ -92,23 +87,38 @@ impl Builder {
     /// secret will have to be provided to [`Library::load()`][load]
     ///
     /// [load]: struct.Library.html#load
-    pub fn root_sec<S: Into<String>>(self, _: S) -> Self {
-        self
+    pub fn root_sec<S: Into<String>>(self, root_sec: S) -> Self {
+        Self {
+            root_sec: Some(root_sec.into()),
+            ..self
+        }
     }
 
     /// Consume the builder and create a Library
+    ///
+    /// Fails if the `offset` already contains a library.
     pub fn build(self) -> Result<Arc<Library>> {
-        // let root = Dirs::new(
-        //     self.offset
-        //         .expect("Builder without `offset` cannot be built"),
-        // );
+        if let Some(ref offset) = self.offset {
+            if Dirs::new(offset).exists() {
+                return Err(Error::InitFailed {
+                    offset: offset.clone(),
+                });
+            }
+        }
+        let root = self.offset.map(Dirs::new);
+
+        let key = Arc::new(KeyPair::from_pw(
+            self.root_sec.as_deref().unwrap_or(""),
+            ROOT_SALT,
+        ));
         let users = RwLock::new(UserTable::new());
         let tag_cache = RwLock::new(TagCache::new());
 
         let store = RwLock::new(Store::new());
         let subs = SubHub::new();
         Library {
-            // root,
+            root,
+            key,
             users,
             tag_cache,
             store,
@@ -118,3 +128,10 @@ impl Builder {
         .map(|l| Arc::new(l))
     }
 }
+
+#[test]
+fn build_twice() {
+    let dir = tempfile::tempdir().unwrap();
+    Builder::new().offset(dir.path()).build().unwrap();
+    assert!(Builder::new().offset(dir.path()).build().is_err());
+}
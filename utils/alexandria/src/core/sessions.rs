@@ -60,9 +60,21 @@ impl<'alex> SessionsApi<'alex> {
     }
 
     /// Open a previously created session
+    ///
+    /// For a library that was loaded from disk, this is also when
+    /// the records of the session are loaded.  Opening a session
+    /// that is already open fails with `Error::AlreadyUnlocked`.
     pub async fn open(&self, id: Id, pw: &str) -> Result<Session> {
-        let ref mut u = self.inner.users.write().await;
-        u.open(id, pw).map(|_| Session::Id(id))
+        let mut u = self.inner.users.write().await;
+        u.open(id, pw)?;
+        drop(u);
+
+        // Don't leave the user open if the records can't be read
+        if let Err(e) = self.inner.load_session(Session::Id(id)).await {
+            self.inner.users.write().await.close(id)?;
+            return Err(e);
+        }
+        Ok(Session::Id(id))
     }
 
     /// Close an active session
@@ -78,14 +90,36 @@ impl<'alex> SessionsApi<'alex> {
     /// Create a new session with a unique encryption key
     pub async fn create(&self, id: Id, pw: &str) -> Result<Session> {
         let ref mut u = self.inner.users.write().await;
-        u.insert(id, pw).map(|_| Session::Id(id))
+        u.insert(id, pw)?;
+        if let Err(e) = self.inner.sync_users(u) {
+            u.delete(id)?;
+            return Err(e);
+        }
+        Ok(Session::Id(id))
+    }
+
+    /// Change the password of a session
+    ///
+    /// The session is opened with the `old` password, if it wasn't
+    /// open yet.  Its records are re-encrypted with a new key, which
+    /// means that the old password can't be used to read them anymore.
+    pub async fn change_pw(&self, id: Id, old: &str, new: &str) -> Result<()> {
+        if self.inner.users.read().await.is_open(id).is_err() {
+            self.open(id, old).await?;
+        }
+
+        self.inner.rekey(id, old, new).await
     }
 
     /// Remove a session Id and corresponding data from the database
     pub async fn destroy(&self, id: Session) -> Result<()> {
-        if let Some(id) = id.id() {
+        if let Some(uid) = id.id() {
             let ref mut u = self.inner.users.write().await;
-            u.delete(id)
+            u.delete(uid)?;
+            self.inner.sync_users(u)?;
+            self.inner.store.write().await.remove_session(uid);
+            self.inner.tag_cache.write().await.remove_session(uid);
+            self.inner.remove_session(id)
         } else {
             Ok(())
         }
@@ -5,8 +5,9 @@ use crate::{
     error::{Error, Result},
     wire::Encoder,
 };
+use keybob::{Key as KeyBuilder, KeyType};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use sodiumoxide::crypto::box_::{self, Nonce, PublicKey, SecretKey};
+use sodiumoxide::crypto::box_::{self, Nonce, PublicKey, SecretKey, Seed};
 
 pub(crate) type SharedKey = KeyPair;
 
@@ -23,6 +24,14 @@ impl KeyPair {
         let (pub_, sec) = box_::gen_keypair();
         Self { pub_, sec }
     }
+
+    /// Derive a keypair from a password and salt
+    pub(crate) fn from_pw(pw: &str, salt: &str) -> Self {
+        // An "Aes128" key is 32 bytes long, which is also the size of a seed
+        let kb = KeyBuilder::from_pw(KeyType::Aes128, pw, salt);
+        let (pub_, sec) = box_::keypair_from_seed(&Seed::from_slice(kb.as_slice()).unwrap());
+        Self { pub_, sec }
+    }
 }
 
 impl<T> Encrypter<T> for KeyPair
@@ -55,6 +64,18 @@ where
     }
 }
 
+#[test]
+fn derived_keys() {
+    let k1 = KeyPair::from_pw("car horse battery staple", "alexandria");
+    let k2 = KeyPair::from_pw("car horse battery staple", "alexandria");
+    assert_eq!(k1.pub_, k2.pub_);
+
+    let data: Vec<u8> = vec![1, 3, 1, 2];
+    let ctext = k1.seal(&data).unwrap();
+    let clear: Vec<u8> = k2.open(&ctext).unwrap();
+    assert_eq!(clear, data);
+}
+
 #[test]
 fn sign_and_encrypt() {
     use ed25519_dalek::Keypair as DKP;
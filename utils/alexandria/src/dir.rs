@@ -1,7 +1,12 @@
 //! Directory helper to create and manage Alexandria instances
 
-use crate::error::Result;
-use std::{fs, path::PathBuf};
+use crate::{
+    error::{Error, Result},
+    utils::Path,
+    Session,
+};
+use sodiumoxide::crypto::hash::sha256;
+use std::{fs, io::ErrorKind, path::PathBuf};
 
 /// Metadata for where things are stored
 pub(crate) struct Dirs {
@@ -16,9 +21,9 @@ impl Dirs {
 
     pub(crate) fn scaffold(&self) -> Result<()> {
         fs::create_dir_all(&self.root)?;
-        fs::create_dir(self.records())?;
-        fs::create_dir(self.meta())?;
-        fs::create_dir(self.cache())?;
+        fs::create_dir_all(self.records())?;
+        fs::create_dir_all(self.meta())?;
+        fs::create_dir_all(self.cache())?;
         Ok(())
     }
 
@@ -36,6 +41,144 @@ impl Dirs {
     pub(crate) fn cache(&self) -> PathBuf {
         self.root.join("cache")
     }
+
+    /// Check if a library was already created in this directory
+    pub(crate) fn exists(&self) -> bool {
+        self.meta().exists()
+    }
+
+    /// Return the path of the user table
+    pub(crate) fn users(&self) -> PathBuf {
+        self.meta().join("users")
+    }
+
+    /// Return the path of the value that checks the root secret
+    pub(crate) fn root_check(&self) -> PathBuf {
+        self.meta().join("root")
+    }
+
+    /// Return the records directory of a session
+    pub(crate) fn session(&self, id: Session) -> PathBuf {
+        match id.id() {
+            Some(id) => self.records().join(hex::encode(id)),
+            None => self.records().join("global"),
+        }
+    }
+
+    /// Return the directory that a session is re-encrypted into
+    pub(crate) fn staging(&self, id: Session) -> PathBuf {
+        self.session(id).with_extension("new")
+    }
+
+    /// Return the file name of a record in a session directory
+    ///
+    /// Record paths are hashed, so that file names don't need to be
+    /// escaped.
+    pub(crate) fn record(dir: PathBuf, path: &Path) -> PathBuf {
+        let hash = sha256::hash(String::from(path).as_bytes());
+        dir.join(hex::encode(hash))
+    }
+
+    /// Replace a file in the library
+    ///
+    /// The data is written to a temporary file first, so that a
+    /// crash can't leave a half-written file behind.  Missing parent
+    /// directories are created.
+    pub(crate) fn write(&self, path: PathBuf, data: &[u8]) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&tmp, data))
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| Error::SyncFailed {
+                msg: format!("{}", e),
+            })
+    }
+
+    /// Read a file from the library, if it exists
+    pub(crate) fn read(&self, path: PathBuf) -> Result<Option<Vec<u8>>> {
+        match fs::read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read all files in a directory of the library
+    ///
+    /// A directory that doesn't exist is empty.  Temporary files that
+    /// were left behind by an interrupted `write` are skipped.
+    pub(crate) fn read_dir(&self, dir: PathBuf) -> Result<Vec<Vec<u8>>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut files = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().map_or(true, |ext| ext != "tmp") {
+                files.push(fs::read(path)?);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Remove a file from the library, if it exists
+    pub(crate) fn remove(&self, path: PathBuf) -> Result<()> {
+        match fs::remove_file(path) {
+            Err(ref e) if e.kind() != ErrorKind::NotFound => Err(Error::SyncFailed {
+                msg: format!("{}", e),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove a directory and its contents, if it exists
+    pub(crate) fn remove_dir(&self, dir: PathBuf) -> Result<()> {
+        match fs::remove_dir_all(dir) {
+            Err(ref e) if e.kind() != ErrorKind::NotFound => Err(Error::SyncFailed {
+                msg: format!("{}", e),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Replace a directory with another one
+    pub(crate) fn replace_dir(&self, from: PathBuf, to: PathBuf) -> Result<()> {
+        self.remove_dir(to.clone())?;
+        fs::rename(from, to).map_err(|e| Error::SyncFailed {
+            msg: format!("{}", e),
+        })
+    }
+}
+
+#[test]
+fn write_and_read() -> Result<()> {
+    use tempfile::tempdir;
+
+    let root = tempdir().unwrap();
+    let d = Dirs::new(root.path());
+    d.scaffold()?;
+
+    let dir = d.session(Session::Global);
+    let path = Dirs::record(dir.clone(), &Path::from("/test:bob"));
+    assert_eq!(d.read(path.clone())?, None);
+    assert_eq!(d.read_dir(dir.clone())?, Vec::<Vec<u8>>::new());
+
+    d.write(path.clone(), &[1, 3, 1, 2])?;
+    d.write(path.clone(), &[1, 3, 3, 7])?;
+    assert_eq!(d.read(path.clone())?, Some(vec![1, 3, 3, 7]));
+    assert_eq!(d.read_dir(dir.clone())?, vec![vec![1, 3, 3, 7]]);
+
+    d.remove(path.clone())?;
+    d.remove(path.clone())?;
+    assert_eq!(d.read(path)?, None);
+
+    d.remove_dir(dir.clone())?;
+    d.remove_dir(dir)?;
+    Ok(())
 }
 
 #[test]
@@ -109,6 +109,11 @@ impl TagCache {
         Ok(())
     }
 
+    /// Drop the tag mappings of a user session
+    pub(crate) fn remove_session(&mut self, id: Id) {
+        self.map.remove(&id);
+    }
+
     /// Get all paths associated with a tag
     #[tracing::instrument(skip(self, cond), level = "debug")]
     pub(crate) fn get_paths<'tags, F>(&self, id: Session, cond: F) -> Vec<Path>
@@ -10,6 +10,7 @@ use crate::{
     },
     error::{Error, Result},
     utils::Id,
+    wire::Encoder,
 };
 use async_std::sync::Arc;
 use serde::{Deserialize, Serialize};
@@ -54,6 +55,12 @@ impl DetachedKey<Key> for UserWithKey {
     }
 }
 
+/// The keys of a user entry, as they were before a `rekey`
+pub(crate) struct UserKeys {
+    key: Option<Arc<Key>>,
+    records: Arc<KeyPair>,
+}
+
 /// A table of users in the database
 #[derive(Serialize, Deserialize)]
 pub(crate) struct UserTable(EncryptedMap<Hid, UserWithKey, Key>);
@@ -90,14 +97,76 @@ impl UserTable {
     /// The provided Id will be hashed, to corresponds to a `Hid`,
     /// which provides a layer of anonymity for users in the database.
     pub(crate) fn open(&mut self, id: Id, pw: &str) -> Result<()> {
+        if self.is_open(id).is_ok() {
+            return Err(Error::AlreadyUnlocked { id: id.to_string() });
+        }
+
         let k = Key::from_pw(pw, &id.to_string());
-        self.0.open(id, &k)
+        self.0.open(id, &k)?;
+
+        // The key isn't part of the encrypted data, but is needed to
+        // close the entry again
+        self.0.get_mut(id)?.key = Some(Arc::new(k));
+        Ok(())
     }
 
     /// Re-seal the user metadata structure in place
     pub(crate) fn close(&mut self, id: Id) -> Result<()> {
         self.0.close(id, None)
     }
+
+    /// Change the password of an open user, and their record key
+    ///
+    /// The old password needs to match the one that the entry was
+    /// opened with.  Records that were encrypted with the previous
+    /// key need to be written again, with the key from `key()`.
+    pub(crate) fn rekey(&mut self, id: Id, old: &str, new: &str) -> Result<UserKeys> {
+        let entry = self.0.get_mut(id)?;
+        let old_key = Key::from_pw(old, &id.to_string());
+        if entry.key.as_ref().map(|k| **k != old_key).unwrap_or(true) {
+            return Err(Error::UnlockFailed { id: id.to_string() });
+        }
+
+        let prev = UserKeys {
+            key: entry.key.take(),
+            records: Arc::clone(&entry.inner.key),
+        };
+        entry.key = Some(Arc::new(Key::from_pw(new, &id.to_string())));
+        entry.inner.key = Arc::new(KeyPair::new());
+        Ok(prev)
+    }
+
+    /// Restore the keys of a user to what they were before a `rekey`
+    pub(crate) fn set_keys(&mut self, id: Id, keys: UserKeys) -> Result<()> {
+        let entry = self.0.get_mut(id)?;
+        entry.key = keys.key;
+        entry.inner.key = keys.records;
+        Ok(())
+    }
+
+    /// Get the record encryption key of an open user
+    pub(crate) fn key(&self, id: Id) -> Result<Arc<KeyPair>> {
+        self.0.get(id).map(|u| Arc::clone(&u.inner.key))
+    }
+
+    /// Encode the table, with all user entries encrypted
+    pub(crate) fn snapshot(&self) -> Result<Vec<u8>> {
+        let mut closed = EncryptedMap::new();
+        for (id, entry) in self.0.iter() {
+            let mut entry = entry.clone();
+            if !entry.encrypted() {
+                entry.close_detached()?;
+            }
+            closed.insert(*id, entry);
+        }
+
+        Ok(Self(closed).encode()?)
+    }
+
+    /// Load a table from a snapshot, with all users closed
+    pub(crate) fn load(data: &Vec<u8>) -> Result<Self> {
+        Ok(Self::decode(data)?)
+    }
 }
 
 #[test]
@@ -115,3 +184,42 @@ fn create_clone_open() {
     u.close(id).unwrap();
     u.open(id, pw).unwrap();
 }
+
+#[test]
+fn snapshot_and_load() {
+    let mut u = UserTable::new();
+    let id = Id::random();
+    let pw = "car horse battery staple";
+    u.insert(id, pw).unwrap();
+    let key = u.key(id).unwrap();
+
+    let mut loaded = UserTable::load(&u.snapshot().unwrap()).unwrap();
+    assert!(loaded.is_open(id).is_err());
+    assert!(loaded.open(id, "wrong password").is_err());
+
+    loaded.open(id, pw).unwrap();
+    assert!(loaded.open(id, pw).is_err());
+    assert_eq!(
+        format!("{:?}", loaded.key(id).unwrap()),
+        format!("{:?}", key)
+    );
+
+    // Re-opened users can be closed again
+    loaded.close(id).unwrap();
+}
+
+#[test]
+fn rekey() {
+    let mut u = UserTable::new();
+    let id = Id::random();
+    u.insert(id, "old password").unwrap();
+    let key = u.key(id).unwrap();
+
+    assert!(u.rekey(id, "wrong password", "new password").is_err());
+    u.rekey(id, "old password", "new password").unwrap();
+    assert_ne!(format!("{:?}", u.key(id).unwrap()), format!("{:?}", key));
+
+    let mut loaded = UserTable::load(&u.snapshot().unwrap()).unwrap();
+    assert!(loaded.open(id, "old password").is_err());
+    loaded.open(id, "new password").unwrap();
+}
@@ -170,11 +170,12 @@ mod harness {
     use async_std::sync::Arc;
     use hex;
     use rand::{rngs::OsRng, RngCore};
-    use tempfile::tempdir;
+    use tempfile::{tempdir, TempDir};
 
     pub struct TestData {
         lib: Arc<Library>,
         rng: OsRng,
+        _dir: TempDir,
     }
 
     impl TestData {
@@ -184,7 +185,11 @@ mod harness {
             let lib = Builder::new().offset(dir.path()).build().unwrap();
             let rng = OsRng {};
 
-            Self { lib, rng }
+            Self {
+                lib,
+                rng,
+                _dir: dir,
+            }
         }
 
         /// Clone the library Arc
@@ -150,6 +150,21 @@ impl Record {
         }
     }
 
+    /// Create an encrypted copy of this record, to write it to disk
+    pub(crate) fn close(&self, key: Arc<KeyPair>) -> Result<Self> {
+        let mut rec = self.clone();
+        rec.header.sec.close(Arc::clone(&key))?;
+        rec.body.close(key)?;
+        Ok(rec)
+    }
+
+    /// Decrypt a record that was read from disk
+    pub(crate) fn open(mut self, key: &KeyPair) -> Result<Self> {
+        self.header.sec.open(key)?;
+        self.body.open(key)?;
+        Ok(self)
+    }
+
     pub fn kv(&self) -> &Kv {
         match self.body.deref() {
             Ok(Body::Kv(ref kv)) => kv,
@@ -10,12 +10,16 @@ use crate::{
     notify::Notify,
     record::Record,
     utils::{Diff, Id, Path, TagSet},
+    wire::Encoder,
     Session,
 };
 use async_std::sync::Arc;
 use std::collections::BTreeMap;
 use tracing::trace;
 
+/// The records of a single session
+type Tree = BTreeMap<Path, Notify<Encrypted<Arc<Record>, KeyPair>>>;
+
 /// Main data store (mirrored to /records)
 #[derive(Default)]
 pub(crate) struct Store {
@@ -141,6 +145,12 @@ impl Store {
         Ok(rec_id)
     }
 
+    /// Drop all records of a user session from the store
+    pub(crate) fn remove_session(&mut self, id: Id) {
+        self.usrd.remove(&id);
+        self.gc_usr.remove(&id);
+    }
+
     #[tracing::instrument(skip(self, db, path), level = "trace")]
     pub(crate) fn destroy(
         &mut self,
@@ -239,6 +249,108 @@ impl Store {
         })
     }
 
+    /// Check if the records of a session are loaded
+    pub(crate) fn loaded(&self, id: Session) -> bool {
+        match id.id() {
+            Some(ref id) => self.usrd.contains_key(id),
+            None => true,
+        }
+    }
+
+    /// Get the record at a path, without falling back to the shared store
+    pub(crate) fn record(&self, id: Session, path: &Path) -> Option<Arc<Record>> {
+        self.tree(id)
+            .and_then(|t| t.get(path))
+            .and_then(|rec| rec.deref().ok().map(Arc::clone))
+    }
+
+    /// Put back a record that was returned by `record` earlier
+    ///
+    /// This is used to undo a change that couldn't be written to
+    /// disk.  `None` removes the record at the path.
+    pub(crate) fn reset(&mut self, id: Session, path: &Path, prev: Option<Arc<Record>>) {
+        let tree = self.tree_mut(id);
+        match (prev, tree.get_mut(path)) {
+            (Some(mut prev), Some(not)) => not.swap(&mut prev),
+            (Some(prev), None) => {
+                tree.insert(path.clone(), Notify::new(Encrypted::new(prev)));
+            }
+            (None, _) => {
+                tree.remove(path);
+                return;
+            }
+        }
+        self.wake_tree(id, path);
+    }
+
+    /// Encode a record, encrypted with a key
+    ///
+    /// Returns `None` if there is no record at the path (anymore).
+    pub(crate) fn snapshot(
+        &self,
+        id: Session,
+        path: &Path,
+        key: Arc<KeyPair>,
+    ) -> Result<Option<Vec<u8>>> {
+        self.tree(id)
+            .and_then(|t| t.get(path))
+            .map(|rec| Self::seal(path, rec, key))
+            .transpose()
+    }
+
+    /// Encode all records of a session, encrypted with a key
+    pub(crate) fn snapshot_all(
+        &self,
+        id: Session,
+        key: Arc<KeyPair>,
+    ) -> Result<Vec<(Path, Vec<u8>)>> {
+        self.tree(id)
+            .into_iter()
+            .flat_map(|t| t.iter())
+            .map(|(path, rec)| {
+                Self::seal(path, rec, Arc::clone(&key)).map(|data| (path.clone(), data))
+            })
+            .collect()
+    }
+
+    fn seal(
+        path: &Path,
+        rec: &Notify<Encrypted<Arc<Record>, KeyPair>>,
+        key: Arc<KeyPair>,
+    ) -> Result<Vec<u8>> {
+        let rec = rec.deref()?.close(key)?;
+        Ok((path.clone(), rec).encode()?)
+    }
+
+    /// Decrypt and load the records of a session from their snapshots
+    ///
+    /// Returns the paths and tags of all loaded records, so that they
+    /// can be added to the tag cache.
+    pub(crate) fn restore(
+        &mut self,
+        id: Session,
+        data: &[Vec<u8>],
+        key: &KeyPair,
+    ) -> Result<Vec<(Path, TagSet)>> {
+        let records = data
+            .iter()
+            .map(|data| {
+                let (path, rec) = <(Path, Record)>::decode(data)?;
+                rec.open(key).map(|rec| (path, rec))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let tree = self.tree_mut(id);
+        Ok(records
+            .into_iter()
+            .map(|(path, rec)| {
+                let tags = rec.header.tags.clone();
+                tree.insert(path.clone(), Notify::new(Encrypted::new(Arc::new(rec))));
+                (path, tags)
+            })
+            .collect())
+    }
+
     /// A helper to wake a tree, depending on Id
     fn wake_tree(&mut self, id: Session, path: &Path) {
         match id.id() {
@@ -264,6 +376,14 @@ impl Store {
         }
     }
 
+    /// A utility function to get the tree, depending on id
+    fn tree(&self, id: Session) -> Option<&Tree> {
+        match id.id() {
+            Some(ref id) => self.usrd.get(id).map(|t| &**t),
+            None => Some(&self.shared),
+        }
+    }
+
     /// A utility function to get the mutable tree, depending on id
     fn tree_mut(
         &mut self,
@@ -460,6 +580,57 @@ fn store_and_delete() {
     assert_eq!(store.usrd.get(&id).unwrap().len(), 0);
 }
 
+#[test]
+fn snapshot_and_restore() {
+    use crate::{
+        delta::{DeltaBuilder, DeltaType},
+        utils::Tag,
+    };
+
+    let id = Session::Id(Id::random());
+    let key = Arc::new(KeyPair::new());
+    let path = Path::from("/test:bob");
+    let tags = TagSet::from(vec![Tag::empty("test")]);
+
+    let mut store = Store::new();
+    let mut db = DeltaBuilder::new(id, DeltaType::Insert);
+    let rec_id = store
+        .insert(
+            &mut db,
+            id,
+            &path,
+            tags.clone(),
+            Diff::map().insert("hello", "world"),
+        )
+        .unwrap();
+
+    let data = store
+        .snapshot(id, &path, Arc::clone(&key))
+        .unwrap()
+        .unwrap();
+    let data = vec![data];
+    assert!(store
+        .snapshot(id, &Path::from("/test:alice"), Arc::clone(&key))
+        .unwrap()
+        .is_none());
+
+    // The wrong key can't open the records
+    let mut other = Store::new();
+    assert!(other.restore(id, &data, &KeyPair::new()).is_err());
+
+    let mut other = Store::new();
+    assert!(!other.loaded(id));
+    assert_eq!(
+        other.restore(id, &data, &key).unwrap(),
+        vec![(path.clone(), tags)]
+    );
+    assert!(other.loaded(id));
+
+    let rec = other.get_path(id, &path).unwrap();
+    assert_eq!(rec.header.id, rec_id);
+    assert_eq!(rec.kv().len(), 1);
+}
+
 #[test]
 fn insert_batch() {
     use crate::{
@@ -510,3 +681,36 @@ fn insert_batch_single() {
 
     assert_eq!(store.length(GLOBAL), 1);
 }
+
+#[test]
+fn reset_update() {
+    use crate::delta::{DeltaBuilder, DeltaType};
+
+    let id = Session::Id(Id::random());
+    let path = Path::from("/test:bob");
+
+    let mut store = Store::new();
+    let mut db = DeltaBuilder::new(id, DeltaType::Insert);
+    store
+        .insert(
+            &mut db,
+            id,
+            &path,
+            TagSet::empty(),
+            Diff::map().insert("hello", "world"),
+        )
+        .unwrap();
+
+    let prev = store.record(id, &path);
+    let mut db = DeltaBuilder::new(id, DeltaType::Update);
+    store
+        .update(&mut db, id, &path, Diff::map().insert("how", "are you?"))
+        .unwrap();
+    assert_eq!(store.get_path(id, &path).unwrap().kv().len(), 2);
+
+    store.reset(id, &path, prev);
+    assert_eq!(store.get_path(id, &path).unwrap().kv().len(), 1);
+
+    store.reset(id, &path, None);
+    assert!(store.record(id, &path).is_none());
+}
@@ -96,6 +96,36 @@ async fn insert_delete_failed_query() {
         .is_err());
 }
 
+#[async_std::test]
+async fn destroy_and_recreate() {
+    let dir = tempdir().unwrap();
+    let t = Test::new(dir.path(), 1);
+    let lib = t.lib();
+    let user = t.users[0];
+    let id = match user {
+        alexandria::Session::Id(id) => id,
+        alexandria::Session::Global => unreachable!(),
+    };
+
+    let path = Path::from("/msg:alice");
+    let tags = TagSet::from(vec![Tag::empty("msg")]);
+    let diff = Diff::from(("msg_count".into(), DiffSeg::Insert(Value::U64(0))));
+    lib.insert(user, path.clone(), tags.clone(), diff)
+        .await
+        .unwrap();
+
+    lib.sessions().destroy(user).await.unwrap();
+    lib.sessions().create(id, harness::PASS).await.unwrap();
+
+    // Nothing of the old session is left over
+    assert!(lib.query(user, Query::Path(path)).await.is_err());
+    match lib.query(user, Query::tags().subset(tags)).await {
+        Ok(QueryResult::Many(ref vec)) => assert!(vec.is_empty()),
+        Ok(QueryResult::Single(_)) => unreachable!(),
+        Err(_) => {}
+    }
+}
+
 #[async_std::test]
 async fn simple_subscription() {
     let dir = tempdir().unwrap();
@@ -218,3 +248,209 @@ async fn test_path_exists() {
 
     assert!(t.lib().path_exists(t.users[0], path).await.unwrap());
 }
+
+#[async_std::test]
+async fn reload_from_disk() {
+    use alexandria::{Library, Session, GLOBAL};
+
+    let dir = tempdir().unwrap();
+    let t = Test::new(dir.path(), 1);
+    let user = t.users[0];
+
+    let path = Path::from("/msg:alice");
+    let tags = vec![Tag::empty("marked")];
+    let diff = Diff::from(("msg_count".into(), DiffSeg::Insert(Value::U64(0))));
+
+    t.lib()
+        .insert(GLOBAL, path.clone(), tags.clone(), diff.clone())
+        .await
+        .unwrap();
+    t.lib()
+        .insert(user, path.clone(), tags.clone(), diff)
+        .await
+        .unwrap();
+    drop(t);
+
+    assert!(Library::load(dir.path(), "wrong secret").is_err());
+    let lib = Library::load(dir.path(), "").unwrap();
+
+    // Global records and tags are available right away
+    assert_eq!(
+        match lib
+            .query(GLOBAL, Query::tags().subset(tags.clone()))
+            .await
+            .unwrap()
+        {
+            QueryResult::Many(ref vec) => vec.len(),
+            QueryResult::Single(_) => unreachable!(),
+        },
+        1
+    );
+
+    // Session records only once the session is opened again
+    assert!(lib.query(user, Query::Path(path.clone())).await.is_err());
+    let id = match user {
+        Session::Id(id) => id,
+        Session::Global => unreachable!(),
+    };
+    assert!(lib.sessions().open(id, "wrong password").await.is_err());
+    lib.sessions().open(id, harness::PASS).await.unwrap();
+
+    assert_eq!(
+        match lib.query(user, Query::tags().subset(tags)).await.unwrap() {
+            QueryResult::Many(ref vec) => vec.len(),
+            QueryResult::Single(_) => unreachable!(),
+        },
+        1
+    );
+}
+
+#[test]
+fn wrong_root_secret() {
+    use alexandria::{Builder, Library};
+
+    // Even an empty library can't be loaded with the wrong secret
+    let dir = tempdir().unwrap();
+    let lib = Builder::new()
+        .offset(dir.path())
+        .root_sec("root secret")
+        .build()
+        .unwrap();
+    drop(lib);
+
+    assert!(Library::load(dir.path(), "wrong secret").is_err());
+    assert!(Library::load(dir.path(), "root secret").is_ok());
+}
+
+#[async_std::test]
+async fn records_on_disk() {
+    use alexandria::{Library, GLOBAL};
+    use std::fs;
+
+    let dir = tempdir().unwrap();
+    let t = Test::new(dir.path(), 0);
+    let records = dir.path().join("records").join("global");
+    let count = || fs::read_dir(&records).unwrap().count();
+
+    let (alice, bob) = (Path::from("/msg:alice"), Path::from("/msg:bob"));
+    let diff = Diff::from(("msg_count".into(), DiffSeg::Insert(Value::U64(0))));
+    for path in vec![alice.clone(), bob.clone()] {
+        t.lib()
+            .insert(GLOBAL, path, TagSet::empty(), diff.clone())
+            .await
+            .unwrap();
+    }
+    assert_eq!(count(), 2);
+
+    // Every record is kept in its own file
+    let diff = Diff::from(("msg_count".into(), DiffSeg::Update(Value::U64(1))));
+    t.lib().update(GLOBAL, bob.clone(), diff).await.unwrap();
+    assert_eq!(count(), 2);
+
+    t.lib().delete(GLOBAL, alice.clone()).await.unwrap();
+    assert_eq!(count(), 1);
+    drop(t);
+
+    let lib = Library::load(dir.path(), "").unwrap();
+    assert!(lib.query(GLOBAL, Query::Path(alice)).await.is_err());
+    match lib.query(GLOBAL, Query::Path(bob)).await.unwrap() {
+        QueryResult::Single(rec) => assert_eq!(rec.kv().get("msg_count"), Some(&Value::U64(1))),
+        QueryResult::Many(_) => unreachable!(),
+    }
+}
+
+#[async_std::test]
+async fn change_pw_reencrypts() {
+    use alexandria::{Library, Session};
+    use std::fs;
+
+    let dir = tempdir().unwrap();
+    let t = Test::new(dir.path(), 1);
+    let user = t.users[0];
+    let id = match user {
+        Session::Id(id) => id,
+        Session::Global => unreachable!(),
+    };
+
+    let path = Path::from("/msg:alice");
+    let diff = Diff::from(("msg_count".into(), DiffSeg::Insert(Value::U64(0))));
+    t.lib()
+        .insert(user, path.clone(), TagSet::empty(), diff)
+        .await
+        .unwrap();
+
+    // Keep a copy of the user table that still uses the old password
+    let users = dir.path().join("meta").join("users");
+    let old_users = fs::read(&users).unwrap();
+
+    assert!(t
+        .lib()
+        .sessions()
+        .change_pw(id, "wrong password", "new password")
+        .await
+        .is_err());
+    t.lib()
+        .sessions()
+        .change_pw(id, harness::PASS, "new password")
+        .await
+        .unwrap();
+    drop(t);
+
+    let lib = Library::load(dir.path(), "").unwrap();
+    assert!(lib.sessions().open(id, harness::PASS).await.is_err());
+    lib.sessions().open(id, "new password").await.unwrap();
+    assert!(lib.query(user, Query::Path(path.clone())).await.is_ok());
+    drop(lib);
+
+    // The old password unlocks the old table, but not the records
+    fs::write(&users, old_users).unwrap();
+    let lib = Library::load(dir.path(), "").unwrap();
+    assert!(lib.sessions().open(id, harness::PASS).await.is_err());
+}
+
+#[async_std::test]
+async fn interrupted_change_pw() {
+    use alexandria::{Library, Session};
+    use std::fs;
+
+    let dir = tempdir().unwrap();
+    let t = Test::new(dir.path(), 1);
+    let user = t.users[0];
+    let id = match user {
+        Session::Id(id) => id,
+        Session::Global => unreachable!(),
+    };
+
+    let path = Path::from("/msg:alice");
+    let diff = Diff::from(("msg_count".into(), DiffSeg::Insert(Value::U64(0))));
+    t.lib()
+        .insert(user, path.clone(), TagSet::empty(), diff)
+        .await
+        .unwrap();
+
+    let records = fs::read_dir(dir.path().join("records"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| !p.ends_with("global"))
+        .unwrap();
+    let old = fs::read_dir(&records).unwrap().next().unwrap().unwrap();
+    let old = (old.file_name(), fs::read(old.path()).unwrap());
+
+    t.lib()
+        .sessions()
+        .change_pw(id, harness::PASS, "new password")
+        .await
+        .unwrap();
+    drop(t);
+
+    // Pretend that the new records were never moved into place
+    let staging = records.with_extension("new");
+    fs::rename(&records, &staging).unwrap();
+    fs::create_dir(&records).unwrap();
+    fs::write(records.join(old.0), old.1).unwrap();
+
+    let lib = Library::load(dir.path(), "").unwrap();
+    lib.sessions().open(id, "new password").await.unwrap();
+    assert!(lib.query(user, Query::Path(path)).await.is_ok());
+    assert!(!staging.exists());
+}
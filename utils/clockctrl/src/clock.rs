@@ -0,0 +1,163 @@
+use crate::sim::{self, SimInner};
+use async_std::task;
+use rand::RngCore;
+use std::{
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// An injectable source of time for a reactor
+///
+/// A reactor that does all of its sleeping, spawning, time-stamping
+/// (and random number generation) via a `Clock` can be run either
+/// normally, on the async-std runtime and system time, or inside a
+/// deterministic [`Simulation`].
+///
+/// [`Simulation`]: struct.Simulation.html
+#[derive(Clone)]
+pub struct Clock {
+    inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+    System(Instant),
+    Sim(Weak<SimInner>),
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::system()
+    }
+}
+
+impl Debug for Clock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.inner {
+            Inner::System(_) => write!(f, "Clock::System"),
+            Inner::Sim(_) => write!(f, "Clock::Sim"),
+        }
+    }
+}
+
+impl Clock {
+    /// A clock using the system time and the async-std runtime
+    pub fn system() -> Self {
+        Self {
+            inner: Inner::System(Instant::now()),
+        }
+    }
+
+    /// Get the clock of the simulation running on this thread
+    ///
+    /// Outside of `Simulation::block_on` this is the system clock.
+    /// It's meant for code that needs a timestamp, but isn't passed a
+    /// clock, like constructors of data types.
+    pub fn current() -> Self {
+        match sim::current() {
+            Some(sim) => Self::sim(sim),
+            None => Self::system(),
+        }
+    }
+
+    pub(crate) fn sim(sim: Weak<SimInner>) -> Self {
+        Self {
+            inner: Inner::Sim(sim),
+        }
+    }
+
+    /// Return `true` if this clock is driven by a simulation
+    pub fn is_simulated(&self) -> bool {
+        match self.inner {
+            Inner::Sim(_) => true,
+            Inner::System(_) => false,
+        }
+    }
+
+    fn get_sim(sim: &Weak<SimInner>) -> Arc<SimInner> {
+        sim.upgrade()
+            .expect("Clock was used after its Simulation was dropped")
+    }
+
+    /// Get the current (wall-clock) time
+    pub fn now(&self) -> SystemTime {
+        match self.inner {
+            Inner::System(_) => SystemTime::now(),
+            Inner::Sim(ref sim) => Self::get_sim(sim).now(),
+        }
+    }
+
+    /// Get the monotonic time passed since this clock was created
+    pub fn elapsed(&self) -> Duration {
+        match self.inner {
+            Inner::System(start) => start.elapsed(),
+            Inner::Sim(ref sim) => Self::get_sim(sim).elapsed(),
+        }
+    }
+
+    /// Wait for a duration to pass
+    pub async fn sleep(&self, dur: Duration) {
+        match self.inner {
+            Inner::System(_) => task::sleep(dur).await,
+            Inner::Sim(ref sim) => {
+                let sim = Self::get_sim(sim);
+                SimSleep {
+                    deadline: sim.elapsed() + dur,
+                    key: sim.timer_key(),
+                    sim,
+                }
+                .await
+            }
+        }
+    }
+
+    /// Spawn a detached task
+    pub fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        match self.inner {
+            Inner::System(_) => {
+                task::spawn(fut);
+            }
+            Inner::Sim(ref sim) => Self::get_sim(sim).spawn(Box::pin(fut)),
+        }
+    }
+
+    /// Fill a buffer with random data
+    ///
+    /// In a simulation the data is taken from the seeded generator,
+    /// to keep runs reproducible.
+    pub fn fill_bytes(&self, buf: &mut [u8]) {
+        match self.inner {
+            Inner::System(_) => rand::thread_rng().fill_bytes(buf),
+            Inner::Sim(ref sim) => Self::get_sim(sim).fill_bytes(buf),
+        }
+    }
+}
+
+/// A future waiting for a point in simulated time
+struct SimSleep {
+    deadline: Duration,
+    key: u64,
+    sim: Arc<SimInner>,
+}
+
+impl Future for SimSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<()> {
+        if self
+            .sim
+            .register_timer(self.deadline, self.key, ctx.waker())
+        {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
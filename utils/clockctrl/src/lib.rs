@@ -35,15 +35,33 @@
 //!         // ...
 //!     });
 //! ```
+//!
+//! ## Simulation
+//!
+//! Reactors that take all their time measurements, sleeps and task
+//! spawns from a [`Clock`] can also be driven by a [`Simulation`]: a
+//! seeded, single threaded scheduler with a virtual clock.  This
+//! makes it possible to reproduce the exact same interleaving of
+//! tasks between runs, and to fast-forward through long periods of
+//! time in tests.
+//!
+//! [`Clock`]: struct.Clock.html
+//! [`Simulation`]: struct.Simulation.html
 
 #![doc(html_favicon_url = "https://qaul.net/favicon.ico")]
 #![doc(html_logo_url = "https://qaul.net/img/qaul_icon-128.png")]
 
+mod clock;
+pub use clock::Clock;
+
 mod ctrl;
 pub use ctrl::{ClockCtrl, Scheduler};
 
 mod error;
 pub use error::Error;
 
+mod sim;
+pub use sim::Simulation;
+
 mod target;
 pub use target::{Interval, Target};
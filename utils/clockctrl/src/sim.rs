@@ -0,0 +1,340 @@
+use futures::task::{waker, ArcWake};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll, Waker},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+type Task = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+thread_local! {
+    /// The simulation that is currently running on this thread
+    static CURRENT: RefCell<Option<Weak<SimInner>>> = RefCell::new(None);
+}
+
+/// Get the simulation that is running on this thread, if any
+pub(crate) fn current() -> Option<Weak<SimInner>> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+/// Resets the current simulation when `block_on` returns
+struct Enter(Option<Weak<SimInner>>);
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        let prev = self.0.take();
+        CURRENT.with(|c| *c.borrow_mut() = prev);
+    }
+}
+
+/// A deterministic, single threaded task scheduler
+///
+/// A simulation runs all tasks spawned via its [`Clock`] on the
+/// thread calling [`block_on`].  Whenever more than one task is ready
+/// to make progress, the next one is chosen by a seeded random number
+/// generator, meaning that two runs with the same seed will always
+/// poll tasks in the same order.
+///
+/// Time inside a simulation is virtual.  It only moves forward when
+/// no task is ready, and jumps straight to the next timer deadline.
+/// This means that a simulated hour of a mostly idle network only
+/// takes as long as the actual work done in it.
+///
+/// Tasks running in a simulation must not rely on any other timer or
+/// thread pool (such as `async_std::task::sleep`), because the
+/// scheduler can't see them, and will consider the simulation stuck.
+/// Code that has no `Clock` passed to it can use [`Clock::current`]
+/// to get the clock of the simulation it runs in.
+///
+/// [`Clock`]: struct.Clock.html
+/// [`block_on`]: struct.Simulation.html#method.block_on
+/// [`Clock::current`]: struct.Clock.html#method.current
+///
+/// ```
+/// use clockctrl::Simulation;
+/// use std::time::Duration;
+///
+/// let sim = Simulation::new(1312);
+/// let clock = sim.clock();
+///
+/// sim.block_on(async {
+///     // This only takes a few micro seconds
+///     clock.sleep(Duration::from_secs(3600)).await;
+/// });
+///
+/// assert_eq!(sim.elapsed(), Duration::from_secs(3600));
+/// ```
+pub struct Simulation {
+    inner: Arc<SimInner>,
+}
+
+pub(crate) struct SimInner {
+    state: Mutex<SimState>,
+}
+
+struct SimState {
+    /// Virtual time passed since the start of the simulation
+    now: Duration,
+    /// The wall-clock time at which the simulation starts
+    start: SystemTime,
+    /// All tasks that are not currently being polled
+    tasks: BTreeMap<u64, Task>,
+    /// Tasks that have been woken
+    ready: Vec<u64>,
+    queued: BTreeSet<u64>,
+    /// Wakers waiting for a point in time, ordered by deadline
+    timers: BTreeMap<(Duration, u64), Waker>,
+    rng: StdRng,
+    next_id: u64,
+}
+
+/// The id used for the future passed to `block_on`
+const MAIN: u64 = 0;
+
+struct TaskWaker {
+    id: u64,
+    sim: Weak<SimInner>,
+}
+
+impl ArcWake for TaskWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if let Some(sim) = arc_self.sim.upgrade() {
+            sim.schedule(arc_self.id);
+        }
+    }
+}
+
+impl Simulation {
+    /// Create a new simulation with a seed
+    ///
+    /// The virtual clock starts at the UNIX epoch.
+    pub fn new(seed: u64) -> Self {
+        Self::starting_at(seed, UNIX_EPOCH)
+    }
+
+    /// Create a new simulation that starts at a particular time
+    pub fn starting_at(seed: u64, start: SystemTime) -> Self {
+        Self {
+            inner: Arc::new(SimInner {
+                state: Mutex::new(SimState {
+                    now: Duration::from_secs(0),
+                    start,
+                    tasks: BTreeMap::new(),
+                    ready: vec![],
+                    queued: BTreeSet::new(),
+                    timers: BTreeMap::new(),
+                    rng: StdRng::seed_from_u64(seed),
+                    next_id: MAIN + 1,
+                }),
+            }),
+        }
+    }
+
+    /// Get a clock handle that spawns and sleeps in this simulation
+    pub fn clock(&self) -> crate::Clock {
+        crate::Clock::sim(Arc::downgrade(&self.inner))
+    }
+
+    /// Return the amount of virtual time passed since the start
+    pub fn elapsed(&self) -> Duration {
+        self.inner.elapsed()
+    }
+
+    /// Run the simulation until the provided future completes
+    ///
+    /// Other tasks spawned into the simulation are polled along the
+    /// way, and stay around after this function returns, meaning
+    /// that `block_on` can be called several times in a row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the future can never complete, because no task is
+    /// ready, and there are no timers left to advance time with.
+    pub fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        let sim = Some(Arc::downgrade(&self.inner));
+        let _enter = Enter(CURRENT.with(|c| c.replace(sim)));
+
+        let mut fut = Box::pin(fut);
+        let main_waker = self.waker(MAIN);
+        self.inner.schedule(MAIN);
+
+        loop {
+            let next = self.inner.state.lock().unwrap().next();
+            match next {
+                Some(MAIN) => {
+                    let mut ctx = Context::from_waker(&main_waker);
+                    if let Poll::Ready(t) = fut.as_mut().poll(&mut ctx) {
+                        return t;
+                    }
+                }
+                Some(id) => self.poll_task(id),
+                None => {
+                    let wakers = self.inner.state.lock().unwrap().advance();
+                    if wakers.is_empty() {
+                        panic!("Simulation is stuck: no task can make progress");
+                    }
+                    wakers.into_iter().for_each(|w| w.wake());
+                }
+            }
+        }
+    }
+
+    fn waker(&self, id: u64) -> Waker {
+        waker(Arc::new(TaskWaker {
+            id,
+            sim: Arc::downgrade(&self.inner),
+        }))
+    }
+
+    fn poll_task(&self, id: u64) {
+        // Tasks are removed from the set while they are being polled
+        // so that they can spawn new tasks without deadlocking
+        let task = self.inner.state.lock().unwrap().tasks.remove(&id);
+        if let Some(mut task) = task {
+            let waker = self.waker(id);
+            let mut ctx = Context::from_waker(&waker);
+            if task.as_mut().poll(&mut ctx).is_pending() {
+                self.inner.state.lock().unwrap().tasks.insert(id, task);
+            }
+        }
+    }
+}
+
+impl SimInner {
+    pub(crate) fn spawn(&self, task: Task) {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.tasks.insert(id, task);
+            id
+        };
+        self.schedule(id);
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    pub(crate) fn now(&self) -> SystemTime {
+        let state = self.state.lock().unwrap();
+        state.start + state.now
+    }
+
+    pub(crate) fn fill_bytes(&self, buf: &mut [u8]) {
+        self.state.lock().unwrap().rng.fill(buf);
+    }
+
+    /// Register a waker for a point in virtual time
+    ///
+    /// Returns `true` if the deadline has already passed.
+    pub(crate) fn register_timer(&self, deadline: Duration, key: u64, waker: &Waker) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if deadline <= state.now {
+            return true;
+        }
+
+        state.timers.insert((deadline, key), waker.clone());
+        false
+    }
+
+    pub(crate) fn timer_key(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        state.next_id
+    }
+
+    fn schedule(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.queued.insert(id) {
+            state.ready.push(id);
+        }
+    }
+}
+
+impl SimState {
+    /// Pick the next ready task
+    fn next(&mut self) -> Option<u64> {
+        if self.ready.is_empty() {
+            return None;
+        }
+
+        let idx = self.rng.gen_range(0, self.ready.len());
+        let id = self.ready.swap_remove(idx);
+        self.queued.remove(&id);
+        Some(id)
+    }
+
+    /// Move time forward to the next deadline and return its wakers
+    fn advance(&mut self) -> Vec<Waker> {
+        let deadline = match self.timers.keys().next() {
+            Some((d, _)) => *d,
+            None => return vec![],
+        };
+
+        self.now = deadline;
+        let later = self.timers.split_off(&(deadline, std::u64::MAX));
+        std::mem::replace(&mut self.timers, later)
+            .into_iter()
+            .map(|(_, w)| w)
+            .collect()
+    }
+}
+
+#[test]
+fn current_clock() {
+    assert!(!crate::Clock::current().is_simulated());
+
+    let sim = Simulation::new(0);
+    let clock = sim.clock();
+    sim.block_on(async {
+        clock.sleep(Duration::from_secs(10)).await;
+        let curr = crate::Clock::current();
+        assert!(curr.is_simulated());
+        assert_eq!(curr.now(), UNIX_EPOCH + Duration::from_secs(10));
+    });
+
+    assert!(!crate::Clock::current().is_simulated());
+}
+
+#[test]
+fn sleep_advances_time() {
+    let sim = Simulation::new(0);
+    let clock = sim.clock();
+    sim.block_on(async {
+        clock.sleep(Duration::from_secs(10)).await;
+        clock.sleep(Duration::from_secs(5)).await;
+    });
+
+    assert_eq!(sim.elapsed(), Duration::from_secs(15));
+}
+
+#[test]
+fn spawned_tasks_are_reproducible() {
+    fn run(seed: u64) -> Vec<u64> {
+        let sim = Simulation::new(seed);
+        let clock = sim.clock();
+        let log = Arc::new(Mutex::new(vec![]));
+
+        for i in 0..16 {
+            let (log, c) = (Arc::clone(&log), clock.clone());
+            clock.spawn(async move {
+                c.sleep(Duration::from_millis(i % 3)).await;
+                log.lock().unwrap().push(i);
+            });
+        }
+
+        sim.block_on(clock.sleep(Duration::from_secs(1)));
+        let log = log.lock().unwrap();
+        log.clone()
+    }
+
+    let a = run(1312);
+    assert_eq!(a.len(), 16);
+    assert_eq!(a, run(1312));
+}